@@ -0,0 +1,11 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use rawbit::internals::archive::entries;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = entries(&mut Cursor::new(data), true);
+    let _ = entries(&mut Cursor::new(data), false);
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rawbit::FilenameFormat;
+use rawler::decoders::RawMetadata;
+
+fuzz_target!(|fmt: &str| {
+    match FilenameFormat::parse(fmt) {
+        Ok(parsed) => {
+            let _ = parsed.render_filename("IMG_0001", &RawMetadata::default());
+        }
+
+        Err(err) => {
+            let _ = err.to_string();
+        }
+    }
+});
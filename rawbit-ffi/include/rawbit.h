@@ -0,0 +1,58 @@
+/*
+ * C API for embedding the rawbit importer, see rawbit/src/ffi.rs for the full documentation.
+ *
+ * An import is planned with rawbit_import_new(), converts in the background once
+ * rawbit_import_start() is called, and is polled with rawbit_import_poll() until it's finished.
+ * Functions returning int return RAWBIT_OK, or RAWBIT_ERROR with the reason in
+ * rawbit_last_error().
+ */
+
+#ifndef RAWBIT_H
+#define RAWBIT_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RAWBIT_OK 0
+#define RAWBIT_ERROR -1
+
+typedef struct RawbitImport RawbitImport;
+
+typedef struct RawbitProgress {
+    size_t total;
+    size_t converted;
+    size_t failed;
+    /* no more files will be converted, because they're all done or the import was cancelled */
+    bool finished;
+} RawbitProgress;
+
+/* Plans an import of the raws in in_dir, format may be NULL for the default. NULL on failure. */
+RawbitImport *rawbit_import_new(const char *in_dir, const char *out_dir, const char *format,
+                                bool recurse);
+
+/* How many files the import will convert. */
+size_t rawbit_import_len(const RawbitImport *import);
+
+/* Starts converting in the background, returning straight away. */
+int rawbit_import_start(RawbitImport *import);
+
+int rawbit_import_poll(const RawbitImport *import, RawbitProgress *progress);
+
+/* Stops the import once the file being converted is done, without waiting for it. */
+void rawbit_import_cancel(RawbitImport *import);
+
+/* Cancels the import, waits for the file being converted, and frees it. */
+void rawbit_import_free(RawbitImport *import);
+
+/* The last error on this thread, or NULL. Valid until the next failing call on the same thread. */
+const char *rawbit_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RAWBIT_H */
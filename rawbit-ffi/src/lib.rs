@@ -0,0 +1,7 @@
+// Copyright (c) Carter J. Canedy <cartercanedy42@gmail.com>
+// rawbit is free software, distributable under the terms of the MIT license
+// See https://raw.githubusercontent.com/cartercanedy/rawbit/refs/heads/master/LICENSE.txt
+
+//! Builds rawbit's C API into `librawbit_ffi`, see `include/rawbit.h`.
+
+pub use rawbit::internals::ffi::*;
@@ -0,0 +1,37 @@
+use std::{env, fs, path::Path};
+
+// rawler doesn't export its version, so read the resolved one from the lockfile for provenance records
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let manifest_dir = Path::new(&manifest_dir);
+
+    let lockfile = [manifest_dir, manifest_dir.parent().unwrap_or(manifest_dir)]
+        .into_iter()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|path| path.exists());
+
+    let version = lockfile
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|lock| rawler_version(&lock))
+        .unwrap_or_else(|| "unknown".into());
+
+    if let Some(path) = lockfile {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-env=RAWBIT_RAWLER_VERSION={version}");
+}
+
+fn rawler_version(lock: &str) -> Option<String> {
+    let mut lines = lock.lines();
+    lines.find(|line| line.trim() == "name = \"rawler\"")?;
+
+    lines
+        .next()?
+        .trim()
+        .strip_prefix("version = \"")?
+        .strip_suffix('"')
+        .map(ToString::to_string)
+}
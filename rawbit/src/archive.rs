@@ -0,0 +1,560 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::{Component, Path},
+};
+
+use flate2::{Crc, read::DeflateDecoder};
+use serde::{Deserialize, Serialize};
+use smlog::warn;
+
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP_END: u32 = 0x0605_4b50;
+const ZIP64_END: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const ZIP64_EXTRA: u16 = 0x0001;
+const ZIP_END_LEN: usize = 22;
+const ZIP_MAX_COMMENT: u64 = u16::MAX as u64;
+const ZIP_STORED: u16 = 0;
+const ZIP_DEFLATED: u16 = 8;
+const ZIP_ENCRYPTED: u16 = 1;
+
+const TAR_BLOCK_LEN: usize = 512;
+const TAR_BLOCK: u64 = TAR_BLOCK_LEN as u64;
+/// Long names and pax records are read whole, anything bigger than this isn't a real one.
+const TAR_MAX_LONG_NAME: u64 = 64 * 1024;
+
+/// How much of a member is allocated up front, the rest grows as it's read.
+const MAX_PREALLOC: u64 = 64 * 1024 * 1024;
+
+/// Whether `path` is an archive rawbit reads members out of, a `.zip` or an uncompressed `.tar`.
+#[must_use]
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip") || ext.eq_ignore_ascii_case("tar"))
+}
+
+/// A file inside an archive, and where its bytes are.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member {
+    /// relative to the archive's root, always `/` separated
+    pub path: String,
+    offset: u64,
+    /// as stored, compressed or not
+    stored_len: u64,
+    len: u64,
+    deflated: bool,
+    /// only zip archives record one
+    crc32: Option<u32>,
+}
+
+impl Member {
+    /// The directory the member is in, relative to the archive's root, empty at the root.
+    #[must_use]
+    pub fn dir(&self) -> &str {
+        self.path.rsplit_once('/').map_or("", |(dir, _)| dir)
+    }
+
+    /// How big the member is once it's read out of the archive.
+    #[must_use]
+    pub const fn size(&self) -> u64 {
+        self.len
+    }
+
+    /// Reads the member out of `archive`, inflating it if it's compressed.
+    pub fn read(&self, archive: &Path) -> io::Result<Vec<u8>> {
+        let mut file = File::open(archive)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+
+        let stored = file.take(self.stored_len);
+        let mut data =
+            Vec::with_capacity(usize::try_from(self.len.min(MAX_PREALLOC)).unwrap_or_default());
+
+        if self.deflated {
+            DeflateDecoder::new(stored).read_to_end(&mut data)?;
+        } else {
+            BufReader::new(stored).read_to_end(&mut data)?;
+        }
+
+        if data.len() as u64 != self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} is cut short", self.path),
+            ));
+        }
+
+        if let Some(crc32) = self.crc32 {
+            let mut crc = Crc::new();
+            crc.update(&data);
+
+            if crc.sum() != crc32 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is corrupt, its checksum doesn't match", self.path),
+                ));
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+/// The files in the archive at `path`, leaving out any that can't be read back, like encrypted
+/// ones, or that would land outside of the output directory.
+pub fn members(path: &Path) -> io::Result<Vec<Member>> {
+    let zip = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    let members = entries(&mut BufReader::new(File::open(path)?), zip)?;
+
+    Ok(members
+        .into_iter()
+        .filter(|member| {
+            let safe = Path::new(&member.path)
+                .components()
+                .all(|part| matches!(part, Component::Normal(_)));
+
+            if !safe {
+                warn!(
+                    "ignoring \"{}\" in \"{}\": it points outside of the archive",
+                    member.path,
+                    path.display()
+                );
+            }
+
+            safe
+        })
+        .collect())
+}
+
+/// Every entry in a zip, or a tar when `zip` is false, read out of `file` as is.
+pub fn entries<R: Read + Seek>(file: &mut R, zip: bool) -> io::Result<Vec<Member>> {
+    if zip {
+        zip_members(file)
+    } else {
+        tar_members(file)
+    }
+}
+
+/// `N` bytes at `at`, or an error if the header is cut short.
+fn bytes_at<const N: usize>(buf: &[u8], at: usize) -> io::Result<[u8; N]> {
+    at.checked_add(N)
+        .and_then(|end| buf.get(at..end))
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid("truncated archive header"))
+}
+
+fn u16_at(buf: &[u8], at: usize) -> io::Result<u16> {
+    bytes_at(buf, at).map(u16::from_le_bytes)
+}
+
+fn u32_at(buf: &[u8], at: usize) -> io::Result<u32> {
+    bytes_at(buf, at).map(u32::from_le_bytes)
+}
+
+fn u64_at(buf: &[u8], at: usize) -> io::Result<u64> {
+    bytes_at(buf, at).map(u64::from_le_bytes)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// `a + b`, or an error naming what overflowed.
+fn add(a: u64, b: u64, what: &str) -> io::Result<u64> {
+    a.checked_add(b).ok_or_else(|| invalid(what))
+}
+
+fn read_at<R: Read + Seek>(file: &mut R, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Where the central directory starts and how many entries it has.
+fn zip_directory<R: Read + Seek>(file: &mut R) -> io::Result<(u64, u64)> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if len < ZIP_END_LEN as u64 {
+        return Err(invalid("not a zip archive"));
+    }
+
+    let tail_len = len.min(ZIP_END_LEN as u64 + ZIP_MAX_COMMENT);
+    let tail = read_at(
+        file,
+        len - tail_len,
+        usize::try_from(tail_len).unwrap_or_default(),
+    )?;
+
+    // the end record is followed by a comment, so it's searched for from the end
+    let end = (0..=tail.len().saturating_sub(ZIP_END_LEN))
+        .rev()
+        .find(|&at| u32_at(&tail, at).is_ok_and(|sig| sig == ZIP_END))
+        .filter(|&end| end + ZIP_END_LEN <= tail.len())
+        .ok_or_else(|| invalid("not a zip archive"))?;
+
+    let entries = u64::from(u16_at(&tail, end + 10)?);
+    let offset = u64::from(u32_at(&tail, end + 16)?);
+
+    if entries != u64::from(u16::MAX) && offset != u64::from(u32::MAX) {
+        return Ok((offset, entries));
+    }
+
+    // too big for the classic record, the zip64 one is found through the locator right before it
+    let end_at = len - tail_len + end as u64;
+    let locator = read_at(
+        file,
+        end_at.checked_sub(20).ok_or_else(|| invalid("bad zip64"))?,
+        20,
+    )?;
+    if u32_at(&locator, 0)? != ZIP64_LOCATOR {
+        return Err(invalid("missing zip64 locator"));
+    }
+
+    let end64 = read_at(file, u64_at(&locator, 8)?, 56)?;
+    if u32_at(&end64, 0)? != ZIP64_END {
+        return Err(invalid("missing zip64 end record"));
+    }
+
+    Ok((u64_at(&end64, 48)?, u64_at(&end64, 32)?))
+}
+
+fn zip_members<R: Read + Seek>(file: &mut R) -> io::Result<Vec<Member>> {
+    let (mut offset, entries) = zip_directory(file)?;
+    let mut members = vec![];
+
+    for _ in 0..entries {
+        let header = read_at(file, offset, 46)?;
+        if u32_at(&header, 0)? != ZIP_CENTRAL_HEADER {
+            return Err(invalid("corrupt zip central directory"));
+        }
+
+        let (name_len, extra_len, comment_len) = (
+            usize::from(u16_at(&header, 28)?),
+            usize::from(u16_at(&header, 30)?),
+            usize::from(u16_at(&header, 32)?),
+        );
+
+        let corrupt = "corrupt zip central directory";
+        let rest = read_at(file, add(offset, 46, corrupt)?, name_len + extra_len)?;
+        offset = add(
+            offset,
+            (46 + name_len + extra_len + comment_len) as u64,
+            corrupt,
+        )?;
+
+        let path = String::from_utf8_lossy(&rest[..name_len]).replace('\\', "/");
+        if path.ends_with('/') {
+            continue;
+        }
+
+        let mut len = u64::from(u32_at(&header, 24)?);
+        let mut stored_len = u64::from(u32_at(&header, 20)?);
+        let mut local_offset = u64::from(u32_at(&header, 42)?);
+
+        // sizes too big for the header are in the zip64 extra field, in this order
+        let mut extras = &rest[name_len..];
+        while extras.len() >= 4 {
+            let (id, size) = (u16_at(extras, 0)?, usize::from(u16_at(extras, 2)?));
+            let data = extras.get(4..4 + size).unwrap_or_default();
+
+            if id == ZIP64_EXTRA {
+                let mut values = data
+                    .chunks_exact(8)
+                    .filter_map(|value| u64_at(value, 0).ok());
+
+                for field in [&mut len, &mut stored_len, &mut local_offset] {
+                    if *field == u64::from(u32::MAX) {
+                        *field = values.next().unwrap_or(*field);
+                    }
+                }
+            }
+
+            extras = extras.get(4 + size..).unwrap_or_default();
+        }
+
+        let (flags, method) = (u16_at(&header, 8)?, u16_at(&header, 10)?);
+        if flags & ZIP_ENCRYPTED != 0 || !matches!(method, ZIP_STORED | ZIP_DEFLATED) {
+            warn!("ignoring \"{path}\": it's encrypted or compressed in a way rawbit can't read");
+            continue;
+        }
+
+        let local = read_at(file, local_offset, 30)?;
+        if u32_at(&local, 0)? != ZIP_LOCAL_HEADER {
+            return Err(invalid("corrupt zip local header"));
+        }
+
+        let local_len = 30 + u64::from(u16_at(&local, 26)?) + u64::from(u16_at(&local, 28)?);
+        members.push(Member {
+            path,
+            offset: add(local_offset, local_len, "corrupt zip local header")?,
+            stored_len,
+            len,
+            deflated: method == ZIP_DEFLATED,
+            crc32: Some(u32_at(&header, 16)?),
+        });
+    }
+
+    Ok(members)
+}
+
+/// A NUL terminated header field.
+fn tar_str(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// A size field, in octal, or big-endian binary for sizes octal can't fit.
+fn tar_size(field: &[u8]) -> io::Result<u64> {
+    if field.first().is_some_and(|&b| b & 0x80 != 0) {
+        return field[1..]
+            .iter()
+            .try_fold(0u64, |size, &b| {
+                size.checked_mul(256)?.checked_add(u64::from(b))
+            })
+            .ok_or_else(|| invalid("corrupt tar header"));
+    }
+
+    let digits = tar_str(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+
+    if digits.is_empty() {
+        return Ok(0);
+    }
+
+    u64::from_str_radix(digits, 8).map_err(|_| invalid("corrupt tar header"))
+}
+
+/// The `path` record of a pax extended header, if it has one.
+fn pax_path(records: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(records).lines().find_map(|record| {
+        let (_len, entry) = record.split_once(' ')?;
+        entry.strip_prefix("path=").map(str::to_string)
+    })
+}
+
+fn tar_members<R: Read + Seek>(file: &mut R) -> io::Result<Vec<Member>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut offset = 0u64;
+    let mut long_name = None;
+    let mut members = vec![];
+
+    while offset.checked_add(TAR_BLOCK).is_some_and(|end| end <= len) {
+        let header = read_at(file, offset, TAR_BLOCK_LEN)?;
+
+        // the archive ends with blocks of zeros
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+
+        let size = tar_size(&header[124..136])?;
+        let data_offset = offset + TAR_BLOCK;
+        offset = size
+            .div_ceil(TAR_BLOCK)
+            .checked_mul(TAR_BLOCK)
+            .and_then(|padded| data_offset.checked_add(padded))
+            .ok_or_else(|| invalid("corrupt tar header"))?;
+
+        if matches!(header[156], b'L' | b'x') && size > TAR_MAX_LONG_NAME {
+            return Err(invalid("tar long name is too long"));
+        }
+
+        match header[156] {
+            // the name of the next entry, too long for its header
+            b'L' => {
+                let name = read_at(file, data_offset, usize::try_from(size).unwrap_or_default())?;
+                long_name = Some(tar_str(&name));
+            }
+
+            b'x' => {
+                let records =
+                    read_at(file, data_offset, usize::try_from(size).unwrap_or_default())?;
+                long_name = pax_path(&records).or(long_name);
+            }
+
+            b'0' | b'\0' | b'7' => {
+                let path = long_name.take().unwrap_or_else(|| {
+                    let name = tar_str(&header[..100]);
+                    let prefix = tar_str(&header[345..500]);
+
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{prefix}/{name}")
+                    } else {
+                        name
+                    }
+                });
+
+                members.push(Member {
+                    path: path.trim_start_matches("./").into(),
+                    offset: data_offset,
+                    stored_len: size,
+                    len: size,
+                    deflated: false,
+                    crc32: None,
+                });
+            }
+
+            _ => long_name = None,
+        }
+    }
+
+    Ok(members)
+}
+
+#[cfg(test)]
+mod archive_tests {
+    use std::{
+        fs,
+        io::{Cursor, Write as _},
+    };
+
+    use flate2::{Compression, Crc, write::DeflateEncoder};
+
+    use super::{entries, members};
+
+    /// A zip of `(name, data, deflate)` entries, the way any zip tool would write them.
+    fn zip(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let (mut zip, mut central) = (vec![], vec![]);
+
+        for &(name, data, deflate) in entries {
+            let stored = if deflate {
+                let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                data.to_vec()
+            };
+
+            let mut crc = Crc::new();
+            crc.update(data);
+
+            let mut fields = vec![];
+            fields.extend(20u16.to_le_bytes());
+            fields.extend(0u16.to_le_bytes());
+            fields.extend((if deflate { 8u16 } else { 0 }).to_le_bytes());
+            fields.extend([0; 4]);
+            fields.extend(crc.sum().to_le_bytes());
+            fields.extend(u32::try_from(stored.len()).unwrap().to_le_bytes());
+            fields.extend(u32::try_from(data.len()).unwrap().to_le_bytes());
+            fields.extend(u16::try_from(name.len()).unwrap().to_le_bytes());
+            fields.extend(0u16.to_le_bytes());
+
+            let local_offset = u32::try_from(zip.len()).unwrap();
+            zip.extend(0x0403_4b50u32.to_le_bytes());
+            zip.extend(&fields);
+            zip.extend(name.as_bytes());
+            zip.extend(&stored);
+
+            central.extend(0x0201_4b50u32.to_le_bytes());
+            central.extend(20u16.to_le_bytes());
+            central.extend(&fields);
+            central.extend([0; 6]);
+            central.extend([0; 4]);
+            central.extend(local_offset.to_le_bytes());
+            central.extend(name.as_bytes());
+        }
+
+        let (offset, count) = (zip.len(), u16::try_from(entries.len()).unwrap());
+        zip.extend(&central);
+        zip.extend(0x0605_4b50u32.to_le_bytes());
+        zip.extend([0; 4]);
+        zip.extend(count.to_le_bytes());
+        zip.extend(count.to_le_bytes());
+        zip.extend(u32::try_from(central.len()).unwrap().to_le_bytes());
+        zip.extend(u32::try_from(offset).unwrap().to_le_bytes());
+        zip.extend(0u16.to_le_bytes());
+
+        zip
+    }
+
+    fn tar(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = vec![];
+
+        for &(name, data) in entries {
+            let mut header = [0; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+
+            tar.extend(header);
+            tar.extend(data);
+            tar.resize(tar.len().div_ceil(512) * 512, 0);
+        }
+
+        tar.extend([0; 1024]);
+        tar
+    }
+
+    #[test]
+    fn reads_members_out_of_zips_and_tars() {
+        let dir = tempfile::tempdir().unwrap();
+        let raw = [7; 1000];
+
+        let zip_path = dir.path().join("card.zip");
+        fs::write(
+            &zip_path,
+            zip(&[
+                ("DCIM/100CANON/IMG_0001.CR3", &raw, true),
+                ("DCIM/100CANON/IMG_0002.CR3", b"stored", false),
+                ("../IMG_0003.CR3", b"escapes", false),
+            ]),
+        )
+        .unwrap();
+
+        let zipped = members(&zip_path).unwrap();
+        assert_eq!(zipped.len(), 2);
+        assert_eq!(zipped[0].dir(), "DCIM/100CANON");
+        assert_eq!(zipped[0].read(&zip_path).unwrap(), raw);
+        assert_eq!(zipped[1].read(&zip_path).unwrap(), b"stored");
+
+        let tar_path = dir.path().join("card.tar");
+        fs::write(
+            &tar_path,
+            tar(&[("DCIM/IMG_0001.CR3", &raw), ("IMG_0002.CR3", b"x")]),
+        )
+        .unwrap();
+
+        let tarred = members(&tar_path).unwrap();
+        assert_eq!(tarred.len(), 2);
+        assert_eq!(tarred[1].path, "IMG_0002.CR3");
+        assert_eq!(tarred[1].dir(), "");
+        assert_eq!(tarred[0].read(&tar_path).unwrap(), raw);
+
+        // a corrupted member is caught by its checksum
+        let mut corrupt = fs::read(&zip_path).unwrap();
+        let at = corrupt.windows(6).position(|w| w == b"stored").unwrap();
+        corrupt[at] = b'S';
+        fs::write(&zip_path, corrupt).unwrap();
+        assert!(zipped[1].read(&zip_path).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_archives_instead_of_panicking() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for data in [&b"PK"[..], b"PK\x05\x06abcd"] {
+            let path = dir.path().join("x.zip");
+            fs::write(&path, data).unwrap();
+            assert!(members(&path).is_err());
+        }
+
+        // an end record cut short by the end of the file
+        let mut cut = zip(&[("IMG_0001.CR3", b"raw", false)]);
+        cut.truncate(cut.len() - 4);
+        assert!(entries(&mut Cursor::new(cut), true).is_err());
+
+        // a base-256 size too big for a u64
+        let mut huge = tar(&[("IMG_0001.CR3", b"raw")]);
+        huge[124] = 0x80;
+        huge[125..136].fill(0xff);
+        assert!(entries(&mut Cursor::new(huge), false).is_err());
+
+        // a long name too big to be one
+        let mut long = tar(&[("././@LongLink", b"name"), ("IMG_0001.CR3", b"raw")]);
+        long[156] = b'L';
+        long[124..135].copy_from_slice(format!("{:011o}", 1 << 20).as_bytes());
+        assert!(entries(&mut Cursor::new(long), false).is_err());
+    }
+}
@@ -1,23 +1,52 @@
 use std::{
-    fs::read_dir,
-    path::{Path, PathBuf},
+    ffi::OsString,
+    fs::{File, read_dir},
+    io::{self, Read as _},
+    path::{self, Path, PathBuf},
+    slice,
     thread::available_parallelism,
 };
 
 use clap::{
-    ArgAction, Args, Parser, arg,
+    ArgAction, Args, Parser, Subcommand, ValueEnum as _,
     builder::{
-        IntoResettable, Styles,
+        IntoResettable, PathBufValueParser, Styles, TypedValueParser as _,
         styling::{AnsiColor, Color, Style},
     },
-    command, value_parser,
+    value_parser,
 };
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use rayon::iter::{IntoParallelIterator as _, ParallelBridge as _, ParallelIterator as _};
 use smlog::{debug, warn};
 
 use rawler::decoders::supported_extensions;
-
-use crate::common::{AppError, RawbitResult, map_err};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    archive::{self, Member},
+    clock::{DateSource, Offset, TimeShift, Zone},
+    common::{AppError, RawbitResult, map_err},
+    conflict::OnConflict,
+    convert::Compression,
+    decode::DecodeParam,
+    ignore::{IGNORE_FILE, Ignores},
+    journal::Journal,
+    layout::Layout,
+    lens::LensProfile,
+    link::{LinkArchive, LinkMode},
+    metadata::MetadataPolicy,
+    order,
+    originals::Originals,
+    parse::{ChronoIndex, Var},
+    paths,
+    perms::{Mode, Owner, Permissions},
+    salvage::ConvertFallback,
+    sanitize::{NameRules, Normalization},
+    sidecar::{self, JpegPolicy},
+    space,
+    stem::{self, StemFallback},
+    undersized::UndersizedPolicy,
+};
 
 macro_rules! style {
     ($style:expr) => {
@@ -44,9 +73,14 @@ const fn cli_style() -> Styles {
     trailing_var_arg = true,
     styles = cli_style(),
     next_line_help = true,
-    color = clap::ColorChoice::Always
+    color = clap::ColorChoice::Always,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
 )]
 pub struct ImportConfig {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[command(flatten)]
     pub source: RawSource,
 
@@ -54,9 +88,37 @@ pub struct ImportConfig {
         short = 'o',
         long = "out-dir",
         value_name = "DIR",
+        conflicts_with = "batch",
         help = "directory to write converted DNGs"
     )]
-    pub output_dir: PathBuf,
+    pub output_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        default_value_if("files", "-", "true"),
+        conflicts_with_all = ["output_dir", "out_dir_relative", "input_dir", "batch", "resume", "files_from", "dry_run", "watch"],
+        help = "write the DNG of the one file given to stdout instead of into --out-dir, for use in a pipeline
+with - as the file, the raw is read from stdin, e.g. rawbit - < IMG_0001.CR3 > IMG_0001.dng"
+    )]
+    pub stdout: bool,
+
+    #[arg(
+        long,
+        value_name = "SUBDIR",
+        conflicts_with_all = ["output_dir", "batch", "audit_card"],
+        help = "write each converted DNG into SUBDIR next to its source, instead of one --out-dir\ne.g. --out-dir-relative converted puts 2024/shoot/IMG_0001.CR3 in 2024/shoot/converted/"
+    )]
+    pub out_dir_relative: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "output_dir",
+        conflicts_with_all = ["transactional", "dry_run"],
+        help = "write to DIR instead if --out-dir becomes unavailable partway, e.g. a drive that's unplugged\nmove the files back once it's there again with rawbit reconcile DIR"
+    )]
+    pub out_dir_fallback: Option<PathBuf>,
 
     #[arg(
         short = 'F',
@@ -66,13 +128,137 @@ pub struct ImportConfig {
     )]
     pub fmt_str: Option<String>,
 
+    #[arg(
+        long,
+        value_name = "LAYOUT",
+        help = "put converted DNGs in a built-in directory structure under --out-dir, named with --format within it\ne.g. --layout lightroom files DSC01234.ARW as 2024/2024-05-17/DSC01234.dng"
+    )]
+    pub layout: Option<Layout>,
+
+    #[arg(
+        long,
+        value_name = "STR",
+        help = "what metadata a file doesn't have renders as in --format, instead of nothing, e.g. --missing-value unknown"
+    )]
+    pub missing_value: Option<String>,
+
+    #[arg(
+        long,
+        help = "fail files that don't have everything --format asks for, naming what's missing, instead of naming them without it"
+    )]
+    pub strict_format: bool,
+
+    #[arg(
+        long,
+        default_missing_value = order::SEQ_FILE,
+        num_args = 0..=1,
+        value_name = "PATH",
+        help = "carry {seq} on from where the last import left off, keeping the last number in PATH\nrelative to the output directory, .rawbit-seq if no PATH is given"
+    )]
+    pub counter_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "NAME=VALUE",
+        help = "set a variable for --format to use as {var.NAME}, repeat it for each one\ne.g. --set project=iceland2025 -F '{var.project}/%Y-%m-%d_'"
+    )]
+    pub set: Vec<Var>,
+
+    #[arg(
+        long,
+        value_name = "OS",
+        help = "make names valid on OS, replacing characters it doesn't allow, e.g. the \":\" of a shutter speed\nauto follows the system rawbit runs on, use windows for a drive shared with Windows"
+    )]
+    pub name_rules: Option<NameRules>,
+
+    #[arg(
+        long,
+        value_name = "CHAR",
+        help = "what replaces characters names can't have (default _)"
+    )]
+    pub replace_char: Option<char>,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "cut names longer than BYTES short, keeping their extension and room for a number (default 255)\nfor file systems that take shorter names, or paths that have to fit within a limit"
+    )]
+    pub max_name_len: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "FORM",
+        help = "put names in a Unicode normalization form, so accented letters are written one way whatever the camera wrote\nnfc composes them, the way Linux and Windows usually have them, nfd decomposes them, none (default) leaves them"
+    )]
+    pub normalize: Option<Normalization>,
+
     #[arg(
         short,
         long,
         value_name = "ARTIST",
-        help = "value of the \"artist\" field in converted DNGs"
+        help = "value of the \"artist\" field in converted DNGs\nrepeat it for several artists, e.g. -a \"Jane Doe\" -a \"John Smith\""
+    )]
+    pub artist: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "MODEL",
+        help = "lens model to record for files whose camera didn't record one, e.g. for manual or adapted lenses\n[[lens-profile]] tables in the config file are checked first"
+    )]
+    pub lens: Option<String>,
+
+    // spelled out so clap parses one JSON array, rather than a list of values
+    #[arg(long, value_name = "JSON", hide = true, value_parser = parse_lens_profiles)]
+    pub lens_profiles: Option<std::vec::Vec<LensProfile>>,
+
+    #[arg(
+        long,
+        value_name = "SERIAL=OFFSET",
+        help = "correct the clock of the camera with serial number SERIAL by OFFSET, [+-]HH:MM:SS, repeat it for each body
+applies to names, capture-time order and the timestamps in the DNG, e.g. --time-shift-for 12345678=+00:00:43"
+    )]
+    pub time_shift_for: Vec<TimeShift>,
+
+    #[arg(
+        long,
+        value_name = "OFFSET",
+        allow_hyphen_values = true,
+        help = "correct the clock of every camera by OFFSET, [+-]HH:MM:SS or like -1h2m30s, on top of any --time-shift-for
+applies to names, capture-time order, the timestamps in the DNG and --mtime-from-exif, e.g. --time-shift=-1h"
+    )]
+    pub time_shift: Option<Offset>,
+
+    #[arg(
+        long,
+        value_name = "SOURCE",
+        help = "which timestamp names, capture-time order and --mtime-from-exif go by, falling back on the others when a file doesn't have it
+original (default) is when it was taken, digitized or create the EXIF CreateDate, modify when the file was last changed
+file-mtime the file's modification time, for scans and other files without EXIF"
+    )]
+    pub date_source: Option<DateSource>,
+
+    #[arg(
+        long,
+        value_name = "ZONE",
+        help = "render dates in ZONE rather than the one each file was taken in, going by the UTC offset the camera recorded
+local for this system's time zone, or an offset like +09:00, for shoots from cameras set to different zones"
+    )]
+    pub timezone: Option<Zone>,
+
+    #[arg(
+        long,
+        conflicts_with = "timezone",
+        help = "render dates in UTC, the same as --timezone +00:00"
     )]
-    pub artist: Option<String>,
+    pub utc: bool,
+
+    #[arg(
+        long,
+        value_name = "KEY=VALUE",
+        help = "set one of rawler's conversion settings, to work around how it decodes a camera's raws, repeat it for each
+image-index=N, crop=best|active-area|none, scaling=true|false, predictor=1-7 or photometric=original|linear"
+    )]
+    pub decode_param: Vec<DecodeParam>,
 
     #[arg(
         short,
@@ -86,18 +272,156 @@ pub struct ImportConfig {
         short,
         long,
         action = ArgAction::SetTrue,
-        help = "overwrite existing files, if they exist"
+        help = "overwrite existing files, if they exist, same as --on-conflict overwrite"
     )]
     pub force: bool,
 
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_enum,
+        conflicts_with = "force",
+        help = "what to do when a file is already where an output would go (default error)\nskip leaves it and doesn't import the new file, rename numbers the new file IMG_0001_001.dng"
+    )]
+    pub on_conflict: Option<OnConflict>,
+
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_enum,
+        help = "name files whose own name leaves nothing to go by, like .NEF, instead of failing them\nhash names them unnamed_ and a hash of their path, counter numbers them unnamed_0001, unnamed_0002, ..."
+    )]
+    pub stem_fallback: Option<StemFallback>,
+
     #[arg(
         short,
         long,
+        visible_short_alias = 'R',
+        visible_alias = "recursive",
         action = ArgAction::SetTrue,
         help = "ingest images from subdirectories as well, preserving directory structure in the output"
     )]
     pub recurse: bool,
 
+    #[arg(
+        long,
+        value_name = "N",
+        help = "with --recurse, go at most N directories below --in-dir, e.g. 1 for only the folders directly inside it"
+    )]
+    pub max_depth: Option<usize>,
+
+    #[arg(
+        short = '0',
+        long = "null",
+        action = ArgAction::SetTrue,
+        help = "the --files-from list is separated by NULs rather than newlines, e.g. from find -print0"
+    )]
+    pub null: bool,
+
+    #[arg(
+        long,
+        value_name = "EXT",
+        value_delimiter = ',',
+        help = "copy files with these extensions through as-is instead of converting them, still renamed with --format\ne.g. --copy heif,mp4 brings a card's HEIFs and clips along with the converted raws"
+    )]
+    pub copy: Vec<String>,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "don't convert anything, copy the raws themselves under the names --format gives them\nwith --originals delete they're moved instead, once each copy is on disk"
+    )]
+    pub no_convert: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "bring the card's video clips along too, .mov, .mp4, .m4v, .mts, .m2ts and .avi, renamed with --format
+dated by when the clip says it was recorded, or when the file was last written to"
+    )]
+    pub videos: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "copy each raw's .xmp and .wav files along with it, renamed to match its output
+e.g. IMG_0001.xmp next to IMG_0001.CR3 becomes 2024-05-06_IMG_0001.xmp next to 2024-05-06_IMG_0001.dng"
+    )]
+    pub sidecars: bool,
+
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_enum,
+        default_value_t,
+        help = "what to do with the JPEG of a RAW+JPEG pair, one with the same name as a raw next to it
+copy brings it along under its own name, rename names it like its raw's output, e.g. 2024-05-06_IMG_0001.jpg"
+    )]
+    pub jpeg_policy: JpegPolicy,
+
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "only import files matching this glob, relative to --in-dir, can be given more than once\ne.g. --include '100CANON/**'"
+    )]
+    pub include: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "skip files matching this glob, relative to --in-dir, can be given more than once, wins over --include\ne.g. --exclude '*_dup.NEF' --exclude '**/rejects/**'"
+    )]
+    pub exclude: Vec<String>,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        overrides_with = "no_follow_symlinks",
+        help = "follow symlinked files and directories while scanning --in-dir (default)\nlinks back to a directory that's already being scanned are skipped"
+    )]
+    pub follow_symlinks: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        overrides_with = "follow_symlinks",
+        help = "skip symlinked files and directories while scanning --in-dir"
+    )]
+    pub no_follow_symlinks: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "put files whose format expanded to nothing, e.g. for lack of metadata, in _unsorted/ in the output directory"
+    )]
+    pub unsorted: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "name every output before converting anything, and don't convert anything if two inputs would be given the same name, list them instead\notherwise they're numbered and listed afterwards"
+    )]
+    pub strict: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t,
+        help = "how much of the camera's metadata to carry over into converted DNGs"
+    )]
+    pub metadata: MetadataPolicy,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t,
+        help = "how converted DNGs store the image, lossless (default) or uncompressed
+convert existing DNGs again to change theirs"
+    )]
+    pub compression: Compression,
+
     #[arg(
         long,
         action = ArgAction::SetTrue,
@@ -105,40 +429,507 @@ pub struct ImportConfig {
     )]
     pub no_preview: bool,
 
-    #[arg(
-        long,
-        action = ArgAction::SetTrue,
-        help = "don't embed image thumbnail in output DNG"
-    )]
-    pub no_thumbnail: bool,
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "don't embed image thumbnail in output DNG"
+    )]
+    pub no_thumbnail: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "only print run information, don't perform conversions or write any data."
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        requires = "dry_run",
+        help = "print the dry-run preview as JSON instead of a table"
+    )]
+    pub json: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        conflicts_with = "dry_run",
+        help = "convert each file in its own process, so a decoder crash only fails that file"
+    )]
+    pub isolate_jobs: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        conflicts_with = "dry_run",
+        help = "if a file can't be converted, recover what's left of it\ntries the file's other raw images, then zero-fills a truncated file, then falls back to the embedded JPEG"
+    )]
+    pub salvage: bool,
+
+    #[arg(
+        long,
+        value_name = "WHAT",
+        value_enum,
+        conflicts_with = "dry_run",
+        help = "what to keep of a file that can't be converted at all, rather than just failing it\nextract-jpeg keeps the camera's embedded JPEG in fallback/ in the output directory, until there's a decoder for the file"
+    )]
+    pub fallback: Option<ConvertFallback>,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = space::parse_size,
+        default_value = "64K",
+        help = "raws smaller than SIZE are taken to have been cut short, most likely by a card write that didn't finish, see --on-undersized\nempty files always are, even ones brought along by --copy"
+    )]
+    pub min_size: u64,
+
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_enum,
+        default_value_t,
+        help = "what to do with files under --min-size, found before anything's converted\nskip leaves them out with a warning, quarantine copies them into _quarantine/ in the output directory, fail stops the import"
+    )]
+    pub on_undersized: UndersizedPolicy,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "try reading a file up to N more times if it fails in a way that might not happen again\nfor flaky network mounts, where reads occasionally fail with an I/O error"
+    )]
+    pub retries: u32,
+
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 500,
+        help = "milliseconds to wait before the first retry, doubling after each one"
+    )]
+    pub retry_delay: u64,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        conflicts_with = "dry_run",
+        help = "skip files the catalog shows were already converted with the same settings, as long as that output is intact\nrecords each conversion in the catalog in the state directory"
+    )]
+    pub cache: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "write or refresh index.json at the root of the output directory once done, listing every DNG under it\nwith its path, capture time, camera, checksum and the run that wrote it"
+    )]
+    pub index: bool,
+
+    #[arg(
+        long,
+        value_name = "CMD",
+        help = "skip files an external catalog or DAM already has, asking it by running CMD with each file's path appended\nCMD is run through the shell and exits 0 if the file exists there, 1 if it doesn't"
+    )]
+    pub exists_cmd: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "append a line of JSON to FILE once done, with how each import went and what it used\nfiles converted and failed, wall and CPU time, bytes read and written, and how many files converted at once"
+    )]
+    pub report: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "record how long each stage of the run took into FILE, a Chrome trace that Perfetto or chrome://tracing opens\nscanning and planning each import, and reading, decoding, converting and writing each file"
+    )]
+    pub trace_output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "copy the card's own index and database files (e.g. Sony .BDM, Canon .CTG) into audit/ in the output directory"
+    )]
+    pub audit_card: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        conflicts_with = "dry_run",
+        help = "unmount and eject the source volume once every file converted, if it's removable"
+    )]
+    pub eject: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with = "dry_run",
+        help = "also link each raw into DIR, under YYYY/MM/DD for when it was taken, rather than copying it\nDIR has to be on the same filesystem as --in-dir"
+    )]
+    pub link_originals: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_enum,
+        requires = "link_originals",
+        help = "how --link-originals links raws: hardlink (default), or reflink on btrfs, XFS and APFS"
+    )]
+    pub link_mode: Option<LinkMode>,
+
+    #[arg(
+        long,
+        value_name = "POLICY",
+        conflicts_with = "dry_run",
+        help = "what to do with each raw once it's converted and its DNG is flushed to disk: keep (default), move:DIR or delete
+moved raws keep their place under --in-dir, raws that had to be salvaged are always kept"
+    )]
+    pub originals: Option<Originals>,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "with --originals move or delete, read each output back before touching its raw: DNGs have to decode, copies have to match"
+    )]
+    pub verify: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        conflicts_with_all = ["dry_run", "eject", "batch", "resume", "files", "files_from"],
+        help = "keep running after converting what's in --in-dir, converting new files as they appear\nfiles are picked up once they've stopped changing for a couple of seconds"
+    )]
+    pub watch: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        conflicts_with_all = ["dry_run", "watch", "out_dir_relative"],
+        help = "all or nothing: stage every output and only move them into the output directory if every file converted\notherwise nothing is written and rawbit exits with an error"
+    )]
+    pub transactional: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "flush each output and its directory entry to disk before reporting it written, so a power loss can't lose it\nslower, meant for importing straight to archival disks"
+    )]
+    pub fsync: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "give each converted DNG its raw's modification and access times, so file managers sort it like the card"
+    )]
+    pub preserve_times: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "set each output's modification time to when it was taken, from its EXIF DateTimeOriginal\nfor DAMs and file managers that sort by file time, overrides --preserve-times for the modification time"
+    )]
+    pub mtime_from_exif: bool,
+
+    #[arg(
+        long,
+        value_name = "MODE",
+        help = "give each output this octal mode, e.g. 0640, instead of what the umask leaves\nthe directories rawbit creates get it too, searchable wherever it's readable, so 0640 makes them 0750"
+    )]
+    pub chmod: Option<Mode>,
+
+    #[arg(
+        long,
+        value_name = "USER[:GROUP]",
+        help = "give each output and the directories rawbit creates this owner, by name or id, e.g. photos:editors or :editors (Unix only)\nchanging the user usually needs root"
+    )]
+    pub chown: Option<Owner>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["dry_run", "out_dir_relative"],
+        help = "also copy each converted DNG into DIR as it's written, at the same place under it as in --out-dir
+repeat it for several backups, e.g. --out-dir /mnt/ssd/shoot --backup-dir /mnt/nas/shoot"
+    )]
+    pub backup_dir: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = space::parse_size,
+        help = "leave at least SIZE free on the destination, e.g. 10G, checked against an estimate of the batch before it starts\nwithout it the batch only has to fit"
+    )]
+    pub min_free_space: Option<u64>,
+
+    #[arg(long, value_name = "RESULT", hide = true)]
+    pub isolated: Option<PathBuf>,
+
+    #[arg(long, value_name = "DIR", hide = true)]
+    pub staging: Option<PathBuf>,
+
+    #[arg(long, value_name = "ID", hide = true)]
+    pub run_id: Option<String>,
+
+    #[arg(long, value_name = "INDEX/OF", hide = true)]
+    pub chrono_index: Option<ChronoIndex>,
+
+    #[arg(long, value_name = "N", hide = true)]
+    pub seq: Option<usize>,
+
+    #[arg(long, value_name = "PATH", hide = true)]
+    pub member: Option<String>,
+
+    #[arg(long, value_name = "NAME", hide = true)]
+    pub fallback_stem: Option<String>,
+
+    #[arg(
+        short = 'j',
+        long,
+        action = ArgAction::Set,
+        default_missing_value = "",
+        num_args = 0..=1,
+        value_name = "N",
+        help = "number of threads to use while processing input images, defaults to number of CPUs"
+    )]
+    pub n_threads: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        env = "RAWBIT_CONFIG",
+        help = "TOML config file, re-read between chunks so edits apply to files not yet converted\ndefaults to config.toml in the platform config directory, if it exists"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub state_config: StateConfig,
+
+    #[command(flatten)]
+    pub log_config: LogConfig,
+}
+
+/// Something other than an import.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Move files an --out-dir-fallback caught back into the output directories they were meant for
+    Reconcile {
+        #[arg(value_name = "FALLBACK_DIR")]
+        fallback: PathBuf,
+    },
+
+    /// Check an output directory against the catalog and its index.json files, listing missing, altered and uncataloged files
+    Audit {
+        #[arg(value_name = "DIR")]
+        dir: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "write the discrepancies found to FILE as JSON"
+        )]
+        report: Option<PathBuf>,
+
+        #[command(flatten)]
+        state_config: StateConfig,
+    },
+
+    /// Find what imports that crashed left half done: partial outputs, uncommitted --transactional batches, stale catalog records and broken journals
+    Fsck {
+        #[arg(
+            long,
+            help = "remove partial outputs, finish or roll back batches, and drop stale records
+without it, fsck only lists them"
+        )]
+        repair: bool,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "write what was found to FILE as JSON"
+        )]
+        report: Option<PathBuf>,
+
+        #[command(flatten)]
+        state_config: StateConfig,
+    },
+
+    /// Ask a running --watch import how it's doing
+    Daemon {
+        #[command(subcommand)]
+        command: DaemonCommand,
+    },
+}
+
+/// Queries for a running `--watch` import, over a socket in the state directory it uses.
+#[derive(Debug, Subcommand)]
+pub enum DaemonCommand {
+    /// Print how long the watch has been running, the files it has converted and failed, its memory use and the files waiting to settle
+    Status {
+        #[arg(long, help = "print the status as JSON")]
+        json: bool,
+
+        #[command(flatten)]
+        state_config: StateConfig,
+    },
+}
+
+impl ImportConfig {
+    #[must_use]
+    pub fn n_threads(&self) -> usize {
+        let default_threads = available_parallelism().unwrap().get();
+        self.n_threads.unwrap_or(default_threads)
+    }
+
+    /// Rejects combinations of options clap can't express, like filtering the metadata of an
+    /// embedded original, which carries all of the camera's.
+    pub fn check(&self) -> RawbitResult<()> {
+        // not left to clap, which doesn't count a - that stands for --stdout
+        if self.output_dir.is_none()
+            && self.out_dir_relative.is_none()
+            && self.source.batch.is_none()
+            && !self.stdout
+        {
+            return Err(AppError::Config(
+                "--out-dir is needed to know where to write the DNGs, or --stdout".into(),
+            ));
+        }
+
+        if self.stdout && !matches!(self.source.files.as_deref(), Some([_])) {
+            return Err(AppError::Config(
+                "--stdout converts one file, given on its own or as - to read it from stdin".into(),
+            ));
+        }
+
+        if self.embed && self.metadata != MetadataPolicy::All {
+            return Err(AppError::Config(
+                "--embed-raw keeps all of the original's metadata, it can't be used with --metadata"
+                    .into(),
+            ));
+        }
+
+        if self.watch && self.source.input_dir.is_none() {
+            return Err(AppError::Config(
+                "--watch needs a directory to watch, --in-dir".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// What to do about files already where outputs would go, from `--on-conflict` or `--force`.
+    #[must_use]
+    pub fn conflict_policy(&self) -> OnConflict {
+        self.on_conflict
+            .unwrap_or_else(|| OnConflict::forced(self.force))
+    }
+
+    /// What's given to the files and directories the import creates, see `--chmod` and `--chown`.
+    #[must_use]
+    pub const fn permissions(&self) -> Permissions {
+        Permissions {
+            mode: self.chmod,
+            owner: self.chown,
+        }
+    }
+
+    /// The `--link-originals` archive, if there is one.
+    #[must_use]
+    pub fn link_archive(&self) -> Option<LinkArchive> {
+        self.link_originals.clone().map(|dir| LinkArchive {
+            dir,
+            mode: self.link_mode.unwrap_or_default(),
+        })
+    }
+
+    /// Arguments that reproduce this run's conversion settings in an isolated worker process.
+    ///
+    /// The format and artist aren't included, since a config reload can change them mid-batch.
+    #[must_use]
+    pub fn isolated_worker_args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![
+            "--quiet".into(),
+            "--n-threads".into(),
+            "1".into(),
+            "--metadata".into(),
+            self.metadata
+                .to_possible_value()
+                .map_or_else(|| "all".into(), |v| v.get_name().into()),
+            "--compression".into(),
+            self.compression
+                .to_possible_value()
+                .map_or_else(|| "lossless".into(), |v| v.get_name().into()),
+        ];
+
+        for (set, flag) in [
+            (self.embed, "--embed-raw"),
+            (self.no_preview, "--no-preview"),
+            (self.no_thumbnail, "--no-thumbnail"),
+            (self.salvage, "--salvage"),
+            (self.unsorted, "--unsorted"),
+            (self.cache, "--cache"),
+            (self.fsync, "--fsync"),
+            (self.preserve_times, "--preserve-times"),
+            (self.mtime_from_exif, "--mtime-from-exif"),
+            (self.sidecars, "--sidecars"),
+        ] {
+            if set {
+                args.push(flag.into());
+            }
+        }
+
+        args.extend([
+            "--on-conflict".into(),
+            self.conflict_policy().to_string().into(),
+            "--retries".into(),
+            self.retries.to_string().into(),
+            "--retry-delay".into(),
+            self.retry_delay.to_string().into(),
+        ]);
+
+        for backup in &self.backup_dir {
+            args.extend(["--backup-dir".into(), backup.into()]);
+        }
+
+        if let Some(fallback) = self
+            .fallback
+            .and_then(|fallback| fallback.to_possible_value())
+        {
+            args.extend(["--fallback".into(), fallback.get_name().into()]);
+        }
+
+        if let Some(policy) = self.jpeg_policy.to_possible_value() {
+            args.extend(["--jpeg-policy".into(), policy.get_name().into()]);
+        }
+
+        if let Some(ref dir) = self.link_originals {
+            args.extend(["--link-originals".into(), dir.into()]);
+        }
+
+        if let Some(mode) = self.link_mode.and_then(|mode| mode.to_possible_value()) {
+            args.extend(["--link-mode".into(), mode.get_name().into()]);
+        }
+
+        if let Some(mode) = self.chmod {
+            args.extend(["--chmod".into(), mode.to_string().into()]);
+        }
 
-    #[arg(
-        long,
-        action = ArgAction::SetTrue,
-        help = "only print run information, don't perform conversions or write any data."
-    )]
-    pub dry_run: bool,
+        if let Some(owner) = self.chown {
+            args.extend(["--chown".into(), owner.to_string().into()]);
+        }
 
-    #[arg(
-        short = 'j',
-        long,
-        action = ArgAction::Set,
-        default_missing_value = "",
-        num_args = 0..=1,
-        value_name = "N",
-        help = "number of threads to use while processing input images, defaults to number of CPUs"
-    )]
-    pub n_threads: Option<usize>,
+        // so the worker reads and writes the same catalog
+        if let Some(ref state_dir) = self.state_config.state_dir {
+            args.extend(["--state-dir".into(), state_dir.into()]);
+        }
 
-    #[command(flatten)]
-    pub log_config: LogConfig,
+        args
+    }
 }
 
-impl ImportConfig {
-    pub fn n_threads(&self) -> usize {
-        let default_threads = available_parallelism().unwrap().get();
-        self.n_threads.unwrap_or(default_threads)
-    }
+// lens profiles reach an isolated worker on its command line, rather than through the config file
+fn parse_lens_profiles(json: &str) -> Result<Vec<LensProfile>, serde_json::Error> {
+    serde_json::from_str(json)
 }
 
 #[derive(Debug, Args)]
@@ -162,17 +953,57 @@ pub struct LogConfig {
 }
 
 #[derive(Debug, Args)]
+pub struct StateConfig {
+    #[arg(
+        long,
+        value_name = "DIR",
+        env = "RAWBIT_STATE_DIR",
+        help = "directory for persistent state (catalog, counters, journals), defaults to the platform state directory"
+    )]
+    pub state_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        env = "RAWBIT_CACHE_DIR",
+        help = "directory for caches, defaults to the platform cache directory"
+    )]
+    pub cache_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Args)]
 #[group(required = true, multiple = false)]
 pub struct RawSource {
     #[arg(
         short = 'i',
         long = "in-dir",
         value_name = "DIR",
-        value_parser = value_parser!(PathBuf).into_resettable(),
+        value_parser = PathBufValueParser::new().map(paths::drive_root),
         help = "directory containing raw files to convert"
     )]
     pub input_dir: Option<PathBuf>,
 
+    #[arg(
+        long,
+        value_name = "JOURNAL",
+        help = "resume an interrupted batch from the journal it left behind"
+    )]
+    pub resume: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "SPEC",
+        help = "run every import described in a TOML batch spec, see https://docs.rs/rawbit for the format"
+    )]
+    pub batch: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "LIST",
+        help = "convert the files listed in LIST, one per line, or read the list from stdin with -\ne.g. find /media/card -name '*.CR3' | rawbit --files-from - -o ./shoot"
+    )]
+    pub files_from: Option<PathBuf>,
+
     #[arg(
         help = "individual files to convert",
         trailing_var_arg = true,
@@ -181,10 +1012,43 @@ pub struct RawSource {
     pub files: Option<Vec<PathBuf>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IngestItem {
     pub input_path: PathBuf,
     pub output_prefix: PathBuf,
+    /// copy the file through as-is rather than converting it, see `--copy`
+    #[serde(default)]
+    pub copy: bool,
+    /// numbered once the whole batch is known, see [`crate::order::number_by_capture_time`]
+    #[serde(default)]
+    pub chrono_index: Option<ChronoIndex>,
+    /// numbered once the whole batch is known, see [`crate::order::number_in_sequence`]
+    #[serde(default)]
+    pub seq: Option<usize>,
+    /// the file inside `input_path`, when that's an archive
+    #[serde(default)]
+    pub member: Option<Member>,
+    /// what the file's named by when its own name can't be used, see [`crate::stem::name_unnamed`]
+    #[serde(default)]
+    pub fallback_stem: Option<String>,
+}
+
+impl IngestItem {
+    /// Where the file came from, through its archive if it's in one, for naming it and for
+    /// messages about it.
+    #[must_use]
+    pub fn source_path(&self) -> PathBuf {
+        source_path(&self.input_path, self.member.as_ref())
+    }
+}
+
+/// `input_path`, or `member` inside it, as though the archive were a directory.
+#[must_use]
+pub fn source_path(input_path: &Path, member: Option<&Member>) -> PathBuf {
+    member.map_or_else(
+        || input_path.to_path_buf(),
+        |member| input_path.join(&member.path),
+    )
 }
 
 impl<I: AsRef<Path>, O: AsRef<Path>> From<(I, O)> for IngestItem {
@@ -192,38 +1056,361 @@ impl<I: AsRef<Path>, O: AsRef<Path>> From<(I, O)> for IngestItem {
         Self {
             input_path: value.0.as_ref().to_path_buf(),
             output_prefix: value.1.as_ref().to_path_buf(),
+            copy: false,
+            chrono_index: None,
+            seq: None,
+            member: None,
+            fallback_stem: None,
+        }
+    }
+}
+
+/// The `--include` and `--exclude` globs, matched against paths relative to `--in-dir`, or against
+/// individual files as they were given, whether scanning `--in-dir` follows symlinks, and how deep
+/// it goes.
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    skip_symlinks: bool,
+    max_depth: Option<usize>,
+    /// resolved directories scans never go into, like an output directory inside the source
+    skipped_dirs: Vec<PathBuf>,
+}
+
+impl Filters {
+    pub fn new(include: &[String], exclude: &[String]) -> RawbitResult<Self> {
+        Ok(Self {
+            include: glob_set(include)?,
+            exclude: glob_set(exclude)?,
+            skip_symlinks: false,
+            max_depth: None,
+            skipped_dirs: vec![],
+        })
+    }
+
+    /// Leaves symlinked files and directories out of directory scans, see `--no-follow-symlinks`.
+    #[must_use]
+    pub const fn skipping_symlinks(mut self, skip: bool) -> Self {
+        self.skip_symlinks = skip;
+        self
+    }
+
+    /// Keeps directory scans from going more than `max_depth` directories down, see `--max-depth`.
+    #[must_use]
+    pub const fn limiting_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Keeps directory scans and `--watch` out of `dir`, which doesn't have to exist yet.
+    #[must_use]
+    pub fn skipping_dir(mut self, dir: &Path) -> Self {
+        self.skipped_dirs.push(resolve(dir));
+        self
+    }
+
+    /// Whether `path`, resolved, is in one of the directories scans stay out of.
+    fn skips_dir(&self, resolved: &Path) -> bool {
+        let skipped = self
+            .skipped_dirs
+            .iter()
+            .any(|dir| resolved.starts_with(dir));
+
+        if skipped {
+            debug!("not scanning \"{}\"", resolved.display());
+        }
+
+        skipped
+    }
+
+    /// Whether a directory scan goes into directories `depth` levels below where it started.
+    fn descends(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+
+    /// Whether a directory scan should pass over `path` for being a symlink.
+    fn skips_link(&self, path: &Path) -> bool {
+        let skipped = self.skip_symlinks
+            && path
+                .symlink_metadata()
+                .is_ok_and(|stat| stat.file_type().is_symlink());
+
+        if skipped {
+            debug!("skipping symlink \"{}\"", path.display());
+        }
+
+        skipped
+    }
+
+    #[must_use]
+    pub fn allows(&self, path: &Path) -> bool {
+        let included = self
+            .include
+            .as_ref()
+            .is_none_or(|globs| globs.is_match(path));
+        let excluded = self
+            .exclude
+            .as_ref()
+            .is_some_and(|globs| globs.is_match(path));
+
+        if included && !excluded {
+            true
+        } else {
+            debug!("filtered out \"{}\"", path.display());
+            false
         }
     }
 }
 
+/// `path` made absolute with its symlinks resolved, as far as it exists, so paths that don't exist
+/// yet can still be compared with ones that do.
+#[must_use]
+pub fn resolve(path: &Path) -> PathBuf {
+    let absolute = path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+    for existing in absolute.ancestors() {
+        if let Ok(resolved) = paths::canonicalize(existing) {
+            let rest = absolute
+                .strip_prefix(existing)
+                .unwrap_or_else(|_| Path::new(""));
+            return if rest.as_os_str().is_empty() {
+                resolved
+            } else {
+                resolved.join(rest)
+            };
+        }
+    }
+
+    absolute
+}
+
+fn glob_set(globs: &[String]) -> RawbitResult<Option<GlobSet>> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+
+    let mut set = GlobSetBuilder::new();
+    for glob in globs {
+        set.add(
+            Glob::new(glob)
+                .map_err(|err| AppError::Config(format!("invalid glob \"{glob}\": {err}")))?,
+        );
+    }
+
+    set.build()
+        .map(Some)
+        .map_err(|err| AppError::Config(format!("invalid globs: {err}")))
+}
+
+fn parse_file_list(list: &[u8], null: bool) -> Vec<PathBuf> {
+    let separator = if null { b'\0' } else { b'\n' };
+
+    list.split(|&byte| byte == separator)
+        .map(|entry| {
+            if null {
+                entry
+            } else {
+                entry.strip_suffix(b"\r").unwrap_or(entry)
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt as _};
+
+    OsStr::from_bytes(bytes).into()
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    String::from_utf8_lossy(bytes).into_owned().into()
+}
+
+/// Points each item at `subdir` next to its source, for `--out-dir-relative`.
+pub fn anchor_to_sources(items: &mut [IngestItem], subdir: &Path) {
+    for item in items {
+        let parent = item.input_path.parent().unwrap_or_else(|| Path::new(""));
+        item.output_prefix = parent.join(subdir);
+    }
+}
+
+/// The `--copy` extensions along with every raw one, so `--no-convert` copies the raws through
+/// under their new names rather than converting them.
+#[must_use]
+pub fn with_raws(mut copy: Vec<String>) -> Vec<String> {
+    copy.extend(
+        supported_extensions()
+            .iter()
+            .chain(&["dng"])
+            .map(ToString::to_string),
+    );
+    copy
+}
+
+/// Whether `path` is a raw, one rawler can decode or a DNG.
+#[must_use]
+pub fn is_raw(path: &Path) -> bool {
+    is_copied(path, &with_raws(vec![]))
+}
+
+/// Whether `path` has one of the `--copy` extensions, ignoring case and any leading dot.
+#[must_use]
+pub fn is_copied(path: &Path, copy: &[String]) -> bool {
+    stem::extension(path).is_some_and(|ext| {
+        let ext = ext.to_string_lossy();
+        copy.iter()
+            .any(|copied| copied.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+    })
+}
+
 impl RawSource {
     fn is_supported_filetype(path: &Path) -> bool {
-        let ext = path
-            .extension()
+        let ext = stem::extension(path)
             .map(|s| s.to_string_lossy().to_string())
             .unwrap_or_default();
 
         supported_extensions().contains(&ext.as_ref()) || ext.to_lowercase() == "dng"
     }
 
-    fn ingest_files(files: Vec<PathBuf>) -> Vec<IngestItem> {
-        files
-            .into_par_iter()
-            .filter_map(|ref item| {
-                if Self::is_supported_filetype(item) {
-                    debug!("found supported file: \"{}\"", item.display());
+    /// Queues the file at `path`, or the files inside it if it's an archive, leaving out any
+    /// `filters` reject. `relative` is the path the filters are matched against.
+    fn ingest_file(
+        path: &Path,
+        relative: &Path,
+        prefix: &Path,
+        recurse: bool,
+        copy: &[String],
+        filters: &Filters,
+    ) -> Vec<IngestItem> {
+        if archive::is_archive(path) && !is_copied(path, copy) {
+            return Self::ingest_archive(path, relative, prefix, recurse, copy, filters);
+        }
 
-                    Some((item, "").into())
-                } else {
-                    warn!("ignoring \"{}\": unsupported filetype", item.display());
+        if !filters.allows(relative) {
+            return vec![];
+        }
 
-                    None
+        Self::ingest_item(path, prefix, copy).into_iter().collect()
+    }
+
+    /// Queues the raws inside an archive, and any with one of the `copy` extensions, mirroring
+    /// the archive's directories under its name when recursing.
+    fn ingest_archive(
+        path: &Path,
+        relative: &Path,
+        prefix: &Path,
+        recurse: bool,
+        copy: &[String],
+        filters: &Filters,
+    ) -> Vec<IngestItem> {
+        let members = match archive::members(path) {
+            Ok(members) => members,
+            Err(err) => {
+                warn!(
+                    "ignoring \"{}\": couldn't read archive: {err}",
+                    path.display()
+                );
+                return vec![];
+            }
+        };
+
+        debug!(
+            "found archive: \"{}\", {} file(s)",
+            path.display(),
+            members.len()
+        );
+
+        members
+            .into_iter()
+            .filter(|member| filters.allows(&relative.join(&member.path)))
+            .filter_map(|member| {
+                let source = source_path(path, Some(&member));
+                let copy = is_copied(&source, copy);
+
+                // cards carry plenty besides raws, so they're passed over quietly
+                if !copy && !Self::is_supported_filetype(&source) {
+                    debug!("ignoring \"{}\": unsupported filetype", source.display());
+                    return None;
                 }
+
+                let output_prefix = if recurse {
+                    prefix
+                        .join(path.file_stem().unwrap_or_default())
+                        .join(member.dir())
+                } else {
+                    prefix.to_path_buf()
+                };
+
+                Some(IngestItem {
+                    copy,
+                    member: Some(member),
+                    ..(path, output_prefix).into()
+                })
+            })
+            .collect()
+    }
+
+    /// Queues `path` for conversion or copying, or `None` if rawbit doesn't handle it.
+    fn ingest_item(path: &Path, prefix: &Path, copy: &[String]) -> Option<IngestItem> {
+        let copy = is_copied(path, copy);
+
+        if copy || Self::is_supported_filetype(path) {
+            debug!("found supported file: \"{}\"", path.display());
+
+            Some(IngestItem {
+                copy,
+                ..(path, prefix).into()
+            })
+        } else if sidecar::is_sidecar(path) {
+            // carried along with its raw by --sidecars, if at all
+            debug!("ignoring \"{}\": sidecar", path.display());
+
+            None
+        } else if sidecar::is_paired_jpeg(path) {
+            // carried along with its raw by --jpeg-policy, if at all
+            debug!(
+                "ignoring \"{}\": the JPEG of a RAW+JPEG pair",
+                path.display()
+            );
+
+            None
+        } else {
+            warn!("ignoring \"{}\": unsupported filetype", path.display());
+
+            None
+        }
+    }
+
+    fn ingest_files(
+        files: Vec<PathBuf>,
+        recurse: bool,
+        copy: &[String],
+        filters: &Filters,
+    ) -> Vec<IngestItem> {
+        files
+            .into_par_iter()
+            .flat_map(|ref item| {
+                Self::ingest_file(item, item, Path::new(""), recurse, copy, filters)
             })
             .collect::<Vec<_>>()
     }
 
-    fn ingest_dir(input_dir: &Path, prefix: &Path, recurse: bool) -> RawbitResult<Vec<IngestItem>> {
+    /// `ancestors` are the directories being scanned above this one, as canonical paths, and
+    /// `ignores` the ignore files found in them.
+    fn ingest_dir(
+        input_dir: &Path,
+        prefix: &Path,
+        recurse: bool,
+        copy: &[String],
+        filters: &Filters,
+        ancestors: &[PathBuf],
+        ignores: &Ignores,
+    ) -> RawbitResult<Vec<IngestItem>> {
         if !input_dir.is_dir() {
             return Err(AppError::DirNotFound(
                 "source directory doesn't exist".into(),
@@ -231,6 +1418,29 @@ impl RawSource {
             ));
         }
 
+        // a symlink back up the tree would otherwise be scanned forever
+        let canonical = map_err!(
+            paths::canonicalize(input_dir),
+            AppError::Io,
+            format!("couldn't resolve directory: {}", input_dir.display()),
+        )?;
+
+        if filters.skips_dir(&canonical) {
+            return Ok(vec![]);
+        }
+
+        if ancestors.contains(&canonical) {
+            warn!(
+                "skipping \"{}\": it links back to a directory that's already being scanned",
+                input_dir.display()
+            );
+
+            return Ok(vec![]);
+        }
+
+        let ancestors = [ancestors, slice::from_ref(&canonical)].concat();
+        let ignores = ignores.descend(input_dir, prefix);
+
         let dir = map_err!(
             read_dir(input_dir),
             AppError::Io,
@@ -240,26 +1450,47 @@ impl RawSource {
         let files = dir
             .par_bridge()
             .filter_map(|item| match item {
-                Ok(ref item) if item.path().is_dir() && recurse => {
-                    let intermediate_dir = prefix.join(item.path().file_name().unwrap());
-
-                    Some(Self::ingest_dir(&item.path(), &intermediate_dir, true))
+                Ok(ref item) if filters.skips_link(&item.path()) => None,
+
+                Ok(ref item)
+                    if item.file_name() == IGNORE_FILE
+                        || ignores
+                            .ignores(&prefix.join(item.file_name()), item.path().is_dir()) =>
+                {
+                    debug!(
+                        "ignoring \"{}\": listed in {IGNORE_FILE}",
+                        item.path().display()
+                    );
+                    None
                 }
 
-                Ok(ref item) if item.path().is_file() => {
-                    let path = item.path();
-
-                    if Self::is_supported_filetype(&path) {
-                        debug!("found supported file: \"{}\"", path.display());
-
-                        Some(Ok(vec![(path, prefix.to_path_buf()).into()]))
-                    } else {
-                        warn!("ignoring \"{}\": unsupported filetype", path.display());
+                Ok(ref item)
+                    if item.path().is_dir()
+                        && recurse
+                        && filters.descends(prefix.components().count() + 1) =>
+                {
+                    let intermediate_dir = prefix.join(item.path().file_name().unwrap());
 
-                        None
-                    }
+                    Some(Self::ingest_dir(
+                        &item.path(),
+                        &intermediate_dir,
+                        true,
+                        copy,
+                        filters,
+                        &ancestors,
+                        &ignores,
+                    ))
                 }
 
+                Ok(ref item) if item.path().is_file() => Some(Ok(Self::ingest_file(
+                    &item.path(),
+                    &prefix.join(item.file_name()),
+                    prefix,
+                    recurse,
+                    copy,
+                    filters,
+                ))),
+
                 _ => None,
             })
             .collect::<RawbitResult<Vec<_>>>()?
@@ -270,16 +1501,91 @@ impl RawSource {
         Ok(files)
     }
 
-    pub fn ingest(self, recurse: bool) -> RawbitResult<Vec<IngestItem>> {
+    /// Replaces `--files-from` with the files it lists, so they're checked and filtered like the
+    /// ones given as arguments. Must be called before [`Self::ingest`].
+    pub fn read_files_from(&mut self, null: bool) -> RawbitResult<()> {
+        let Some(list) = self.files_from.take() else {
+            // `requires` isn't enforced against members of the source group, so it's checked here
+            return if null {
+                Err(AppError::Config(
+                    "-0/--null only applies to --files-from".into(),
+                ))
+            } else {
+                Ok(())
+            };
+        };
+
+        let mut buf = vec![];
+        let read = if list.as_os_str() == "-" {
+            io::stdin().lock().read_to_end(&mut buf)
+        } else {
+            File::open(&list).and_then(|mut file| file.read_to_end(&mut buf))
+        };
+
+        map_err!(
+            read,
+            AppError::Io,
+            format!("couldn't read file list: {}", list.display())
+        )?;
+
+        self.files = Some(parse_file_list(&buf, null));
+        Ok(())
+    }
+
+    /// The items for a file that appeared under `root` while watching it, none if it wouldn't
+    /// have been ingested from there.
+    #[must_use]
+    pub fn watched_items(
+        root: &Path,
+        path: &Path,
+        recurse: bool,
+        copy: &[String],
+        filters: &Filters,
+    ) -> Vec<IngestItem> {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return vec![];
+        };
+
+        let prefix = relative.parent().unwrap_or_else(|| Path::new(""));
+
+        let depth = prefix.components().count();
+        if !recurse && depth > 0
+            || !filters.descends(depth)
+            || filters.skips_link(path)
+            || filters.skips_dir(&resolve(path))
+        {
+            return vec![];
+        }
+
+        if path.file_name() == Some(IGNORE_FILE.as_ref())
+            || Ignores::for_dir(root, prefix).is_none_or(|ignores| ignores.ignores(relative, false))
+        {
+            return vec![];
+        }
+
+        Self::ingest_file(path, relative, prefix, recurse, copy, filters)
+    }
+
+    /// Lists the files to import, including any with one of the `copy` extensions and any inside
+    /// zip and tar archives, leaving out any `filters` reject.
+    pub fn ingest(
+        self,
+        recurse: bool,
+        copy: &[String],
+        filters: &Filters,
+    ) -> RawbitResult<Vec<IngestItem>> {
         assert!(
-            self.files.is_some() || self.input_dir.is_some(),
-            "expected input dir or a list of individual files, got neither"
+            self.files.is_some() || self.input_dir.is_some() || self.resume.is_some(),
+            "expected input dir, a journal, or a list of individual files, got none"
         );
 
-        if let Some(ref dir) = self.input_dir {
-            Self::ingest_dir(dir, &PathBuf::new(), recurse)
+        if let Some(ref journal) = self.resume {
+            Ok(Journal::read(journal)?.pending)
+        } else if let Some(ref dir) = self.input_dir {
+            let ignores = Ignores::default();
+            Self::ingest_dir(dir, &PathBuf::new(), recurse, copy, filters, &[], &ignores)
         } else if let Some(files) = self.files {
-            Ok(Self::ingest_files(files))
+            Ok(Self::ingest_files(files, recurse, copy, filters))
         } else {
             unreachable!()
         }
@@ -295,7 +1601,9 @@ mod path_tests {
     };
     use tempfile::{TempDir, tempdir, tempdir_in};
 
-    use super::{IngestItem, RawSource};
+    use super::{
+        Filters, IngestItem, RawSource, anchor_to_sources, parse_file_list, resolve, with_raws,
+    };
 
     fn setup_nested_dir(parent: Option<&Path>) -> Result<([TempDir; 2], Vec<PathBuf>)> {
         let (input_dir, mut files) = setup_flat_dir(parent)?;
@@ -306,6 +1614,7 @@ mod path_tests {
         Ok(([input_dir, nested_dir], files))
     }
 
+    #[allow(clippy::option_if_let_else)]
     fn setup_flat_dir(parent: Option<&Path>) -> Result<(TempDir, Vec<PathBuf>)> {
         let input_dir = match parent {
             Some(dir) => tempdir_in(dir),
@@ -333,15 +1642,19 @@ mod path_tests {
 
         let args = RawSource {
             input_dir: Some(input_path.to_path_buf()),
+            resume: None,
+            batch: None,
+            files_from: None,
             files: None,
         };
 
-        let ingest = args.ingest(false).unwrap();
+        let ingest = args.ingest(false, &[], &Filters::default()).unwrap();
         assert_eq!(ingest.len(), 10);
 
         for IngestItem {
             input_path,
             output_prefix,
+            ..
         } in &ingest
         {
             assert!(temp_paths.contains(input_path));
@@ -358,15 +1671,19 @@ mod path_tests {
 
         let args = RawSource {
             input_dir: Some(input_path.to_path_buf()),
+            resume: None,
+            batch: None,
+            files_from: None,
             files: None,
         };
 
-        let ingest = args.ingest(true).unwrap();
+        let ingest = args.ingest(true, &[], &Filters::default()).unwrap();
         assert_eq!(ingest.len(), 20);
 
         for IngestItem {
             input_path,
             output_prefix,
+            ..
         } in &ingest
         {
             assert!(temp_paths.contains(input_path));
@@ -385,15 +1702,19 @@ mod path_tests {
 
         let args = RawSource {
             input_dir: Some(input_path.to_path_buf()),
+            resume: None,
+            batch: None,
+            files_from: None,
             files: None,
         };
 
-        let ingest = args.ingest(false).unwrap();
+        let ingest = args.ingest(false, &[], &Filters::default()).unwrap();
         assert_eq!(ingest.len(), 10);
 
         for IngestItem {
             input_path,
             output_prefix,
+            ..
         } in &ingest
         {
             assert!(temp_paths.contains(input_path));
@@ -404,4 +1725,191 @@ mod path_tests {
 
         Ok(())
     }
+
+    #[test]
+    fn ingests_copied_extensions_alongside_raws() -> Result<()> {
+        let (input_dir, _) = setup_flat_dir(None)?;
+        File::create(input_dir.path().join("IMG_0001.HEIF"))?;
+        File::create(input_dir.path().join("C0001.mp4"))?;
+        File::create(input_dir.path().join("notes.txt"))?;
+
+        let args = RawSource {
+            input_dir: Some(input_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        let ingest = args
+            .ingest(false, &["heif".into(), ".MP4".into()], &Filters::default())
+            .unwrap();
+        assert_eq!(ingest.len(), 12);
+
+        let copied = ingest.iter().filter(|item| item.copy).count();
+        assert_eq!(copied, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn filters_before_ingesting() -> Result<()> {
+        let ([input_dir, nested_dir], _) = setup_nested_dir(None)?;
+        File::create(input_dir.path().join("IMG_0001_dup.ARW"))?;
+
+        let nested = nested_dir.path().file_name().unwrap().to_string_lossy();
+        let filters = Filters::new(
+            &["*.ARW".into()],
+            &["*_dup.ARW".into(), format!("**/{nested}/**")],
+        )
+        .unwrap();
+
+        let args = RawSource {
+            input_dir: Some(input_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        let ingest = args.ingest(true, &[], &filters).unwrap();
+        assert_eq!(ingest.len(), 10);
+        assert!(
+            ingest
+                .iter()
+                .all(|item| item.output_prefix.as_os_str().is_empty())
+        );
+
+        assert!(Filters::new(&["a[".into()], &[]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn stops_recursing_at_the_max_depth() -> Result<()> {
+        let ([input_dir, nested_dir], _) = setup_nested_dir(None)?;
+        let _deepest = setup_flat_dir(Some(nested_dir.path()))?;
+
+        let ingest = |max_depth| {
+            let args = RawSource {
+                input_dir: Some(input_dir.path().to_path_buf()),
+                ..Default::default()
+            };
+
+            let filters = Filters::default().limiting_depth(max_depth);
+            args.ingest(true, &[], &filters).unwrap().len()
+        };
+
+        assert_eq!(ingest(None), 30);
+        assert_eq!(ingest(Some(0)), 10);
+        assert_eq!(ingest(Some(1)), 20);
+        assert_eq!(ingest(Some(2)), 30);
+
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_skipped_directories_out_of_the_scan() -> Result<()> {
+        let ([input_dir, nested_dir], _) = setup_nested_dir(None)?;
+
+        let args = RawSource {
+            input_dir: Some(input_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        // written through a `..`, still the same directory
+        let output_dir = nested_dir
+            .path()
+            .join("../")
+            .join(nested_dir.path().file_name().unwrap());
+        let filters = Filters::default().skipping_dir(&output_dir);
+        assert_eq!(args.ingest(true, &[], &filters).unwrap().len(), 10);
+
+        // a directory that doesn't exist yet resolves alongside ones that do
+        assert_eq!(
+            resolve(&input_dir.path().join("out/2024")),
+            input_dir.path().canonicalize()?.join("out/2024")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn copies_raws_through_without_converting_them() -> Result<()> {
+        let (input_dir, _) = setup_flat_dir(None)?;
+        File::create(input_dir.path().join("IMG_0001.HEIF"))?;
+
+        let args = RawSource {
+            input_dir: Some(input_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        let ingest = args
+            .ingest(false, &with_raws(vec!["heif".into()]), &Filters::default())
+            .unwrap();
+        assert_eq!(ingest.len(), 11);
+        assert!(ingest.iter().all(|item| item.copy));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinks_without_looping() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let (input_dir, _) = setup_flat_dir(None)?;
+        let (backup_dir, _) = setup_flat_dir(None)?;
+        symlink(backup_dir.path(), input_dir.path().join("backup"))?;
+        symlink(input_dir.path(), input_dir.path().join("loop"))?;
+
+        let ingest = |filters: &Filters| {
+            let args = RawSource {
+                input_dir: Some(input_dir.path().to_path_buf()),
+                ..Default::default()
+            };
+
+            args.ingest(true, &[], filters).unwrap()
+        };
+
+        let followed = ingest(&Filters::default());
+        assert_eq!(followed.len(), 20);
+        assert!(
+            followed
+                .iter()
+                .all(|item| !item.output_prefix.starts_with("loop"))
+        );
+
+        assert_eq!(
+            ingest(&Filters::default().skipping_symlinks(true)).len(),
+            10
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parses_newline_and_null_separated_file_lists() {
+        assert_eq!(
+            parse_file_list(b"a/IMG_0001.CR3\r\n\nb c/IMG_0002.CR3\n", false),
+            [
+                PathBuf::from("a/IMG_0001.CR3"),
+                PathBuf::from("b c/IMG_0002.CR3")
+            ]
+        );
+        assert_eq!(
+            parse_file_list(b"line\nbreak.CR3\0IMG_0002.CR3\0", true),
+            [
+                PathBuf::from("line\nbreak.CR3"),
+                PathBuf::from("IMG_0002.CR3")
+            ]
+        );
+    }
+
+    #[test]
+    fn anchors_outputs_next_to_their_sources() {
+        let mut items = vec![
+            IngestItem::from(("2024/shoot/IMG_0001.CR3", "shoot")),
+            IngestItem::from(("IMG_0002.CR3", "")),
+        ];
+
+        anchor_to_sources(&mut items, Path::new("converted"));
+
+        assert_eq!(items[0].output_prefix, Path::new("2024/shoot/converted"));
+        assert_eq!(items[1].output_prefix, Path::new("converted"));
+    }
 }
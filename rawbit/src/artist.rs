@@ -0,0 +1,123 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// What goes between names when a file has several artists, as EXIF asks for.
+const SEPARATOR: &str = "; ";
+
+/// A clean-up applied to artist names before they're written, from `artist-normalize` in the
+/// config file.
+///
+/// ```toml
+/// artist = ["jane doe ", "John  Smith", "Jane Doe"]
+/// artist-normalize = ["trim", "title-case", "dedupe"]
+/// ```
+///
+/// Rules always apply in the order above, whatever order they're listed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Normalize {
+    /// drop surrounding whitespace and squeeze runs of it to one space
+    Trim,
+    /// capitalize each word and lowercase the rest, `jane DOE` becomes `Jane Doe`
+    TitleCase,
+    /// drop names that came up earlier, ignoring case
+    Dedupe,
+}
+
+/// The value of the artist tag for `artists`, cleaned up by `rules`, or nothing if no name is left.
+#[must_use]
+pub fn tag(artists: &[String], rules: &[Normalize]) -> Option<String> {
+    let names = normalize(artists, rules);
+    (!names.is_empty()).then(|| names.join(SEPARATOR))
+}
+
+/// `artists` cleaned up by `rules`, without any that end up empty.
+#[must_use]
+pub fn normalize(artists: &[String], rules: &[Normalize]) -> Vec<String> {
+    let mut names: Vec<String> = artists
+        .iter()
+        .map(|name| {
+            let name = if rules.contains(&Normalize::Trim) {
+                name.split_whitespace().collect::<Vec<_>>().join(" ")
+            } else {
+                name.clone()
+            };
+
+            if rules.contains(&Normalize::TitleCase) {
+                title_case(&name)
+            } else {
+                name
+            }
+        })
+        .filter(|name| !name.trim().is_empty())
+        .collect();
+
+    if rules.contains(&Normalize::Dedupe) {
+        let mut seen = vec![];
+        names.retain(|name| {
+            let key = name.to_lowercase();
+            let first = !seen.contains(&key);
+            seen.push(key);
+            first
+        });
+    }
+
+    names
+}
+
+/// Capitalizes the first letter after whitespace, `-` or `'`, so `o'brien-smith` becomes
+/// `O'Brien-Smith`.
+fn title_case(name: &str) -> String {
+    let mut titled = String::with_capacity(name.len());
+    let mut word_start = true;
+
+    for c in name.chars() {
+        if word_start {
+            titled.extend(c.to_uppercase());
+        } else {
+            titled.extend(c.to_lowercase());
+        }
+
+        word_start = c.is_whitespace() || matches!(c, '-' | '\'');
+    }
+
+    titled
+}
+
+/// Reads `artist = "A"` and `artist = ["A", "B"]` alike.
+pub fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(name) => vec![name],
+        OneOrMany::Many(names) => names,
+    })
+}
+
+#[cfg(test)]
+mod artist_tests {
+    use super::{Normalize, tag};
+
+    #[test]
+    fn normalizes_and_joins_several_artists() {
+        let artists = ["  jane   DOE ", "o'brien-smith", "", "Jane Doe"].map(String::from);
+
+        assert_eq!(
+            tag(&artists, &[]).as_deref(),
+            Some("  jane   DOE ; o'brien-smith; Jane Doe")
+        );
+        assert_eq!(
+            tag(
+                &artists,
+                &[Normalize::Dedupe, Normalize::TitleCase, Normalize::Trim]
+            )
+            .as_deref(),
+            Some("Jane Doe; O'Brien-Smith")
+        );
+        assert_eq!(tag(&[" ".into()], &[Normalize::Trim]), None);
+    }
+}
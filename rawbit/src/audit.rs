@@ -0,0 +1,284 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use rayon::iter::{IntoParallelIterator as _, ParallelIterator as _};
+use serde::Serialize;
+
+use crate::{
+    args, catalog,
+    common::{AppError, RawbitResult, map_err},
+    index::{INDEX_FILE, Index},
+    paths, provenance,
+};
+
+/// Something about an archive that doesn't match what was recorded when it was written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Discrepancy {
+    /// `manifest` lists `path`, but it isn't there
+    Missing { path: PathBuf, manifest: PathBuf },
+    /// `path` isn't the file `manifest` recorded
+    ChecksumMismatch {
+        path: PathBuf,
+        manifest: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    /// a DNG the catalog has no record of converting
+    Uncataloged { path: PathBuf },
+}
+
+impl Discrepancy {
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Missing { path, .. }
+            | Self::ChecksumMismatch { path, .. }
+            | Self::Uncataloged { path } => path,
+        }
+    }
+}
+
+/// What `rawbit audit` found in an output directory.
+#[derive(Debug, Default, Serialize)]
+pub struct Audit {
+    pub root: PathBuf,
+    /// the catalog checked against, if there was one
+    pub catalog: Option<PathBuf>,
+    /// every `index.json` under the root
+    pub indexes: Vec<PathBuf>,
+    /// how many DNGs are under the root
+    pub files: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// Checks the DNGs under `root` against the catalog at `catalog` and every `index.json` under
+/// `root`. Without a catalog, nothing is reported as uncataloged.
+pub fn audit(root: &Path, catalog: Option<&Path>) -> RawbitResult<Audit> {
+    let root = map_err!(
+        paths::canonicalize(root),
+        AppError::Io,
+        format!("couldn't open archive: {}", root.display())
+    )?;
+
+    let mut dngs = Vec::new();
+    let mut indexes = Vec::new();
+    map_err!(
+        find_files(&root, &mut dngs, &mut indexes),
+        AppError::Io,
+        format!("couldn't scan archive: {}", root.display())
+    )?;
+
+    // the latest record of each output is the one that describes it
+    let recorded: BTreeMap<PathBuf, String> = match catalog {
+        Some(path) => catalog::entries(path)?
+            .into_iter()
+            .map(|entry| (args::resolve(&entry.output_path), entry.output_sha256))
+            .filter(|(output, _)| output.starts_with(&root))
+            .collect(),
+        None => BTreeMap::new(),
+    };
+
+    let mut expected = Vec::new();
+
+    for index in &indexes {
+        let dir = index.parent().unwrap_or(&root);
+
+        expected.extend(
+            read_index(index)?
+                .files
+                .into_iter()
+                .map(|entry| (dir.join(entry.path), entry.sha256, index.clone())),
+        );
+    }
+
+    if let Some(catalog) = catalog {
+        expected.extend(
+            recorded
+                .iter()
+                .map(|(output, sha256)| (output.clone(), sha256.clone(), catalog.to_path_buf())),
+        );
+    }
+
+    let checksums = checksums(
+        dngs.iter()
+            .cloned()
+            .chain(expected.iter().map(|(path, ..)| path.clone()))
+            .collect(),
+    );
+
+    let mut discrepancies = expected
+        .into_iter()
+        .filter_map(|(path, sha256, manifest)| match checksums.get(&path) {
+            None => Some(Discrepancy::Missing { path, manifest }),
+            Some(actual) if *actual != sha256 => Some(Discrepancy::ChecksumMismatch {
+                path,
+                manifest,
+                expected: sha256,
+                actual: actual.clone(),
+            }),
+            Some(_) => None,
+        })
+        .collect::<Vec<_>>();
+
+    if catalog.is_some() {
+        discrepancies.extend(
+            dngs.iter()
+                .filter(|dng| !recorded.contains_key(*dng))
+                .map(|dng| Discrepancy::Uncataloged { path: dng.clone() }),
+        );
+    }
+
+    discrepancies.sort_by(|a, b| a.path().cmp(b.path()));
+
+    Ok(Audit {
+        root,
+        catalog: catalog.map(Path::to_path_buf),
+        indexes,
+        files: dngs.len(),
+        discrepancies,
+    })
+}
+
+/// The checksum of each of `paths` that's there to read.
+fn checksums(paths: BTreeSet<PathBuf>) -> BTreeMap<PathBuf, String> {
+    paths
+        .into_par_iter()
+        .filter_map(|path| {
+            let sha256 = provenance::sha256_file(&path).ok()?;
+            Some((path, sha256))
+        })
+        .collect()
+}
+
+fn read_index(path: &Path) -> RawbitResult<Index> {
+    let file = map_err!(
+        File::open(path),
+        AppError::Io,
+        format!("couldn't open index: {}", path.display())
+    )?;
+
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+        AppError::Other(
+            format!("couldn't read index: {}", path.display()),
+            Box::new(e),
+        )
+    })
+}
+
+fn find_files(dir: &Path, dngs: &mut Vec<PathBuf>, indexes: &mut Vec<PathBuf>) -> io::Result<()> {
+    for item in fs::read_dir(dir)? {
+        let path = item?.path();
+
+        if path.is_dir() {
+            find_files(&path, dngs, indexes)?;
+        } else if path.file_name().is_some_and(|name| name == INDEX_FILE) {
+            indexes.push(path);
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("dng"))
+        {
+            dngs.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod audit_tests {
+    use std::fs;
+
+    use super::{Discrepancy, audit};
+    use crate::{
+        catalog::{Catalog, Entry},
+        index::{self, Index},
+        provenance::sha256_hex,
+    };
+
+    #[test]
+    fn reports_missing_altered_and_uncataloged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let archive = root.join("archive");
+        fs::create_dir(&archive).unwrap();
+
+        let record = |name: &str, contents: &[u8]| Entry {
+            source_sha256: name.into(),
+            params_sha256: "params".into(),
+            output_path: archive.join(name),
+            output_sha256: sha256_hex(contents),
+        };
+
+        let catalog_path = root.join("catalog.jsonl");
+        let catalog = Catalog::open(&catalog_path).unwrap();
+        for name in ["kept.dng", "edited.dng", "deleted.dng"] {
+            fs::write(archive.join(name), b"dng").unwrap();
+            catalog.record(record(name, b"dng")).unwrap();
+        }
+        // another archive's output isn't this one's business
+        catalog
+            .record(Entry {
+                output_path: root.join("elsewhere.dng"),
+                ..record("elsewhere.dng", b"dng")
+            })
+            .unwrap();
+        drop(catalog);
+
+        let index = Index {
+            files: vec![index::Entry {
+                path: "kept.dng".into(),
+                captured: None,
+                make: String::new(),
+                model: String::new(),
+                sha256: sha256_hex(b"other"),
+                run_id: None,
+                size: 3,
+                modified: 0,
+            }],
+        };
+        fs::write(
+            archive.join(index::INDEX_FILE),
+            serde_json::to_vec(&index).unwrap(),
+        )
+        .unwrap();
+
+        fs::write(archive.join("edited.dng"), b"edited").unwrap();
+        fs::remove_file(archive.join("deleted.dng")).unwrap();
+        fs::write(archive.join("stray.dng"), b"dng").unwrap();
+
+        let found = audit(&archive, Some(&catalog_path)).unwrap();
+        assert_eq!(found.files, 3);
+
+        let kinds = found
+            .discrepancies
+            .iter()
+            .map(|discrepancy| {
+                let kind = match discrepancy {
+                    Discrepancy::Missing { .. } => "missing",
+                    Discrepancy::ChecksumMismatch { .. } => "mismatch",
+                    Discrepancy::Uncataloged { .. } => "uncataloged",
+                };
+                let name = discrepancy.path().file_name().unwrap().to_str().unwrap();
+                (name, kind)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            [
+                ("deleted.dng", "missing"),
+                ("edited.dng", "mismatch"),
+                ("kept.dng", "mismatch"),
+                ("stray.dng", "uncataloged"),
+            ]
+        );
+
+        let without_catalog = audit(&archive, None).unwrap();
+        assert_eq!(without_catalog.discrepancies.len(), 1);
+    }
+}
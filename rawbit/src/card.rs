@@ -0,0 +1,161 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use crate::common::{AppError, RawbitResult, map_err};
+
+/// Where `--audit-card` copies the card's structure files to, inside the output directory.
+pub const AUDIT_DIR: &str = "audit";
+
+const DCIM: &str = "DCIM";
+
+/// Extensions of the indexes and databases cameras keep alongside the images.
+const STRUCTURE_EXTENSIONS: &[&str] = &[
+    // Sony/Panasonic AVCHD indexes, playlists and clip info
+    "bdm", "mpl", "cpi", // Canon catalogs
+    "ctg", "cif", // Sony card index
+    "ind",
+];
+
+/// Structure files that are known by name rather than extension.
+const STRUCTURE_FILES: &[&str] = &[
+    // Sony XAVC clip database
+    "mediapro.xml",
+    "status.bin",
+    // Nikon file list
+    "nc_fllst.dat",
+    // DPOF print order
+    "autprint.mrk",
+];
+
+/// The root of the card holding `path`, the nearest directory containing a `DCIM` folder.
+///
+/// Falls back to `path`'s own directory for sources that don't look like a camera card.
+#[must_use]
+pub fn root(path: &Path) -> PathBuf {
+    let start = if path.is_dir() {
+        path
+    } else {
+        path.parent().unwrap_or(path)
+    };
+
+    start
+        .ancestors()
+        .find(|dir| dir.join(DCIM).is_dir())
+        .unwrap_or(start)
+        .to_path_buf()
+}
+
+fn is_structure_file(path: &Path) -> bool {
+    let Some(name) = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+    else {
+        return false;
+    };
+
+    STRUCTURE_FILES.contains(&name.as_str())
+        || path.extension().is_some_and(|ext| {
+            STRUCTURE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+        })
+}
+
+/// Every structure file on the card, relative to its root.
+pub fn structure_files(root: &Path) -> RawbitResult<Vec<PathBuf>> {
+    let mut found = vec![];
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = map_err!(
+            fs::read_dir(&dir),
+            AppError::Io,
+            format!("couldn't read card directory {}", dir.display())
+        )?;
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            // not following symlinks, a link back up the card would never finish
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                dirs.push(path);
+            } else if is_structure_file(&path)
+                && let Ok(relative) = path.strip_prefix(root)
+            {
+                found.push(relative.to_path_buf());
+            }
+        }
+    }
+
+    found.sort();
+    Ok(found)
+}
+
+/// Copies structure files from the card into `audit_dir`, keeping their paths and modification
+/// times.
+pub fn copy_structure_files(root: &Path, files: &[PathBuf], audit_dir: &Path) -> RawbitResult<()> {
+    for relative in files {
+        let src = root.join(relative);
+        let dest = audit_dir.join(relative);
+
+        if let Some(parent) = dest.parent() {
+            map_err!(
+                fs::create_dir_all(parent),
+                AppError::Io,
+                format!("couldn't create audit directory {}", parent.display())
+            )?;
+        }
+
+        map_err!(
+            fs::copy(&src, &dest),
+            AppError::Io,
+            format!("couldn't copy {} for audit", src.display())
+        )?;
+
+        let modified = fs::metadata(&src).and_then(|md| md.modified());
+        if let Ok(modified) = modified {
+            let _ = File::options()
+                .write(true)
+                .open(&dest)
+                .and_then(|file| file.set_modified(modified));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod card_tests {
+    use std::fs;
+
+    use super::{copy_structure_files, root, structure_files};
+
+    #[test]
+    fn finds_and_copies_structure_files_from_the_card_root() {
+        let card = tempfile::tempdir().unwrap();
+        let images = card.path().join("DCIM/100CANON");
+        let clips = card.path().join("PRIVATE/AVCHD/BDMV");
+        fs::create_dir_all(&images).unwrap();
+        fs::create_dir_all(&clips).unwrap();
+
+        fs::write(images.join("IMG_0001.CR3"), b"raw").unwrap();
+        fs::create_dir_all(card.path().join("DCIM/CANONMSC")).unwrap();
+        fs::write(card.path().join("DCIM/CANONMSC/M0100.CTG"), b"catalog").unwrap();
+        fs::write(clips.join("INDEX.BDM"), b"index").unwrap();
+
+        let card_root = root(&images.join("IMG_0001.CR3"));
+        assert_eq!(card_root, card.path());
+
+        let files = structure_files(&card_root).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|f| !f.ends_with("IMG_0001.CR3")));
+
+        let out = tempfile::tempdir().unwrap();
+        copy_structure_files(&card_root, &files, &out.path().join("audit")).unwrap();
+
+        assert_eq!(
+            fs::read(out.path().join("audit/PRIVATE/AVCHD/BDMV/INDEX.BDM")).unwrap(),
+            b"index"
+        );
+    }
+}
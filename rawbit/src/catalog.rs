@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{AppError, RawbitResult, map_err},
+    provenance,
+};
+
+/// One conversion recorded in the catalog.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub source_sha256: String,
+    /// see [`Provenance::params_sha256`](crate::provenance::Provenance::params_sha256)
+    pub params_sha256: String,
+    pub output_path: PathBuf,
+    pub output_sha256: String,
+}
+
+impl Entry {
+    /// The output is still where it was written, byte for byte.
+    fn is_intact(&self) -> bool {
+        provenance::sha256_file(&self.output_path).is_ok_and(|sha256| sha256 == self.output_sha256)
+    }
+}
+
+/// Conversions done by earlier runs, so `--cache` can skip ones that would come out the same.
+///
+/// Kept as JSON lines in the state directory, every process appends to the same file.
+#[derive(Debug)]
+pub struct Catalog {
+    file: Mutex<File>,
+    entries: Mutex<HashMap<(String, String), Vec<Entry>>>,
+}
+
+impl Catalog {
+    pub fn open(path: &Path) -> RawbitResult<Self> {
+        let contents = read(path)?;
+        let mut entries: HashMap<_, Vec<Entry>> = HashMap::new();
+
+        for entry in parse(&contents) {
+            let key = (entry.source_sha256.clone(), entry.params_sha256.clone());
+            entries.entry(key).or_default().push(entry);
+        }
+
+        let mut file = map_err!(
+            fs::OpenOptions::new().create(true).append(true).open(path),
+            AppError::Io,
+            format!("couldn't open catalog: {}", path.display())
+        )?;
+
+        // keep the next record off the end of a cut short line
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            map_err!(
+                file.write_all(b"\n"),
+                AppError::Io,
+                format!("couldn't write to catalog: {}", path.display())
+            )?;
+        }
+
+        Ok(Self {
+            file: Mutex::new(file),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// The recorded conversion of this source with these parameters into `output_path`, as long
+    /// as the output is still intact.
+    #[must_use]
+    pub fn find(&self, source_sha256: &str, params_sha256: &str, output_path: &Path) -> bool {
+        let candidate = self
+            .entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(&(source_sha256.into(), params_sha256.into()))
+            .and_then(|entries| {
+                entries
+                    .iter()
+                    .rev()
+                    .find(|entry| entry.output_path == output_path)
+                    .cloned()
+            });
+
+        candidate.is_some_and(|entry| entry.is_intact())
+    }
+
+    pub fn record(&self, entry: Entry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        // one write per line, so appends from other processes don't interleave within it
+        self.file
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .write_all(&line)?;
+
+        self.entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .entry((entry.source_sha256.clone(), entry.params_sha256.clone()))
+            .or_default()
+            .push(entry);
+
+        Ok(())
+    }
+}
+
+/// Every conversion recorded in the catalog at `path`, oldest first, without opening it for
+/// appending.
+pub fn entries(path: &Path) -> RawbitResult<Vec<Entry>> {
+    Ok(parse(&read(path)?).collect())
+}
+
+fn read(path: &Path) -> RawbitResult<String> {
+    if !path.exists() {
+        return Ok(String::new());
+    }
+
+    map_err!(
+        fs::read_to_string(path),
+        AppError::Io,
+        format!("couldn't read catalog: {}", path.display())
+    )
+}
+
+fn parse(contents: &str) -> impl Iterator<Item = Entry> {
+    // a line cut short by a crash mid-append is just a conversion that gets redone
+    contents
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+}
+
+#[cfg(test)]
+mod catalog_tests {
+    use std::fs;
+
+    use super::{Catalog, Entry};
+    use crate::provenance::sha256_hex;
+
+    #[test]
+    fn finds_only_intact_outputs_across_reopens() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("catalog.jsonl");
+        let output = dir.path().join("IMG_0001.dng");
+        fs::write(&output, b"dng").unwrap();
+
+        let catalog = Catalog::open(&path).unwrap();
+        assert!(!catalog.find("src", "params", &output));
+
+        catalog
+            .record(Entry {
+                source_sha256: "src".into(),
+                params_sha256: "params".into(),
+                output_path: output.clone(),
+                output_sha256: sha256_hex(b"dng"),
+            })
+            .unwrap();
+
+        assert!(catalog.find("src", "params", &output));
+        assert!(!catalog.find("src", "other params", &output));
+        assert!(!catalog.find("src", "params", &dir.path().join("elsewhere.dng")));
+
+        drop(catalog);
+        let mut lines = fs::read_to_string(&path).unwrap();
+        lines.push_str("{\"source_sha256\": \"trunc");
+        fs::write(&path, lines).unwrap();
+
+        let catalog = Catalog::open(&path).unwrap();
+        assert!(catalog.find("src", "params", &output));
+        assert!(fs::read_to_string(&path).unwrap().ends_with('\n'));
+
+        fs::write(&output, b"edited").unwrap();
+        assert!(!catalog.find("src", "params", &output));
+    }
+}
@@ -0,0 +1,739 @@
+use std::{
+    fmt::{self, Display},
+    fs,
+    io::{self, Read, Seek, Write},
+    iter,
+    path::Path,
+    str::FromStr,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeDelta, TimeZone as _};
+use clap::ValueEnum;
+use rawler::{decoders::RawMetadata, exif::Exif};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    parse,
+    tiff::{Entry, TAG_EXIF_IFD, Tiff},
+};
+
+const EXIF_DT_FMT: &str = "%Y:%m:%d %H:%M:%S";
+
+const EXIF_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const EXIF_CREATE_DATE: u16 = 0x9004;
+
+/// How far one camera body's clock was off, `SERIAL=+HH:MM:SS`, see `--time-shift-for`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeShift {
+    /// the body's serial number, as its EXIF records it
+    pub serial: String,
+    /// added to each of the body's timestamps
+    pub offset: TimeDelta,
+}
+
+impl FromStr for TimeShift {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (serial, offset) = s.split_once('=').ok_or_else(|| {
+            format!("expected SERIAL=OFFSET, e.g. 12345678=+00:00:43, got \"{s}\"")
+        })?;
+
+        let serial = serial.trim();
+        if serial.is_empty() {
+            return Err(format!("no serial number before the offset in \"{s}\""));
+        }
+
+        Ok(Self {
+            serial: serial.into(),
+            offset: parse_offset(offset.trim())?,
+        })
+    }
+}
+
+impl TryFrom<String> for TimeShift {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TimeShift> for String {
+    fn from(shift: TimeShift) -> Self {
+        shift.to_string()
+    }
+}
+
+impl Display for TimeShift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.serial, Offset(self.offset))
+    }
+}
+
+/// How far every camera's clock was off, `[+-]HH:MM:SS` or like `-1h2m30s`, see `--time-shift`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Offset(pub TimeDelta);
+
+impl FromStr for Offset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_offset(s.trim()).map(Self)
+    }
+}
+
+impl TryFrom<String> for Offset {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Offset> for String {
+    fn from(offset: Offset) -> Self {
+        offset.to_string()
+    }
+}
+
+impl Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.num_seconds();
+        let sign = if secs < 0 { '-' } else { '+' };
+        let secs = secs.unsigned_abs();
+
+        write!(
+            f,
+            "{sign}{:02}:{:02}:{:02}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )
+    }
+}
+
+/// Parses `+HH:MM:SS` or `-HH:MM:SS`, or the same in units like `-1h2m30s`, a missing sign is
+/// taken as `+`.
+fn parse_offset(s: &str) -> Result<TimeDelta, String> {
+    let invalid =
+        || format!("expected an offset like +00:00:43, -01:30:00 or -1h2m30s, got \"{s}\"");
+
+    let (sign, unsigned) = s
+        .strip_prefix('-')
+        .map_or_else(|| (1, s.strip_prefix('+').unwrap_or(s)), |rest| (-1, rest));
+
+    if !unsigned.contains(':') {
+        return parse_units(unsigned)
+            .and_then(|total| TimeDelta::try_seconds(sign * total))
+            .ok_or_else(invalid);
+    }
+
+    let fields = unsigned
+        .split(':')
+        .map(|field| {
+            field
+                .bytes()
+                .all(|b| b.is_ascii_digit())
+                .then(|| field.parse::<u32>().ok())
+                .flatten()
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(invalid)?;
+
+    let [hours, minutes, seconds] = fields[..] else {
+        return Err(invalid());
+    };
+
+    if minutes >= 60 || seconds >= 60 {
+        return Err(invalid());
+    }
+
+    let total = i64::from(hours) * 3600 + i64::from(minutes) * 60 + i64::from(seconds);
+    Ok(TimeDelta::seconds(sign * total))
+}
+
+/// Seconds in an offset like `1h2m30s`, with days, hours, minutes and seconds in that order and
+/// each of them optional.
+fn parse_units(s: &str) -> Option<i64> {
+    let mut units = "dhms";
+    let mut rest = s;
+    let mut total = 0_i64;
+
+    if s.is_empty() {
+        return None;
+    }
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .filter(|&at| at > 0)?;
+        let (count, tail) = rest.split_at(digits);
+        let unit = tail.chars().next()?;
+
+        // each unit once, and bigger ones first
+        units = &units[units.find(unit)? + 1..];
+
+        let scale = match unit {
+            'd' => 86_400,
+            'h' => 3600,
+            'm' => 60,
+            _ => 1,
+        };
+
+        total = total.checked_add(count.parse::<i64>().ok()?.checked_mul(scale)?)?;
+        rest = &tail[unit.len_utf8()..];
+    }
+
+    Some(total)
+}
+
+/// Which of a file's timestamps it's named and ordered by, see `--date-source`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum DateSource {
+    /// when it was taken, EXIF `DateTimeOriginal`
+    #[default]
+    Original,
+    /// when it was digitized, EXIF `DateTimeDigitized`, which exiftool calls `CreateDate`
+    Digitized,
+    /// the same as [`DateSource::Digitized`], by exiftool's name for it
+    Create,
+    /// when the file was last changed, by the camera or software that edited it, EXIF `DateTime`
+    Modify,
+    /// when the file was last modified, for scans that have no EXIF
+    FileMtime,
+}
+
+impl DateSource {
+    /// The order the EXIF timestamps are fallen back on in when the one asked for is missing.
+    const FALLBACKS: [Self; 3] = [Self::Original, Self::Digitized, Self::Modify];
+
+    /// Makes the timestamp from this source the one `md` was taken at, or the first of the
+    /// others it has. `file` is where `md` came from, if it came from a file.
+    pub fn pick(self, md: &mut RawMetadata, file: Option<&Path>) {
+        let exif = &mut md.exif;
+
+        if self == Self::FileMtime
+            && let Some(modified) = file.and_then(modified)
+        {
+            exif.date_time_original = Some(modified.format(EXIF_DT_FMT).to_string());
+            exif.sub_sec_time_original = Some(modified.format("%3f").to_string());
+            exif.offset_time_original = Some(modified.format("%:z").to_string());
+            return;
+        }
+
+        let Some(source) = iter::once(self)
+            .chain(Self::FALLBACKS)
+            .find(|source| source.timestamps(exif).0.as_deref().is_some_and(readable))
+        else {
+            return;
+        };
+
+        let (date, subsec, offset) = source.timestamps(exif);
+        let (date, subsec, offset) = (date.clone(), subsec.clone(), offset.clone());
+
+        exif.date_time_original = date;
+        exif.sub_sec_time_original = subsec;
+        exif.offset_time_original = offset;
+    }
+
+    /// The source's date, its fraction of a second and its UTC offset.
+    const fn timestamps(self, exif: &Exif) -> (&Option<String>, &Option<String>, &Option<String>) {
+        match self {
+            Self::Original | Self::FileMtime => (
+                &exif.date_time_original,
+                &exif.sub_sec_time_original,
+                &exif.offset_time_original,
+            ),
+            Self::Digitized | Self::Create => (
+                &exif.create_date,
+                &exif.sub_sec_time_digitized,
+                &exif.offset_time_digitized,
+            ),
+            Self::Modify => (&exif.modify_date, &exif.sub_sec_time, &exif.offset_time),
+        }
+    }
+}
+
+/// When `file` was last modified, in the local time zone.
+fn modified(file: &Path) -> Option<DateTime<Local>> {
+    fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(DateTime::from)
+}
+
+/// Whether an EXIF timestamp has a date in it, rather than being blank like some cameras leave it.
+fn readable(date: &str) -> bool {
+    NaiveDateTime::parse_from_str(date.trim_end_matches('\0').trim(), EXIF_DT_FMT).is_ok()
+}
+
+/// Corrections for cameras whose clocks were off, so several bodies at one event end up on the
+/// same timeline, which of their timestamps to go by and what zone to render them in.
+#[derive(Debug, Clone, Default)]
+pub struct Clocks {
+    pub shifts: Vec<TimeShift>,
+    /// how far every body's clock was off, on top of its own shift
+    pub shift: Option<TimeDelta>,
+    pub source: DateSource,
+    /// what zone dates are moved into, the zone they were taken in if `None`
+    pub zone: Option<Zone>,
+}
+
+impl Clocks {
+    /// How far off the clock of the body that took the file was, every body's shift plus its own
+    /// going by its serial number.
+    #[must_use]
+    pub fn offset(&self, md: &RawMetadata) -> Option<TimeDelta> {
+        let serial = md.exif.serial_number.as_deref().map(str::trim);
+        let own = serial.and_then(|serial| {
+            self.shifts
+                .iter()
+                .find(|shift| shift.serial == serial)
+                .map(|shift| shift.offset)
+        });
+
+        match (self.shift, own) {
+            (None, None) => None,
+            (all, own) => Some(all.unwrap_or_default() + own.unwrap_or_default()),
+        }
+    }
+
+    /// Takes the file's date from the source asked for, moves its timestamps onto the corrected
+    /// clock and into the time zone asked for, returning how far the clock was moved. `file` is
+    /// where `md` came from, if it came from a file.
+    pub fn correct(&self, md: &mut RawMetadata, file: Option<&Path>) -> Option<TimeDelta> {
+        self.source.pick(md, file);
+
+        let offset = self.offset(md);
+
+        if let Some(offset) = offset {
+            let exif = &mut md.exif;
+            for date in [
+                &mut exif.date_time_original,
+                &mut exif.create_date,
+                &mut exif.modify_date,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                if let Some(shifted) = shift(date, offset) {
+                    *date = shifted;
+                }
+            }
+        }
+
+        if let Some(zone) = self.zone {
+            zone.convert(md);
+        }
+
+        offset
+    }
+}
+
+/// The time zone dates are rendered in, see `--timezone`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Zone {
+    /// the time zone of the system rawbit runs on
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl Zone {
+    pub const UTC: Self = Self::Fixed(FixedOffset::east_opt(0).unwrap());
+
+    /// Moves the file's capture time into this zone, along with the offset it records.
+    fn convert(self, md: &mut RawMetadata) {
+        let Some(taken) = taken_in_zone(md) else {
+            return;
+        };
+
+        let converted = match self {
+            Self::Local => taken.with_timezone(&Local).fixed_offset(),
+            Self::Fixed(offset) => taken.with_timezone(&offset),
+        };
+
+        md.exif.date_time_original = Some(converted.format(EXIF_DT_FMT).to_string());
+        md.exif.offset_time_original = Some(converted.format("%:z").to_string());
+    }
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(Self::Local),
+            "utc" | "UTC" | "Z" => Ok(Self::UTC),
+            _ => s.parse().map(Self::Fixed).map_err(|_| {
+                format!("expected local, utc or a UTC offset like +09:00 or -05:30, got \"{s}\"")
+            }),
+        }
+    }
+}
+
+impl TryFrom<String> for Zone {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Zone> for String {
+    fn from(zone: Zone) -> Self {
+        zone.to_string()
+    }
+}
+
+impl Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local => write!(f, "local"),
+            Self::Fixed(offset) => write!(f, "{offset}"),
+        }
+    }
+}
+
+/// The moment the file was taken, at the UTC offset the camera recorded with it, or in the local
+/// time zone if it didn't record one.
+#[must_use]
+pub fn taken_at(md: &RawMetadata) -> Option<SystemTime> {
+    taken_in_zone(md).map(Into::into)
+}
+
+/// [`taken_at`], in the zone it was taken in.
+fn taken_in_zone(md: &RawMetadata) -> Option<DateTime<FixedOffset>> {
+    let taken = parse::capture_time(md)?;
+    let offset = md.exif.offset_time_original.as_deref().and_then(|offset| {
+        offset
+            .trim_end_matches('\0')
+            .trim()
+            .parse::<FixedOffset>()
+            .ok()
+    });
+
+    offset.map_or_else(
+        || {
+            Local
+                .from_local_datetime(&taken)
+                .earliest()
+                .map(|taken| taken.fixed_offset())
+        },
+        |offset| offset.from_local_datetime(&taken).single(),
+    )
+}
+
+/// An EXIF timestamp moved by `offset`, if it's one that can be read.
+fn shift(date: &str, offset: TimeDelta) -> Option<String> {
+    let date =
+        NaiveDateTime::parse_from_str(date.trim_end_matches('\0').trim(), EXIF_DT_FMT).ok()?;
+
+    Some(
+        date.checked_add_signed(offset)?
+            .format(EXIF_DT_FMT)
+            .to_string(),
+    )
+}
+
+/// Moves the capture timestamps in a finished DNG by `offset`, like [`Clocks::correct`] did for
+/// its name.
+///
+/// IFD0's `DateTime` is left alone, in a converted DNG it's when the conversion happened.
+pub fn write<F: Read + Write + Seek>(offset: TimeDelta, file: &mut F) -> io::Result<()> {
+    let mut tiff = Tiff::open(file)?;
+    let ifd0 = tiff.read_ifd0()?;
+
+    let Some(pointer) = ifd0.get(TAG_EXIF_IFD) else {
+        return Ok(());
+    };
+
+    let exif = tiff.read_ifd(tiff.pointer(pointer))?;
+    let exif_entries = shift_entries(&mut tiff, exif.entries, offset)?;
+    let exif_offset = tiff.append_ifd(exif_entries, exif.next)?;
+
+    let mut root_entries = ifd0.entries;
+    root_entries.retain(|entry| entry.tag != TAG_EXIF_IFD);
+    root_entries.push(tiff.pointer_entry(TAG_EXIF_IFD, exif_offset));
+
+    let offset = tiff.append_ifd(root_entries, ifd0.next)?;
+    tiff.set_ifd0_offset(offset)?;
+
+    tiff.flush()
+}
+
+/// The EXIF `entries` with each capture timestamp replaced by a shifted copy appended to the file.
+fn shift_entries<F: Read + Write + Seek>(
+    tiff: &mut Tiff<F>,
+    entries: Vec<Entry>,
+    offset: TimeDelta,
+) -> io::Result<Vec<Entry>> {
+    entries
+        .into_iter()
+        .map(|entry| {
+            if ![EXIF_DATE_TIME_ORIGINAL, EXIF_CREATE_DATE].contains(&entry.tag) {
+                return Ok(entry);
+            }
+
+            let data = tiff.read_data(&entry)?;
+            let shifted = std::str::from_utf8(&data)
+                .ok()
+                .and_then(|date| shift(date, offset));
+
+            shifted.map_or(Ok(entry), |date| tiff.append_ascii(entry.tag, &date))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod clock_tests {
+    use std::{
+        io::Cursor,
+        path::Path,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use chrono::{DateTime, Local, TimeDelta};
+    use rawler::decoders::RawMetadata;
+
+    use super::{
+        Clocks, DateSource, EXIF_DATE_TIME_ORIGINAL, EXIF_DT_FMT, Offset, TimeShift, Zone,
+        taken_at, write,
+    };
+    use crate::tiff::{TAG_EXIF_IFD, Tiff, tiff_tests};
+
+    #[test]
+    fn shifts_only_the_named_bodys_timestamps() {
+        let shift = "12345678=-01:02:03".parse::<TimeShift>().unwrap();
+        assert_eq!(shift.offset, -TimeDelta::seconds(3723));
+        assert_eq!(shift.to_string(), "12345678=-01:02:03");
+
+        assert!("12345678".parse::<TimeShift>().is_err());
+        assert!("12345678=+00:61:00".parse::<TimeShift>().is_err());
+        assert!("=+00:00:43".parse::<TimeShift>().is_err());
+
+        let clocks = Clocks {
+            shifts: vec!["12345678=+00:00:43".parse().unwrap()],
+            ..Default::default()
+        };
+
+        let taken_by = |serial: &str| {
+            let mut md = RawMetadata::default();
+            md.exif.serial_number = Some(serial.into());
+            md.exif.date_time_original = Some("2024:12:31 23:59:30".into());
+            md
+        };
+
+        let mut md = taken_by("12345678");
+        assert_eq!(clocks.correct(&mut md, None), Some(TimeDelta::seconds(43)));
+        assert_eq!(
+            md.exif.date_time_original.as_deref(),
+            Some("2025:01:01 00:00:13")
+        );
+
+        let mut md = taken_by("87654321");
+        assert_eq!(clocks.correct(&mut md, None), None);
+        assert_eq!(
+            md.exif.date_time_original.as_deref(),
+            Some("2024:12:31 23:59:30")
+        );
+
+        let mut tiff = Tiff::open(Cursor::new(tiff_tests::sample(true))).unwrap();
+        let ifd0 = tiff.read_ifd0().unwrap();
+        let taken = tiff
+            .append_ascii(EXIF_DATE_TIME_ORIGINAL, "2024:05:06 07:08:09")
+            .unwrap();
+        let exif = tiff.append_ifd(vec![taken], 0).unwrap();
+        let mut entries = ifd0.entries;
+        entries.push(tiff.pointer_entry(TAG_EXIF_IFD, exif));
+        let offset = tiff.append_ifd(entries, ifd0.next).unwrap();
+        tiff.set_ifd0_offset(offset).unwrap();
+
+        let mut file = tiff.into_inner();
+        write(TimeDelta::seconds(43), &mut file).unwrap();
+
+        let mut tiff = Tiff::open(file).unwrap();
+        let ifd0 = tiff.read_ifd0().unwrap();
+        let exif = tiff
+            .read_ifd(tiff.pointer(ifd0.get(TAG_EXIF_IFD).unwrap()))
+            .unwrap();
+        let date = tiff
+            .read_data(exif.get(EXIF_DATE_TIME_ORIGINAL).unwrap())
+            .unwrap();
+        assert_eq!(date, b"2024:05:06 07:08:52\0");
+    }
+
+    #[test]
+    fn shifts_every_bodys_timestamps() {
+        let offset = |s: &str| {
+            s.parse::<Offset>()
+                .map(|Offset(offset)| offset.num_seconds())
+        };
+        assert_eq!(offset("-1h2m30s"), Ok(-3750));
+        assert_eq!(offset("+1d"), Ok(86_400));
+        assert_eq!(offset("90s"), Ok(90));
+        assert_eq!(offset("-00:00:43"), Ok(-43));
+        for invalid in [
+            "",
+            "-",
+            "1x",
+            "30s1h",
+            "1h1h",
+            "h",
+            "1.5h",
+            "99999999999999999d",
+        ] {
+            assert!(
+                offset(invalid).is_err(),
+                "expected {invalid:?} to be rejected"
+            );
+        }
+        assert_eq!(
+            "-1h2m30s".parse::<Offset>().unwrap().to_string(),
+            "-01:02:30"
+        );
+
+        let clocks = Clocks {
+            shifts: vec!["12345678=+00:00:43".parse().unwrap()],
+            shift: Some(-TimeDelta::hours(1)),
+            ..Default::default()
+        };
+
+        let taken_by = |serial: Option<&str>| {
+            let mut md = RawMetadata::default();
+            md.exif.serial_number = serial.map(Into::into);
+            md.exif.date_time_original = Some("2024:05:06 07:08:09".into());
+            md
+        };
+
+        // on top of the body's own
+        let mut md = taken_by(Some("12345678"));
+        assert_eq!(
+            clocks.correct(&mut md, None),
+            Some(TimeDelta::seconds(43 - 3600))
+        );
+        assert_eq!(
+            md.exif.date_time_original.as_deref(),
+            Some("2024:05:06 06:08:52")
+        );
+
+        let mut md = taken_by(None);
+        assert_eq!(clocks.correct(&mut md, None), Some(-TimeDelta::hours(1)));
+        assert_eq!(
+            md.exif.date_time_original.as_deref(),
+            Some("2024:05:06 06:08:09")
+        );
+    }
+
+    #[test]
+    fn takes_the_date_from_the_source_asked_for() {
+        let mut md = RawMetadata::default();
+        md.exif.date_time_original = Some("0000:00:00 00:00:00".into());
+        md.exif.create_date = Some("2024:05:06 07:08:09".into());
+        md.exif.sub_sec_time_digitized = Some("25".into());
+        md.exif.modify_date = Some("2024:05:06 07:08:10".into());
+
+        let picked = |source: DateSource, file: Option<&Path>| {
+            let mut md = md.clone();
+            source.pick(&mut md, file);
+            (md.exif.date_time_original, md.exif.sub_sec_time_original)
+        };
+
+        // a blank original falls back on the digitized date
+        assert_eq!(
+            picked(DateSource::Original, None),
+            (Some("2024:05:06 07:08:09".into()), Some("25".into()))
+        );
+        assert_eq!(
+            picked(DateSource::Create, None),
+            picked(DateSource::Digitized, None)
+        );
+        assert_eq!(
+            picked(DateSource::Modify, None),
+            (Some("2024:05:06 07:08:10".into()), None)
+        );
+
+        // with no digitized date either, the modified one is the last resort
+        let mut modified_only = md.clone();
+        modified_only.exif.create_date = None;
+        DateSource::Create.pick(&mut modified_only, None);
+        assert_eq!(
+            modified_only.exif.date_time_original.as_deref(),
+            Some("2024:05:06 07:08:10")
+        );
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let modified =
+            DateTime::<Local>::from(file.as_file().metadata().unwrap().modified().unwrap());
+        assert_eq!(
+            picked(DateSource::FileMtime, Some(file.path())).0,
+            Some(modified.format(EXIF_DT_FMT).to_string())
+        );
+        // nothing to take it from, as for a raw on stdin
+        assert_eq!(
+            picked(DateSource::FileMtime, None).0,
+            Some("2024:05:06 07:08:09".into())
+        );
+
+        let mut md = RawMetadata::default();
+        DateSource::Modify.pick(&mut md, None);
+        assert_eq!(md.exif.date_time_original, None);
+    }
+
+    #[test]
+    fn renders_dates_in_the_zone_asked_for() {
+        assert_eq!("utc".parse::<Zone>(), Ok(Zone::UTC));
+        assert_eq!("+09:00".parse::<Zone>().unwrap().to_string(), "+09:00");
+        assert_eq!("local".parse::<Zone>(), Ok(Zone::Local));
+        assert!("Asia/Tokyo".parse::<Zone>().is_err());
+
+        let clocks = |zone: &str| Clocks {
+            zone: Some(zone.parse().unwrap()),
+            ..Default::default()
+        };
+
+        let mut md = RawMetadata::default();
+        md.exif.date_time_original = Some("2024:05:06 07:08:09".into());
+        md.exif.offset_time_original = Some("+09:00".into());
+        md.exif.sub_sec_time_original = Some("25".into());
+        let taken = taken_at(&md);
+
+        clocks("utc").correct(&mut md, None);
+        assert_eq!(
+            md.exif.date_time_original.as_deref(),
+            Some("2024:05:05 22:08:09")
+        );
+        assert_eq!(md.exif.offset_time_original.as_deref(), Some("+00:00"));
+        // the same moment
+        assert_eq!(taken_at(&md), taken);
+
+        clocks("-05:30").correct(&mut md, None);
+        assert_eq!(
+            md.exif.date_time_original.as_deref(),
+            Some("2024:05:05 16:38:09")
+        );
+
+        let mut md = RawMetadata::default();
+        clocks("utc").correct(&mut md, None);
+        assert_eq!(md.exif.date_time_original, None);
+    }
+
+    #[test]
+    fn takes_the_recorded_utc_offset() {
+        let mut md = RawMetadata::default();
+        assert_eq!(taken_at(&md), None);
+
+        md.exif.date_time_original = Some("2024:05:06 07:08:09".into());
+        md.exif.offset_time_original = Some("+02:00".into());
+
+        assert_eq!(
+            taken_at(&md).unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_714_972_089)
+        );
+    }
+}
@@ -13,7 +13,11 @@ pub enum AppError {
     Io(String, io::Error),
     DirNotFound(String, PathBuf),
     AlreadyExists(String, PathBuf),
-    #[allow(unused)]
+    Config(String),
+    Interrupted(String, PathBuf),
+    RolledBack(String),
+    Discrepancies(String),
+    Undersized(String),
     Other(String, Box<dyn Error + Send + Sync>),
 }
 
@@ -25,6 +29,27 @@ impl Display for AppError {
 
 impl Error for AppError {}
 
+impl AppError {
+    /// A user-facing message, the underlying cause (if any) and the process exit code.
+    #[must_use]
+    pub fn describe(&self) -> (String, Option<&dyn Display>, u32) {
+        use AppError::*;
+
+        match self {
+            FmtStrParse(e) => (e.to_string(), None, 1),
+            Io(s, e) => (s.clone(), Some(e), 2),
+            DirNotFound(s, e) => (format!("{s}: {}", e.display()), None, 3),
+            AlreadyExists(s, e) => (format!("{s}: {}", e.display()), None, 4),
+            Other(s, e) => (s.clone(), Some(e), 5),
+            Config(s) => (s.clone(), None, 6),
+            Interrupted(s, p) => (format!("{s}: {}", p.display()), None, 7),
+            RolledBack(s) => (s.clone(), None, 8),
+            Discrepancies(s) => (s.clone(), None, 9),
+            Undersized(s) => (s.clone(), None, 10),
+        }
+    }
+}
+
 macro_rules! map_err {
     ($r:expr, $err_t:path, $($s:expr),+ $(,)?) => {
         $r.map_err(|e| ($err_t)($($s.into()),+, e))
@@ -0,0 +1,342 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use directories::ProjectDirs;
+use serde::Deserialize;
+
+use crate::{
+    artist::{self, Normalize},
+    clock::{Clocks, DateSource, Offset, TimeShift, Zone},
+    common::{AppError, RawbitResult, map_err},
+    decode::DecodeParam,
+    layout::Layout,
+    lens::{LensProfile, Lenses},
+    parse::{self, Var},
+    sanitize::{DEFAULT_MAX_NAME_LEN, DEFAULT_REPLACEMENT, NameRules, Normalization, Sanitizer},
+};
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// Settings read from a TOML config file.
+///
+/// Anything given on the command line takes precedence over the file.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub format: Option<String>,
+    /// what `{date}` in the format stands for, instead of `%Y-%m-%d`
+    #[serde(rename = "date-format")]
+    pub date_format: Option<String>,
+    pub layout: Option<Layout>,
+    /// what metadata a file doesn't have renders as, see `--missing-value`
+    #[serde(rename = "missing-value")]
+    pub missing_value: Option<String>,
+    /// fail files the format can't be filled in for, see `--strict-format`
+    #[serde(rename = "strict-format")]
+    pub strict_format: Option<bool>,
+    /// where `{seq}` is carried on from, relative to the output directory, see `--counter-file`
+    #[serde(rename = "counter-file")]
+    pub counter_file: Option<PathBuf>,
+    /// whose rules rendered names follow, see `--name-rules`
+    #[serde(rename = "name-rules")]
+    pub name_rules: Option<NameRules>,
+    /// what replaces characters names can't have, see `--replace-char`
+    #[serde(rename = "replace-char")]
+    pub replace_char: Option<char>,
+    /// how long names can be, in bytes, see `--max-name-len`
+    #[serde(rename = "max-name-len")]
+    pub max_name_len: Option<usize>,
+    /// the Unicode normalization form of names, see `--normalize`
+    pub normalize: Option<Normalization>,
+    /// what `{var.NAME}` renders as, see `--set`
+    #[serde(rename = "set")]
+    pub vars: Vec<Var>,
+    #[serde(deserialize_with = "artist::one_or_many")]
+    pub artist: Vec<String>,
+    #[serde(rename = "artist-normalize")]
+    pub artist_normalize: Vec<Normalize>,
+    pub lens: Option<String>,
+    #[serde(rename = "lens-profile")]
+    pub lens_profiles: Vec<LensProfile>,
+    #[serde(rename = "time-shift-for")]
+    pub time_shifts: Vec<TimeShift>,
+    /// how far every camera's clock was off, see `--time-shift`
+    #[serde(rename = "time-shift")]
+    pub time_shift: Option<Offset>,
+    /// which timestamp files are named and ordered by, see `--date-source`
+    #[serde(rename = "date-source")]
+    pub date_source: Option<DateSource>,
+    /// what zone dates are rendered in, see `--timezone`
+    pub timezone: Option<Zone>,
+    #[serde(rename = "decode-param")]
+    pub decode_params: Vec<DecodeParam>,
+    /// extensions to copy through as-is instead of converting
+    pub copy: Vec<String>,
+}
+
+impl Config {
+    /// `config.toml` in the platform config directory, e.g. `~/.config/rawbit/config.toml`.
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "rawbit").map(|dirs| dirs.config_dir().join(CONFIG_FILE))
+    }
+
+    pub fn load(path: &Path) -> RawbitResult<Self> {
+        let contents = map_err!(
+            fs::read_to_string(path),
+            AppError::Io,
+            format!("couldn't read config file {}", path.display())
+        )?;
+
+        let config: Self = toml::from_str(&contents).map_err(|err| {
+            AppError::Config(format!("invalid config file {}: {err}", path.display()))
+        })?;
+
+        if let Some(ref date) = config.date_format {
+            parse::check_date_format(date)?;
+        }
+
+        config.sanitizer()?;
+
+        Ok(config)
+    }
+
+    /// Fills in anything `cli` didn't set from this config.
+    #[must_use]
+    pub fn under(self, cli: &Self) -> Self {
+        Self {
+            format: cli.format.clone().or(self.format),
+            date_format: cli.date_format.clone().or(self.date_format),
+            layout: cli.layout.or(self.layout),
+            missing_value: cli.missing_value.clone().or(self.missing_value),
+            strict_format: cli.strict_format.or(self.strict_format),
+            counter_file: cli.counter_file.clone().or(self.counter_file),
+            name_rules: cli.name_rules.or(self.name_rules),
+            replace_char: cli.replace_char.or(self.replace_char),
+            max_name_len: cli.max_name_len.or(self.max_name_len),
+            normalize: cli.normalize.or(self.normalize),
+            // the later of two values for the same name wins
+            vars: [self.vars, cli.vars.clone()].concat(),
+            artist: if cli.artist.is_empty() {
+                self.artist
+            } else {
+                cli.artist.clone()
+            },
+            artist_normalize: if cli.artist_normalize.is_empty() {
+                self.artist_normalize
+            } else {
+                cli.artist_normalize.clone()
+            },
+            lens: cli.lens.clone().or(self.lens),
+            lens_profiles: if cli.lens_profiles.is_empty() {
+                self.lens_profiles
+            } else {
+                cli.lens_profiles.clone()
+            },
+            time_shifts: if cli.time_shifts.is_empty() {
+                self.time_shifts
+            } else {
+                cli.time_shifts.clone()
+            },
+            time_shift: cli.time_shift.or(self.time_shift),
+            date_source: cli.date_source.or(self.date_source),
+            timezone: cli.timezone.or(self.timezone),
+            decode_params: if cli.decode_params.is_empty() {
+                self.decode_params
+            } else {
+                cli.decode_params.clone()
+            },
+            copy: if cli.copy.is_empty() {
+                self.copy
+            } else {
+                cli.copy.clone()
+            },
+        }
+    }
+
+    /// The format with the layout's directories in front of it, and `{date}` spelled out if
+    /// there's a `date-format`.
+    #[must_use]
+    pub fn filename_format(&self) -> Option<String> {
+        let format = self
+            .layout
+            .unwrap_or_default()
+            .apply(self.format.as_deref())?;
+
+        Some(match self.date_format {
+            Some(ref date) => parse::with_date_format(&format, date),
+            None => format,
+        })
+    }
+
+    /// What makes rendered names valid, under the `name-rules` with the `replace-char`, no longer
+    /// than `max-name-len` and in the `normalize` form.
+    pub fn sanitizer(&self) -> RawbitResult<Sanitizer> {
+        let sanitizer = Sanitizer::new(
+            self.name_rules.unwrap_or_default(),
+            self.replace_char.unwrap_or(DEFAULT_REPLACEMENT),
+        )?
+        .with_max_name_len(self.max_name_len.unwrap_or(DEFAULT_MAX_NAME_LEN))?;
+
+        Ok(sanitizer.with_normalization(self.normalize.unwrap_or_default()))
+    }
+
+    /// The artist tag to write, every artist normalized and joined.
+    #[must_use]
+    pub fn artist_tag(&self) -> Option<String> {
+        artist::tag(&self.artist, &self.artist_normalize)
+    }
+
+    #[must_use]
+    pub fn lenses(&self) -> Lenses {
+        Lenses {
+            profiles: self.lens_profiles.clone(),
+            fallback: self.lens.clone(),
+        }
+    }
+
+    #[must_use]
+    pub fn clocks(&self) -> Clocks {
+        Clocks {
+            shifts: self.time_shifts.clone(),
+            shift: self.time_shift.map(|Offset(shift)| shift),
+            source: self.date_source.unwrap_or_default(),
+            zone: self.timezone,
+        }
+    }
+}
+
+/// Notices when a config file changes so a running batch can pick up the new settings.
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+        Self { path, modified }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reloads the config if the file was modified since it was last read.
+    pub fn poll(&mut self) -> Option<RawbitResult<Config>> {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+
+        self.modified = modified;
+
+        // a deleted config falls back to the defaults
+        Some(if self.path.exists() {
+            Config::load(&self.path)
+        } else {
+            Ok(Config::default())
+        })
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|md| md.modified()).ok()
+}
+
+#[cfg(test)]
+mod config_tests {
+    use std::{fs, time::Duration};
+
+    use super::{Config, ConfigWatcher};
+
+    #[test]
+    fn cli_takes_precedence() {
+        let file = Config {
+            format: Some("%Y%m%d_{image.original_filename}".into()),
+            artist: vec!["File".into()],
+            ..Default::default()
+        };
+
+        let cli = Config {
+            artist: vec!["Cli".into()],
+            ..Default::default()
+        };
+
+        let merged = file.under(&cli);
+        assert_eq!(
+            merged.format.as_deref(),
+            Some("%Y%m%d_{image.original_filename}")
+        );
+        assert_eq!(merged.artist, ["Cli"]);
+    }
+
+    #[test]
+    fn date_format_stands_in_for_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "format = \"{date}_{image.original_filename}\"\ndate-format = \"%d.%m.%Y\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(
+            config.filename_format().as_deref(),
+            Some("%d.%m.%Y_{image.original_filename}")
+        );
+
+        fs::write(&path, "date-format = \"{camera.make}\"\n").unwrap();
+        assert!(Config::load(&path).is_err());
+    }
+
+    #[test]
+    fn watcher_reloads_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "format = \"a\"\n").unwrap();
+
+        let mut watcher = ConfigWatcher::new(path.clone());
+        assert!(watcher.poll().is_none());
+
+        fs::write(&path, "format = \"b\"\n").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(std::time::SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+
+        let reloaded = watcher.poll().unwrap().unwrap();
+        assert_eq!(reloaded.format.as_deref(), Some("b"));
+        assert!(watcher.poll().is_none());
+    }
+
+    #[test]
+    fn reads_lens_profiles() {
+        let config: Config = toml::from_str(
+            r#"
+            lens = "Manual"
+
+            [[lens-profile]]
+            camera = "ILCE-7M3"
+            focal-length = 35
+            model = "Nokton 35"
+            "#,
+        )
+        .unwrap();
+
+        let lenses = config.lenses();
+        assert_eq!(lenses.fallback.as_deref(), Some("Manual"));
+        assert_eq!(lenses.profiles[0].focal_length, Some(35.0));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(toml::from_str::<Config>("fromat = \"a\"").is_err());
+    }
+}
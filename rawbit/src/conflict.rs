@@ -0,0 +1,267 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use smlog::debug;
+
+/// The highest suffix tried before giving up on a name, `_999`.
+pub const MAX_SUFFIX: u32 = 999;
+
+/// What to do when an output would land on a file that's already there, see `--on-conflict`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnConflict {
+    /// leave the file that's there alone, and don't import the new one
+    Skip,
+    /// replace the file that's there
+    Overwrite,
+    /// write the new file under the first free numbered name
+    Rename,
+    /// fail the file
+    #[default]
+    Error,
+}
+
+impl OnConflict {
+    /// The policy `--force` stands for, when it's given.
+    #[must_use]
+    pub const fn forced(force: bool) -> Self {
+        if force { Self::Overwrite } else { Self::Error }
+    }
+}
+
+impl Display for OnConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Skip => "skip",
+            Self::Overwrite => "overwrite",
+            Self::Rename => "rename",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// `path` with `_NNN` added to its stem, e.g. `IMG_0001_002.dng`.
+#[must_use]
+pub fn numbered(path: &Path, n: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{stem}_{n:03}");
+
+    if let Some(ext) = path.extension() {
+        name.push('.');
+        name.push_str(&ext.to_string_lossy());
+    }
+
+    path.with_file_name(name)
+}
+
+/// Whether names that differ only by case are the same file in `dir`, as they are by default on
+/// macOS and Windows.
+///
+/// It's looked up in the nearest directory that exists, going by a file already in it, or with
+/// `write_probe`, a file made for the purpose. Otherwise it's the platform's default.
+#[must_use]
+pub fn is_case_insensitive(dir: &Path, write_probe: bool) -> bool {
+    let found = dir.ancestors().find(|dir| dir.is_dir()).and_then(|dir| {
+        existing_name_case(dir).or_else(|| write_probe.then(|| probe_case(dir))?)
+    });
+
+    found.unwrap_or(cfg!(any(target_os = "macos", windows)))
+}
+
+/// Whether a file in `dir` with letters in its name can be found under the name in another case.
+fn existing_name_case(dir: &Path) -> Option<bool> {
+    fs::read_dir(dir).ok()?.flatten().find_map(|entry| {
+        let name = entry.file_name().to_str()?.to_string();
+        let swapped = swap_case(&name);
+
+        // two files named apart by case would be taken for one, which only numbers more names
+        (swapped != name).then(|| dir.join(swapped).symlink_metadata().is_ok())
+    })
+}
+
+/// Creates a file in `dir` to look it up under an upper-case name, removing it again.
+fn probe_case(dir: &Path) -> Option<bool> {
+    let name = format!(".rawbit-case-probe-{}", process::id());
+    let probe = dir.join(&name);
+
+    fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .ok()?;
+
+    let found = dir.join(swap_case(&name)).exists();
+
+    if let Err(err) = fs::remove_file(&probe) {
+        debug!("couldn't remove {}: {err}", probe.display());
+    }
+
+    Some(found)
+}
+
+fn swap_case(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_lowercase() {
+                c.to_uppercase().next().unwrap_or(c)
+            } else {
+                c.to_lowercase().next().unwrap_or(c)
+            }
+        })
+        .collect()
+}
+
+/// Output paths handed out so far in a batch, used to rename outputs that would land on one
+/// another or on a file that's already there.
+///
+/// On a case-insensitive destination, paths that differ only by case are the same claim.
+#[derive(Debug, Default)]
+pub struct Claims {
+    claimed: HashSet<PathBuf>,
+    /// whether each destination directory seen so far is case-insensitive
+    case_insensitive: HashMap<PathBuf, bool>,
+    /// whether a probe file can be written to find out, see [`is_case_insensitive`]
+    write_probe: bool,
+}
+
+impl Claims {
+    /// Claims for a batch that writes to its destination, which can be probed with a file of
+    /// rawbit's own when there's nothing in it yet to go by.
+    #[must_use]
+    pub fn writing() -> Self {
+        Self {
+            write_probe: true,
+            ..Self::default()
+        }
+    }
+
+    /// What `path` is claimed as, folded to lower case on a case-insensitive destination.
+    pub fn key(&mut self, path: &Path) -> PathBuf {
+        let dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        let write_probe = self.write_probe;
+
+        let case_insensitive = *self.case_insensitive.entry(dir).or_insert_with_key(|dir| {
+            let case_insensitive = is_case_insensitive(dir, write_probe);
+            if case_insensitive {
+                debug!(
+                    "{} is case-insensitive, names that differ only by case are treated as the same",
+                    dir.display()
+                );
+            }
+
+            case_insensitive
+        });
+
+        if case_insensitive {
+            path.to_string_lossy().to_lowercase().into()
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    /// Claims `path` if it's free, otherwise its first free numbered variant.
+    ///
+    /// `exists` decides whether a path is already taken on disk, so planning can run without
+    /// touching the destination. Returns [`None`] once every suffix is taken.
+    pub fn claim(&mut self, path: &Path, exists: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+        let free =
+            |claims: &mut Self, candidate: &Path| !claims.contains(candidate) && !exists(candidate);
+
+        let claimed = if free(self, path) {
+            path.to_path_buf()
+        } else {
+            (1..=MAX_SUFFIX)
+                .map(|n| numbered(path, n))
+                .find(|candidate| free(self, candidate))?
+        };
+
+        self.insert(&claimed);
+        Some(claimed)
+    }
+
+    /// Whether another output in the batch already has `path`.
+    pub fn contains(&mut self, path: &Path) -> bool {
+        let key = self.key(path);
+        self.claimed.contains(&key)
+    }
+
+    /// Claims `path` as it is, whether or not it's taken.
+    pub fn insert(&mut self, path: &Path) {
+        let key = self.key(path);
+        self.claimed.insert(key);
+    }
+
+    /// Forgets every claim, for a new batch whose outputs only have to stay clear of what's on
+    /// disk by now. Destinations are probed again, the one there may have been swapped.
+    pub fn clear(&mut self) {
+        self.claimed.clear();
+        self.case_insensitive.clear();
+    }
+}
+
+#[cfg(test)]
+mod conflict_tests {
+    use std::{collections::HashMap, path::Path};
+
+    use super::{Claims, numbered};
+
+    #[test]
+    fn numbers_past_claimed_and_existing_names() {
+        assert_eq!(
+            numbered(Path::new("out/IMG_0001.dng"), 2),
+            Path::new("out/IMG_0001_002.dng")
+        );
+
+        let existing = Path::new("out/a_001.dng");
+        let exists = |path: &Path| path == Path::new("out/b.dng") || path == existing;
+
+        let mut claims = Claims::default();
+        let a = Path::new("out/a.dng");
+
+        assert_eq!(claims.claim(a, exists).unwrap(), a);
+        assert_eq!(claims.claim(a, exists).unwrap(), Path::new("out/a_002.dng"));
+        assert_eq!(claims.claim(a, exists).unwrap(), Path::new("out/a_003.dng"));
+        assert_eq!(
+            claims.claim(Path::new("out/b.dng"), exists).unwrap(),
+            Path::new("out/b_001.dng")
+        );
+
+        claims.clear();
+        assert_eq!(claims.claim(a, exists).unwrap(), a);
+    }
+
+    #[test]
+    fn names_differing_by_case_collide_on_a_case_insensitive_destination() {
+        let insensitive = Path::new("card");
+        let mut claims = Claims {
+            case_insensitive: HashMap::from([(insensitive.into(), true)]),
+            ..Claims::default()
+        };
+
+        let a = Path::new("card/IMG_0001.dng");
+        assert_eq!(claims.claim(a, |_| false).unwrap(), a);
+        assert!(claims.contains(Path::new("card/img_0001.DNG")));
+        assert_eq!(
+            claims
+                .claim(Path::new("card/img_0001.dng"), |_| false)
+                .unwrap(),
+            Path::new("card/img_0001_001.dng")
+        );
+
+        // a directory the probe found case-sensitive keeps them apart
+        let sensitive = tempfile::tempdir().unwrap();
+        std::fs::write(sensitive.path().join("IMG_0001.dng"), b"").unwrap();
+        if !super::is_case_insensitive(sensitive.path(), true) {
+            let mut claims = Claims::writing();
+            claims.insert(&sensitive.path().join("IMG_0001.dng"));
+            assert!(!claims.contains(&sensitive.path().join("img_0001.dng")));
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    io::Cursor,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use clap::ValueEnum;
+use rawler::{
+    decoders::RawDecodeParams,
+    dng::{self, CropMode, DngCompression, convert::ConvertParams},
+    get_decoder,
+    rawsource::RawSource,
+};
+use tokio::{io, runtime::Builder};
+
+use crate::{
+    args::IngestItem,
+    clock::{self, Clocks},
+    common::{RawbitResult, map_err},
+    conflict::{Claims, OnConflict},
+    job::{Error, Job as _, JobConfig, Outcome, RawConvertJob, Retry},
+    lens::Lenses,
+    metadata::MetadataPolicy,
+    parse::FilenameFormat,
+    perms::Permissions,
+    provenance::{self, Provenance},
+    reprocess::{self, EmbeddedOriginal},
+    sidecar::JpegPolicy,
+};
+
+/// The conversion settings rawbit uses unless told otherwise.
+#[must_use]
+pub fn convert_params(embed: bool, preview: bool, thumbnail: bool) -> ConvertParams {
+    ConvertParams {
+        apply_scaling: false,
+        crop: CropMode::Best,
+        compression: DngCompression::Lossless,
+        embedded: embed,
+        index: 0,
+        preview,
+        thumbnail,
+        software: "rawbit".into(),
+        ..Default::default()
+    }
+}
+
+/// How the image data in converted DNGs is stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// lossless JPEG, about half the size
+    #[default]
+    Lossless,
+    /// the raw sensor values as they are, quicker to write and read
+    Uncompressed,
+}
+
+impl From<Compression> for DngCompression {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Lossless => Self::Lossless,
+            Compression::Uncompressed => Self::Uncompressed,
+        }
+    }
+}
+
+/// Settings for [`convert_file`], meant to be built once and reused for every file.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub filename_format: Arc<FilenameFormat<'static>>,
+    pub lenses: Arc<Lenses>,
+    pub clocks: Arc<Clocks>,
+    pub on_conflict: OnConflict,
+    pub convert_opts: ConvertParams,
+    pub metadata: MetadataPolicy,
+    pub salvage: bool,
+    /// recorded in every output's provenance record, so files converted together can be told apart
+    pub run_id: Arc<str>,
+}
+
+impl Options {
+    /// The command line's defaults, naming files with `format`.
+    pub fn new(format: &str) -> RawbitResult<Self> {
+        Ok(Self {
+            filename_format: Arc::new(FilenameFormat::parse(format)?.into_owned()),
+            lenses: Arc::default(),
+            clocks: Arc::default(),
+            on_conflict: OnConflict::default(),
+            convert_opts: convert_params(false, true, true),
+            metadata: MetadataPolicy::default(),
+            salvage: false,
+            run_id: provenance::new_run_id().into(),
+        })
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new("").expect("the empty format always parses")
+    }
+}
+
+/// Converts one file into `output_dir`, blocking until it's written.
+///
+/// For scripts and tests that don't have an async runtime of their own, a single-threaded one is
+/// started for the length of the call, so this can't be called from inside one.
+pub fn convert_file(input: &Path, output_dir: &Path, options: &Options) -> Result<Outcome, Error> {
+    convert_item(&IngestItem::from((input, "")), output_dir, options)
+}
+
+/// Converts an ingested file, which might be inside an archive, into its place under
+/// `output_root`, like [`convert_file`].
+pub fn convert_item(
+    item: &IngestItem,
+    output_root: &Path,
+    options: &Options,
+) -> Result<Outcome, Error> {
+    let input = &item.input_path;
+
+    if !input.is_file() {
+        return Err(Error::Io(
+            format!("no such input file: {}", input.display()),
+            io::ErrorKind::NotFound.into(),
+        ));
+    }
+
+    let runtime = map_err!(
+        Builder::new_current_thread().enable_all().build(),
+        Error::Io,
+        "couldn't start a runtime for the conversion"
+    )?;
+
+    let config = JobConfig {
+        input_path: input.clone(),
+        output_dir: output_root.join(&item.output_prefix),
+        filename_format: options.filename_format.clone(),
+        lenses: options.lenses.clone(),
+        clocks: options.clocks.clone(),
+        on_conflict: options.on_conflict,
+        claims: Arc::new(Mutex::new(Claims::default())),
+        convert_opts: options.convert_opts.clone(),
+        isolation: None,
+        run_id: options.run_id.clone(),
+        metadata: options.metadata,
+        salvage: options.salvage,
+        fallback: None,
+        copy: false,
+        unsorted: false,
+        catalog: None,
+        chrono_index: item.chrono_index,
+        seq: item.seq,
+        member: item.member.clone(),
+        fallback_stem: item.fallback_stem.clone(),
+        retry: Retry::default(),
+        staging: None,
+        fsync: false,
+        preserve_times: false,
+        mtime_from_exif: false,
+        backups: Vec::new(),
+        permissions: Permissions::default(),
+        link_archive: None,
+        intents: None,
+        sidecars: false,
+        jpeg_policy: JpegPolicy::Ignore,
+    };
+
+    runtime.block_on(RawConvertJob::new(config).run())
+}
+
+/// Converts the raw in `source` into a DNG in memory, for pipelines that don't go through the
+/// filesystem, see `--stdout`. `name` is the raw's file name, recorded in the DNG.
+///
+/// The lens, clock corrections, metadata policy and provenance record are applied as they are to
+/// a DNG written to disk. `options`' format and conflict policy don't apply, there's no name.
+pub fn convert_bytes(source: &[u8], name: &str, options: &Options) -> Result<Vec<u8>, Error> {
+    let raw = RawSource::new_from_slice(source);
+    let decoder =
+        get_decoder(&raw).map_err(|err| Error::ImgOp("no decoder for the raw".into(), err))?;
+    let mut md = decoder
+        .raw_metadata(&raw, &RawDecodeParams::default())
+        .map_err(|err| Error::ImgOp("couldn't extract image metadata".into(), err))?;
+
+    let lens = options.lenses.fill(&mut md);
+    let shift = options.clocks.correct(&mut md, None);
+
+    let mut provenance = Provenance::new(&options.run_id, name, &options.convert_opts);
+    provenance.source_sha256 = provenance::sha256_hex(source);
+
+    // a DNG keeps the original embedded in it, as when it's converted from a file
+    let mut params = options.convert_opts.clone();
+    let original = (params.embedded && reprocess::is_dng(Path::new(name)))
+        .then(|| {
+            params.embedded = false;
+            EmbeddedOriginal::read(source)
+        })
+        .flatten();
+
+    let mut dng = Cursor::new(vec![]);
+    dng::convert::convert_raw_source(&raw, &mut dng, name, &params)
+        .map_err(|err| Error::ImgOp("couldn't convert image to DNG".into(), err))?;
+
+    let finish = |dng: &mut Cursor<Vec<u8>>| {
+        if let Some(ref lens) = lens {
+            lens.write(dng)?;
+        }
+
+        if let Some(shift) = shift {
+            clock::write(shift, dng)?;
+        }
+
+        if let Some(ref original) = original {
+            original.write(dng)?;
+        }
+
+        options.metadata.apply(dng)?;
+        provenance::write_xmp(dng, provenance.to_xmp().as_bytes())
+    };
+
+    map_err!(finish(&mut dng), Error::Io, "couldn't finish DNG")?;
+    Ok(dng.into_inner())
+}
+
+#[cfg(test)]
+pub mod convert_tests {
+    use std::{fs, io::Cursor};
+
+    use rawler::{
+        decoders::RawMetadata,
+        dng::{DNG_VERSION_V1_4, DngCompression, original::OriginalCompressed, writer::DngWriter},
+        exif::Exif,
+    };
+
+    use super::{Options, convert_bytes, convert_file};
+    use crate::{job::Error, provenance};
+
+    /// A small DNG from a "Testmake T1", taken 2024-05-06.
+    #[must_use]
+    pub fn dng() -> Vec<u8> {
+        dng_with_original(None)
+    }
+
+    /// [`dng`], with `original` embedded in it as if it had been converted from it with
+    /// `--embed-raw`.
+    #[must_use]
+    pub fn dng_with_original(original: Option<&[u8]>) -> Vec<u8> {
+        let mut dng = Cursor::new(vec![]);
+
+        let mut writer = DngWriter::new(&mut dng, DNG_VERSION_V1_4).unwrap();
+        writer
+            .load_metadata(&RawMetadata {
+                make: "Testmake".into(),
+                model: "T1".into(),
+                exif: Exif {
+                    date_time_original: Some("2024:05:06 07:08:09".into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .unwrap();
+
+        let mut frame = writer.subframe_on_root(0);
+        frame
+            .rgb_image_u8(&[128; 16 * 16 * 3], 16, 16, DngCompression::Uncompressed, 1)
+            .unwrap();
+        frame.finalize().unwrap();
+
+        if let Some(original) = original {
+            let original = OriginalCompressed::compress(&mut Cursor::new(original)).unwrap();
+            writer.original_file(&original, "IMG_0001.CR3").unwrap();
+        }
+
+        writer.close().unwrap();
+
+        dng.into_inner()
+    }
+
+    #[test]
+    fn converts_without_a_runtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("IMG_0001.DNG");
+        fs::write(&input, dng()).unwrap();
+
+        let out = dir.path().join("out");
+        let options = Options::new("%Y_").unwrap();
+
+        let outcome = convert_file(&input, &out, &options).unwrap();
+        assert_eq!(outcome.output_path, out.join("2024_IMG_0001.dng"));
+        assert!(outcome.output_path.is_file());
+
+        assert!(matches!(
+            convert_file(&input, &out, &options),
+            Err(Error::AlreadyExists(..))
+        ));
+        assert!(matches!(
+            convert_file(&dir.path().join("missing.DNG"), &out, &options),
+            Err(Error::Io(..))
+        ));
+    }
+
+    #[test]
+    fn converts_in_memory() {
+        let options = Options::default();
+        let converted = convert_bytes(&dng(), "IMG_0001.DNG", &options).unwrap();
+
+        let run_id = provenance::read_run_id(&mut Cursor::new(converted.clone())).unwrap();
+        assert_eq!(run_id.as_deref(), Some(&*options.run_id));
+        assert!(convert_bytes(&converted, "IMG_0001.dng", &options).is_ok());
+
+        assert!(matches!(
+            convert_bytes(b"not a raw", "IMG_0001.CR3", &options),
+            Err(Error::ImgOp(..))
+        ));
+    }
+}
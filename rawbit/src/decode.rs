@@ -0,0 +1,221 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use rawler::dng::{CropMode, DngPhotometricConversion, convert::ConvertParams};
+use serde::{Deserialize, Serialize};
+
+/// The keys `--decode-param` knows, with what each takes.
+const KEYS: &[(&str, &str)] = &[
+    (
+        "image-index",
+        "which image to convert from raws with several, counting from 0",
+    ),
+    ("crop", "best, active-area or none"),
+    (
+        "scaling",
+        "true or false, whether to scale values to the white level",
+    ),
+    ("predictor", "the lossless JPEG predictor, 1 to 7"),
+    ("photometric", "original or linear"),
+];
+
+/// One of rawler's conversion settings, `KEY=VALUE`, for working around a decoder's quirks with
+/// a camera. See `--decode-param`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum DecodeParam {
+    ImageIndex(usize),
+    Crop(Crop),
+    Scaling(bool),
+    Predictor(u8),
+    Photometric(Photometric),
+}
+
+/// How much of the sensor a converted DNG keeps, see [`CropMode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Crop {
+    /// the area the camera says is meant to be seen
+    Best,
+    /// every pixel with image data
+    ActiveArea,
+    /// the whole sensor, masked pixels too
+    None,
+}
+
+impl From<Crop> for CropMode {
+    fn from(crop: Crop) -> Self {
+        match crop {
+            Crop::Best => Self::Best,
+            Crop::ActiveArea => Self::ActiveArea,
+            Crop::None => Self::None,
+        }
+    }
+}
+
+/// How a converted DNG's image data relates to the raw's, see [`DngPhotometricConversion`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Photometric {
+    /// as the sensor recorded it
+    Original,
+    /// demosaiced into linear RGB
+    Linear,
+}
+
+impl From<Photometric> for DngPhotometricConversion {
+    fn from(photometric: Photometric) -> Self {
+        match photometric {
+            Photometric::Original => Self::Original,
+            Photometric::Linear => Self::Linear,
+        }
+    }
+}
+
+/// `params` applied to `opts` in order, so a key given twice takes its last value. Keys that
+/// aren't given are rawbit's defaults, whatever `opts` had.
+#[must_use]
+pub fn apply(params: &[DecodeParam], opts: ConvertParams) -> ConvertParams {
+    let defaults = ConvertParams::default();
+
+    let mut opts = ConvertParams {
+        index: 0,
+        crop: CropMode::Best,
+        apply_scaling: false,
+        predictor: defaults.predictor,
+        photometric_conversion: defaults.photometric_conversion,
+        ..opts
+    };
+
+    for param in params {
+        match *param {
+            DecodeParam::ImageIndex(index) => opts.index = index,
+            DecodeParam::Crop(crop) => opts.crop = crop.into(),
+            DecodeParam::Scaling(scaling) => opts.apply_scaling = scaling,
+            DecodeParam::Predictor(predictor) => opts.predictor = predictor,
+            DecodeParam::Photometric(photometric) => {
+                opts.photometric_conversion = photometric.into();
+            }
+        }
+    }
+
+    opts
+}
+
+impl FromStr for DecodeParam {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, e.g. crop=none, got \"{s}\""))?;
+
+        let (key, value) = (key.trim(), value.trim());
+        let invalid = || {
+            let expected = KEYS
+                .iter()
+                .find(|(known, _)| *known == key)
+                .map_or("", |(_, expected)| *expected);
+            format!("invalid value \"{value}\" for {key}, expected {expected}")
+        };
+
+        match key {
+            "image-index" => value.parse().map(Self::ImageIndex).map_err(|_| invalid()),
+            "crop" => Crop::from_str(value, false)
+                .map(Self::Crop)
+                .map_err(|_| invalid()),
+            "scaling" => value.parse().map(Self::Scaling).map_err(|_| invalid()),
+            "predictor" => match value.parse() {
+                Ok(predictor @ 1..=7) => Ok(Self::Predictor(predictor)),
+                _ => Err(invalid()),
+            },
+            "photometric" => Photometric::from_str(value, false)
+                .map(Self::Photometric)
+                .map_err(|_| invalid()),
+            _ => {
+                let known = KEYS.iter().map(|(known, _)| *known).collect::<Vec<_>>();
+                Err(format!(
+                    "unknown decode parameter \"{key}\", expected one of {}",
+                    known.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for DecodeParam {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<DecodeParam> for String {
+    fn from(param: DecodeParam) -> Self {
+        param.to_string()
+    }
+}
+
+impl Display for DecodeParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |value: Option<clap::builder::PossibleValue>| {
+            value
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default()
+        };
+
+        match self {
+            Self::ImageIndex(index) => write!(f, "image-index={index}"),
+            Self::Crop(crop) => write!(f, "crop={}", name(crop.to_possible_value())),
+            Self::Scaling(scaling) => write!(f, "scaling={scaling}"),
+            Self::Predictor(predictor) => write!(f, "predictor={predictor}"),
+            Self::Photometric(photometric) => {
+                write!(f, "photometric={}", name(photometric.to_possible_value()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod decode_tests {
+    use rawler::dng::convert::ConvertParams;
+
+    use super::{DecodeParam, apply};
+
+    #[test]
+    fn parses_and_applies_decode_params() {
+        let params = [
+            "crop=none",
+            "image-index=1",
+            "predictor = 6",
+            "image-index=2",
+        ]
+        .map(|param| param.parse::<DecodeParam>().unwrap());
+
+        for param in params {
+            assert_eq!(param.to_string().parse::<DecodeParam>(), Ok(param));
+        }
+
+        let opts = apply(
+            &params,
+            ConvertParams {
+                apply_scaling: true,
+                ..Default::default()
+            },
+        );
+        assert!(matches!(opts.crop, rawler::dng::CropMode::None));
+        assert_eq!(opts.index, 2);
+        assert_eq!(opts.predictor, 6);
+        assert!(!opts.apply_scaling);
+
+        let err = "white-level=1".parse::<DecodeParam>().unwrap_err();
+        assert!(err.contains("unknown decode parameter \"white-level\""));
+        assert!(err.contains("image-index, crop"));
+
+        let err = "predictor=9".parse::<DecodeParam>().unwrap_err();
+        assert!(err.contains("1 to 7"));
+        assert!("crop".parse::<DecodeParam>().is_err());
+    }
+}
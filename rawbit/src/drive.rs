@@ -0,0 +1,193 @@
+use std::io::Cursor;
+
+use crate::tiff::{TAG_EXIF_IFD, TAG_MAKE, TAG_MAKER_NOTE, Tiff};
+
+/// Canon's `CameraSettings`, the drive mode is the 6th value.
+const CANON_CAMERA_SETTINGS: u16 = 0x0001;
+const CANON_CONTINUOUS_DRIVE: usize = 5;
+
+/// Nikon's `ShootingMode` flags.
+const NIKON_SHOOTING_MODE: u16 = 0x0089;
+const NIKON_CONTINUOUS: u16 = 1;
+const NIKON_SELF_TIMER: u16 = 1 << 3;
+/// exposure, white balance and Active D-Lighting bracketing
+const NIKON_BRACKETING: u16 = 1 << 4 | 1 << 6 | 1 << 8;
+
+/// Sony's `ReleaseMode`, which the older bodies record.
+const SONY_RELEASE_MODE: u16 = 0xB049;
+
+/// How the camera was set to take pictures when it took one, see `{camera.drive_mode}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriveMode {
+    Single,
+    Continuous,
+    ContinuousLow,
+    ContinuousHigh,
+    /// a single frame with the electronic shutter
+    Silent,
+    ContinuousSilent,
+    SelfTimer,
+    Bracket,
+}
+
+impl DriveMode {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Single => "single",
+            Self::Continuous => "continuous",
+            Self::ContinuousLow => "continuous-low",
+            Self::ContinuousHigh => "continuous-high",
+            Self::Silent => "silent",
+            Self::ContinuousSilent => "continuous-silent",
+            Self::SelfTimer => "self-timer",
+            Self::Bracket => "bracket",
+        }
+    }
+}
+
+/// The drive mode in a raw's maker notes, for Canon, Nikon and older Sony bodies. rawler doesn't
+/// read maker notes, so they're found here.
+#[must_use]
+pub fn drive_mode(raw: &[u8]) -> Option<DriveMode> {
+    if let Some(notes) = cr3_maker_notes(raw) {
+        let mut notes = Tiff::open(Cursor::new(notes)).ok()?;
+        let ifd = notes.read_ifd0().ok()?;
+        let settings = notes.read_shorts(ifd.get(CANON_CAMERA_SETTINGS)?).ok()?;
+
+        return canon(&settings);
+    }
+
+    let mut tiff = Tiff::open(Cursor::new(raw)).ok()?;
+    let ifd0 = tiff.read_ifd0().ok()?;
+    let make = tiff.read_data(ifd0.get(TAG_MAKE)?).ok()?;
+
+    let exif = tiff.pointer(ifd0.get(TAG_EXIF_IFD)?);
+    let exif = tiff.read_ifd(exif).ok()?;
+    let note = exif.get(TAG_MAKER_NOTE)?;
+    let offset = tiff.offset(note)?;
+    let data = tiff.read_data(note).ok()?;
+
+    if make.starts_with(b"Canon") {
+        let ifd = tiff.read_ifd(offset).ok()?;
+        let settings = tiff.read_shorts(ifd.get(CANON_CAMERA_SETTINGS)?).ok()?;
+
+        canon(&settings)
+    } else if data.starts_with(b"Nikon\0") {
+        // a TIFF of its own, after the name and version
+        let mut notes = Tiff::open(Cursor::new(data.get(10..)?)).ok()?;
+        let ifd = notes.read_ifd0().ok()?;
+        let mode = *notes
+            .read_shorts(ifd.get(NIKON_SHOOTING_MODE)?)
+            .ok()?
+            .first()?;
+
+        Some(if mode & NIKON_CONTINUOUS != 0 {
+            DriveMode::Continuous
+        } else if mode & NIKON_SELF_TIMER != 0 {
+            DriveMode::SelfTimer
+        } else if mode & NIKON_BRACKETING != 0 {
+            DriveMode::Bracket
+        } else {
+            DriveMode::Single
+        })
+    } else if data.starts_with(b"SONY DSC ") || data.starts_with(b"SONY CAM ") {
+        let ifd = tiff.read_ifd(offset.checked_add(12)?).ok()?;
+        let mode = *tiff
+            .read_shorts(ifd.get(SONY_RELEASE_MODE)?)
+            .ok()?
+            .first()?;
+
+        match mode {
+            0 => Some(DriveMode::Single),
+            2 => Some(DriveMode::Continuous),
+            5 | 6 | 8 => Some(DriveMode::Bracket),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+fn canon(settings: &[u16]) -> Option<DriveMode> {
+    match settings.get(CANON_CONTINUOUS_DRIVE)? {
+        0 => Some(DriveMode::Single),
+        1 => Some(DriveMode::Continuous),
+        4 => Some(DriveMode::ContinuousLow),
+        // 3 is speed priority, 8 is high+
+        3 | 5 | 8 => Some(DriveMode::ContinuousHigh),
+        6 | 9 => Some(DriveMode::Silent),
+        10 => Some(DriveMode::ContinuousSilent),
+        _ => None,
+    }
+}
+
+/// The `CMT3` box of a CR3, a TIFF holding Canon's maker notes. It's in the header, near the
+/// start of the file.
+fn cr3_maker_notes(raw: &[u8]) -> Option<&[u8]> {
+    if raw.get(4..12)? != b"ftypcrx " {
+        return None;
+    }
+
+    let header = &raw[..raw.len().min(1 << 20)];
+    let at = header.windows(4).position(|window| window == b"CMT3")?;
+    let start = at.checked_sub(4)?;
+    let size = u32::from_be_bytes(raw.get(start..at)?.try_into().ok()?);
+
+    raw.get(at + 4..start.checked_add(usize::try_from(size).ok()?)?)
+}
+
+#[cfg(test)]
+mod drive_tests {
+    use super::{DriveMode, drive_mode};
+
+    /// A little-endian IFD of `(tag, type, count, value)` entries.
+    fn ifd(entries: &[(u16, u16, u32, u32)]) -> Vec<u8> {
+        let mut ifd = u16::try_from(entries.len()).unwrap().to_le_bytes().to_vec();
+
+        for &(tag, kind, count, value) in entries {
+            ifd.extend(tag.to_le_bytes());
+            ifd.extend(kind.to_le_bytes());
+            ifd.extend(count.to_le_bytes());
+            ifd.extend(value.to_le_bytes());
+        }
+
+        ifd.extend(0u32.to_le_bytes());
+        ifd
+    }
+
+    fn canon_settings(drive: u16) -> Vec<u8> {
+        [0, 0, 0, 0, 0, drive]
+            .iter()
+            .flat_map(|short: &u16| short.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn reads_the_drive_mode_from_maker_notes() {
+        let mut cr2 = b"II*\0".to_vec();
+        cr2.extend(8u32.to_le_bytes());
+        cr2.extend(ifd(&[(0x010F, 2, 6, 38), (0x8769, 4, 1, 44)]));
+        cr2.extend(b"Canon\0");
+        cr2.extend(ifd(&[(0x927C, 7, 30, 62)]));
+        cr2.extend(ifd(&[(0x0001, 3, 6, 80)]));
+        cr2.extend(canon_settings(5));
+        assert_eq!(drive_mode(&cr2), Some(DriveMode::ContinuousHigh));
+
+        // a CR3's maker notes are a TIFF in a box of their own
+        let mut notes = b"II*\0".to_vec();
+        notes.extend(8u32.to_le_bytes());
+        notes.extend(ifd(&[(0x0001, 3, 6, 26)]));
+        notes.extend(canon_settings(10));
+
+        let mut cr3 = 24u32.to_be_bytes().to_vec();
+        cr3.extend(b"ftypcrx ");
+        cr3.resize(24, 0);
+        cr3.extend(u32::try_from(notes.len() + 8).unwrap().to_be_bytes());
+        cr3.extend(b"CMT3");
+        cr3.extend(&notes);
+        assert_eq!(drive_mode(&cr3), Some(DriveMode::ContinuousSilent));
+
+        assert_eq!(drive_mode(b"not a raw"), None);
+    }
+}
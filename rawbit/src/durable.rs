@@ -0,0 +1,105 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::Path,
+};
+
+/// Flushes `path` to disk, along with the entries for it in every directory from its own up to
+/// `root`, so a power loss can't take back a file that's been reported written. See `--fsync`.
+pub fn sync_file(path: &Path, root: &Path) -> io::Result<()> {
+    // Windows only flushes handles opened for writing
+    OpenOptions::new().write(true).open(path)?.sync_all()?;
+
+    for dir in path.ancestors().skip(1) {
+        sync_dir(dir)?;
+
+        if dir == root {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Flushes a directory's entries, so files created in or moved into it survive a power loss.
+///
+/// Windows can't open a directory to flush it, NTFS journals directory changes itself.
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+
+    if cfg!(unix) {
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Moves `from` to `to`, copying it across when they're on different filesystems.
+///
+/// A copy, like from a card to an archive disk, is flushed to disk before `from` is removed, and
+/// removed again if it doesn't come out whole.
+pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    let dir = to.parent().unwrap_or(to);
+    fs::create_dir_all(dir)?;
+
+    if fs::rename(from, to).is_ok() {
+        return sync_dir(dir);
+    }
+
+    let copied = fs::copy(from, to).and_then(|_| {
+        sync_file(to, dir)?;
+
+        if fs::metadata(to)?.len() == fs::metadata(from)?.len() {
+            Ok(())
+        } else {
+            Err(io::Error::other("the copy came out a different size"))
+        }
+    });
+
+    if let Err(err) = copied {
+        let _ = fs::remove_file(to);
+        return Err(err);
+    }
+
+    fs::remove_file(from)?;
+    sync_dir(from.parent().unwrap_or(from))
+}
+
+#[cfg(test)]
+mod durable_tests {
+    use std::fs;
+
+    use super::{move_file, sync_file};
+
+    #[test]
+    fn syncs_a_file_and_the_directories_up_to_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("2024/05");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(nested.join("a.dng"), b"dng").unwrap();
+
+        sync_file(&nested.join("a.dng"), dir.path()).unwrap();
+        assert!(sync_file(&nested.join("missing.dng"), dir.path()).is_err());
+    }
+
+    #[test]
+    fn moves_a_file_into_a_new_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("a.dng");
+        fs::write(&from, b"dng").unwrap();
+
+        let to = dir.path().join("2024/05/a.dng");
+        move_file(&from, &to).unwrap();
+        assert_eq!(fs::read(&to).unwrap(), b"dng");
+        assert!(!from.exists());
+
+        // nothing's left behind when there's nothing to copy
+        let missing = dir.path().join("2024/05/b.dng");
+        assert!(move_file(&dir.path().join("b.dng"), &missing).is_err());
+        assert!(!missing.exists());
+    }
+}
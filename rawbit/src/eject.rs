@@ -0,0 +1,196 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use tokio::process::Command;
+
+use crate::common::{AppError, RawbitResult, map_err};
+
+/// A mounted filesystem and the device backing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Volume {
+    pub device: String,
+    pub mount_point: PathBuf,
+}
+
+impl Volume {
+    /// Finds the volume `path` lives on.
+    pub async fn containing(path: &Path) -> RawbitResult<Self> {
+        let stdout = output(Command::new("df").arg("-P").arg(path)).await?;
+
+        parse_df(&stdout).ok_or_else(|| {
+            AppError::Config(format!(
+                "couldn't determine the volume of {}",
+                path.display()
+            ))
+        })
+    }
+
+    /// Whether the volume sits on removable media, e.g. a card reader or USB drive.
+    #[must_use]
+    pub fn is_removable(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        {
+            linux::block_device(&self.device).is_some_and(|sys| {
+                let sys_path = sys.to_string_lossy();
+
+                std::fs::read_to_string(sys.join("removable")).is_ok_and(|r| r.trim() == "1")
+                    || sys_path.contains("/usb")
+                    || sys_path.contains("/mmc")
+            })
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            std::process::Command::new("diskutil")
+                .arg("info")
+                .arg(&self.mount_point)
+                .output()
+                .is_ok_and(|out| {
+                    String::from_utf8_lossy(&out.stdout).lines().any(|line| {
+                        let line = line.trim();
+                        line.starts_with("Ejectable:") && line.ends_with("Yes")
+                    })
+                })
+        }
+
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        {
+            false
+        }
+    }
+
+    /// Unmounts the volume and, where the platform allows, powers the device off.
+    pub async fn eject(&self) -> RawbitResult<()> {
+        #[cfg(target_os = "linux")]
+        {
+            linux::eject(self).await
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            output(Command::new("diskutil").arg("eject").arg(&self.mount_point))
+                .await
+                .map(|_| ())
+        }
+
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        {
+            Err(AppError::Config(
+                "--eject isn't supported on this platform".into(),
+            ))
+        }
+    }
+}
+
+async fn output(cmd: &mut Command) -> RawbitResult<String> {
+    let program = cmd.as_std().get_program().to_string_lossy().to_string();
+
+    let out = map_err!(
+        cmd.output().await,
+        AppError::Io,
+        format!("couldn't run {program}")
+    )?;
+
+    if out.status.success() {
+        Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+    } else {
+        Err(AppError::Other(
+            format!("{program} failed ({})", out.status),
+            Box::new(io::Error::other(
+                String::from_utf8_lossy(&out.stderr).trim().to_string(),
+            )),
+        ))
+    }
+}
+
+/// Parses POSIX `df -P` output: a header, then `device blocks used available capacity mount`.
+fn parse_df(stdout: &str) -> Option<Volume> {
+    let line = stdout.lines().nth(1)?;
+    let mut fields = line.split_whitespace();
+
+    let device = fields.next()?.to_string();
+    let mount_point = fields.skip(4).collect::<Vec<_>>().join(" ");
+
+    (!mount_point.is_empty()).then(|| Volume {
+        device,
+        mount_point: mount_point.into(),
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use tokio::process::Command;
+
+    use super::{Volume, output};
+    use crate::common::RawbitResult;
+
+    /// The sysfs directory of the whole disk behind a partition like `/dev/sdb1`.
+    pub fn block_device(device: &str) -> Option<PathBuf> {
+        let name = Path::new(device).strip_prefix("/dev").ok()?;
+        let sys = fs::canonicalize(Path::new("/sys/class/block").join(name)).ok()?;
+
+        if sys.join("partition").exists() {
+            sys.parent().map(Path::to_path_buf)
+        } else {
+            Some(sys)
+        }
+    }
+
+    pub async fn eject(volume: &Volume) -> RawbitResult<()> {
+        let udisks = output(
+            Command::new("udisksctl")
+                .args(["unmount", "--no-user-interaction", "-b"])
+                .arg(&volume.device),
+        )
+        .await;
+
+        if udisks.is_err() {
+            output(Command::new("umount").arg(&volume.mount_point)).await?;
+        }
+
+        // powering off is a nicety, the volume is already safe to remove once it's unmounted
+        if let Some(disk) = block_device(&volume.device)
+            .as_deref()
+            .and_then(Path::file_name)
+        {
+            let _ = output(
+                Command::new("udisksctl")
+                    .args(["power-off", "--no-user-interaction", "-b"])
+                    .arg(Path::new("/dev").join(disk)),
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod eject_tests {
+    use super::parse_df;
+
+    #[test]
+    fn parses_df_output() {
+        let volume = parse_df(
+            "Filesystem     1024-blocks    Used Available Capacity Mounted on\n\
+             /dev/sdb1         62498816 1203456  61295360       2% /media/me/EOS DIGITAL\n",
+        )
+        .unwrap();
+
+        assert_eq!(volume.device, "/dev/sdb1");
+        assert_eq!(volume.mount_point.to_str(), Some("/media/me/EOS DIGITAL"));
+    }
+
+    #[test]
+    fn rejects_truncated_df_output() {
+        assert!(parse_df("Filesystem 1024-blocks Used Available Capacity Mounted on\n").is_none());
+        assert!(parse_df("").is_none());
+    }
+}
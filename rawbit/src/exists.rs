@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use futures::{StreamExt as _, stream};
+use smlog::{debug, info, warn};
+use tokio::process::Command;
+
+use crate::args::IngestItem;
+
+/// A command asked whether each file is already in an external catalog or DAM, so files it has
+/// are left out of the import, see `--exists-cmd`.
+///
+/// The command is run through the shell with the source path appended as its last argument, and
+/// exits 0 if the file already exists there or 1 if it doesn't. Anything else is reported and the
+/// file is imported anyway.
+#[derive(Debug, Clone)]
+pub struct ExistsCmd(String);
+
+impl ExistsCmd {
+    #[must_use]
+    pub const fn new(cmd: String) -> Self {
+        Self(cmd)
+    }
+
+    fn command(&self, path: &Path) -> Command {
+        #[cfg(windows)]
+        {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(&self.0).arg(path);
+            command
+        }
+
+        #[cfg(not(windows))]
+        {
+            let mut command = Command::new("sh");
+            command
+                .arg("-c")
+                .arg(format!("{} \"$@\"", self.0))
+                .arg("rawbit")
+                .arg(path);
+            command
+        }
+    }
+
+    /// Whether the external catalog already has `path`.
+    pub async fn contains(&self, path: &Path) -> bool {
+        match self.command(path).status().await {
+            Ok(status) if status.code() == Some(0) => {
+                debug!("\"{}\" already exists, skipping", path.display());
+                true
+            }
+
+            Ok(status) if status.code() == Some(1) => false,
+
+            Ok(status) => {
+                warn!(
+                    "couldn't tell whether \"{}\" already exists, importing it: --exists-cmd exited with {status}",
+                    path.display()
+                );
+                false
+            }
+
+            Err(err) => {
+                warn!(
+                    "couldn't tell whether \"{}\" already exists, importing it: couldn't run --exists-cmd: {err}",
+                    path.display()
+                );
+                false
+            }
+        }
+    }
+
+    /// Leaves out the items the external catalog already has, asking about up to `concurrency`
+    /// of them at once.
+    pub async fn retain_new(&self, items: Vec<IngestItem>, concurrency: usize) -> Vec<IngestItem> {
+        let total = items.len();
+
+        let kept = stream::iter(items)
+            .map(|item| async move {
+                let exists = self.contains(&item.source_path()).await;
+                (!exists).then_some(item)
+            })
+            .buffered(concurrency.max(1))
+            .filter_map(|item| async move { item })
+            .collect::<Vec<_>>()
+            .await;
+
+        if kept.len() < total {
+            info!(
+                "{} file(s) already exist according to --exists-cmd, skipped them",
+                total - kept.len()
+            );
+        }
+
+        kept
+    }
+}
+
+#[cfg(test)]
+mod exists_tests {
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn skips_what_the_command_says_exists() {
+        use std::{fs, os::unix::fs::PermissionsExt as _, path::Path};
+
+        use super::ExistsCmd;
+        use crate::args::IngestItem;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("exists.sh");
+        fs::write(
+            &script,
+            "#!/bin/sh\ncase \"$1\" in\n  *a.DNG) exit 0 ;;\n  *b.DNG) exit 1 ;;\n  *) exit 3 ;;\nesac\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let cmd = ExistsCmd::new(format!("'{}'", script.display()));
+        let items = ["a.DNG", "b.DNG", "c.DNG"]
+            .map(|name| IngestItem::from((Path::new("card").join(name), "")))
+            .to_vec();
+
+        let kept = cmd.retain_new(items, 2).await;
+        let kept = kept
+            .iter()
+            .map(|item| item.input_path.file_name().unwrap())
+            .collect::<Vec<_>>();
+
+        // c.DNG's answer is unclear, so it's imported rather than lost
+        assert_eq!(kept, ["b.DNG", "c.DNG"]);
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead as _, BufReader, Write as _},
+    path::{self, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use smlog::{info, warn};
+
+use crate::{
+    common::{AppError, RawbitResult, map_err},
+    durable::move_file,
+};
+
+/// Kept in an `--out-dir-fallback`, a line per file written there while the primary output
+/// directory was unavailable, so `rawbit reconcile` knows where each one belongs.
+pub const MANIFEST: &str = ".rawbit-failover.jsonl";
+
+/// A file written to the fallback instead of `primary`, at `path` under both.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub primary: PathBuf,
+    pub path: PathBuf,
+}
+
+/// Records that `written`, under `fallback`, was meant for `primary`.
+pub fn record(fallback: &Path, primary: &Path, written: &Path) -> io::Result<()> {
+    let entry = Entry {
+        primary: path::absolute(primary)?,
+        path: written
+            .strip_prefix(fallback)
+            .unwrap_or(written)
+            .to_path_buf(),
+    };
+
+    let mut line = serde_json::to_vec(&entry)?;
+    line.push(b'\n');
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(fallback.join(MANIFEST))?
+        .write_all(&line)
+}
+
+fn read(manifest: &Path) -> RawbitResult<Vec<Entry>> {
+    let file = map_err!(
+        File::open(manifest),
+        AppError::Io,
+        format!("couldn't open failover manifest: {}", manifest.display())
+    )?;
+
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = map_err!(
+            line,
+            AppError::Io,
+            format!("couldn't read failover manifest: {}", manifest.display())
+        )?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        entries.push(serde_json::from_str(&line).map_err(|err| {
+            AppError::Other(
+                format!("invalid failover manifest: {}", manifest.display()),
+                Box::new(err),
+            )
+        })?);
+    }
+
+    Ok(entries)
+}
+
+/// How a [`reconcile`] went.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Reconciled {
+    pub moved: usize,
+    /// still in the fallback, since their primary is unavailable or already has a file there
+    pub left: usize,
+}
+
+/// Moves the files in `fallback` back into the primary output directories they were meant for.
+///
+/// Files whose primary is still unavailable, or already has something in their place, are left
+/// for a later run, everything else is dropped from the manifest.
+pub fn reconcile(fallback: &Path) -> RawbitResult<Reconciled> {
+    let manifest = fallback.join(MANIFEST);
+    if !manifest.exists() {
+        info!("nothing to reconcile in {}", fallback.display());
+        return Ok(Reconciled::default());
+    }
+
+    let mut reconciled = Reconciled::default();
+    let mut left = vec![];
+
+    for entry in read(&manifest)? {
+        let from = fallback.join(&entry.path);
+        let to = entry.primary.join(&entry.path);
+
+        // moved by hand, or by a reconcile that didn't get to rewrite the manifest
+        if !from.exists() {
+            continue;
+        }
+
+        let moved = if !entry.primary.is_dir() {
+            Err(format!("{} is unavailable", entry.primary.display()))
+        } else if to.exists() {
+            Err(format!("{} already exists", to.display()))
+        } else {
+            move_file(&from, &to).map_err(|err| err.to_string())
+        };
+
+        match moved {
+            Ok(()) => {
+                info!("moved \"{}\" to \"{}\"", from.display(), to.display());
+                reconciled.moved += 1;
+            }
+            Err(err) => {
+                warn!("left \"{}\" in the fallback: {err}", from.display());
+                left.push(entry);
+            }
+        }
+    }
+
+    reconciled.left = left.len();
+
+    let rewritten = if left.is_empty() {
+        fs::remove_file(&manifest)
+    } else {
+        left.iter()
+            .map(|entry| serde_json::to_string(entry).map(|line| line + "\n"))
+            .collect::<Result<String, _>>()
+            .map_err(io::Error::from)
+            .and_then(|lines| fs::write(&manifest, lines))
+    };
+
+    map_err!(
+        rewritten,
+        AppError::Io,
+        format!("couldn't update failover manifest: {}", manifest.display())
+    )?;
+
+    Ok(reconciled)
+}
+
+#[cfg(test)]
+mod failover_tests {
+    use std::fs;
+
+    use super::{MANIFEST, Reconciled, reconcile, record};
+
+    #[test]
+    fn moves_failed_over_files_back_once_the_primary_is_there() {
+        let dir = tempfile::tempdir().unwrap();
+        let primary = dir.path().join("nas");
+        let fallback = dir.path().join("local");
+        fs::create_dir_all(fallback.join("2024")).unwrap();
+
+        for name in ["2024/a.dng", "b.dng", "c.dng"] {
+            fs::write(fallback.join(name), name).unwrap();
+            record(&fallback, &primary, &fallback.join(name)).unwrap();
+        }
+
+        // the primary is still gone, so everything stays put
+        assert_eq!(
+            reconcile(&fallback).unwrap(),
+            Reconciled { moved: 0, left: 3 }
+        );
+
+        fs::create_dir(&primary).unwrap();
+        fs::write(primary.join("c.dng"), "someone else's").unwrap();
+
+        assert_eq!(
+            reconcile(&fallback).unwrap(),
+            Reconciled { moved: 2, left: 1 }
+        );
+        assert_eq!(
+            fs::read_to_string(primary.join("2024/a.dng")).unwrap(),
+            "2024/a.dng"
+        );
+        assert_eq!(
+            fs::read_to_string(primary.join("c.dng")).unwrap(),
+            "someone else's"
+        );
+        assert!(fallback.join("c.dng").exists());
+
+        fs::remove_file(primary.join("c.dng")).unwrap();
+        assert_eq!(
+            reconcile(&fallback).unwrap(),
+            Reconciled { moved: 1, left: 0 }
+        );
+        assert!(!fallback.join(MANIFEST).exists());
+    }
+}
@@ -0,0 +1,362 @@
+//! A C API for embedding rawbit's importer in other applications, enabled by the `ffi` feature.
+//!
+//! The `rawbit-ffi` crate builds it into a shared or static library, with the declarations in
+//! `rawbit-ffi/include/rawbit.h`. An import is planned with [`rawbit_import_new`], converts in the
+//! background once started, and is polled for progress until it's finished or cancelled.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_int},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    args::{Filters, IngestItem, RawSource},
+    convert::{Options, convert_item},
+};
+
+pub const RAWBIT_OK: c_int = 0;
+pub const RAWBIT_ERROR: c_int = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(msg: impl Into<String>) -> c_int {
+    let msg = CString::new(msg.into().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(msg));
+
+    RAWBIT_ERROR
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    converted: AtomicUsize,
+    failed: AtomicUsize,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+}
+
+/// Marks the import finished when it's dropped, however the worker stops.
+struct Finished<'a>(&'a Progress);
+
+impl Drop for Finished<'_> {
+    fn drop(&mut self) {
+        self.0.finished.store(true, Ordering::Release);
+    }
+}
+
+/// A planned import, opaque to C.
+#[derive(Debug)]
+pub struct RawbitImport {
+    items: Arc<Vec<IngestItem>>,
+    output_dir: PathBuf,
+    options: Options,
+    progress: Arc<Progress>,
+    worker: Option<JoinHandle<()>>,
+}
+
+/// How far along an import is, filled in by [`rawbit_import_poll`].
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RawbitProgress {
+    pub total: usize,
+    pub converted: usize,
+    pub failed: usize,
+    /// no more files will be converted, because they're all done or the import was cancelled
+    pub finished: bool,
+}
+
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, String> {
+    if ptr.is_null() {
+        return Err(format!("{name} is null"));
+    }
+
+    // SAFETY: non-null and NUL-terminated, per the caller
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|_| format!("{name} isn't valid UTF-8"))
+}
+
+/// Plans an import of the raws in `in_dir` into `out_dir`, named with `format`.
+///
+/// Returns null on failure, see [`rawbit_last_error`].
+///
+/// # Safety
+///
+/// `in_dir` and `out_dir` must point to NUL-terminated strings, and `format` must either do the
+/// same or be null for the default format.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rawbit_import_new(
+    in_dir: *const c_char,
+    out_dir: *const c_char,
+    format: *const c_char,
+    recurse: bool,
+) -> *mut RawbitImport {
+    // SAFETY: forwarded from the caller
+    let args = unsafe {
+        str_arg(in_dir, "in_dir").and_then(|in_dir| {
+            let out_dir = str_arg(out_dir, "out_dir")?;
+            let format = if format.is_null() {
+                ""
+            } else {
+                str_arg(format, "format")?
+            };
+
+            Ok((in_dir, out_dir, format))
+        })
+    };
+
+    let planned = args.and_then(|(in_dir, out_dir, format)| {
+        let options = Options::new(format).map_err(|err| err.describe().0)?;
+        let source = RawSource {
+            input_dir: Some(in_dir.into()),
+            ..Default::default()
+        };
+
+        let items = source
+            .ingest(recurse, &[], &Filters::default())
+            .map_err(|err| err.describe().0)?;
+
+        Ok(RawbitImport {
+            items: Arc::new(items),
+            output_dir: out_dir.into(),
+            options,
+            progress: Arc::default(),
+            worker: None,
+        })
+    });
+
+    match planned {
+        Ok(import) => Box::into_raw(Box::new(import)),
+        Err(msg) => {
+            fail(msg);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// How many files an import will convert.
+///
+/// # Safety
+///
+/// `import` must come from [`rawbit_import_new`] and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rawbit_import_len(import: *const RawbitImport) -> usize {
+    // SAFETY: valid, per the caller
+    unsafe { import.as_ref() }.map_or(0, |import| import.items.len())
+}
+
+/// Starts converting in the background, returning straight away.
+///
+/// # Safety
+///
+/// `import` must come from [`rawbit_import_new`] and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rawbit_import_start(import: *mut RawbitImport) -> c_int {
+    // SAFETY: valid, per the caller
+    let Some(import) = (unsafe { import.as_mut() }) else {
+        return fail("import is null");
+    };
+
+    if import.worker.is_some() {
+        return fail("import was already started");
+    }
+
+    let items = import.items.clone();
+    let output_dir = import.output_dir.clone();
+    let options = import.options.clone();
+    let progress = import.progress.clone();
+
+    import.worker = Some(thread::spawn(move || {
+        // even if the thread dies, a caller polling for the end mustn't wait forever
+        let _finished = Finished(&progress);
+
+        // one file at a time, the decoder already spreads each one across the rayon pool
+        for item in items.iter() {
+            if progress.cancelled.load(Ordering::Relaxed) {
+                break;
+            }
+
+            // a panic in the decoder should fail this file, not the whole import
+            let converted = panic::catch_unwind(AssertUnwindSafe(|| {
+                convert_item(item, &output_dir, &options)
+            }));
+
+            let counter = match converted {
+                Ok(Ok(_)) => &progress.converted,
+                Ok(Err(_)) | Err(_) => &progress.failed,
+            };
+
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+
+    RAWBIT_OK
+}
+
+/// Fills in `progress` with how far along the import is.
+///
+/// # Safety
+///
+/// `import` must come from [`rawbit_import_new`] and not have been freed, and `progress` must
+/// point to a writable [`RawbitProgress`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rawbit_import_poll(
+    import: *const RawbitImport,
+    progress: *mut RawbitProgress,
+) -> c_int {
+    // SAFETY: valid, per the caller
+    let (Some(import), Some(out)) = (unsafe { import.as_ref() }, unsafe { progress.as_mut() })
+    else {
+        return fail("import or progress is null");
+    };
+
+    let progress = &import.progress;
+    *out = RawbitProgress {
+        total: import.items.len(),
+        finished: progress.finished.load(Ordering::Acquire),
+        converted: progress.converted.load(Ordering::Relaxed),
+        failed: progress.failed.load(Ordering::Relaxed),
+    };
+
+    RAWBIT_OK
+}
+
+/// Stops the import once the file being converted is done, without waiting for it.
+///
+/// # Safety
+///
+/// `import` must come from [`rawbit_import_new`] and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rawbit_import_cancel(import: *mut RawbitImport) {
+    // SAFETY: valid, per the caller
+    if let Some(import) = unsafe { import.as_ref() } {
+        import.progress.cancelled.store(true, Ordering::Relaxed);
+
+        // one that was never started has nothing left to do
+        if import.worker.is_none() {
+            import.progress.finished.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Cancels the import, waits for the file being converted, and frees it.
+///
+/// # Safety
+///
+/// `import` must be null or come from [`rawbit_import_new`], and not be used again.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rawbit_import_free(import: *mut RawbitImport) {
+    if import.is_null() {
+        return;
+    }
+
+    // SAFETY: owned by the caller until now, per the contract above
+    let mut import = unsafe { Box::from_raw(import) };
+    import.progress.cancelled.store(true, Ordering::Relaxed);
+
+    if let Some(worker) = import.worker.take() {
+        let _ = worker.join();
+    }
+}
+
+/// The last error on this thread, or null. Valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn rawbit_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |msg| msg.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod ffi_tests {
+    use std::{ffi::CString, fs, ptr, thread, time::Duration};
+
+    use super::{
+        RAWBIT_OK, RawbitProgress, rawbit_import_free, rawbit_import_len, rawbit_import_new,
+        rawbit_import_poll, rawbit_import_start, rawbit_last_error,
+    };
+    use crate::convert::convert_tests;
+
+    #[test]
+    fn plans_runs_and_polls_an_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let card = dir.path().join("card");
+        fs::create_dir(&card).unwrap();
+        fs::write(card.join("IMG_0001.DNG"), convert_tests::dng()).unwrap();
+        fs::write(card.join("IMG_0002.DNG"), b"not a raw").unwrap();
+
+        let in_dir = CString::new(card.to_str().unwrap()).unwrap();
+        let out_dir = CString::new(dir.path().join("out").to_str().unwrap()).unwrap();
+        let format = CString::new("%Y_").unwrap();
+
+        unsafe {
+            let import =
+                rawbit_import_new(in_dir.as_ptr(), out_dir.as_ptr(), format.as_ptr(), false);
+            assert!(!import.is_null());
+            assert_eq!(rawbit_import_len(import), 2);
+            assert_eq!(rawbit_import_start(import), RAWBIT_OK);
+            assert_ne!(rawbit_import_start(import), RAWBIT_OK);
+
+            let mut progress = RawbitProgress::default();
+            while !progress.finished {
+                thread::sleep(Duration::from_millis(10));
+                assert_eq!(rawbit_import_poll(import, &raw mut progress), RAWBIT_OK);
+            }
+
+            assert_eq!(
+                (progress.total, progress.converted, progress.failed),
+                (2, 1, 1)
+            );
+            assert!(dir.path().join("out/2024_IMG_0001.dng").is_file());
+
+            rawbit_import_free(import);
+
+            let missing = CString::new("/nonexistent/card").unwrap();
+            let import = rawbit_import_new(missing.as_ptr(), out_dir.as_ptr(), ptr::null(), false);
+            assert!(import.is_null());
+            assert!(!rawbit_last_error().is_null());
+        }
+    }
+
+    #[test]
+    fn fails_a_corrupt_raw_without_stopping_the_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let card = dir.path().join("card");
+        fs::create_dir(&card).unwrap();
+
+        let dng = convert_tests::dng();
+        fs::write(card.join("IMG_0001.DNG"), &dng[..dng.len() / 2]).unwrap();
+        fs::write(card.join("IMG_0002.DNG"), &dng).unwrap();
+
+        let in_dir = CString::new(card.to_str().unwrap()).unwrap();
+        let out_dir = CString::new(dir.path().join("out").to_str().unwrap()).unwrap();
+
+        unsafe {
+            let import = rawbit_import_new(in_dir.as_ptr(), out_dir.as_ptr(), ptr::null(), false);
+            assert_eq!(rawbit_import_start(import), RAWBIT_OK);
+
+            let mut progress = RawbitProgress::default();
+            while !progress.finished {
+                thread::sleep(Duration::from_millis(10));
+                assert_eq!(rawbit_import_poll(import, &raw mut progress), RAWBIT_OK);
+            }
+
+            assert_eq!((progress.converted, progress.failed), (1, 1));
+            rawbit_import_free(import);
+        }
+    }
+}
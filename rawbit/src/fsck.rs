@@ -0,0 +1,389 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    catalog,
+    common::{AppError, RawbitResult, map_err},
+    intents::{self, Intent, Unfinished},
+    journal::Journal,
+    originals::{self, Verdict},
+    provenance,
+    state::StateDirs,
+    transaction::{self, Transaction},
+};
+
+/// Something a crash left inconsistent, and what `rawbit fsck` does about it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Repair {
+    /// an output that was being written and doesn't read back, removed
+    Partial { path: PathBuf, source: PathBuf },
+    /// an output that was being written and reads back fine, or whose source is gone so it
+    /// can't be `checked`, kept
+    Unconfirmed { path: PathBuf, checked: bool },
+    /// a `--transactional` batch that never started committing, thrown away
+    RolledBack { staging: PathBuf },
+    /// a `--transactional` batch that was part way through committing, finished
+    RolledForward {
+        staging: PathBuf,
+        output_dir: PathBuf,
+    },
+    /// a catalog record of an output that's gone or was changed, dropped
+    StaleRecord { path: PathBuf },
+    /// an `--resume` journal cut short, removed
+    BrokenJournal { path: PathBuf },
+}
+
+/// What `rawbit fsck` found, and with `repair`, fixed.
+#[derive(Debug, Default, Serialize)]
+pub struct Fsck {
+    pub repairs: Vec<Repair>,
+    /// runs still going, whose logs were left alone, and the catalog with them
+    pub running: usize,
+    /// whether the repairs were made, or only listed
+    pub repaired: bool,
+}
+
+/// Reconciles the outputs, catalog and journals with what the intent logs of crashed runs say
+/// they were in the middle of, making the repairs if `repair` is set.
+pub fn fsck(state_dirs: &StateDirs, repair: bool) -> RawbitResult<Fsck> {
+    let (logs, running) = intents::unfinished(&state_dirs.intents_dir()?)?;
+    let mut repairs = vec![];
+
+    for log in &logs {
+        repairs.extend(reconcile(log, repair)?);
+
+        if repair {
+            log.remove()?;
+        }
+    }
+
+    // a running import could be appending to it
+    let written = written_by(&logs);
+    if running == 0 && !written.is_empty() {
+        repairs.extend(compact_catalog(
+            &state_dirs.existing_catalog_path()?,
+            &written,
+            repair,
+        )?);
+    }
+
+    repairs.extend(broken_journals(&state_dirs.journals_dir()?, repair)?);
+
+    Ok(Fsck {
+        repairs,
+        running,
+        repaired: repair,
+    })
+}
+
+/// Deals with everything one crashed run was in the middle of.
+fn reconcile(log: &Unfinished, repair: bool) -> RawbitResult<Vec<Repair>> {
+    let mut repairs = vec![];
+    let uncommitted = log.uncommitted();
+
+    for &(output_dir, staging) in &uncommitted {
+        if !staging.exists() {
+            continue;
+        }
+
+        if repair {
+            Transaction::new(output_dir.into(), staging.into()).commit()?;
+        }
+
+        repairs.push(Repair::RolledForward {
+            staging: staging.into(),
+            output_dir: output_dir.into(),
+        });
+    }
+
+    let mut staged = BTreeSet::new();
+
+    for (path, source) in log.unconfirmed() {
+        // a batch that didn't get as far as committing never reached the output directory
+        if let Some(staging) = transaction::staging_root(path) {
+            if !uncommitted.iter().any(|&(_, dir)| dir == staging) && staging.exists() {
+                staged.insert(staging);
+            }
+            continue;
+        }
+
+        if !path.is_file() {
+            continue;
+        }
+
+        // only an output that's known to be wrong is removed, it may be the only copy left
+        let verdict = originals::verdict(source, path);
+        if !matches!(verdict, Verdict::Broken(_)) {
+            repairs.push(Repair::Unconfirmed {
+                path: path.into(),
+                checked: verdict == Verdict::Intact,
+            });
+            continue;
+        }
+
+        if repair {
+            map_err!(
+                fs::remove_file(path),
+                AppError::Io,
+                format!("couldn't remove partial output: {}", path.display())
+            )?;
+        }
+
+        repairs.push(Repair::Partial {
+            path: path.into(),
+            source: source.into(),
+        });
+    }
+
+    for staging in staged {
+        if repair {
+            map_err!(
+                fs::remove_dir_all(staging),
+                AppError::Io,
+                format!("couldn't remove staging directory: {}", staging.display())
+            )?;
+        }
+
+        repairs.push(Repair::RolledBack {
+            staging: staging.into(),
+        });
+    }
+
+    Ok(repairs)
+}
+
+/// Every output the crashed runs in `logs` started writing, where it ends up: a staged one is
+/// where its batch would have committed it.
+fn written_by(logs: &[Unfinished]) -> BTreeSet<PathBuf> {
+    logs.iter()
+        .flat_map(|log| &log.intents)
+        .filter_map(|intent| match intent {
+            Intent::Write { path, .. } => Some(path),
+            _ => None,
+        })
+        .map(|path| {
+            let committed = transaction::staging_root(path)
+                .and_then(|staging| {
+                    let output_dir = staging.parent()?;
+                    Some(output_dir.join(path.strip_prefix(staging).ok()?))
+                })
+                .unwrap_or_else(|| path.clone());
+
+            std::path::absolute(&committed).unwrap_or(committed)
+        })
+        .collect()
+}
+
+/// Drops the catalog records of the `written` outputs that are gone or changed, rewriting the
+/// catalog in one step.
+///
+/// Only the outputs of crashed runs are checked, and only where their directory is there, one on
+/// a drive or share that isn't mounted isn't known to be gone.
+fn compact_catalog(
+    path: &Path,
+    written: &BTreeSet<PathBuf>,
+    repair: bool,
+) -> RawbitResult<Vec<Repair>> {
+    let (intact, stale): (Vec<_>, Vec<_>) =
+        catalog::entries(path)?.into_iter().partition(|entry| {
+            let output = &entry.output_path;
+
+            !written.contains(output)
+                || !output.parent().is_some_and(Path::is_dir)
+                || provenance::sha256_file(output).is_ok_and(|sha256| sha256 == entry.output_sha256)
+        });
+
+    if repair && !stale.is_empty() {
+        let partial = path.with_extension("jsonl.partial");
+
+        let written = File::create(&partial).and_then(|mut file| {
+            for entry in &intact {
+                let mut line = serde_json::to_vec(entry)?;
+                line.push(b'\n');
+                file.write_all(&line)?;
+            }
+
+            file.sync_all()?;
+            fs::rename(&partial, path)
+        });
+
+        map_err!(
+            written,
+            AppError::Io,
+            format!("couldn't rewrite catalog: {}", path.display())
+        )?;
+    }
+
+    Ok(stale
+        .into_iter()
+        .map(|entry| Repair::StaleRecord {
+            path: entry.output_path,
+        })
+        .collect())
+}
+
+/// `--resume` journals that don't read, since the crash cut them short.
+fn broken_journals(dir: &Path, repair: bool) -> RawbitResult<Vec<Repair>> {
+    let journals = map_err!(
+        fs::read_dir(dir).and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()
+        }),
+        AppError::Io,
+        format!("couldn't list journals: {}", dir.display())
+    )?;
+
+    let mut repairs = vec![];
+
+    for path in journals {
+        if path.extension().is_none_or(|ext| ext != "json") || Journal::read(&path).is_ok() {
+            continue;
+        }
+
+        if repair {
+            Journal::remove(&path)?;
+        }
+
+        repairs.push(Repair::BrokenJournal { path });
+    }
+
+    Ok(repairs)
+}
+
+#[cfg(test)]
+mod fsck_tests {
+    use std::fs;
+
+    use super::{Repair, fsck};
+    use crate::{
+        catalog::{Catalog, Entry},
+        intents::{Intent, Intents},
+        provenance::sha256_hex,
+        state::StateDirs,
+        transaction,
+    };
+
+    #[test]
+    fn repairs_what_a_crashed_run_left_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_dirs = StateDirs::resolve(Some(dir.path().join("state")), None);
+        let out = dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+
+        let source = dir.path().join("IMG_0001.HEIF");
+        fs::write(&source, b"heif").unwrap();
+
+        let catalog = Catalog::open(&state_dirs.catalog_path().unwrap()).unwrap();
+        let intents =
+            Intents::open(&state_dirs.intents_dir().unwrap(), "crashed", false, true).unwrap();
+
+        let copy = out.join("IMG_0001.HEIF");
+        let cut_short = out.join("IMG_0002.HEIF");
+        let staging = transaction::staging_dir(&out, "crashed");
+        fs::create_dir(&staging).unwrap();
+
+        for (path, contents) in [
+            (&copy, &b"heif"[..]),
+            (&cut_short, b"he"),
+            (&staging.join("IMG_0003.HEIF"), b"heif"),
+        ] {
+            intents.record(&Intent::Write {
+                path: path.clone(),
+                source: source.clone(),
+            });
+            fs::write(path, contents).unwrap();
+        }
+
+        // a copy whose source went away can't be checked, so it's left alone
+        let orphan = out.join("IMG_0004.HEIF");
+        intents.record(&Intent::Write {
+            path: orphan.clone(),
+            source: dir.path().join("IMG_0004-gone.HEIF"),
+        });
+        fs::write(&orphan, b"he").unwrap();
+
+        // one the crashed run was writing to a drive that isn't plugged in now
+        let offline = dir.path().join("nas/IMG_0005.HEIF");
+        intents.record(&Intent::Write {
+            path: offline.clone(),
+            source,
+        });
+
+        // only the outputs of the crashed run are checked, another import's is left alone
+        for output_path in [
+            out.join("IMG_0003.HEIF"),
+            offline.clone(),
+            out.join("IMG_0006.HEIF"),
+        ] {
+            catalog
+                .record(Entry {
+                    source_sha256: sha256_hex(b"heif"),
+                    params_sha256: "params".into(),
+                    output_path,
+                    output_sha256: sha256_hex(b"heif"),
+                })
+                .unwrap();
+        }
+        fs::write(
+            state_dirs
+                .journals_dir()
+                .unwrap()
+                .join("interrupted-1.json"),
+            "{\"out",
+        )
+        .unwrap();
+
+        // the run dies before it gets to commit its batch
+        drop((intents, catalog));
+
+        let found = fsck(&state_dirs, false).unwrap();
+        let kinds = found
+            .repairs
+            .iter()
+            .map(|repair| match repair {
+                Repair::Partial { .. } => "partial",
+                Repair::Unconfirmed { .. } => "unconfirmed",
+                Repair::RolledBack { .. } => "rolled back",
+                Repair::RolledForward { .. } => "rolled forward",
+                Repair::StaleRecord { .. } => "stale record",
+                Repair::BrokenJournal { .. } => "broken journal",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                "unconfirmed",
+                "partial",
+                "unconfirmed",
+                "rolled back",
+                "stale record",
+                "broken journal"
+            ]
+        );
+        assert!(cut_short.exists());
+
+        let repaired = fsck(&state_dirs, true).unwrap();
+        assert_eq!(repaired.repairs, found.repairs);
+        assert!(copy.exists());
+        assert!(!cut_short.exists());
+        assert!(orphan.exists());
+        assert!(!staging.exists());
+
+        let kept = crate::catalog::entries(&state_dirs.catalog_path().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.output_path)
+            .collect::<Vec<_>>();
+        assert_eq!(kept, [offline, out.join("IMG_0006.HEIF")]);
+
+        assert!(fsck(&state_dirs, true).unwrap().repairs.is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use globset::{GlobBuilder, GlobMatcher};
+use smlog::warn;
+
+/// A file of gitignore-style patterns in a source directory, leaving what they match out of the
+/// import so reject lists travel with the folder.
+pub const IGNORE_FILE: &str = ".rawbitignore";
+
+#[derive(Debug)]
+struct Rule {
+    glob: GlobMatcher,
+    /// `!pattern`, brings back something an earlier rule ignored
+    negated: bool,
+    /// `pattern/`, only matches directories
+    dir_only: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Result<Self, globset::Error>> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, pattern) = line
+            .strip_prefix('!')
+            .map_or((false, line), |rest| (true, rest));
+
+        // `\#` and `\!` start patterns that would otherwise be a comment or negated
+        let pattern = pattern.strip_prefix('\\').unwrap_or(pattern);
+
+        let (dir_only, pattern) = pattern
+            .strip_suffix('/')
+            .map_or((false, pattern), |rest| (true, rest));
+
+        // a pattern with a slash in it is relative to the ignore file, otherwise it matches at
+        // any depth
+        let glob = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if pattern.contains('/') => pattern.to_string(),
+            None => format!("**/{pattern}"),
+        };
+
+        let glob = GlobBuilder::new(&glob)
+            .literal_separator(true)
+            .build()
+            .map(|glob| glob.compile_matcher());
+
+        Some(glob.map(|glob| Self {
+            glob,
+            negated,
+            dir_only,
+        }))
+    }
+}
+
+/// The rules of one ignore file, matched against paths relative to the directory it's in.
+#[derive(Debug)]
+struct Layer {
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+/// The ignore files that apply somewhere in a directory scan, the one in the directory being
+/// scanned and those in the directories above it, up to where the scan started.
+#[derive(Debug, Clone, Default)]
+pub struct Ignores(Vec<Arc<Layer>>);
+
+impl Ignores {
+    /// The rules for `dir`, these plus its own ignore file if it has one. `relative` is where
+    /// `dir` is under the directory the scan started in.
+    #[must_use]
+    pub fn descend(&self, dir: &Path, relative: &Path) -> Self {
+        let path = dir.join(IGNORE_FILE);
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return self.clone();
+        };
+
+        let rules = contents
+            .lines()
+            .filter_map(Rule::parse)
+            .filter_map(|rule| {
+                rule.map_err(|err| warn!("ignoring a pattern in \"{}\": {err}", path.display()))
+                    .ok()
+            })
+            .collect();
+
+        let mut layers = self.0.clone();
+        layers.push(Arc::new(Layer {
+            base: relative.to_path_buf(),
+            rules,
+        }));
+
+        Self(layers)
+    }
+
+    /// The rules for the directory `relative` under `root`, as a scan from `root` would have
+    /// gathered them on the way down, none if the scan wouldn't have gone into it.
+    #[must_use]
+    pub fn for_dir(root: &Path, relative: &Path) -> Option<Self> {
+        let mut ignores = Self::default().descend(root, Path::new(""));
+        let mut walked = PathBuf::new();
+
+        for component in relative {
+            walked.push(component);
+
+            if ignores.ignores(&walked, true) {
+                return None;
+            }
+
+            ignores = ignores.descend(&root.join(&walked), &walked);
+        }
+
+        Some(ignores)
+    }
+
+    /// Whether `relative`, under the directory the scan started in, should be left out. The
+    /// deepest ignore file has the last word, and within a file the last matching pattern does.
+    #[must_use]
+    pub fn ignores(&self, relative: &Path, is_dir: bool) -> bool {
+        for layer in self.0.iter().rev() {
+            let Ok(path) = relative.strip_prefix(&layer.base) else {
+                continue;
+            };
+
+            let decided = layer
+                .rules
+                .iter()
+                .rev()
+                .find(|rule| (is_dir || !rule.dir_only) && rule.glob.is_match(path));
+
+            if let Some(rule) = decided {
+                return !rule.negated;
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod ignore_tests {
+    use std::{fs, path::Path};
+
+    use super::{IGNORE_FILE, Ignores};
+
+    #[test]
+    fn applies_gitignore_style_rules_per_subtree() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("day1/rejects")).unwrap();
+
+        fs::write(
+            root.join(IGNORE_FILE),
+            "# blurry ones\n*_blur.ARW\nrejects/\n/TOP.ARW\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("day1").join(IGNORE_FILE),
+            "!keep_blur.ARW\nbad/*.ARW\n",
+        )
+        .unwrap();
+
+        let top = Ignores::default().descend(root, Path::new(""));
+        assert!(top.ignores(Path::new("IMG_0001_blur.ARW"), false));
+        assert!(top.ignores(Path::new("TOP.ARW"), false));
+        assert!(!top.ignores(Path::new("day1/TOP.ARW"), false));
+        assert!(top.ignores(Path::new("day1/rejects"), true));
+        assert!(!top.ignores(Path::new("rejects"), false));
+
+        assert!(Ignores::for_dir(root, Path::new("day1/rejects")).is_none());
+
+        let day1 = Ignores::for_dir(root, Path::new("day1")).unwrap();
+        assert!(day1.ignores(Path::new("day1/a_blur.ARW"), false));
+        assert!(!day1.ignores(Path::new("day1/keep_blur.ARW"), false));
+        assert!(day1.ignores(Path::new("day1/bad/IMG_0001.ARW"), false));
+        assert!(!day1.ignores(Path::new("day1/bad/deeper/IMG_0001.ARW"), false));
+        assert!(!day1.ignores(Path::new("IMG_0001.ARW"), false));
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    args::RawSource,
+    artist,
+    clock::{DateSource, Offset, TimeShift, Zone},
+    common::{AppError, RawbitResult, map_err},
+    config::Config,
+    conflict::OnConflict,
+    decode::DecodeParam,
+    lanes::Priority,
+    layout::Layout,
+    parse::Var,
+    paths,
+};
+
+/// Several independent imports described by a `--batch` spec file.
+///
+/// ```toml
+/// concurrent = true
+///
+/// [[import]]
+/// name = "client a"
+/// in-dir = "/media/card-a/DCIM"
+/// out-dir = "/photos/client-a"
+/// format = "%Y-%m-%d_{image.original_filename}"
+/// lens = "Helios 44-2"
+///
+/// [[import]]
+/// files = ["/media/card-b/DCIM/100CANON/IMG_0001.CR3"]
+/// out-dir = "/photos/client-b"
+/// priority = "urgent"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchSpec {
+    /// run the imports at the same time rather than one after another
+    #[serde(default)]
+    pub concurrent: bool,
+
+    #[serde(rename = "import")]
+    pub imports: Vec<ImportSpec>,
+}
+
+/// One import in a [`BatchSpec`], anything left unset falls back to the command line.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ImportSpec {
+    pub name: Option<String>,
+    pub in_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+    pub out_dir: PathBuf,
+    pub format: Option<String>,
+    pub layout: Option<Layout>,
+    #[serde(default, deserialize_with = "artist::one_or_many")]
+    pub artist: Vec<String>,
+    pub lens: Option<String>,
+    #[serde(default)]
+    pub time_shift_for: Vec<TimeShift>,
+    pub time_shift: Option<Offset>,
+    pub date_source: Option<DateSource>,
+    pub timezone: Option<Zone>,
+    #[serde(default)]
+    pub decode_param: Vec<DecodeParam>,
+    /// variables for the format, like `--set`, over the command line's
+    #[serde(default)]
+    pub set: Vec<Var>,
+    #[serde(default)]
+    pub copy: Vec<String>,
+    pub recurse: Option<bool>,
+    pub force: Option<bool>,
+    pub on_conflict: Option<OnConflict>,
+    /// when running concurrently, how this import's files are queued for the shared workers
+    #[serde(default)]
+    pub priority: Priority,
+    /// keep watching `in-dir` after the first pass, like `--watch`, only in a concurrent batch
+    #[serde(default)]
+    pub watch: bool,
+}
+
+impl BatchSpec {
+    /// Reads a spec file, relative paths in it are taken relative to the file itself.
+    pub fn load(path: &Path) -> RawbitResult<Self> {
+        let contents = map_err!(
+            fs::read_to_string(path),
+            AppError::Io,
+            format!("couldn't read batch spec {}", path.display())
+        )?;
+
+        let invalid =
+            |msg: String| AppError::Config(format!("invalid batch spec {}: {msg}", path.display()));
+
+        let mut spec: Self = toml::from_str(&contents).map_err(|err| invalid(err.to_string()))?;
+
+        if spec.imports.is_empty() {
+            return Err(invalid("no [[import]] entries".into()));
+        }
+
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+        for (i, import) in spec.imports.iter_mut().enumerate() {
+            if import.in_dir.is_some() != import.files.is_empty() {
+                return Err(invalid(format!(
+                    "{} needs exactly one of `in-dir` or `files`",
+                    import.label(i)
+                )));
+            }
+
+            if import.watch && (import.in_dir.is_none() || !spec.concurrent) {
+                return Err(invalid(format!(
+                    "{} can only be watched with `in-dir`, in a `concurrent` batch",
+                    import.label(i)
+                )));
+            }
+
+            import.in_dir = import
+                .in_dir
+                .take()
+                .map(|dir| base.join(paths::drive_root(dir)));
+            import.out_dir = base.join(&import.out_dir);
+            import.files = import.files.drain(..).map(|file| base.join(file)).collect();
+        }
+
+        Ok(spec)
+    }
+}
+
+impl ImportSpec {
+    /// The import's name, or its position in the spec file if it doesn't have one.
+    #[must_use]
+    pub fn label(&self, index: usize) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("import #{}", index + 1))
+    }
+
+    #[must_use]
+    pub fn source(&self) -> RawSource {
+        RawSource {
+            input_dir: self.in_dir.clone(),
+            files: (!self.files.is_empty()).then(|| self.files.clone()),
+            ..Default::default()
+        }
+    }
+
+    /// The spec's own settings win over the command line's.
+    #[must_use]
+    pub fn settings(&self, cli: &Config) -> Config {
+        cli.clone().under(&Config {
+            format: self.format.clone(),
+            layout: self.layout,
+            artist: self.artist.clone(),
+            lens: self.lens.clone(),
+            time_shifts: self.time_shift_for.clone(),
+            time_shift: self.time_shift,
+            date_source: self.date_source,
+            timezone: self.timezone,
+            decode_params: self.decode_param.clone(),
+            vars: self.set.clone(),
+            copy: self.copy.clone(),
+            ..Default::default()
+        })
+    }
+
+    /// The import's own `on-conflict`, or the policy its `force` stands for.
+    #[must_use]
+    pub fn conflict_policy(&self) -> Option<OnConflict> {
+        self.on_conflict
+            .or_else(|| self.force.map(OnConflict::forced))
+    }
+}
+
+#[cfg(test)]
+mod imports_tests {
+    use std::{fs, path::Path};
+
+    use super::BatchSpec;
+    use crate::{config::Config, lanes::Priority};
+
+    fn load(contents: &str) -> Result<BatchSpec, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("imports.toml");
+        fs::write(&path, contents).unwrap();
+
+        BatchSpec::load(&path).map_err(|err| err.describe().0)
+    }
+
+    #[test]
+    fn resolves_paths_against_the_spec_file() {
+        let spec = load(
+            r#"
+            [[import]]
+            in-dir = "card"
+            out-dir = "/abs/out"
+            "#,
+        )
+        .unwrap();
+
+        let import = &spec.imports[0];
+        assert!(import.in_dir.as_deref().unwrap().ends_with("card"));
+        assert!(import.in_dir.as_deref().unwrap().is_absolute());
+        assert_eq!(import.out_dir, Path::new("/abs/out"));
+        assert!(!spec.concurrent);
+        assert_eq!(import.priority, Priority::Normal);
+    }
+
+    #[test]
+    fn requires_exactly_one_source() {
+        let err = load(
+            r#"
+            [[import]]
+            name = "both"
+            in-dir = "a"
+            files = ["b.ARW"]
+            out-dir = "out"
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.contains("both"));
+
+        assert!(load("[[import]]\nout-dir = \"out\"\n").is_err());
+        assert!(load("concurrent = true\n").is_err());
+    }
+
+    #[test]
+    fn watches_only_a_directory_in_a_concurrent_batch() {
+        let spec = load(
+            r#"
+            concurrent = true
+
+            [[import]]
+            in-dir = "hot"
+            out-dir = "out"
+            watch = true
+            priority = "urgent"
+            "#,
+        )
+        .unwrap();
+        assert!(spec.imports[0].watch);
+        assert_eq!(spec.imports[0].priority, Priority::Urgent);
+
+        let err =
+            load("[[import]]\nname = \"hot\"\nin-dir = \"a\"\nout-dir = \"out\"\nwatch = true\n")
+                .unwrap_err();
+        assert!(err.contains("hot"));
+
+        assert!(
+            load("concurrent = true\n[[import]]\nfiles = [\"a.ARW\"]\nout-dir = \"out\"\nwatch = true\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn spec_settings_override_the_command_line() {
+        let spec = load(
+            r#"
+            [[import]]
+            in-dir = "a"
+            out-dir = "out"
+            artist = "Spec"
+            "#,
+        )
+        .unwrap();
+
+        let settings = spec.imports[0].settings(&Config {
+            format: Some("cli".into()),
+            artist: vec!["Cli".into()],
+            ..Default::default()
+        });
+
+        assert_eq!(settings.format.as_deref(), Some("cli"));
+        assert_eq!(settings.artist, ["Spec"]);
+    }
+}
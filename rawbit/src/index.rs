@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use chrono::NaiveDateTime;
+use rawler::{decoders::RawDecodeParams, get_decoder, rawsource::RawSource};
+use rayon::iter::{IntoParallelIterator as _, ParallelIterator as _};
+use serde::{Deserialize, Serialize};
+use smlog::warn;
+
+use crate::{
+    common::{AppError, RawbitResult, map_err},
+    provenance,
+};
+
+/// Written at the root of the output directory by `--index`.
+pub const INDEX_FILE: &str = "index.json";
+
+const EXIF_DT_FMT: &str = "%Y:%m:%d %H:%M:%S";
+
+/// One DNG in the output tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// relative to the output root, always `/` separated
+    pub path: String,
+    /// `YYYY-MM-DDTHH:MM:SS`, in the camera's local time
+    pub captured: Option<String>,
+    pub make: String,
+    pub model: String,
+    pub sha256: String,
+    /// the run that wrote it, missing for DNGs rawbit didn't write
+    pub run_id: Option<String>,
+    pub size: u64,
+    /// seconds since the epoch, with `size` tells whether an entry is still current
+    pub modified: u64,
+}
+
+/// Every DNG under an output directory, for scripts and gallery generators that shouldn't have
+/// to read each file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Index {
+    pub files: Vec<Entry>,
+}
+
+impl Index {
+    /// Brings `root`'s index up to date with the DNGs under it, returning how many there are.
+    ///
+    /// Files whose size and modification time match the previous index are carried over as they
+    /// were, everything else is read again.
+    pub fn refresh(root: &Path) -> RawbitResult<usize> {
+        let path = root.join(INDEX_FILE);
+        let mut previous = Self::read(&path)
+            .files
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect::<HashMap<_, _>>();
+
+        let mut dngs = vec![];
+        map_err!(
+            find_dngs(root, &mut dngs),
+            AppError::Io,
+            format!("couldn't list output directory: {}", root.display())
+        )?;
+
+        let candidates = dngs
+            .into_iter()
+            .map(|dng| {
+                let relative = relative_path(root, &dng);
+                let known = previous.remove(&relative);
+                (dng, relative, known)
+            })
+            .collect::<Vec<_>>();
+
+        let mut files = candidates
+            .into_par_iter()
+            .filter_map(
+                |(dng, relative, known)| match describe(&dng, relative, known) {
+                    Ok(entry) => Some(entry),
+                    Err(err) => {
+                        warn!("couldn't index \"{}\": {err}", dng.display());
+                        None
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let count = files.len();
+
+        Self { files }.write(&path)?;
+        Ok(count)
+    }
+
+    /// The index at `path`, or an empty one if there isn't a readable one there.
+    fn read(path: &Path) -> Self {
+        let Ok(file) = File::open(path) else {
+            return Self::default();
+        };
+
+        serde_json::from_reader(io::BufReader::new(file)).unwrap_or_else(|err| {
+            warn!(
+                "couldn't read the previous index, rebuilding it: {}: {err}",
+                path.display()
+            );
+
+            Self::default()
+        })
+    }
+
+    /// Replaces the index at `path` in one step, so readers never see half of it.
+    fn write(&self, path: &Path) -> RawbitResult<()> {
+        let partial = path.with_extension("json.partial");
+
+        let file = map_err!(
+            File::create(&partial),
+            AppError::Io,
+            format!("couldn't create index: {}", partial.display())
+        )?;
+
+        let mut writer = io::BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self).map_err(|e| {
+            AppError::Other(
+                format!("couldn't write index: {}", partial.display()),
+                Box::new(e),
+            )
+        })?;
+
+        // on disk before it replaces the old one, or a crash could leave neither
+        map_err!(
+            writer
+                .into_inner()
+                .map_err(io::IntoInnerError::into_error)
+                .and_then(|file| file.sync_all()),
+            AppError::Io,
+            format!("couldn't write index: {}", partial.display())
+        )?;
+
+        map_err!(
+            fs::rename(&partial, path),
+            AppError::Io,
+            format!("couldn't replace index: {}", path.display())
+        )
+    }
+}
+
+fn find_dngs(dir: &Path, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    for item in fs::read_dir(dir)? {
+        let path = item?.path();
+
+        if path.is_dir() {
+            find_dngs(&path, found)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("dng"))
+        {
+            found.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The entry for the DNG at `path`, reusing `known` if the file hasn't changed since.
+fn describe(path: &Path, relative: String, known: Option<Entry>) -> io::Result<Entry> {
+    let stat = fs::metadata(path)?;
+    let size = stat.len();
+    let modified = stat
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    if let Some(entry) = known.filter(|entry| (entry.size, entry.modified) == (size, modified)) {
+        return Ok(entry);
+    }
+
+    let src = RawSource::new(path).map_err(io::Error::other)?;
+    let md = get_decoder(&src)
+        .and_then(|decoder| decoder.raw_metadata(&src, &RawDecodeParams::default()))
+        .map_err(io::Error::other)?;
+
+    let captured = md
+        .exif
+        .date_time_original
+        .as_deref()
+        .and_then(|date| NaiveDateTime::parse_from_str(date, EXIF_DT_FMT).ok())
+        .map(|date| date.format("%Y-%m-%dT%H:%M:%S").to_string());
+
+    Ok(Entry {
+        path: relative,
+        captured,
+        make: md.make,
+        model: md.model,
+        sha256: provenance::sha256_file(path)?,
+        run_id: provenance::read_run_id(&mut File::open(path)?)?,
+        size,
+        modified,
+    })
+}
+
+#[cfg(test)]
+mod index_tests {
+    use std::fs;
+
+    use super::{INDEX_FILE, Index};
+    use crate::convert::{Options, convert_file, convert_tests};
+
+    #[test]
+    fn indexes_the_output_tree_and_drops_removed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("IMG_0001.DNG");
+        fs::write(&input, convert_tests::dng()).unwrap();
+
+        let out = dir.path().join("out");
+        let options = Options::new("%Y_").unwrap();
+        let outcome = convert_file(&input, &out.join("card1"), &options).unwrap();
+
+        assert_eq!(Index::refresh(&out).unwrap(), 1);
+
+        let index: Index =
+            serde_json::from_slice(&fs::read(out.join(INDEX_FILE)).unwrap()).unwrap();
+        let entry = &index.files[0];
+
+        assert_eq!(entry.path, "card1/2024_IMG_0001.dng");
+        assert_eq!(entry.captured.as_deref(), Some("2024-05-06T07:08:09"));
+        assert_eq!(entry.run_id.as_deref(), Some(&*options.run_id));
+
+        fs::remove_file(outcome.output_path).unwrap();
+        assert_eq!(Index::refresh(&out).unwrap(), 0);
+    }
+}
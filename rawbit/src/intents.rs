@@ -0,0 +1,323 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::{self, Write as _},
+    path::{self, Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use smlog::warn;
+
+use crate::common::{AppError, RawbitResult, map_err};
+
+/// One step of a run, written down before it's taken and once it's done, so `rawbit fsck` can
+/// tell what a crash left half finished.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "intent", rename_all = "kebab-case")]
+pub enum Intent {
+    /// `path` is about to be written from `source`
+    Write { path: PathBuf, source: PathBuf },
+    /// `path` is complete, and with `--fsync`, on disk
+    Written { path: PathBuf },
+    /// a `--transactional` batch staged in `staging` is about to be moved into `output_dir`
+    Commit {
+        output_dir: PathBuf,
+        staging: PathBuf,
+    },
+    /// the batch staged in `staging` is in place
+    Committed { staging: PathBuf },
+}
+
+impl Intent {
+    /// The same intent with absolute paths, so `rawbit fsck` can be run from anywhere.
+    fn absolute(&self) -> Self {
+        let absolute = |path: &PathBuf| path::absolute(path).unwrap_or_else(|_| path.clone());
+
+        match self {
+            Self::Write { path, source } => Self::Write {
+                path: absolute(path),
+                source: absolute(source),
+            },
+            Self::Written { path } => Self::Written {
+                path: absolute(path),
+            },
+            Self::Commit {
+                output_dir,
+                staging,
+            } => Self::Commit {
+                output_dir: absolute(output_dir),
+                staging: absolute(staging),
+            },
+            Self::Committed { staging } => Self::Committed {
+                staging: absolute(staging),
+            },
+        }
+    }
+}
+
+/// A run's intent log, a JSON line for each [`Intent`].
+///
+/// It's appended to by the run and its isolated workers, and removed once the run's over. One
+/// that's still there after its run is gone is what a crash left behind. The run that owns it holds a lock on a file next to it for as long as it runs.
+#[derive(Debug)]
+pub struct Intents {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// flush each intent to disk before the write it announces, see `--fsync`
+    fsync: bool,
+    /// held by the run that owns the log, `None` in an isolated worker
+    lock: Option<(PathBuf, File)>,
+}
+
+impl Intents {
+    /// Opens the log for the run `run_id` in `dir`, locking it unless this is one of the run's
+    /// isolated workers.
+    pub fn open(dir: &Path, run_id: &str, fsync: bool, owner: bool) -> RawbitResult<Self> {
+        let path = dir.join(format!("{run_id}.jsonl"));
+
+        let lock = owner
+            .then(|| {
+                let lock_path = path.with_extension("lock");
+                let lock = File::create(&lock_path).and_then(|file| {
+                    file.lock()?;
+                    Ok(file)
+                });
+
+                map_err!(
+                    lock,
+                    AppError::Io,
+                    format!("couldn't lock intent log: {}", lock_path.display())
+                )
+                .map(|lock| (lock_path, lock))
+            })
+            .transpose()?;
+
+        let file = map_err!(
+            OpenOptions::new().create(true).append(true).open(&path),
+            AppError::Io,
+            format!("couldn't open intent log: {}", path.display())
+        )?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            fsync,
+            lock,
+        })
+    }
+
+    /// Records `intent`. A log that can't be written to only costs `rawbit fsck` its view of
+    /// this run, so it's a warning rather than a failure.
+    pub fn record(&self, intent: &Intent) {
+        let recorded = serde_json::to_vec(&intent.absolute())
+            .map_err(io::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+
+                // one write per line, so the run's workers don't interleave within it
+                let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+                file.write_all(&line)?;
+
+                if self.fsync {
+                    file.sync_data()?;
+                }
+                drop(file);
+
+                Ok(())
+            });
+
+        if let Err(err) = recorded {
+            warn!("couldn't write to {}: {err}", self.path.display());
+        }
+    }
+
+    /// Removes the log of a run that got to its end, whatever became of its files. The lock is
+    /// let go with the process.
+    pub fn close(&self) {
+        let Some((ref lock_path, _)) = self.lock else {
+            return;
+        };
+
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("couldn't remove {}: {err}", self.path.display());
+        }
+
+        let _ = fs::remove_file(lock_path);
+    }
+}
+
+/// An intent log a run left behind.
+#[derive(Debug)]
+pub struct Unfinished {
+    pub path: PathBuf,
+    pub intents: Vec<Intent>,
+}
+
+impl Unfinished {
+    /// Writes that were started and never confirmed, in the order they were started.
+    #[must_use]
+    pub fn unconfirmed(&self) -> Vec<(&Path, &Path)> {
+        let written = self
+            .intents
+            .iter()
+            .filter_map(|intent| match intent {
+                Intent::Written { path } => Some(path.as_path()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+
+        self.intents
+            .iter()
+            .filter_map(|intent| match intent {
+                Intent::Write { path, source } if !written.contains(path.as_path()) => {
+                    Some((path.as_path(), source.as_path()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// `--transactional` commits that were started and never finished, as the output directory
+    /// and staging directory of each.
+    #[must_use]
+    pub fn uncommitted(&self) -> Vec<(&Path, &Path)> {
+        let committed = self
+            .intents
+            .iter()
+            .filter_map(|intent| match intent {
+                Intent::Committed { staging } => Some(staging.as_path()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+
+        self.intents
+            .iter()
+            .filter_map(|intent| match intent {
+                Intent::Commit {
+                    output_dir,
+                    staging,
+                } if !committed.contains(staging.as_path()) => {
+                    Some((output_dir.as_path(), staging.as_path()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Removes the log once what it describes has been dealt with.
+    pub fn remove(&self) -> RawbitResult<()> {
+        map_err!(
+            fs::remove_file(&self.path),
+            AppError::Io,
+            format!("couldn't remove intent log: {}", self.path.display())
+        )?;
+
+        let _ = fs::remove_file(self.path.with_extension("lock"));
+        Ok(())
+    }
+}
+
+/// The intent logs in `dir` left behind by runs that aren't running anymore, and how many belong
+/// to runs that still are.
+pub fn unfinished(dir: &Path) -> RawbitResult<(Vec<Unfinished>, usize)> {
+    let mut logs = map_err!(
+        fs::read_dir(dir),
+        AppError::Io,
+        format!("couldn't list intent logs: {}", dir.display())
+    )?
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+    .collect::<Vec<_>>();
+
+    logs.sort();
+
+    let mut unfinished = vec![];
+    let mut running = 0;
+
+    for path in logs {
+        if is_running(&path) {
+            running += 1;
+            continue;
+        }
+
+        let contents = map_err!(
+            fs::read_to_string(&path),
+            AppError::Io,
+            format!("couldn't read intent log: {}", path.display())
+        )?;
+
+        // a line cut short by the crash is a step that was never taken
+        let intents = contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+
+        unfinished.push(Unfinished { path, intents });
+    }
+
+    Ok((unfinished, running))
+}
+
+/// Whether the run that owns the log at `path` still holds its lock.
+fn is_running(path: &Path) -> bool {
+    File::open(path.with_extension("lock")).is_ok_and(|lock| lock.try_lock().is_err())
+}
+
+#[cfg(test)]
+mod intents_tests {
+    use super::{Intent, Intents, unfinished};
+
+    #[test]
+    fn leaves_unconfirmed_writes_for_fsck() {
+        let dir = tempfile::tempdir().unwrap();
+        let at = |path: &str| dir.path().join(path);
+
+        let finished = Intents::open(dir.path(), "finished", false, true).unwrap();
+        finished.record(&Intent::Write {
+            path: at("out/a.dng"),
+            source: at("in/a.CR3"),
+        });
+        finished.close();
+
+        let crashed = Intents::open(dir.path(), "crashed", false, true).unwrap();
+        // one of its isolated workers
+        Intents::open(dir.path(), "crashed", false, false)
+            .unwrap()
+            .record(&Intent::Write {
+                path: at("out/b.dng"),
+                source: at("in/b.CR3"),
+            });
+        crashed.record(&Intent::Written {
+            path: at("out/b.dng"),
+        });
+        crashed.record(&Intent::Write {
+            path: at("out/c.dng"),
+            source: at("in/c.CR3"),
+        });
+        crashed.record(&Intent::Commit {
+            output_dir: at("out"),
+            staging: at("out/.staging"),
+        });
+
+        let (logs, running) = unfinished(dir.path()).unwrap();
+        assert_eq!((logs.len(), running), (0, 1));
+
+        // the process dies, and its lock with it
+        drop(crashed);
+
+        let (logs, running) = unfinished(dir.path()).unwrap();
+        assert_eq!((logs.len(), running), (1, 0));
+        assert_eq!(
+            logs[0].unconfirmed(),
+            [(at("out/c.dng").as_path(), at("in/c.CR3").as_path())]
+        );
+        assert_eq!(
+            logs[0].uncommitted(),
+            [(at("out").as_path(), at("out/.staging").as_path())]
+        );
+
+        logs[0].remove().unwrap();
+        assert_eq!(unfinished(dir.path()).unwrap().0.len(), 0);
+    }
+}
@@ -1,13 +1,25 @@
 use std::{
+    any::Any,
+    borrow::Cow,
+    env::{current_exe, temp_dir},
     error,
-    fs::{create_dir_all, remove_file},
-    path::{Path, PathBuf},
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    fs::{self as std_fs, FileTimes, remove_file},
+    io::{BufWriter, IntoInnerError, Seek as _, Write as _},
+    panic::{self, AssertUnwindSafe},
+    path::{self, Path, PathBuf},
+    process,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime},
 };
 
-use tokio::{
-    fs::OpenOptions,
-    io::{self, AsyncReadExt as _},
-};
+use chrono::{NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io, process::Command, task::JoinError};
 
 use async_trait::async_trait;
 use rawler::{
@@ -18,145 +30,1542 @@ use rawler::{
     rawsource::RawSource,
 };
 
-use smlog::info;
+use smlog::{debug, info, warn};
+use tracing::{Instrument as _, Span, info_span};
 
-use crate::{common::map_err, parse::FilenameFormat};
+use crate::{
+    archive::Member,
+    args,
+    catalog::{self, Catalog},
+    clock::{self, Clocks},
+    common::map_err,
+    conflict::{Claims, OnConflict},
+    drive::{self, DriveMode},
+    durable,
+    intents::{Intent, Intents},
+    lens::{Lens, Lenses},
+    link::LinkArchive,
+    metadata::MetadataPolicy,
+    parse::{
+        self, ChronoIndex, FilenameFormat, ImageInfo, MetadataKind, RenderContext, RenderedFilename,
+    },
+    perms::Permissions,
+    provenance::{self, Digest, Provenance},
+    reprocess::{self, EmbeddedOriginal},
+    salvage::{self, ConvertFallback, Salvaged, Strategy},
+    sidecar::{self, JpegPolicy},
+    stem, video,
+};
 
 #[derive(Debug)]
 pub enum Error {
     ImgOp(String, RawlerError),
     Io(String, io::Error),
+    DestUnavailable(String, io::Error),
     AlreadyExists(String),
-    #[allow(unused)]
+    /// the format couldn't be filled in completely with `--strict-format`, or a `regex` in it
+    /// didn't match
+    Unresolved(String),
+    Panicked(String),
+    Isolated(String),
     Other(String, Box<dyn error::Error + Send + Sync>),
 }
 
+impl Error {
+    #[must_use]
+    pub fn describe(&self) -> (&str, Option<&dyn Display>) {
+        use Error::*;
+
+        match self {
+            AlreadyExists(err_str)
+            | Unresolved(err_str)
+            | Panicked(err_str)
+            | Isolated(err_str) => (err_str, None),
+            Io(err_str, cause) | DestUnavailable(err_str, cause) => (err_str, Some(cause)),
+            ImgOp(err_str, cause) => (err_str, Some(cause)),
+            Other(err_str, cause) => (err_str, Some(cause)),
+        }
+    }
+
+    /// The error and its cause, on one line.
+    #[must_use]
+    pub fn message(&self) -> String {
+        let (err_str, cause) = self.describe();
+
+        cause.map_or_else(
+            || err_str.to_string(),
+            |cause| format!("{err_str}: {cause}"),
+        )
+    }
+}
+
+#[must_use]
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(ToString::to_string)
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".into())
+}
+
+fn join_error(err: JoinError) -> Error {
+    if err.is_panic() {
+        Error::Panicked(panic_message(&*err.into_panic()))
+    } else {
+        Error::Other("async error".into(), Box::new(err))
+    }
+}
+
+// errors that mean the whole destination went away (read-only remount, dropped share), as opposed
+// to a problem with this one file, like one it isn't allowed to replace or `--chown`
+fn dest_io_error(msg: String, err: io::Error) -> Error {
+    use io::ErrorKind::*;
+
+    match err.kind() {
+        ReadOnlyFilesystem | StaleNetworkFileHandle | NotConnected => {
+            Error::DestUnavailable(msg, err)
+        }
+
+        _ => Error::Io(msg, err),
+    }
+}
+
+/// Where `--unsorted` puts files whose format came out blank, inside the output directory.
+pub const UNSORTED_DIR: &str = "_unsorted";
+
+/// Where `--fallback extract-jpeg` puts the previews of files that couldn't be converted, inside
+/// the output directory.
+pub const FALLBACK_DIR: &str = "fallback";
+
+/// Something worth a second look about a file that was otherwise imported fine.
+///
+/// Each kind is serialized with a `code` that stays the same between releases, so tools reading
+/// `--report` or `--json` output can match on it rather than on the message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "code", rename_all = "kebab-case")]
+pub enum Warning {
+    /// none of the format's expansions could be filled in from the file's metadata, so it was
+    /// named after the original alone
+    BlankFormat {
+        missing: Vec<String>,
+        /// moved into [`UNSORTED_DIR`] by `--unsorted`
+        unsorted: bool,
+    },
+    /// the metadata couldn't be read, so `--salvage` named the file without it
+    MissingMetadata { reason: String },
+    /// the decoder failed and `--salvage` recovered a DNG another way
+    SalvagedDecode { strategy: Strategy, reason: String },
+    /// the decoder failed and only the camera's embedded preview JPEG could be kept
+    FallbackPreview { reason: String },
+    /// another file in the batch was given the same name first, so this one was numbered
+    Numbered { name: String },
+    /// the raw couldn't be linked into the `--link-originals` archive
+    LinkFailed { reason: String },
+}
+
+impl Warning {
+    /// The warning's stable code, as it's serialized.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::BlankFormat { .. } => "blank-format",
+            Self::MissingMetadata { .. } => "missing-metadata",
+            Self::SalvagedDecode { .. } => "salvaged-decode",
+            Self::FallbackPreview { .. } => "fallback-preview",
+            Self::Numbered { .. } => "numbered",
+            Self::LinkFailed { .. } => "link-failed",
+        }
+    }
+
+    fn salvaged(strategy: Strategy, reason: String) -> Self {
+        if strategy == Strategy::EmbeddedJpeg {
+            Self::FallbackPreview { reason }
+        } else {
+            Self::SalvagedDecode { strategy, reason }
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BlankFormat { missing, unsorted } => {
+                write!(
+                    f,
+                    "nothing in the format could be filled in (missing {}), named after the original only",
+                    missing.join(" ")
+                )?;
+
+                if *unsorted {
+                    write!(f, ", put in {UNSORTED_DIR}/")?;
+                }
+
+                Ok(())
+            }
+
+            Self::MissingMetadata { reason } => {
+                write!(f, "couldn't read metadata, named without it: {reason}")
+            }
+
+            Self::SalvagedDecode { strategy, reason } => {
+                write!(f, "couldn't convert, salvaged it ({strategy}): {reason}")
+            }
+
+            Self::FallbackPreview { reason } => write!(
+                f,
+                "couldn't convert, kept only the embedded preview JPEG: {reason}"
+            ),
+
+            Self::Numbered { name } => {
+                write!(
+                    f,
+                    "another file in the batch is named {name}, numbered this one"
+                )
+            }
+
+            Self::LinkFailed { reason } => {
+                write!(f, "couldn't link the original into the archive: {reason}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Outcome {
+    pub output_path: PathBuf,
+    pub empty_items: Vec<String>,
+    /// set when `--salvage` had to fall back to recover the file
+    #[serde(default)]
+    pub salvaged: Option<Strategy>,
+    #[serde(default)]
+    pub warnings: Vec<Warning>,
+    /// skipped by `--cache`, since the catalog shows the same conversion is already there
+    #[serde(default)]
+    pub cached: bool,
+    /// the `--on-conflict` policy applied because something was already where the file would have
+    /// gone, [`OnConflict::Skip`] if it was left out
+    #[serde(default)]
+    pub conflict: Option<OnConflict>,
+    /// for the run's `--report`
+    #[serde(default)]
+    pub bytes_read: u64,
+    #[serde(default)]
+    pub bytes_written: u64,
+    /// the catalog record of a conversion in a `--transactional` batch, only made once the batch
+    /// commits
+    #[serde(default)]
+    pub catalog_entry: Option<catalog::Entry>,
+}
+
 #[async_trait]
 pub trait Job {
     fn new(config: JobConfig) -> Self;
-    async fn run(self) -> Result<(), Error>;
+    async fn run(self) -> Result<Outcome, Error>;
 }
 
-#[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone)]
 pub struct JobConfig {
     pub input_path: PathBuf,
     pub output_dir: PathBuf,
-    pub filename_format: &'static FilenameFormat<'static>,
-    pub force: bool,
+    pub filename_format: Arc<FilenameFormat<'static>>,
+    /// lens metadata for files whose camera didn't record any
+    pub lenses: Arc<Lenses>,
+    /// corrections for camera clocks that were off, see `--time-shift-for`
+    pub clocks: Arc<Clocks>,
+    /// what to do about files already where the output would go
+    pub on_conflict: OnConflict,
+    /// outputs handed out so far in the batch, so that [`OnConflict::Rename`] doesn't give two
+    /// files the same name
+    pub claims: Arc<Mutex<Claims>>,
     pub convert_opts: ConvertParams,
+    /// arguments for an isolated worker process, see [`IsolatedJob`]
+    pub isolation: Option<Arc<[OsString]>>,
+    /// recorded in every output's provenance record
+    pub run_id: Arc<str>,
+    /// applied to each DNG after it's written, before the provenance record is added
+    pub metadata: MetadataPolicy,
+    /// fall back to [`salvage::salvage`] when the conversion fails
+    pub salvage: bool,
+    /// what's kept of a file that can't be converted at all, see `--fallback`
+    pub fallback: Option<ConvertFallback>,
+    /// copy the file as-is instead of converting it, see [`CopyJob`]
+    pub copy: bool,
+    /// put files whose format came out blank into [`UNSORTED_DIR`]
+    pub unsorted: bool,
+    /// skip conversions already in the catalog, and record new ones, see `--cache`
+    pub catalog: Option<Arc<Catalog>>,
+    /// the file's place in the batch by capture time, for `{chrono_index}`
+    pub chrono_index: Option<ChronoIndex>,
+    /// the file's place in the batch by path, for `{seq}`
+    pub seq: Option<usize>,
+    /// the file inside `input_path`, when that's an archive
+    pub member: Option<Member>,
+    /// what the file's named by when its own name can't be used, see `--stem-fallback`
+    pub fallback_stem: Option<String>,
+    /// how reading the input is retried when it fails, see `--retries`
+    pub retry: Retry,
+    /// where the output is written until the batch commits, see `--transactional`
+    pub staging: Option<PathBuf>,
+    /// flush the output and its directory to disk before reporting it written, see `--fsync`
+    pub fsync: bool,
+    /// give each output its source's file times, see `--preserve-times`
+    pub preserve_times: bool,
+    /// give each output its capture time as its modification time, see `--mtime-from-exif`
+    pub mtime_from_exif: bool,
+    /// where each output is copied once it's written, see `--backup-dir`
+    pub backups: Vec<PathBuf>,
+    /// given to each output and the directories made for it, see `--chmod` and `--chown`
+    pub permissions: Permissions,
+    /// where each raw is linked into, dated by when it was taken, see `--link-originals`
+    pub link_archive: Option<LinkArchive>,
+    /// where each output is announced before it's written and confirmed once it's settled, for
+    /// `rawbit fsck`
+    pub intents: Option<Arc<Intents>>,
+    /// copy the input's sidecars next to its output, see `--sidecars`
+    pub sidecars: bool,
+    /// what's done with the JPEG the camera wrote along with the raw, see `--jpeg-policy`
+    pub jpeg_policy: JpegPolicy,
+}
+
+/// How many more times to try reading an input after a transient failure, like a network mount
+/// dropping out, and how long to wait before the first retry. The wait doubles after each one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retry {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+/// Whether reading might succeed if it's tried again: interruptions, timeouts, dropped
+/// connections and plain I/O errors, which SMB and NFS mounts return for all of those. A missing
+/// or unreadable file, or a corrupt archive, fails the same way every time.
+fn is_transient(err: &io::Error) -> bool {
+    use io::ErrorKind::*;
+
+    // EIO has no kind of its own
+    const EIO: i32 = 5;
+
+    matches!(
+        err.kind(),
+        Interrupted
+            | TimedOut
+            | WouldBlock
+            | ResourceBusy
+            | StaleNetworkFileHandle
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+    ) || (cfg!(unix) && err.raw_os_error() == Some(EIO))
+}
+
+impl JobConfig {
+    /// Where the input came from, through its archive if it's in one, see
+    /// [`IngestItem::source_path`](crate::args::IngestItem::source_path).
+    #[must_use]
+    pub fn source_path(&self) -> PathBuf {
+        args::source_path(&self.input_path, self.member.as_ref())
+    }
 }
 
 #[derive(Debug)]
 pub struct RawConvertJob(JobConfig);
 
-fn build_output_filename(input_path: &Path, fmt: &FilenameFormat, md: &RawMetadata) -> PathBuf {
-    let input_fname_no_ext = input_path
-        .file_stem()
-        .unwrap_or_else(|| panic!("couldn't deduce filename from {}", input_path.display()))
-        .to_string_lossy();
+fn build_output_filename(
+    config: &JobConfig,
+    md: &RawMetadata,
+    from_raw: FromRaw,
+    ext: &str,
+) -> Result<RenderedFilename, Error> {
+    let input_path = config.source_path();
+    let input_fname_no_ext = stem::stem(&input_path)
+        .or_else(|| config.fallback_stem.as_deref().map(Cow::Borrowed))
+        .ok_or_else(|| {
+            Error::Io(
+                format!(
+                    "couldn't deduce a name from {}, see --stem-fallback",
+                    input_path.display()
+                ),
+                io::ErrorKind::InvalidFilename.into(),
+            )
+        })?;
+
+    let context = RenderContext {
+        original_filename: input_fname_no_ext.as_ref(),
+        chrono_index: config.chrono_index,
+        seq: config.seq,
+        archive_path: config.member.as_ref().map(Member::dir),
+        drive_mode: from_raw.drive_mode,
+        image: from_raw.image,
+    };
+
+    let mut rendered = config.filename_format.render_in(&context, md);
+
+    if !rendered.unmatched.is_empty() {
+        return Err(Error::Unresolved(format!(
+            "{} didn't match {}, give it a fallback after a | or set --missing-value",
+            rendered.unmatched.join(", "),
+            input_path.display()
+        )));
+    }
+
+    if config.filename_format.is_strict() && !rendered.empty_items.is_empty() {
+        return Err(Error::Unresolved(format!(
+            "couldn't fill in {} for {}, see --strict-format",
+            rendered.empty_items.join(", "),
+            input_path.display()
+        )));
+    }
 
-    let output_fname = fmt.render_filename(input_fname_no_ext.as_ref(), md) + ".dng";
+    rendered.filename = config
+        .filename_format
+        .sanitizer()
+        .fit(&rendered.filename, ext);
+    rendered.filename.push('.');
+    rendered.filename.push_str(ext);
 
-    output_fname.into()
+    Ok(rendered)
 }
 
-impl RawConvertJob {
-    async fn run_async(self) -> Result<(), Error> {
-        let config = self.0;
+/// Warns about a name that says nothing about the file, moving it into [`UNSORTED_DIR`] if asked
+/// to.
+fn check_rendered(config: &mut JobConfig, rendered: &RenderedFilename) -> Vec<Warning> {
+    if !rendered.blank {
+        return vec![];
+    }
 
-        let mut input = map_err!(
-            OpenOptions::new()
-                .read(true)
-                .write(false)
-                .open(&config.input_path)
-                .await,
+    if config.unsorted {
+        config.output_dir.push(UNSORTED_DIR);
+
+        if let Some(ref mut staging) = config.staging {
+            staging.push(UNSORTED_DIR);
+        }
+    }
+
+    vec![Warning::BlankFormat {
+        missing: rendered.empty_items.clone(),
+        unsorted: config.unsorted,
+    }]
+}
+
+/// The extension a job's output is written with, copies keep the original's.
+fn output_extension(config: &JobConfig) -> String {
+    if config.copy {
+        stem::extension(&config.source_path())
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    } else {
+        "dng".into()
+    }
+}
+
+/// What names are rendered from in the raw itself rather than its metadata.
+#[derive(Clone, Copy, Debug, Default)]
+struct FromRaw {
+    drive_mode: Option<DriveMode>,
+    image: Option<ImageInfo>,
+}
+
+/// What the filename format needs from the raw in `raw`, only reading what it has an expansion
+/// for.
+fn read_from_raw(config: &JobConfig, raw: &[u8]) -> FromRaw {
+    use MetadataKind::*;
+
+    let format = &config.filename_format;
+
+    FromRaw {
+        drive_mode: format
+            .uses(CameraDriveMode)
+            .then(|| drive::drive_mode(raw))
+            .flatten(),
+        image: [ImageWidth, ImageHeight, ImageBitDepth]
+            .into_iter()
+            .any(|kind| format.uses(kind))
+            .then(|| image_info(raw))
+            .flatten(),
+    }
+}
+
+/// The raw's size, from a decode that stops short of decompressing it.
+fn image_info(raw: &[u8]) -> Option<ImageInfo> {
+    let src = RawSource::new_from_slice(raw);
+    let image = get_decoder(&src)
+        .ok()?
+        .raw_image(&src, &RawDecodeParams::default(), true)
+        .ok()?;
+
+    let (width, height) = image
+        .crop_area
+        .map_or((image.width, image.height), |area| (area.d.w, area.d.h));
+
+    Some(ImageInfo {
+        width,
+        height,
+        bit_depth: image.bps,
+    })
+}
+
+/// Metadata for naming a copied file, empty if none of the raw decoders understand it.
+fn copied_metadata(config: &JobConfig) -> (RawMetadata, FromRaw) {
+    // a clip says when it was recorded in its container rather than in EXIF, and the raw
+    // decoders can't be trusted not to panic on the ISO media files some raws are too
+    if video::is_video(&config.source_path()) {
+        let mut md = RawMetadata::default();
+
+        if config.member.is_none() {
+            md.exif.date_time_original = video::capture_time(&config.input_path)
+                .map(|recorded| recorded.format(parse::EXIF_DT_FMT).to_string());
+        }
+
+        config.clocks.correct(&mut md, Some(&config.input_path));
+        return (md, FromRaw::default());
+    }
+
+    let read = |src: RawSource| {
+        let decoder = get_decoder(&src).ok()?;
+        let md = decoder
+            .raw_metadata(&src, &RawDecodeParams::default())
+            .ok()?;
+
+        Some((md, read_from_raw(config, src.buf())))
+    };
+
+    let metadata = config.member.as_ref().map_or_else(
+        || RawSource::new(&config.input_path).ok().and_then(read),
+        |member| {
+            let data = member.read(&config.input_path).ok()?;
+            read(RawSource::new_from_slice(&data))
+        },
+    );
+
+    let (mut md, from_raw) = metadata.unwrap_or_else(|| {
+        debug!(
+            "no metadata for \"{}\", naming it without",
+            config.source_path().display()
+        );
+
+        (RawMetadata::default(), FromRaw::default())
+    });
+
+    config.clocks.correct(&mut md, Some(&config.input_path));
+    (md, from_raw)
+}
+
+/// Copies the input to `output_path` as it is, out of its archive if it's in one, returning how
+/// many bytes it was.
+fn copy_source(config: &JobConfig, output_path: &Path) -> io::Result<u64> {
+    let Some(ref member) = config.member else {
+        return std_fs::copy(&config.input_path, output_path);
+    };
+
+    let data = member.read(&config.input_path)?;
+    std_fs::write(output_path, &data)?;
+
+    Ok(data.len() as u64)
+}
+
+/// Where a job's output goes, once `--on-conflict` has dealt with anything in the way.
+enum Destination {
+    /// write it to `path`, which is in the staging directory in a `--transactional` batch.
+    /// `conflict` is the policy that made room for it, if something was in the way.
+    Write {
+        path: PathBuf,
+        conflict: Option<OnConflict>,
+    },
+    /// `--on-conflict skip` left the file already at this path alone
+    Skip(PathBuf),
+}
+
+/// Makes room for the converted file as [`JobConfig::on_conflict`] says to, refusing to clobber
+/// anything by default. Files in the batch that would land on one another are numbered instead,
+/// whatever the policy, with a warning added to `warnings`.
+fn prepare_output_path(
+    config: &JobConfig,
+    filename: &str,
+    warnings: &mut Vec<Warning>,
+) -> Result<Destination, Error> {
+    let mut output_path = config.output_dir.join(filename);
+    let mut conflict = None;
+
+    let claimed = claim(config, &output_path)?;
+
+    if claimed != output_path {
+        debug!(
+            "\"{}\" is taken, writing \"{}\" instead",
+            output_path.display(),
+            claimed.display()
+        );
+
+        if config.on_conflict == OnConflict::Rename {
+            conflict = Some(OnConflict::Rename);
+        } else {
+            warnings.push(Warning::Numbered {
+                name: filename.to_string(),
+            });
+        }
+
+        output_path = claimed;
+    } else if output_path.exists() {
+        if config.on_conflict == OnConflict::Error {
+            return Err(Error::AlreadyExists(format!(
+                "won't overwrite existing file: {}",
+                output_path.display()
+            )));
+        } else if output_path.is_dir() {
+            return Err(Error::AlreadyExists(format!(
+                "computed filepath already exists as a directory: {}",
+                output_path.display()
+            )));
+        } else if config.on_conflict == OnConflict::Skip {
+            return Ok(Destination::Skip(output_path));
+        } else if config.staging.is_none() {
+            // otherwise it's replaced when the batch commits
+            map_err!(
+                remove_file(&output_path),
+                dest_io_error,
+                format!("couldn't remove existing file: {}", output_path.display()),
+            )?;
+        }
+
+        conflict = Some(config.on_conflict);
+    }
+
+    let path = write_path(config, &output_path);
+
+    // the format can put the file in directories of its own
+    if let Some(write_dir) = path.parent() {
+        map_err!(
+            config.permissions.create_dir_all(write_dir),
+            dest_io_error,
+            format!("couldn't make output dir: {}", write_dir.display())
+        )?;
+    }
+
+    intend(
+        config,
+        &Intent::Write {
+            path: path.clone(),
+            source: config.input_path.clone(),
+        },
+    );
+
+    Ok(Destination::Write { path, conflict })
+}
+
+/// Records `intent` in the run's intent log, if it keeps one.
+fn intend(config: &JobConfig, intent: &Intent) {
+    if let Some(ref intents) = config.intents {
+        intents.record(intent);
+    }
+}
+
+/// Claims `output_path` for this file, numbering it if another file in the batch already has,
+/// or with [`OnConflict::Rename`], if anything is already there.
+///
+/// A file already on disk is otherwise left to the policy, and the name is only claimed if it's
+/// going to be overwritten, so a file that's refused or skipped doesn't get others numbered.
+fn claim(config: &JobConfig, output_path: &Path) -> Result<PathBuf, Error> {
+    let taken = |path: &Path| path.exists() || write_path(config, path).exists();
+    let mut claims = config.claims.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if config.on_conflict == OnConflict::Rename || claims.contains(output_path) {
+        return claims.claim(output_path, taken).ok_or_else(|| {
+            Error::AlreadyExists(format!(
+                "every numbered name is taken: {}",
+                output_path.display()
+            ))
+        });
+    }
+
+    if config.on_conflict == OnConflict::Overwrite || !taken(output_path) {
+        claims.insert(output_path);
+    }
+    drop(claims);
+
+    Ok(output_path.to_path_buf())
+}
+
+/// Where the file bound for `output_path` is written, see [`write_root`].
+fn write_path(config: &JobConfig, output_path: &Path) -> PathBuf {
+    output_path.strip_prefix(&config.output_dir).map_or_else(
+        |_| output_path.to_path_buf(),
+        |relative| write_root(config).join(relative),
+    )
+}
+
+fn file_len(path: &Path) -> u64 {
+    std_fs::metadata(path).map_or(0, |stat| stat.len())
+}
+
+/// Where outputs are written: the staging directory in a `--transactional` batch, otherwise the
+/// output directory.
+fn write_root(config: &JobConfig) -> &Path {
+    config.staging.as_ref().unwrap_or(&config.output_dir)
+}
+
+/// Settles a finished output before it's reported written: gives it its source's times with
+/// `--preserve-times`, flushes it to disk with `--fsync` and copies it into each `--backup-dir`.
+fn sync_output(config: &JobConfig, path: &Path, taken: Option<SystemTime>) -> Result<(), Error> {
+    map_err!(
+        config.permissions.apply(path),
+        dest_io_error,
+        format!("couldn't set the permissions of {}", path.display()),
+    )?;
+
+    if config.preserve_times {
+        map_err!(
+            copy_times(&config.input_path, path),
+            dest_io_error,
+            format!("couldn't set the file times of {}", path.display()),
+        )?;
+    }
+
+    if let Some(taken) = taken {
+        map_err!(
+            std_fs::File::options()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_modified(taken)),
+            dest_io_error,
+            format!("couldn't set the modification time of {}", path.display()),
+        )?;
+    }
+
+    if config.fsync {
+        map_err!(
+            durable::sync_file(path, write_root(config)),
+            dest_io_error,
+            format!("couldn't flush {} to disk", path.display()),
+        )?;
+    }
+
+    let relative = path.strip_prefix(write_root(config)).unwrap_or(path);
+
+    for backup in &config.backups {
+        back_up(
+            config,
+            path,
+            &backup.join(relative),
+            backup,
+            taken.is_some(),
+        )?;
+    }
+
+    intend(
+        config,
+        &Intent::Written {
+            path: path.to_path_buf(),
+        },
+    );
+
+    Ok(())
+}
+
+/// Copies `written` to `copy` in the backup directory `root`, along with its file times if they
+/// were `stamped` on it.
+///
+/// A backup going missing doesn't fail over like the output directory does, the file fails.
+fn back_up(
+    config: &JobConfig,
+    written: &Path,
+    copy: &Path,
+    root: &Path,
+    stamped: bool,
+) -> Result<(), Error> {
+    if copy.exists() && config.on_conflict != OnConflict::Overwrite {
+        return Err(Error::AlreadyExists(format!(
+            "won't overwrite existing backup: {}",
+            copy.display()
+        )));
+    }
+
+    if let Some(dir) = copy.parent() {
+        map_err!(
+            config.permissions.create_dir_all(dir),
             Error::Io,
-            "Couldn't open input RAW file",
+            format!("couldn't create backup directory: {}", dir.display()),
         )?;
+    }
+
+    intend(
+        config,
+        &Intent::Write {
+            path: copy.to_path_buf(),
+            source: written.to_path_buf(),
+        },
+    );
 
-        let mut buf = vec![];
+    map_err!(
+        std_fs::copy(written, copy),
+        Error::Io,
+        format!("couldn't back up to {}", copy.display()),
+    )?;
 
+    map_err!(
+        config.permissions.apply(copy),
+        Error::Io,
+        format!("couldn't set the permissions of {}", copy.display()),
+    )?;
+
+    if config.preserve_times || stamped {
         map_err!(
-            input.read_to_end(&mut buf).await,
+            copy_times(written, copy),
             Error::Io,
-            format!("couldn't read from file: '{}'", config.input_path.display())
+            format!("couldn't set the file times of {}", copy.display()),
+        )?;
+    }
+
+    if config.fsync {
+        map_err!(
+            durable::sync_file(copy, root),
+            Error::Io,
+            format!("couldn't flush {} to disk", copy.display()),
         )?;
+    }
+
+    intend(
+        config,
+        &Intent::Written {
+            path: copy.to_path_buf(),
+        },
+    );
+
+    Ok(())
+}
+
+/// What's made of a file's metadata besides its name: the lens and clock corrections written
+/// into its DNG, and when it was taken, for its modification time.
+struct Corrections {
+    lens: Option<Lens>,
+    shift: Option<TimeDelta>,
+    taken: Option<SystemTime>,
+    /// the original embedded in a DNG that's being converted again, see [`take_original`]
+    original: Option<EmbeddedOriginal>,
+}
+
+impl Corrections {
+    /// Fills in the lens and moves the timestamps in `md`, which is then named by them, and
+    /// takes the original out of a DNG in `source`.
+    fn apply(config: &mut JobConfig, md: &mut RawMetadata, source: &[u8]) -> Self {
+        let lens = config.lenses.fill(md);
+        let shift = config.clocks.correct(md, Some(&config.input_path));
+
+        Self {
+            lens,
+            shift,
+            taken: taken_at(config, md),
+            original: take_original(config, source),
+        }
+    }
+}
+
+/// With `--embed-raw`, a DNG that's converted again keeps the original embedded in it, if it has
+/// one, rather than having the DNG itself embedded.
+fn take_original(config: &mut JobConfig, source: &[u8]) -> Option<EmbeddedOriginal> {
+    if !config.convert_opts.embedded || !reprocess::is_dng(&config.source_path()) {
+        return None;
+    }
+
+    config.convert_opts.embedded = false;
+    EmbeddedOriginal::read(source)
+}
+
+/// When the file was taken, if it's to be its output's modification time, see `--mtime-from-exif`.
+fn taken_at(config: &JobConfig, md: &RawMetadata) -> Option<SystemTime> {
+    if !config.mtime_from_exif {
+        return None;
+    }
+
+    let taken = clock::taken_at(md);
+    if taken.is_none() {
+        debug!(
+            "no capture time to date {} by",
+            config.source_path().display()
+        );
+    }
+
+    taken
+}
+
+/// Gives `to` the access and modification times of `from`.
+fn copy_times(from: &Path, to: &Path) -> io::Result<()> {
+    let stat = std_fs::metadata(from)?;
+    let times = FileTimes::new()
+        .set_accessed(stat.accessed()?)
+        .set_modified(stat.modified()?);
+
+    std_fs::File::options()
+        .write(true)
+        .open(to)?
+        .set_times(times)
+}
+
+/// Where a file written by [`prepare_output_path`] ends up once the batch is done with it.
+fn committed_path(config: &JobConfig, written: &Path) -> PathBuf {
+    written.strip_prefix(write_root(config)).map_or_else(
+        |_| written.to_path_buf(),
+        |relative| config.output_dir.join(relative),
+    )
+}
+
+/// The input's bytes, read out of its archive if it's in one, trying again after transient
+/// failures as many times as [`JobConfig::retry`] allows.
+async fn read_input(config: &JobConfig) -> Result<Vec<u8>, Error> {
+    let Retry { retries, mut delay } = config.retry;
+    let mut attempt = 0;
+
+    loop {
+        match read_input_once(config).await {
+            Err(Error::Io(_, ref err)) if attempt < retries && is_transient(err) => {
+                attempt += 1;
+                warn!(
+                    "couldn't read '{}', retrying in {delay:?} ({attempt}/{retries}): {err}",
+                    config.source_path().display()
+                );
+
+                tokio::time::sleep(delay).await;
+                delay = delay.saturating_mul(2);
+            }
+            read => return read,
+        }
+    }
+}
+
+async fn read_input_once(config: &JobConfig) -> Result<Vec<u8>, Error> {
+    let Some(member) = config.member.clone() else {
+        return map_err!(
+            fs::read(&config.input_path).await,
+            Error::Io,
+            format!("couldn't read from file: '{}'", config.input_path.display())
+        );
+    };
+
+    let archive = config.input_path.clone();
+    let read = tokio::task::spawn_blocking(move || member.read(&archive))
+        .await
+        .map_err(join_error)?;
+
+    map_err!(
+        read,
+        Error::Io,
+        format!(
+            "couldn't read from archive: '{}'",
+            config.source_path().display()
+        )
+    )
+}
+
+/// `f`, run in the span it's made in, for work handed to another thread.
+fn in_current_span<T>(f: impl FnOnce() -> T) -> impl FnOnce() -> T {
+    let span = Span::current();
+    move || span.in_scope(f)
+}
+
+/// The RAW's metadata, or with `--salvage`, none and a warning saying why if it can't be read.
+fn read_metadata(
+    config: &JobConfig,
+    raw_file: &RawSource,
+) -> Result<(RawMetadata, Option<Warning>), Error> {
+    let _decode = info_span!("decode").entered();
+
+    let decoder = map_err!(
+        get_decoder(raw_file),
+        Error::ImgOp,
+        "no compatible RAW image decoder available",
+    )?;
+
+    match decoder.raw_metadata(raw_file, &RawDecodeParams::default()) {
+        Ok(md) => Ok((md, None)),
+
+        // the fallbacks might still recover the image, just not what it should be named
+        Err(err) if config.salvage => Ok((
+            RawMetadata::default(),
+            Some(Warning::MissingMetadata {
+                reason: err.to_string(),
+            }),
+        )),
+
+        Err(err) => Err(Error::ImgOp("couldn't extract image metadata".into(), err)),
+    }
+}
+
+impl RawConvertJob {
+    async fn run_async(self) -> Result<Outcome, Error> {
+        let mut config = self.0;
+
+        let buf = Arc::new(read_input(&config).instrument(info_span!("read")).await?);
+        let mut digest = Digest::spawn(Arc::clone(&buf));
 
         let raw_file = RawSource::new_from_slice(&buf[..]);
+        let mut provenance = new_provenance(&config);
 
-        let decoder = map_err!(
-            get_decoder(&raw_file),
-            Error::ImgOp,
-            "no compatible RAW image decoder available",
+        let (mut md, unread) = read_metadata(&config, &raw_file)?;
+
+        let corrections = Corrections::apply(&mut config, &mut md, &buf);
+        let captured = parse::capture_time(&md);
+
+        let rendered = build_output_filename(&config, &md, read_from_raw(&config, &buf), "dng")?;
+        let mut warnings = check_rendered(&mut config, &rendered);
+        warnings.extend(unread);
+
+        let (transformed_fname, empty_items) = (rendered.filename, rendered.empty_items);
+        let params_sha256 = provenance.params_sha256(&catalog_settings(&config, &corrections));
+
+        let bytes_read = buf.len() as u64;
+
+        // the catalog is looked up by the source's digest, otherwise it's hashed while converting
+        if config.catalog.is_some()
+            && let Some(output_path) = unchanged(
+                &config,
+                digest.wait().await,
+                &params_sha256,
+                &transformed_fname,
+            )
+        {
+            link_original(&config, captured, &mut warnings);
+            return Ok(Outcome::cached(
+                output_path,
+                empty_items,
+                warnings,
+                bytes_read,
+            ));
+        }
+
+        let (output_path, conflict) =
+            match prepare_output_path(&config, &transformed_fname, &mut warnings)? {
+                Destination::Write { path, conflict } => (path, conflict),
+                Destination::Skip(output_path) => {
+                    link_original(&config, captured, &mut warnings);
+                    return Ok(Outcome::skipped(
+                        output_path,
+                        empty_items,
+                        warnings,
+                        bytes_read,
+                    ));
+                }
+            };
+
+        let (output_path, salvaged, bytes_written, warnings, catalog_entry) =
+            tokio::task::spawn_blocking(in_current_span(move || {
+                let fallback = write_dng(
+                    &config,
+                    &buf,
+                    &output_path,
+                    corrections.taken,
+                    &mut warnings,
+                )?;
+                let salvaged = fallback.as_ref().map(|fallback| fallback.strategy);
+
+                if let Some(Fallback {
+                    strategy,
+                    reason,
+                    path,
+                }) = fallback
+                {
+                    warnings.push(Warning::salvaged(strategy, reason));
+
+                    if strategy == Strategy::EmbeddedJpeg {
+                        let written = file_len(&path);
+                        carry_sidecars(&config, &path, corrections.taken, &mut warnings)?;
+                        link_original(&config, captured, &mut warnings);
+                        let committed = committed_path(&config, &path);
+                        return Ok((committed, salvaged, written, warnings, None));
+                    }
+                }
+
+                let committed = committed_path(&config, &output_path);
+
+                provenance.salvaged = salvaged;
+                provenance.source_sha256 = digest.blocking_finish();
+
+                finish_dng(&config, &corrections, &provenance, &output_path)?;
+
+                let entry = catalog_conversion(
+                    &config,
+                    provenance,
+                    params_sha256,
+                    &output_path,
+                    &committed,
+                );
+
+                carry_sidecars(&config, &output_path, corrections.taken, &mut warnings)?;
+                link_original(&config, captured, &mut warnings);
+
+                Ok((committed, salvaged, file_len(&output_path), warnings, entry))
+            }))
+            .await
+            .map_err(join_error)??;
+
+        Ok(Outcome {
+            output_path,
+            empty_items,
+            salvaged,
+            warnings,
+            cached: false,
+            conflict,
+            bytes_read,
+            bytes_written,
+            catalog_entry,
+        })
+    }
+}
+
+/// Copies the input's sidecars next to its output at `output_path`, named to match it, with the
+/// same conflict policy. See `--sidecars`. The JPEG of a RAW+JPEG pair goes along with them as
+/// `--jpeg-policy` says.
+fn carry_sidecars(
+    config: &JobConfig,
+    output_path: &Path,
+    taken: Option<SystemTime>,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), Error> {
+    if config.member.is_some() {
+        return Ok(());
+    }
+
+    let relative = output_path
+        .strip_prefix(write_root(config))
+        .unwrap_or(output_path);
+
+    let mut carried = vec![];
+
+    if config.sidecars {
+        for sidecar in sidecar::find(&config.input_path) {
+            if let Some(ext) = sidecar.extension() {
+                carried.push((
+                    relative.with_extension(ext.to_string_lossy().to_lowercase()),
+                    sidecar,
+                ));
+            }
+        }
+    }
+
+    // a --fallback output is the camera's JPEG already
+    let jpeg = (config.jpeg_policy != JpegPolicy::Ignore && !sidecar::is_jpeg(output_path))
+        .then(|| sidecar::find_jpeg(&config.input_path))
+        .flatten();
+
+    if let Some(jpeg) = jpeg
+        && let (Some(name), Some(ext)) = (jpeg.file_name(), jpeg.extension())
+    {
+        let name = if config.jpeg_policy == JpegPolicy::Rename {
+            relative.with_extension(ext.to_string_lossy().to_lowercase())
+        } else {
+            relative.with_file_name(name)
+        };
+
+        carried.push((name, jpeg));
+    }
+
+    for (name, source) in carried {
+        let path = match prepare_output_path(config, &name.to_string_lossy(), warnings)? {
+            Destination::Write { path, .. } => path,
+            Destination::Skip(_) => continue,
+        };
+
+        debug!("Copying \"{}\" to \"{}\"", source.display(), path.display());
+
+        map_err!(
+            std_fs::copy(&source, &path),
+            dest_io_error,
+            format!("couldn't copy {} to {}", source.display(), path.display()),
         )?;
+        sync_output(config, &path, taken)?;
+    }
 
-        let md = map_err!(
-            decoder.raw_metadata(&raw_file, &RawDecodeParams::default()),
-            Error::ImgOp,
-            "couldn't extract image metadata",
+    Ok(())
+}
+
+/// Links the raw into the `--link-originals` archive once its output is written, unless it's
+/// inside an archive of its own. The output is already there, so a link that fails only warns.
+fn link_original(config: &JobConfig, taken: Option<NaiveDateTime>, warnings: &mut Vec<Warning>) {
+    let (Some(archive), None, Some(name)) = (
+        &config.link_archive,
+        &config.member,
+        config.input_path.file_name(),
+    ) else {
+        return;
+    };
+
+    let path = archive.path_for(&name.to_string_lossy(), taken);
+
+    match archive.link(&config.input_path, &path, config.permissions) {
+        Ok(linked) => debug!(
+            "Linked \"{}\" to \"{}\"",
+            config.input_path.display(),
+            linked.display()
+        ),
+        Err(err) => warnings.push(Warning::LinkFailed {
+            reason: format!("{}: {err}", path.display()),
+        }),
+    }
+}
+
+impl Outcome {
+    /// A file `--cache` left alone, the catalog shows it was already converted to `output_path`.
+    const fn cached(
+        output_path: PathBuf,
+        empty_items: Vec<String>,
+        warnings: Vec<Warning>,
+        bytes_read: u64,
+    ) -> Self {
+        Self {
+            output_path,
+            empty_items,
+            salvaged: None,
+            warnings,
+            cached: true,
+            conflict: None,
+            bytes_read,
+            bytes_written: 0,
+            catalog_entry: None,
+        }
+    }
+
+    /// A file left out by `--on-conflict skip`, since something was already at `output_path`.
+    fn skipped(
+        output_path: PathBuf,
+        empty_items: Vec<String>,
+        warnings: Vec<Warning>,
+        bytes_read: u64,
+    ) -> Self {
+        info!("\"{}\" already exists, skipping it", output_path.display());
+
+        Self {
+            output_path,
+            empty_items,
+            salvaged: None,
+            warnings,
+            cached: false,
+            conflict: Some(OnConflict::Skip),
+            bytes_read,
+            bytes_written: 0,
+            catalog_entry: None,
+        }
+    }
+}
+
+/// Writes what rawler doesn't into a freshly converted DNG: the lens, the corrected timestamps, the
+/// metadata filter and the provenance record.
+fn finish_dng(
+    config: &JobConfig,
+    corrections: &Corrections,
+    provenance: &Provenance,
+    output_path: &Path,
+) -> Result<(), Error> {
+    let _write = info_span!("write").entered();
+
+    let mut output_file = map_err!(
+        std_fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(output_path),
+        dest_io_error,
+        format!("couldn't reopen DNG: {}", output_path.display()),
+    )?;
+
+    if let Some(ref lens) = corrections.lens {
+        map_err!(
+            lens.write(&mut output_file),
+            dest_io_error,
+            format!("couldn't write lens metadata: {}", output_path.display()),
         )?;
+    }
 
-        let transformed_fname =
-            build_output_filename(&config.input_path, config.filename_format, &md);
+    if let Some(shift) = corrections.shift {
+        map_err!(
+            clock::write(shift, &mut output_file),
+            dest_io_error,
+            format!("couldn't correct timestamps: {}", output_path.display()),
+        )?;
+    }
 
+    if let Some(ref original) = corrections.original {
         map_err!(
-            create_dir_all(&config.output_dir),
-            Error::Io,
-            format!("couldn't make output dir: {}", config.output_dir.display())
-        )?;
-
-        let output_path = config.output_dir.join(transformed_fname);
-
-        if output_path.exists() {
-            if !config.force {
-                Err(Error::AlreadyExists(format!(
-                    "won't overwrite existing file: {}",
-                    output_path.display()
-                )))
-            } else if output_path.is_dir() {
-                Err(Error::AlreadyExists(format!(
-                    "computed filepath already exists as a directory: {}",
-                    output_path.display()
-                )))
-            } else {
-                map_err!(
-                    remove_file(&output_path),
-                    Error::Io,
-                    format!("couldn't remove existing file: {}", output_path.display()),
-                )
-            }?;
-        }
+            original.write(&mut output_file),
+            dest_io_error,
+            format!("couldn't embed original raw: {}", output_path.display()),
+        )?;
+    }
+
+    map_err!(
+        config.metadata.apply(&mut output_file),
+        dest_io_error,
+        format!("couldn't filter metadata: {}", output_path.display()),
+    )?;
+
+    map_err!(
+        provenance::write_xmp(&mut output_file, provenance.to_xmp().as_bytes()),
+        dest_io_error,
+        format!(
+            "couldn't write provenance record: {}",
+            output_path.display()
+        ),
+    )?;
+
+    sync_output(config, output_path, corrections.taken)
+}
+
+/// The provenance record for a conversion, until its source's digest and salvage are filled in.
+fn new_provenance(config: &JobConfig) -> Provenance {
+    Provenance::new(
+        &config.run_id,
+        &config
+            .source_path()
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy(),
+        &config.convert_opts,
+    )
+}
+
+/// What goes into a conversion's catalog key besides the source and the [`ConvertParams`].
+fn catalog_settings(config: &JobConfig, corrections: &Corrections) -> String {
+    let lens = corrections.lens.as_ref();
+
+    // only when there is one, so conversions cached before there were shifts still match
+    let shift = corrections
+        .shift
+        .map(|shift| format!(" time_shift={}", shift.num_seconds()))
+        .unwrap_or_default();
+
+    format!(
+        "artist={:?} metadata={:?} lens={lens:?}{shift}",
+        config.convert_opts.artist, config.metadata
+    )
+}
+
+/// Where the catalog shows this exact conversion already went, if that output is still intact.
+fn unchanged(
+    config: &JobConfig,
+    source_sha256: &str,
+    params_sha256: &str,
+    filename: &str,
+) -> Option<PathBuf> {
+    let catalog = config.catalog.as_ref()?;
+    let output_path = path::absolute(config.output_dir.join(filename)).ok()?;
+
+    if !catalog.find(source_sha256, params_sha256, &output_path) {
+        return None;
+    }
+
+    info!(
+        "\"{}\" was already converted to \"{}\" with the same settings, skipping",
+        config.source_path().display(),
+        output_path.display()
+    );
+
+    Some(output_path)
+}
+
+/// Adds a conversion written to `written` to the catalog, at `committed` where it ends up.
+///
+/// In a `--transactional` batch, the record is returned instead, to be added once the batch
+/// commits, one that's rolled back never wrote it.
+fn catalog_conversion(
+    config: &JobConfig,
+    provenance: Provenance,
+    params_sha256: String,
+    written: &Path,
+    committed: &Path,
+) -> Option<catalog::Entry> {
+    let catalog = config.catalog.as_ref()?;
+
+    // a salvaged file might convert properly with a later decoder, so it isn't cached
+    if provenance.salvaged.is_some() {
+        return None;
+    }
+
+    let entry = path::absolute(committed).and_then(|output_path| {
+        Ok(catalog::Entry {
+            source_sha256: provenance.source_sha256,
+            params_sha256,
+            output_sha256: provenance::sha256_file(written)?,
+            output_path,
+        })
+    });
 
-        let output_file = std::fs::OpenOptions::new()
+    let entry = entry
+        .map_err(|err| {
+            warn!(
+                "couldn't add \"{}\" to the catalog, it'll be converted again next time: {err}",
+                committed.display()
+            );
+        })
+        .ok()?;
+
+    if config.staging.is_some() {
+        return Some(entry);
+    }
+
+    record_conversion(catalog, entry);
+    None
+}
+
+/// Adds a conversion to the catalog, so `--cache` can leave it alone next time.
+pub fn record_conversion(catalog: &Catalog, entry: catalog::Entry) {
+    let output_path = entry.output_path.clone();
+
+    if let Err(err) = catalog.record(entry) {
+        warn!(
+            "couldn't add \"{}\" to the catalog, it'll be converted again next time: {err}",
+            output_path.display()
+        );
+    }
+}
+
+/// What `--salvage` fell back to after the decoder failed.
+struct Fallback {
+    strategy: Strategy,
+    /// why the decoder failed
+    reason: String,
+    /// where the recovered file was written
+    path: PathBuf,
+}
+
+/// Swaps the failed DNG at `output_path` for a `.jpg` next to it, for the embedded preview.
+fn create_preview_output(
+    config: &JobConfig,
+    output_path: &Path,
+    warnings: &mut Vec<Warning>,
+) -> Result<(std_fs::File, PathBuf), Error> {
+    map_err!(
+        remove_file(output_path),
+        dest_io_error,
+        format!("couldn't remove failed DNG: {}", output_path.display()),
+    )?;
+
+    let jpeg_path = output_path.with_extension("jpg");
+    let jpeg_name = jpeg_path
+        .strip_prefix(write_root(config))
+        .unwrap_or(&jpeg_path)
+        .to_string_lossy();
+    let Destination::Write {
+        path: jpeg_path, ..
+    } = prepare_output_path(config, &jpeg_name, warnings)?
+    else {
+        return Err(Error::AlreadyExists(format!(
+            "won't overwrite existing file: {}",
+            config.output_dir.join(&*jpeg_name).display()
+        )));
+    };
+
+    let jpeg_file = map_err!(
+        std_fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&jpeg_path),
+        dest_io_error,
+        format!("couldn't create output file: {}", jpeg_path.display()),
+    )?;
+
+    Ok((jpeg_file, jpeg_path))
+}
+
+/// Converts the RAW into a new DNG at `output_path`, falling back to whatever `--salvage` can
+/// recover if the decoder fails.
+///
+/// An embedded JPEG is written next to where the DNG would have gone, with a `.jpg` extension.
+fn write_dng(
+    config: &JobConfig,
+    source: &[u8],
+    output_path: &Path,
+    taken: Option<SystemTime>,
+    warnings: &mut Vec<Warning>,
+) -> Result<Option<Fallback>, Error> {
+    // rawler decodes the image as it converts it
+    let _convert = info_span!("convert").entered();
+
+    let output_file = map_err!(
+        std_fs::OpenOptions::new()
             .write(true)
             .create_new(true)
-            .open(&output_path);
+            .open(output_path),
+        dest_io_error,
+        format!("couldn't create output file: {}", output_path.display()),
+    )?;
+
+    info!("Writing DNG: \"{}\"", output_path.display());
+
+    let mut output_file = BufWriter::new(output_file);
+    let raw_file = RawSource::new_from_slice(source);
+    let mut convert = || {
+        dng::convert::convert_raw_source(
+            &raw_file,
+            &mut output_file,
+            config.source_path().to_string_lossy(),
+            &config.convert_opts,
+        )
+    };
+
+    let converted = if config.salvage {
+        // a decoder that panics on this file might still cope with one of the fallbacks
+        panic::catch_unwind(AssertUnwindSafe(convert))
+            .unwrap_or_else(|panic| Err(RawlerError::DecoderFailed(panic_message(&*panic))))
+    } else {
+        convert()
+    };
+
+    let output_file = map_err!(
+        output_file.into_inner().map_err(IntoInnerError::into_error),
+        dest_io_error,
+        format!("couldn't write DNG: {}", output_path.display()),
+    )?;
 
+    let err = match converted {
+        Ok(()) => return Ok(None),
+        Err(err) if !config.salvage => {
+            return Err(Error::ImgOp("couldn't convert image to DNG".into(), err));
+        }
+        Err(err) => err,
+    };
+
+    debug!(
+        "couldn't convert \"{}\", trying to salvage it: {err}",
+        config.source_path().display()
+    );
+
+    let Some(Salvaged { strategy, data }) = salvage::salvage(
+        source,
+        &config.source_path().to_string_lossy(),
+        &config.convert_opts,
+    ) else {
+        return Err(Error::ImgOp(
+            "couldn't convert image to DNG, and nothing could be salvaged".into(),
+            err,
+        ));
+    };
+
+    let (mut output_file, path) = if strategy == Strategy::EmbeddedJpeg {
+        drop(output_file);
+        create_preview_output(config, output_path, warnings)?
+    } else {
+        let mut output_file = output_file;
         map_err!(
-            tokio::task::spawn_blocking(move || {
-                let mut output_file = std::io::BufWriter::new(map_err!(
-                    output_file,
-                    Error::Io,
-                    format!("couldn't create output file: {}", output_path.display()),
-                )?);
-
-                info!("Writing DNG: \"{}\"", output_path.display());
-
-                let cvt_result = dng::convert::convert_raw_source(
-                    &raw_file,
-                    &mut output_file,
-                    config.input_path.to_string_lossy(),
-                    &config.convert_opts,
-                );
+            output_file.set_len(0).and_then(|()| output_file.rewind()),
+            dest_io_error,
+            format!("couldn't truncate failed DNG: {}", output_path.display()),
+        )?;
 
-                map_err!(cvt_result, Error::ImgOp, "couldn't convert image to DNG",)
-            })
-            .await
-            .map_err(Box::new),
-            Error::Other,
-            format!("async error")
-        )?
+        (output_file, output_path.to_path_buf())
+    };
+
+    map_err!(
+        output_file.write_all(&data),
+        dest_io_error,
+        format!("couldn't write salvaged output: {}", path.display()),
+    )?;
+
+    // a salvaged DNG is flushed once it's finished, the preview is already done
+    if strategy == Strategy::EmbeddedJpeg {
+        sync_output(config, &path, taken)?;
     }
+
+    Ok(Some(Fallback {
+        strategy,
+        reason: err.to_string(),
+        path,
+    }))
 }
 
 #[async_trait]
@@ -167,13 +1576,105 @@ impl Job for RawConvertJob {
         Self(config)
     }
 
-    async fn run(self) -> Result<(), Error> {
-        self.run_async().await
+    async fn run(self) -> Result<Outcome, Error> {
+        let fallback = self.0.fallback.map(|_| self.0.clone());
+
+        match self.run_async().await {
+            Err(err @ Error::ImgOp(..)) => match fallback {
+                Some(config) => extract_fallback(config, err).await,
+                None => Err(err),
+            },
+            result => result,
+        }
     }
 }
 
+/// Keeps the embedded JPEG of a file that couldn't be converted in [`FALLBACK_DIR`], so something
+/// usable survives until there's a decoder for it. Fails with `err` if there isn't one either.
+async fn extract_fallback(config: JobConfig, err: Error) -> Result<Outcome, Error> {
+    let buf = read_input(&config).await?;
+    let bytes_read = buf.len() as u64;
+
+    let Some(jpeg) = tokio::task::spawn_blocking(move || salvage::extract_jpeg(&buf))
+        .await
+        .map_err(join_error)?
+    else {
+        return Err(err);
+    };
+
+    let source = config.source_path();
+    let Some(stem) =
+        stem::stem(&source).or_else(|| config.fallback_stem.as_deref().map(Cow::Borrowed))
+    else {
+        return Err(err);
+    };
+
+    let mut warnings = vec![Warning::FallbackPreview {
+        reason: err.message(),
+    }];
+
+    let filename = format!("{FALLBACK_DIR}/{stem}.jpg");
+    let (path, conflict) = match prepare_output_path(&config, &filename, &mut warnings)? {
+        Destination::Write { path, conflict } => (path, conflict),
+        Destination::Skip(output_path) => {
+            return Ok(Outcome::skipped(output_path, vec![], warnings, bytes_read));
+        }
+    };
+
+    info!("Writing fallback JPEG: \"{}\"", path.display());
+
+    map_err!(
+        std_fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(&jpeg)),
+        dest_io_error,
+        format!("couldn't write fallback JPEG: {}", path.display()),
+    )?;
+
+    sync_output(&config, &path, None)?;
+
+    Ok(Outcome {
+        output_path: committed_path(&config, &path),
+        empty_items: vec![],
+        salvaged: Some(Strategy::EmbeddedJpeg),
+        warnings,
+        cached: false,
+        conflict,
+        bytes_read,
+        bytes_written: jpeg.len() as u64,
+        catalog_entry: None,
+    })
+}
+
 pub struct DryRunJob(JobConfig);
 
+impl DryRunJob {
+    fn metadata(config: &JobConfig, buf: &[u8]) -> Result<(RawMetadata, FromRaw), Error> {
+        const DECODE_PARAMS: RawDecodeParams = RawDecodeParams { image_index: 0 };
+
+        let src = RawSource::new_from_slice(buf).with_path(config.source_path());
+        let _decode = info_span!("decode").entered();
+
+        let decoder = map_err!(get_decoder(&src), Error::ImgOp, "no available decoder")?;
+
+        let mut md = map_err!(
+            decoder.raw_metadata(&src, &DECODE_PARAMS),
+            Error::ImgOp,
+            format!(
+                "error while retreiving metadata from RAW: {}",
+                config.source_path().display()
+            )
+        )?;
+
+        config.lenses.fill(&mut md);
+        config.clocks.correct(&mut md, Some(&config.input_path));
+
+        Ok((md, read_from_raw(config, buf)))
+    }
+}
+
 #[async_trait]
 impl Job for DryRunJob {
     fn new(config: JobConfig) -> Self {
@@ -182,48 +1683,830 @@ impl Job for DryRunJob {
         Self(config)
     }
 
-    async fn run(self) -> Result<(), Error> {
+    async fn run(self) -> Result<Outcome, Error> {
+        let mut config = self.0;
+
+        let buf = if config.copy {
+            None
+        } else {
+            Some(read_input(&config).instrument(info_span!("read")).await?)
+        };
+
+        tokio::task::spawn_blocking(in_current_span(move || {
+            let (md, from_raw) = match buf {
+                Some(buf) => Self::metadata(&config, &buf)?,
+                None => info_span!("decode").in_scope(|| copied_metadata(&config)),
+            };
+
+            let rendered =
+                build_output_filename(&config, &md, from_raw, &output_extension(&config))?;
+            let warnings = check_rendered(&mut config, &rendered);
+
+            let output_path = config.output_dir.join(rendered.filename);
+
+            debug!("dry run: would've written: {}", output_path.display());
+
+            Ok(Outcome {
+                output_path,
+                empty_items: rendered.empty_items,
+                salvaged: None,
+                warnings,
+                cached: false,
+                conflict: None,
+                bytes_read: 0,
+                bytes_written: 0,
+                catalog_entry: None,
+            })
+        }))
+        .await
+        .map_err(join_error)?
+    }
+}
+
+/// Copies a `--copy` file into the output directory as-is, renamed with the filename format.
+///
+/// Files the raw decoders understand are named from their metadata like a conversion would be,
+/// anything else is named without it.
+pub struct CopyJob(JobConfig);
+
+#[async_trait]
+impl Job for CopyJob {
+    fn new(config: JobConfig) -> Self {
+        assert!(config.input_path.is_file());
+        assert!(config.copy);
+
+        Self(config)
+    }
+
+    async fn run(self) -> Result<Outcome, Error> {
+        let mut config = self.0;
+
+        tokio::task::spawn_blocking(in_current_span(move || {
+            let (md, from_raw) = info_span!("decode").in_scope(|| copied_metadata(&config));
+
+            let rendered =
+                build_output_filename(&config, &md, from_raw, &output_extension(&config))?;
+            let mut warnings = check_rendered(&mut config, &rendered);
+
+            let (output_path, conflict) =
+                match prepare_output_path(&config, &rendered.filename, &mut warnings)? {
+                    Destination::Write { path, conflict } => (path, conflict),
+                    Destination::Skip(output_path) => {
+                        return Ok(Outcome::skipped(
+                            output_path,
+                            rendered.empty_items,
+                            warnings,
+                            0,
+                        ));
+                    }
+                };
+
+            info!(
+                "Copying \"{}\" to \"{}\"",
+                config.source_path().display(),
+                output_path.display()
+            );
+            let _write = info_span!("write").entered();
+
+            let copied = map_err!(
+                copy_source(&config, &output_path),
+                dest_io_error,
+                format!("couldn't copy to {}", output_path.display()),
+            )?;
+            let taken = taken_at(&config, &md);
+            sync_output(&config, &output_path, taken)?;
+
+            // with --no-convert, a raw copied through keeps its sidecars as a DNG would
+            if args::is_raw(&config.input_path) {
+                carry_sidecars(&config, &output_path, taken, &mut warnings)?;
+            }
+
+            Ok(Outcome {
+                output_path: committed_path(&config, &output_path),
+                empty_items: rendered.empty_items,
+                salvaged: None,
+                warnings,
+                cached: false,
+                conflict,
+                bytes_read: copied,
+                bytes_written: copied,
+                catalog_entry: None,
+            })
+        }))
+        .await
+        .map_err(join_error)?
+    }
+}
+
+/// What an isolated worker process reports back to the batch that spawned it.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum IsolatedResult {
+    Converted(Outcome),
+    Failed(String),
+    Crashed(String),
+}
+
+impl IsolatedResult {
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_vec(self)
+            .map_err(|e| Error::Other("couldn't serialize job result".into(), Box::new(e)))?;
+
+        map_err!(
+            std_fs::write(path, json),
+            Error::Io,
+            format!("couldn't write job result: {}", path.display())
+        )
+    }
+}
+
+/// Runs the conversion in a child `rawbit` process, so that a crash in the decoder only takes
+/// down the one file that triggered it.
+pub struct IsolatedJob(JobConfig);
+
+#[async_trait]
+impl Job for IsolatedJob {
+    fn new(config: JobConfig) -> Self {
+        assert!(config.input_path.is_file());
+        assert!(config.isolation.is_some());
+
+        Self(config)
+    }
+
+    async fn run(self) -> Result<Outcome, Error> {
+        static N_SPAWNED: AtomicUsize = AtomicUsize::new(0);
+
         let config = self.0;
+        let worker_args = config.isolation.unwrap_or_default();
 
-        let input_file = OpenOptions::new()
-            .read(true)
-            .write(false)
-            .open(&config.input_path)
-            .await;
+        let result_path = temp_dir().join(format!(
+            "rawbit-{}-{}.json",
+            process::id(),
+            N_SPAWNED.fetch_add(1, Ordering::Relaxed)
+        ));
 
-        let mut input_file = map_err!(
-            input_file,
+        let exe = map_err!(
+            current_exe(),
             Error::Io,
-            format!("couldn't read file: {}", config.input_path.display())
+            "couldn't locate the rawbit executable"
         )?;
 
-        let mut buf = vec![];
-        map_err!(
-            input_file.read_to_end(&mut buf).await,
+        let chrono_index = config
+            .chrono_index
+            .map(|index| format!("{}/{}", index.index, index.of));
+        let seq = config.seq.map(|seq| seq.to_string());
+
+        let status = map_err!(
+            Command::new(exe)
+                .args(worker_args.iter())
+                .args(
+                    chrono_index
+                        .iter()
+                        .flat_map(|index| ["--chrono-index", index.as_str()]),
+                )
+                .args(seq.iter().flat_map(|seq| ["--seq", seq.as_str()]))
+                .args(
+                    config
+                        .member
+                        .iter()
+                        .flat_map(|member| ["--member", member.path.as_str()]),
+                )
+                .args(
+                    config
+                        .fallback_stem
+                        .iter()
+                        .flat_map(|stem| ["--fallback-stem", stem.as_str()]),
+                )
+                .args(
+                    config
+                        .staging
+                        .iter()
+                        .flat_map(|staging| [OsStr::new("--staging"), staging.as_os_str()]),
+                )
+                .arg("--run-id")
+                .arg(&*config.run_id)
+                .arg("--isolated")
+                .arg(&result_path)
+                .arg("--out-dir")
+                .arg(&config.output_dir)
+                .arg("--")
+                .arg(&config.input_path)
+                .status()
+                .await,
             Error::Io,
-            format!("couldn't read from file: '{}'", config.input_path.display())
+            "couldn't spawn isolated worker"
         )?;
 
-        let src = RawSource::new_from_slice(&buf[..]).with_path(&config.input_path);
+        let result = std_fs::read(&result_path)
+            .ok()
+            .and_then(|json| serde_json::from_slice::<IsolatedResult>(&json).ok());
 
-        let decoder = map_err!(get_decoder(&src), Error::ImgOp, "no available decoder")?;
+        let _ = std_fs::remove_file(&result_path);
 
-        const DECODE_PARAMS: RawDecodeParams = RawDecodeParams { image_index: 0 };
-        let md = map_err!(
-            decoder.raw_metadata(&src, &DECODE_PARAMS),
-            Error::ImgOp,
-            format!(
-                "error while retreiving metadata from RAW: {}",
-                config.input_path.display()
-            )
-        )?;
+        match result {
+            Some(IsolatedResult::Converted(outcome)) => Ok(outcome),
+            Some(IsolatedResult::Failed(err_str)) => Err(Error::Isolated(err_str)),
+            Some(IsolatedResult::Crashed(err_str)) => Err(Error::Panicked(err_str)),
+            None => Err(Error::Panicked(format!(
+                "isolated worker crashed ({status})"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod job_tests {
+    use std::{
+        fs, panic,
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use std::io;
+
+    use rawler::dng::{DngCompression, convert::ConvertParams};
+
+    use super::{
+        Error, IsolatedResult, Job as _, JobConfig, JpegPolicy, Outcome, Permissions,
+        RawConvertJob, Retry, dest_io_error, is_transient, panic_message,
+    };
+    use crate::{
+        catalog::Catalog,
+        conflict::OnConflict,
+        convert::{Options, convert_tests},
+        reprocess::EmbeddedOriginal,
+    };
+
+    #[test]
+    fn panic_message_reads_str_and_string_payloads() {
+        let payload = panic::catch_unwind(|| panic!("bad tile")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "bad tile");
+
+        let payload = panic::catch_unwind(|| panic!("bad tile {}", 3)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "bad tile 3");
+    }
+
+    #[test]
+    fn retries_only_errors_that_might_go_away() {
+        assert!(is_transient(&io::ErrorKind::TimedOut.into()));
+        assert!(is_transient(&io::ErrorKind::StaleNetworkFileHandle.into()));
+        #[cfg(unix)]
+        assert!(is_transient(&io::Error::from_raw_os_error(5)));
+
+        assert!(!is_transient(&io::ErrorKind::NotFound.into()));
+        assert!(!is_transient(&io::ErrorKind::PermissionDenied.into()));
+        assert!(!is_transient(&io::ErrorKind::InvalidData.into()));
+    }
+
+    #[test]
+    fn only_a_destination_that_went_away_pauses_the_batch() {
+        let dest = |kind: io::ErrorKind| dest_io_error(String::new(), kind.into());
+
+        assert!(matches!(
+            dest(io::ErrorKind::ReadOnlyFilesystem),
+            Error::DestUnavailable(..)
+        ));
+        assert!(matches!(
+            dest(io::ErrorKind::NotConnected),
+            Error::DestUnavailable(..)
+        ));
+
+        // e.g. --chown without root, or an output that can't be replaced
+        assert!(matches!(
+            dest(io::ErrorKind::PermissionDenied),
+            Error::Io(..)
+        ));
+    }
+
+    #[test]
+    fn isolated_result_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("result.json");
+
+        IsolatedResult::Converted(Outcome {
+            output_path: "out/a.dng".into(),
+            empty_items: vec![],
+            salvaged: None,
+            warnings: vec![],
+            cached: false,
+            conflict: None,
+            bytes_read: 0,
+            bytes_written: 0,
+            catalog_entry: None,
+        })
+        .write(&path)
+        .unwrap();
+
+        let json = std::fs::read(&path).unwrap();
+        assert!(matches!(
+            serde_json::from_slice(&json).unwrap(),
+            IsolatedResult::Converted(Outcome { output_path, .. }) if output_path.ends_with("a.dng")
+        ));
+    }
+
+    #[tokio::test]
+    async fn converts_a_dng_again_keeping_its_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("IMG_0001.DNG");
+        let dng = convert_tests::dng_with_original(Some(b"the camera's own raw"));
+        fs::write(&input, &dng).unwrap();
+
+        let options = Options::new("{image.original_filename}").unwrap();
+        let config = |output_dir: &str, embedded: bool| JobConfig {
+            input_path: input.clone(),
+            output_dir: dir.path().join(output_dir),
+            filename_format: options.filename_format.clone(),
+            lenses: options.lenses.clone(),
+            clocks: options.clocks.clone(),
+            on_conflict: OnConflict::Error,
+            claims: Arc::default(),
+            convert_opts: ConvertParams {
+                embedded,
+                compression: DngCompression::Uncompressed,
+                ..options.convert_opts.clone()
+            },
+            isolation: None,
+            run_id: options.run_id.clone(),
+            metadata: options.metadata,
+            salvage: false,
+            fallback: None,
+            copy: false,
+            unsorted: false,
+            catalog: None,
+            chrono_index: None,
+            seq: None,
+            member: None,
+            fallback_stem: None,
+            retry: Retry::default(),
+            staging: None,
+            fsync: false,
+            preserve_times: false,
+            mtime_from_exif: false,
+            backups: Vec::new(),
+            permissions: Permissions::default(),
+            link_archive: None,
+            intents: None,
+            sidecars: false,
+            jpeg_policy: JpegPolicy::Ignore,
+        };
+
+        // the DNG's original rather than the DNG itself
+        let kept = RawConvertJob::new(config("kept", true))
+            .run()
+            .await
+            .unwrap();
+        let original = EmbeddedOriginal::read(&dng);
+        assert!(original.is_some());
+        assert_eq!(
+            EmbeddedOriginal::read(&fs::read(kept.output_path).unwrap()),
+            original
+        );
+
+        let stripped = RawConvertJob::new(config("stripped", false))
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(
+            EmbeddedOriginal::read(&fs::read(stripped.output_path).unwrap()),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_conversions_the_catalog_has_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("IMG_0001.DNG");
+        fs::write(&input, convert_tests::dng()).unwrap();
+
+        let options = Options::new("%Y_").unwrap();
+        let catalog = Arc::new(Catalog::open(&dir.path().join("catalog.jsonl")).unwrap());
+        let config = JobConfig {
+            input_path: input,
+            output_dir: dir.path().join("out"),
+            filename_format: options.filename_format.clone(),
+            lenses: options.lenses.clone(),
+            clocks: options.clocks.clone(),
+            on_conflict: OnConflict::Error,
+            claims: Arc::default(),
+            convert_opts: options.convert_opts,
+            isolation: None,
+            run_id: options.run_id.clone(),
+            metadata: options.metadata,
+            salvage: false,
+            fallback: None,
+            copy: false,
+            unsorted: false,
+            catalog: Some(catalog),
+            chrono_index: None,
+            seq: None,
+            member: None,
+            fallback_stem: None,
+            retry: Retry::default(),
+            staging: None,
+            fsync: false,
+            preserve_times: false,
+            mtime_from_exif: false,
+            backups: Vec::new(),
+            permissions: Permissions::default(),
+            link_archive: None,
+            intents: None,
+            sidecars: false,
+            jpeg_policy: JpegPolicy::Ignore,
+        };
+
+        let first = RawConvertJob::new(config.clone()).run().await.unwrap();
+        assert!(!first.cached);
+
+        let second = RawConvertJob::new(config.clone()).run().await.unwrap();
+        assert!(second.cached);
+        assert!(second.output_path.ends_with("out/2024_IMG_0001.dng"));
+
+        // an edited output is converted again by a later run, or refused without --force
+        fs::write(&first.output_path, b"edited").unwrap();
+        let later = JobConfig {
+            claims: Arc::default(),
+            ..config
+        };
+        assert!(RawConvertJob::new(later).run().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn settles_conflicts_with_the_chosen_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("IMG_0001.DNG");
+        fs::write(&input, convert_tests::dng()).unwrap();
+
+        let output_dir = dir.path().join("out");
+        let existing = output_dir.join("IMG_0001.dng");
+        fs::create_dir(&output_dir).unwrap();
+        fs::write(&existing, b"already here").unwrap();
+
+        let options = Options::new("").unwrap();
+        let config = |on_conflict| JobConfig {
+            input_path: input.clone(),
+            output_dir: output_dir.clone(),
+            filename_format: options.filename_format.clone(),
+            lenses: options.lenses.clone(),
+            clocks: options.clocks.clone(),
+            on_conflict,
+            claims: Arc::default(),
+            convert_opts: options.convert_opts.clone(),
+            isolation: None,
+            run_id: options.run_id.clone(),
+            metadata: options.metadata,
+            salvage: false,
+            fallback: None,
+            copy: false,
+            unsorted: false,
+            catalog: None,
+            chrono_index: None,
+            seq: None,
+            member: None,
+            fallback_stem: None,
+            retry: Retry::default(),
+            staging: None,
+            fsync: false,
+            preserve_times: false,
+            mtime_from_exif: false,
+            backups: Vec::new(),
+            permissions: Permissions::default(),
+            link_archive: None,
+            intents: None,
+            sidecars: false,
+            jpeg_policy: JpegPolicy::Ignore,
+        };
+
+        assert!(
+            RawConvertJob::new(config(OnConflict::Error))
+                .run()
+                .await
+                .is_err()
+        );
+
+        let skipped = RawConvertJob::new(config(OnConflict::Skip))
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(skipped.conflict, Some(OnConflict::Skip));
+        assert_eq!(fs::read(&existing).unwrap(), b"already here");
+
+        // files renamed in the same batch don't take each other's numbers
+        let renaming = config(OnConflict::Rename);
+        let first = RawConvertJob::new(renaming.clone()).run().await.unwrap();
+        let second = RawConvertJob::new(renaming).run().await.unwrap();
+        assert_eq!(first.conflict, Some(OnConflict::Rename));
+        assert_eq!(first.output_path, output_dir.join("IMG_0001_001.dng"));
+        assert_eq!(second.output_path, output_dir.join("IMG_0001_002.dng"));
+
+        let overwritten = RawConvertJob::new(config(OnConflict::Overwrite))
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(overwritten.conflict, Some(OnConflict::Overwrite));
+        assert_ne!(fs::read(&existing).unwrap(), b"already here");
+    }
+
+    #[tokio::test]
+    async fn copies_each_output_into_the_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("IMG_0001.DNG");
+        fs::write(&input, convert_tests::dng()).unwrap();
+
+        let backups = vec![dir.path().join("nas"), dir.path().join("usb")];
+        let options = Options::new("%Y/").unwrap();
+        let config = JobConfig {
+            input_path: input,
+            output_dir: dir.path().join("out"),
+            filename_format: options.filename_format.clone(),
+            lenses: options.lenses.clone(),
+            clocks: options.clocks.clone(),
+            on_conflict: OnConflict::Error,
+            claims: Arc::default(),
+            convert_opts: options.convert_opts.clone(),
+            isolation: None,
+            run_id: options.run_id.clone(),
+            metadata: options.metadata,
+            salvage: false,
+            fallback: None,
+            copy: false,
+            unsorted: false,
+            catalog: None,
+            chrono_index: None,
+            seq: None,
+            member: None,
+            fallback_stem: None,
+            retry: Retry::default(),
+            staging: None,
+            fsync: false,
+            preserve_times: false,
+            mtime_from_exif: false,
+            backups: backups.clone(),
+            permissions: Permissions::default(),
+            link_archive: None,
+            intents: None,
+            sidecars: false,
+            jpeg_policy: JpegPolicy::Ignore,
+        };
+
+        let written = RawConvertJob::new(config).run().await.unwrap();
+        let dng = fs::read(&written.output_path).unwrap();
+
+        for backup in backups {
+            assert_eq!(fs::read(backup.join("2024/IMG_0001.dng")).unwrap(), dng);
+        }
+    }
+
+    #[tokio::test]
+    async fn carries_sidecars_renamed_to_match_the_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("IMG_0001.DNG");
+        fs::write(&input, convert_tests::dng()).unwrap();
+        fs::write(dir.path().join("IMG_0001.xmp"), b"xmp").unwrap();
+        fs::write(dir.path().join("IMG_0001.WAV"), b"wav").unwrap();
+        fs::write(dir.path().join("IMG_0002.xmp"), b"another").unwrap();
+
+        let options = Options::new("%Y_").unwrap();
+        let config = JobConfig {
+            input_path: input,
+            output_dir: dir.path().join("out"),
+            filename_format: options.filename_format.clone(),
+            lenses: options.lenses.clone(),
+            clocks: options.clocks.clone(),
+            on_conflict: OnConflict::Error,
+            claims: Arc::default(),
+            convert_opts: options.convert_opts.clone(),
+            isolation: None,
+            run_id: options.run_id.clone(),
+            metadata: options.metadata,
+            salvage: false,
+            fallback: None,
+            copy: false,
+            unsorted: false,
+            catalog: None,
+            chrono_index: None,
+            seq: None,
+            member: None,
+            fallback_stem: None,
+            retry: Retry::default(),
+            staging: None,
+            fsync: false,
+            preserve_times: false,
+            mtime_from_exif: false,
+            backups: Vec::new(),
+            permissions: Permissions::default(),
+            link_archive: None,
+            intents: None,
+            sidecars: true,
+            jpeg_policy: JpegPolicy::Ignore,
+        };
+
+        RawConvertJob::new(config.clone()).run().await.unwrap();
+
+        let out = dir.path().join("out");
+        let mut written = fs::read_dir(&out)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        written.sort();
+        assert_eq!(
+            written,
+            [
+                "2024_IMG_0001.dng",
+                "2024_IMG_0001.wav",
+                "2024_IMG_0001.xmp"
+            ]
+        );
+        assert_eq!(fs::read(out.join("2024_IMG_0001.xmp")).unwrap(), b"xmp");
+
+        // a sidecar in the way is refused like the DNG would be
+        fs::remove_file(out.join("2024_IMG_0001.dng")).unwrap();
+        let again = JobConfig {
+            claims: Arc::default(),
+            ..config
+        };
+        assert!(RawConvertJob::new(again).run().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn brings_the_jpeg_of_a_raw_jpeg_pair() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("IMG_0001.DNG");
+        fs::write(&input, convert_tests::dng()).unwrap();
+        fs::write(dir.path().join("IMG_0001.JPG"), b"jpeg").unwrap();
+
+        let options = Options::new("%Y_").unwrap();
+        let config = JobConfig {
+            input_path: input,
+            output_dir: dir.path().join("out"),
+            filename_format: options.filename_format.clone(),
+            lenses: options.lenses.clone(),
+            clocks: options.clocks.clone(),
+            on_conflict: OnConflict::Error,
+            claims: Arc::default(),
+            convert_opts: options.convert_opts.clone(),
+            isolation: None,
+            run_id: options.run_id.clone(),
+            metadata: options.metadata,
+            salvage: false,
+            fallback: None,
+            copy: false,
+            unsorted: false,
+            catalog: None,
+            chrono_index: None,
+            seq: None,
+            member: None,
+            fallback_stem: None,
+            retry: Retry::default(),
+            staging: None,
+            fsync: false,
+            preserve_times: false,
+            mtime_from_exif: false,
+            backups: Vec::new(),
+            permissions: Permissions::default(),
+            link_archive: None,
+            intents: None,
+            sidecars: false,
+            jpeg_policy: JpegPolicy::Rename,
+        };
+
+        for (policy, out, jpeg) in [
+            (JpegPolicy::Rename, "renamed", "2024_IMG_0001.jpg"),
+            (JpegPolicy::Copy, "copied", "IMG_0001.JPG"),
+        ] {
+            let config = JobConfig {
+                output_dir: dir.path().join(out),
+                claims: Arc::default(),
+                jpeg_policy: policy,
+                ..config.clone()
+            };
+            RawConvertJob::new(config).run().await.unwrap();
+
+            assert_eq!(fs::read(dir.path().join(out).join(jpeg)).unwrap(), b"jpeg");
+        }
+
+        let ignored = JobConfig {
+            output_dir: dir.path().join("ignored"),
+            jpeg_policy: JpegPolicy::Ignore,
+            ..config
+        };
+        RawConvertJob::new(ignored).run().await.unwrap();
+        assert_eq!(fs::read_dir(dir.path().join("ignored")).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_outputs_their_sources_times() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("IMG_0001.DNG");
+        fs::write(&input, convert_tests::dng()).unwrap();
+
+        let taken = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&input)
+            .unwrap()
+            .set_modified(taken)
+            .unwrap();
+
+        let options = Options::new("").unwrap();
+        let config = JobConfig {
+            input_path: input,
+            output_dir: dir.path().join("out"),
+            filename_format: options.filename_format.clone(),
+            lenses: options.lenses.clone(),
+            clocks: options.clocks.clone(),
+            on_conflict: OnConflict::Error,
+            claims: Arc::default(),
+            convert_opts: options.convert_opts.clone(),
+            isolation: None,
+            run_id: options.run_id.clone(),
+            metadata: options.metadata,
+            salvage: false,
+            fallback: None,
+            copy: false,
+            unsorted: false,
+            catalog: None,
+            chrono_index: None,
+            seq: None,
+            member: None,
+            fallback_stem: None,
+            retry: Retry::default(),
+            staging: None,
+            fsync: false,
+            preserve_times: true,
+            mtime_from_exif: false,
+            backups: Vec::new(),
+            permissions: Permissions::default(),
+            link_archive: None,
+            intents: None,
+            sidecars: false,
+            jpeg_policy: JpegPolicy::Ignore,
+        };
+
+        let written = RawConvertJob::new(config).run().await.unwrap();
+        let modified = fs::metadata(written.output_path)
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(modified, taken);
+    }
 
-        let output_fname = build_output_filename(&config.input_path, config.filename_format, &md);
+    #[tokio::test]
+    async fn numbers_names_another_file_in_the_batch_took() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("IMG_0001.DNG");
+        fs::write(&input, convert_tests::dng()).unwrap();
 
-        let output_path = config.output_dir.join(output_fname);
+        let output_dir = dir.path().join("out");
+        let options = Options::new("").unwrap();
+        let config = JobConfig {
+            input_path: input,
+            output_dir: output_dir.clone(),
+            filename_format: options.filename_format.clone(),
+            lenses: options.lenses.clone(),
+            clocks: options.clocks.clone(),
+            on_conflict: OnConflict::Error,
+            claims: Arc::default(),
+            convert_opts: options.convert_opts.clone(),
+            isolation: None,
+            run_id: options.run_id.clone(),
+            metadata: options.metadata,
+            salvage: false,
+            fallback: None,
+            copy: false,
+            unsorted: false,
+            catalog: None,
+            chrono_index: None,
+            seq: None,
+            member: None,
+            fallback_stem: None,
+            retry: Retry::default(),
+            staging: None,
+            fsync: false,
+            preserve_times: false,
+            mtime_from_exif: false,
+            backups: Vec::new(),
+            permissions: Permissions::default(),
+            link_archive: None,
+            intents: None,
+            sidecars: false,
+            jpeg_policy: JpegPolicy::Ignore,
+        };
 
-        info!("dry run: would've written DNG: {}", output_path.display());
+        let first = RawConvertJob::new(config.clone()).run().await.unwrap();
+        let second = RawConvertJob::new(config.clone()).run().await.unwrap();
+        assert_eq!(first.output_path, output_dir.join("IMG_0001.dng"));
+        assert_eq!(second.output_path, output_dir.join("IMG_0001_001.dng"));
+        assert!(
+            second
+                .warnings
+                .iter()
+                .any(|warning| warning.code() == "numbered")
+        );
 
-        Ok(())
+        // a later run still refuses to touch what's on disk
+        let later = JobConfig {
+            claims: Arc::default(),
+            ..config
+        };
+        assert!(RawConvertJob::new(later).run().await.is_err());
     }
 }
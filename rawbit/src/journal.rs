@@ -0,0 +1,137 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    args::IngestItem,
+    common::{AppError, RawbitResult, map_err},
+    paths,
+    state::StateDirs,
+};
+
+/// Files left over from an interrupted batch, so it can be picked back up with `--resume`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Journal {
+    /// where the batch was going, before any `--out-dir-fallback`
+    pub output_dir: PathBuf,
+    pub pending: Vec<IngestItem>,
+}
+
+impl Journal {
+    pub fn write(&self, state_dirs: &StateDirs) -> RawbitResult<PathBuf> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        let path = state_dirs
+            .journals_dir()?
+            .join(format!("interrupted-{started}.json"));
+
+        // written aside and renamed into place, so a crash can't leave half a journal
+        let partial = path.with_extension("json.partial");
+
+        let file = map_err!(
+            File::create(&partial),
+            AppError::Io,
+            format!("couldn't create journal: {}", partial.display())
+        )?;
+
+        serde_json::to_writer_pretty(&file, self).map_err(|e| {
+            AppError::Other(
+                format!("couldn't write journal: {}", partial.display()),
+                Box::new(e),
+            )
+        })?;
+
+        map_err!(
+            file.sync_all().and_then(|()| fs::rename(&partial, &path)),
+            AppError::Io,
+            format!("couldn't write journal: {}", path.display())
+        )?;
+
+        Ok(path)
+    }
+
+    pub fn read(path: &Path) -> RawbitResult<Self> {
+        let file = map_err!(
+            File::open(path),
+            AppError::Io,
+            format!("couldn't open journal: {}", path.display())
+        )?;
+
+        serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+            AppError::Other(
+                format!("couldn't read journal: {}", path.display()),
+                Box::new(e),
+            )
+        })
+    }
+
+    /// Makes sure the batch is resumed into the output directory it was interrupted writing to, the
+    /// rest of it would end up somewhere else otherwise.
+    pub fn check_output_dir(&self, output_dir: &Path) -> RawbitResult<()> {
+        let resolve = |dir: &Path| {
+            paths::canonicalize(dir)
+                .or_else(|_| std::path::absolute(dir))
+                .unwrap_or_else(|_| dir.to_path_buf())
+        };
+
+        if resolve(&self.output_dir) == resolve(output_dir) {
+            return Ok(());
+        }
+
+        Err(AppError::Config(format!(
+            "the batch being resumed was going to {}, not {}, resume it with --out-dir {0}",
+            self.output_dir.display(),
+            output_dir.display()
+        )))
+    }
+
+    pub fn remove(path: &Path) -> RawbitResult<()> {
+        map_err!(
+            fs::remove_file(path),
+            AppError::Io,
+            format!("couldn't remove journal: {}", path.display())
+        )
+    }
+}
+
+#[cfg(test)]
+mod journal_tests {
+    use std::io::Result;
+
+    use tempfile::tempdir;
+
+    use super::Journal;
+    use crate::{args::IngestItem, state::StateDirs};
+
+    #[test]
+    fn round_trips_pending_items() -> Result<()> {
+        let state = tempdir()?;
+        let state_dirs = StateDirs::resolve(Some(state.path().into()), None);
+
+        let journal = Journal {
+            output_dir: "out".into(),
+            pending: vec![IngestItem::from(("in/a.ARW", "nested"))],
+        };
+
+        let path = journal.write(&state_dirs).unwrap();
+        let read = Journal::read(&path).unwrap();
+
+        assert_eq!(read.pending.len(), 1);
+        assert_eq!(read.pending[0].output_prefix.to_string_lossy(), "nested");
+
+        read.check_output_dir("./out".as_ref()).unwrap();
+        assert!(read.check_output_dir("elsewhere".as_ref()).is_err());
+
+        Journal::remove(&path).unwrap();
+        assert!(!path.exists());
+
+        Ok(())
+    }
+}
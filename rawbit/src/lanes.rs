@@ -0,0 +1,228 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::{Mutex, MutexGuard},
+};
+
+use serde::Deserialize;
+use tokio::sync::oneshot;
+
+/// How urgently an import's files should be converted when several imports share the workers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Priority {
+    /// only runs on workers nothing else is waiting for
+    Background,
+    #[default]
+    Normal,
+    /// takes the next free worker ahead of everything else
+    Urgent,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    priority: Priority,
+    /// earlier arrivals go first within a priority, so imports in the same lane share fairly
+    arrival: Reverse<u64>,
+    wake: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        (self.priority, self.arrival) == (other.priority, other.arrival)
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.priority, self.arrival).cmp(&(other.priority, other.arrival))
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    free: usize,
+    arrivals: u64,
+    waiting: BinaryHeap<Waiter>,
+}
+
+/// Worker slots shared by every import in a run, handed out one file at a time.
+///
+/// A file that's already converting is never interrupted, but each time a worker frees up it goes
+/// to the most urgent file waiting, so an urgent import overtakes a background one at the next file
+/// boundary.
+#[derive(Debug)]
+pub struct Lanes(Mutex<State>);
+
+/// A worker slot, given back to the lanes when dropped.
+#[derive(Debug)]
+pub struct Permit<'a>(&'a Lanes);
+
+/// A place in line for a worker slot.
+///
+/// Dropped once the slot's been handed over but before it's turned into a [`Permit`], e.g. when
+/// the file waiting for it is given up on, it hands the slot on rather than losing it.
+#[derive(Debug)]
+struct Waiting<'a> {
+    lanes: &'a Lanes,
+    wake: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let Some(ref mut wake) = self.wake else {
+            return;
+        };
+
+        // nothing can be handed over once it's closed, so what's there now is all there'll be
+        wake.close();
+        if wake.try_recv().is_ok() {
+            self.lanes.release();
+        }
+    }
+}
+
+impl Lanes {
+    #[must_use]
+    pub const fn new(workers: usize) -> Self {
+        Self(Mutex::new(State {
+            free: workers,
+            arrivals: 0,
+            waiting: BinaryHeap::new(),
+        }))
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // the state is only ever left consistent, so a panic elsewhere can't have broken it
+        self.0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Waits for a worker slot.
+    pub async fn acquire(&self, priority: Priority) -> Permit<'_> {
+        let wait = {
+            let mut state = self.state();
+
+            if state.free > 0 && state.waiting.is_empty() {
+                state.free -= 1;
+                return Permit(self);
+            }
+
+            let (wake, wait) = oneshot::channel();
+            state.arrivals += 1;
+
+            let arrival = Reverse(state.arrivals);
+            state.waiting.push(Waiter {
+                priority,
+                arrival,
+                wake,
+            });
+
+            wait
+        };
+
+        let mut waiting = Waiting {
+            lanes: self,
+            wake: Some(wait),
+        };
+
+        // the sender is only dropped after it's been used to hand over a slot
+        if let Some(ref mut wake) = waiting.wake {
+            let _ = wake.await;
+        }
+
+        // the slot's the permit's to give back now
+        waiting.wake = None;
+        Permit(self)
+    }
+
+    fn release(&self) {
+        let mut state = self.state();
+
+        while let Some(waiter) = state.waiting.pop() {
+            if waiter.wake.send(()).is_ok() {
+                return;
+            }
+        }
+
+        state.free += 1;
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+#[cfg(test)]
+mod lanes_tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use futures::FutureExt as _;
+
+    use super::{Lanes, Priority};
+
+    #[tokio::test]
+    async fn frees_workers_to_the_most_urgent_waiter_first() {
+        let lanes = Arc::new(Lanes::new(1));
+        let order = Arc::new(Mutex::new(vec![]));
+
+        let held = lanes.acquire(Priority::Normal).await;
+
+        let mut tasks = vec![];
+        for (name, priority) in [
+            ("background", Priority::Background),
+            ("normal a", Priority::Normal),
+            ("urgent", Priority::Urgent),
+            ("normal b", Priority::Normal),
+        ] {
+            let (lanes, order) = (lanes.clone(), order.clone());
+
+            tasks.push(tokio::spawn(async move {
+                let _permit = lanes.acquire(priority).await;
+                order.lock().unwrap().push(name);
+            }));
+
+            // let each one queue up before the next
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        drop(held);
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(
+            *order.lock().unwrap(),
+            ["urgent", "normal a", "normal b", "background"]
+        );
+    }
+
+    #[test]
+    fn hands_on_a_slot_whose_waiter_gave_up() {
+        let lanes = Lanes::new(1);
+        let held = lanes.acquire(Priority::Normal).now_or_never().unwrap();
+
+        let mut waiting = Box::pin(lanes.acquire(Priority::Normal));
+        assert!((&mut waiting).now_or_never().is_none());
+
+        // the slot's handed to the waiter, which goes away before it gets to take it
+        drop(held);
+        drop(waiting);
+
+        assert!(lanes.acquire(Priority::Normal).now_or_never().is_some());
+    }
+}
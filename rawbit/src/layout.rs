@@ -0,0 +1,93 @@
+use std::fmt::{self, Display};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// A built-in directory structure for the output, see `--layout`.
+///
+/// Each is a directory template put in front of `--format`, so the format names the file within
+/// it, or the original name is kept if there's no format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// everything directly in the output directory
+    #[default]
+    Flat,
+    /// a directory per day, `2024-05-17/`
+    DateYmd,
+    /// nested year, month and day directories, `2024/05/17/`
+    #[value(name = "date-y/m/d")]
+    #[serde(rename = "date-y/m/d")]
+    DateNested,
+    /// Lightroom's "by date" import, `2024/2024-05-17/`
+    Lightroom,
+    /// a Capture One session's capture folder with a directory per day, `Capture/2024-05-17/`
+    CaptureOne,
+}
+
+impl Layout {
+    /// The directory template, in `--format` syntax.
+    #[must_use]
+    pub const fn directories(self) -> &'static str {
+        match self {
+            Self::Flat => "",
+            Self::DateYmd => "%Y-%m-%d/",
+            Self::DateNested => "%Y/%m/%d/",
+            Self::Lightroom => "%Y/%Y-%m-%d/",
+            Self::CaptureOne => "Capture/%Y-%m-%d/",
+        }
+    }
+
+    /// `format` placed in this layout's directories.
+    #[must_use]
+    pub fn apply(self, format: Option<&str>) -> Option<String> {
+        match (self, format) {
+            (Self::Flat, format) => format.map(str::to_string),
+            (layout, format) => Some(format!("{}{}", layout.directories(), format.unwrap_or(""))),
+        }
+    }
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Flat => "flat",
+            Self::DateYmd => "date-ymd",
+            Self::DateNested => "date-y/m/d",
+            Self::Lightroom => "lightroom",
+            Self::CaptureOne => "capture-one",
+        })
+    }
+}
+
+#[cfg(test)]
+mod layout_tests {
+    use rawler::decoders::RawMetadata;
+
+    use super::Layout;
+    use crate::parse::FilenameFormat;
+
+    #[test]
+    fn puts_the_format_in_the_layouts_directories() {
+        let mut md = RawMetadata::default();
+        md.exif.date_time_original = Some("2024:05:17 10:20:30".into());
+
+        let render = |layout: Layout, format: Option<&str>| {
+            let format = layout.apply(format).unwrap_or_default();
+            FilenameFormat::parse(&format)
+                .unwrap()
+                .render_filename("DSC01234", &md)
+        };
+
+        assert_eq!(render(Layout::Flat, None), "DSC01234");
+        assert_eq!(render(Layout::DateNested, None), "2024/05/17/DSC01234");
+        assert_eq!(
+            render(Layout::Lightroom, Some("{image.original_filename}_edit")),
+            "2024/2024-05-17/DSC01234_edit"
+        );
+        assert_eq!(
+            render(Layout::CaptureOne, None),
+            "Capture/2024-05-17/DSC01234"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use std::io::{self, Read, Seek, Write};
+
+use rawler::decoders::RawMetadata;
+use serde::{Deserialize, Serialize};
+
+use crate::tiff::{TAG_EXIF_IFD, Tiff};
+
+const EXIF_LENS_MAKE: u16 = 0xA433;
+const EXIF_LENS_MODEL: u16 = 0xA434;
+
+/// How far a recorded focal length can be from a profile's and still match it, in mm.
+const FOCAL_LENGTH_TOLERANCE: f64 = 0.5;
+
+/// Lens metadata for a manual or adapted lens, in the `[[lens-profile]]` tables of the config file.
+///
+/// ```toml
+/// [[lens-profile]]
+/// camera = "ILCE-7M3"
+/// focal-length = 35
+/// make = "Voigtlander"
+/// model = "Nokton 35mm F1.4"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LensProfile {
+    /// only match files from this camera model
+    pub camera: Option<String>,
+    /// only match files recorded at this focal length, for lenses with a chipped adapter
+    pub focal_length: Option<f64>,
+    pub make: Option<String>,
+    pub model: String,
+}
+
+impl LensProfile {
+    fn matches(&self, md: &RawMetadata) -> bool {
+        let camera = self
+            .camera
+            .as_ref()
+            .is_none_or(|camera| camera.eq_ignore_ascii_case(md.model.trim()));
+
+        let focal_length = self.focal_length.is_none_or(|mm| {
+            md.exif
+                .focal_length
+                .filter(|focal| focal.d != 0)
+                .is_some_and(|focal| {
+                    (f64::from(focal.n) / f64::from(focal.d) - mm).abs() < FOCAL_LENGTH_TOLERANCE
+                })
+        });
+
+        camera && focal_length
+    }
+}
+
+/// Where lens metadata comes from for files whose camera didn't record any.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lenses {
+    pub profiles: Vec<LensProfile>,
+    /// `--lens`, used when none of the profiles match
+    pub fallback: Option<String>,
+}
+
+/// The lens filled in for one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lens {
+    pub make: Option<String>,
+    pub model: String,
+}
+
+impl Lenses {
+    /// Fills in the lens for a file that doesn't name one, returning what was filled in.
+    ///
+    /// The first matching profile wins, then `--lens`. Lenses the camera did record are left alone.
+    pub fn fill(&self, md: &mut RawMetadata) -> Option<Lens> {
+        if md
+            .exif
+            .lens_model
+            .as_ref()
+            .is_some_and(|m| !m.trim().is_empty())
+        {
+            return None;
+        }
+
+        let lens = self
+            .profiles
+            .iter()
+            .find(|profile| profile.matches(md))
+            .map(|profile| Lens {
+                make: profile.make.clone(),
+                model: profile.model.clone(),
+            })
+            .or_else(|| {
+                self.fallback
+                    .clone()
+                    .map(|model| Lens { make: None, model })
+            })?;
+
+        md.exif.lens_model = Some(lens.model.clone());
+        if lens.make.is_some() {
+            md.exif.lens_make.clone_from(&lens.make);
+        }
+
+        Some(lens)
+    }
+}
+
+impl Lens {
+    /// Writes the lens into the EXIF directory of a finished DNG, adding one if there isn't any.
+    pub fn write<F: Read + Write + Seek>(&self, file: &mut F) -> io::Result<()> {
+        let mut tiff = Tiff::open(file)?;
+        let ifd0 = tiff.read_ifd0()?;
+
+        let (mut exif_entries, exif_next) = match ifd0.get(TAG_EXIF_IFD) {
+            Some(pointer) => {
+                let exif = tiff.read_ifd(tiff.pointer(pointer))?;
+                (exif.entries, exif.next)
+            }
+            None => (vec![], 0),
+        };
+
+        let mut lens_entries = vec![tiff.append_ascii(EXIF_LENS_MODEL, &self.model)?];
+        if let Some(ref make) = self.make {
+            lens_entries.push(tiff.append_ascii(EXIF_LENS_MAKE, make)?);
+        }
+
+        exif_entries.retain(|entry| !lens_entries.iter().any(|lens| lens.tag == entry.tag));
+        exif_entries.extend(lens_entries);
+
+        let exif_offset = tiff.append_ifd(exif_entries, exif_next)?;
+
+        let mut root_entries = ifd0.entries;
+        root_entries.retain(|entry| entry.tag != TAG_EXIF_IFD);
+        root_entries.push(tiff.pointer_entry(TAG_EXIF_IFD, exif_offset));
+
+        let offset = tiff.append_ifd(root_entries, ifd0.next)?;
+        tiff.set_ifd0_offset(offset)?;
+
+        tiff.flush()
+    }
+}
+
+#[cfg(test)]
+mod lens_tests {
+    use std::io::Cursor;
+
+    use rawler::{decoders::RawMetadata, formats::tiff::Rational};
+
+    use super::{Lens, LensProfile, Lenses};
+    use crate::tiff::{TAG_EXIF_IFD, Tiff, tiff_tests};
+
+    fn metadata(model: &str, focal_length: Option<u32>) -> RawMetadata {
+        let mut md = RawMetadata {
+            model: model.into(),
+            ..Default::default()
+        };
+        md.exif.focal_length = focal_length.map(|mm| Rational::new(mm * 10, 10));
+        md
+    }
+
+    #[test]
+    fn fills_only_missing_lenses_from_the_first_match() {
+        let lenses = Lenses {
+            profiles: vec![
+                LensProfile {
+                    camera: Some("ILCE-7M3".into()),
+                    focal_length: Some(35.0),
+                    make: Some("Voigtlander".into()),
+                    model: "Nokton 35".into(),
+                },
+                LensProfile {
+                    camera: Some("ilce-7m3".into()),
+                    focal_length: None,
+                    make: None,
+                    model: "Helios 44-2".into(),
+                },
+            ],
+            fallback: Some("Manual".into()),
+        };
+
+        let mut md = metadata("ILCE-7M3", Some(35));
+        let lens = lenses.fill(&mut md).unwrap();
+        assert_eq!(lens.make.as_deref(), Some("Voigtlander"));
+        assert_eq!(md.exif.lens_model.as_deref(), Some("Nokton 35"));
+
+        let mut md = metadata("ILCE-7M3", None);
+        assert_eq!(lenses.fill(&mut md).unwrap().model, "Helios 44-2");
+
+        let mut md = metadata("X-T4", Some(35));
+        assert_eq!(lenses.fill(&mut md).unwrap().model, "Manual");
+
+        md.exif.lens_model = Some("XF35mmF1.4 R".into());
+        assert!(lenses.fill(&mut md).is_none());
+        assert_eq!(md.exif.lens_model.as_deref(), Some("XF35mmF1.4 R"));
+    }
+
+    #[test]
+    fn writes_an_exif_directory_with_the_lens() {
+        for le in [true, false] {
+            let mut file = Cursor::new(tiff_tests::sample(le));
+
+            Lens {
+                make: Some("Zeiss".into()),
+                model: "Planar 50".into(),
+            }
+            .write(&mut file)
+            .unwrap();
+
+            let mut tiff = Tiff::open(file).unwrap();
+            let ifd0 = tiff.read_ifd0().unwrap();
+            let exif = *ifd0.get(TAG_EXIF_IFD).unwrap();
+            let exif = tiff.read_ifd(tiff.pointer(&exif)).unwrap();
+            let model = *exif.get(0xA434).unwrap();
+            let offset = tiff.offset(&model).unwrap() as usize;
+
+            let bytes = tiff.into_inner().into_inner();
+            assert_eq!(&bytes[offset..offset + 10], b"Planar 50\0");
+            assert_eq!(ifd0.entries.len(), 3);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright (c) Carter J. Canedy <cartercanedy42@gmail.com>
+// rawbit is free software, distributable under the terms of the MIT license
+// See https://raw.githubusercontent.com/cartercanedy/rawbit/refs/heads/master/LICENSE.txt
+
+#![deny(
+    clippy::all,
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::cargo,
+    clippy::cast_possible_wrap
+)]
+#![allow(
+    clippy::enum_glob_use,
+    clippy::multiple_crate_versions,
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc
+)]
+
+mod archive;
+mod args;
+mod artist;
+mod audit;
+mod card;
+mod catalog;
+mod clock;
+mod common;
+mod config;
+mod conflict;
+mod convert;
+mod decode;
+mod drive;
+mod durable;
+mod eject;
+mod exists;
+mod failover;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod fsck;
+mod ignore;
+mod imports;
+mod index;
+mod intents;
+mod job;
+mod journal;
+mod lanes;
+mod layout;
+mod lens;
+mod link;
+mod metadata;
+mod order;
+mod originals;
+mod parse;
+mod paths;
+mod perms;
+mod preview;
+mod provenance;
+mod repeats;
+mod report;
+mod reprocess;
+mod salvage;
+mod sanitize;
+mod sidecar;
+mod space;
+mod state;
+mod stem;
+mod tiff;
+mod trace;
+mod transaction;
+mod undersized;
+mod video;
+mod watch;
+
+pub use common::{AppError, RawbitResult};
+pub use convert::{Options, convert_bytes, convert_file, convert_item};
+pub use job::{Error, Outcome};
+pub use parse::{Error as FormatError, ErrorKind as FormatErrorKind, FilenameFormat};
+
+/// What the `rawbit` binary, the C API in `rawbit-ffi` and the fuzz targets are built from. None
+/// of it is a stable API, only what's exported above is.
+#[doc(hidden)]
+pub mod internals {
+    macro_rules! reexport {
+        ($($module:ident),+ $(,)?) => {
+            $(pub mod $module {
+                pub use crate::$module::*;
+            })+
+        };
+    }
+
+    reexport!(
+        archive,
+        args,
+        artist,
+        audit,
+        card,
+        catalog,
+        clock,
+        common,
+        config,
+        conflict,
+        convert,
+        decode,
+        eject,
+        exists,
+        failover,
+        fsck,
+        imports,
+        index,
+        intents,
+        job,
+        journal,
+        lanes,
+        lens,
+        link,
+        metadata,
+        order,
+        originals,
+        parse,
+        paths,
+        perms,
+        preview,
+        provenance,
+        repeats,
+        report,
+        salvage,
+        sidecar,
+        space,
+        state,
+        stem,
+        trace,
+        transaction,
+        undersized,
+        video,
+        watch,
+    );
+
+    #[cfg(feature = "ffi")]
+    pub mod ffi {
+        pub use crate::ffi::*;
+    }
+}
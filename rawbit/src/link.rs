@@ -0,0 +1,246 @@
+use std::{
+    fs, io, iter,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Datelike as _, NaiveDateTime};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    conflict::{MAX_SUFFIX, numbered},
+    paths,
+    perms::Permissions,
+};
+
+/// Where raws that don't say when they were taken are linked, inside the archive.
+pub const UNDATED_DIR: &str = "undated";
+
+/// How a raw is put into the `--link-originals` archive without copying it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkMode {
+    /// a second name for the same file
+    #[default]
+    Hardlink,
+    /// a copy sharing the file's blocks until either is changed, on btrfs, XFS and APFS
+    Reflink,
+}
+
+/// An archive of the raws themselves, dated `YYYY/MM/DD` by when each was taken, made of links to
+/// the files on the source rather than copies of them. See `--link-originals`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkArchive {
+    pub dir: PathBuf,
+    pub mode: LinkMode,
+}
+
+impl LinkArchive {
+    /// Where the raw named `name` and taken at `taken` goes in the archive.
+    #[must_use]
+    pub fn path_for(&self, name: &str, taken: Option<NaiveDateTime>) -> PathBuf {
+        let dated = taken.map_or_else(
+            || PathBuf::from(UNDATED_DIR),
+            |taken| {
+                PathBuf::from(format!(
+                    "{:04}/{:02}/{:02}",
+                    taken.year(),
+                    taken.month(),
+                    taken.day()
+                ))
+            },
+        );
+
+        self.dir.join(dated).join(name)
+    }
+
+    /// Links `source` into the archive at `path`, making the directories it needs with
+    /// `permissions`, and returns where it ended up. A link that's already there from an earlier
+    /// import is left as it is, a different raw with the same name gets numbered like
+    /// `--on-conflict rename` does.
+    pub fn link(
+        &self,
+        source: &Path,
+        path: &Path,
+        permissions: Permissions,
+    ) -> io::Result<PathBuf> {
+        if let Some(dir) = path.parent() {
+            permissions.create_dir_all(dir)?;
+        }
+
+        let candidates =
+            iter::once(path.to_path_buf()).chain((1..=MAX_SUFFIX).map(|n| numbered(path, n)));
+
+        for candidate in candidates {
+            let linked = match self.mode {
+                LinkMode::Hardlink => fs::hard_link(source, &candidate),
+                LinkMode::Reflink => reflink(source, &candidate),
+            };
+
+            match linked {
+                Ok(()) => return Ok(candidate),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    if same_contents(source, &candidate)? {
+                        return Ok(candidate);
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("every numbered name for {} is taken", path.display()),
+        ))
+    }
+
+    /// Makes sure the archive is on the same filesystem as `input_dir`, since neither kind of link
+    /// can cross from one to another.
+    pub fn check(&self, input_dir: &Path) -> Result<(), String> {
+        let archive = self
+            .dir
+            .ancestors()
+            .find(|dir| dir.exists())
+            .unwrap_or_else(|| Path::new("."));
+
+        match (paths::device(input_dir), paths::device(archive)) {
+            (Some(input), Some(archive)) if input != archive => Err(format!(
+                "{} isn't on the same filesystem as {}, the raws can't be linked into it",
+                self.dir.display(),
+                input_dir.display()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether the file at `path` is `source` already, or a copy of it.
+fn same_contents(source: &Path, path: &Path) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt as _;
+
+        let (source, path) = (fs::metadata(source)?, fs::metadata(path)?);
+        if (source.dev(), source.ino()) == (path.dev(), path.ino()) {
+            return Ok(true);
+        }
+    }
+
+    Ok(fs::read(source)? == fs::read(path)?)
+}
+
+/// `_IOW(0x94, 9, int)`, which libc doesn't have a constant for in the version we're on.
+#[cfg(target_os = "linux")]
+const FICLONE: libc::Ioctl = 0x4004_9409;
+
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, path: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd as _;
+
+    let from = fs::File::open(source)?;
+    let to = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+
+    // SAFETY: both descriptors are open for as long as the call
+    if unsafe { libc::ioctl(to.as_raw_fd(), FICLONE, from.as_raw_fd()) } == 0 {
+        return Ok(());
+    }
+
+    let err = io::Error::last_os_error();
+    drop(to);
+    let _ = fs::remove_file(path);
+
+    Err(err)
+}
+
+#[cfg(target_os = "macos")]
+fn reflink(source: &Path, path: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt as _};
+
+    let c_path = |path: &Path| CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other);
+    let (source, path) = (c_path(source)?, c_path(path)?);
+
+    // SAFETY: both paths are NUL-terminated
+    if unsafe { libc::clonefile(source.as_ptr(), path.as_ptr(), 0) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn reflink(_source: &Path, _path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflinks aren't supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod link_tests {
+    use std::fs;
+
+    use chrono::NaiveDate;
+
+    use super::{LinkArchive, LinkMode};
+    use crate::perms::Permissions;
+
+    #[test]
+    fn links_raws_into_dated_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("IMG_0001.CR3");
+        fs::write(&source, b"raw").unwrap();
+
+        let archive = LinkArchive {
+            dir: dir.path().join("archive"),
+            mode: LinkMode::Hardlink,
+        };
+        archive.check(dir.path()).unwrap();
+
+        let taken = NaiveDate::from_ymd_opt(2024, 5, 6)
+            .unwrap()
+            .and_hms_opt(7, 8, 9)
+            .unwrap();
+        let path = archive.path_for("IMG_0001.CR3", Some(taken));
+        assert_eq!(path, dir.path().join("archive/2024/05/06/IMG_0001.CR3"));
+        assert_eq!(
+            archive.path_for("IMG_0002.CR3", None),
+            dir.path().join("archive/undated/IMG_0002.CR3")
+        );
+
+        assert_eq!(
+            archive
+                .link(&source, &path, Permissions::default())
+                .unwrap(),
+            path
+        );
+        // imported again
+        assert_eq!(
+            archive
+                .link(&source, &path, Permissions::default())
+                .unwrap(),
+            path
+        );
+        assert_eq!(fs::read(&path).unwrap(), b"raw");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt as _;
+            assert_eq!(fs::metadata(&source).unwrap().nlink(), 2);
+        }
+
+        // a different raw by the same name, from another card, gets numbered
+        let other = dir.path().join("other/IMG_0001.CR3");
+        fs::create_dir(dir.path().join("other")).unwrap();
+        fs::write(&other, b"another raw").unwrap();
+        let numbered = archive.link(&other, &path, Permissions::default()).unwrap();
+        assert_eq!(numbered, path.with_file_name("IMG_0001_001.CR3"));
+        assert_eq!(fs::read(&numbered).unwrap(), b"another raw");
+        assert_eq!(
+            archive.link(&other, &path, Permissions::default()).unwrap(),
+            numbered
+        );
+    }
+}
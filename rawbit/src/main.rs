@@ -15,27 +15,86 @@
     clippy::module_name_repetitions
 )]
 
-use std::fmt::Display;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    io::{self, IsTerminal as _, Read as _, Write as _},
+    mem,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    slice,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
-use clap::Parser as _;
-use futures::future::join_all;
-use parse::FilenameFormat;
-use rawler::dng::{CropMode, DngCompression, convert::ConvertParams};
+use clap::{Parser as _, ValueEnum as _};
+use futures::{FutureExt as _, future::join_all};
+use rawler::dng::convert::ConvertParams;
 use rayon::{
     ThreadPoolBuilder,
     iter::{IntoParallelRefIterator as _, ParallelIterator as _},
 };
-use smlog::{Log, debug, error, ignore, log::LevelFilter, warn};
-use tokio::{fs, runtime::Builder};
+use smlog::{Log, debug, error, ignore, info, log::LevelFilter, warn};
+use tokio::runtime::Builder;
+use tracing::{Instrument as _, info_span};
 
-mod args;
-mod common;
-mod job;
-mod parse;
+use rawbit::{
+    FilenameFormat, Options,
+    internals::{
+        args::{
+            self, Command, DaemonCommand, Filters, ImportConfig, IngestItem, LogConfig, RawSource,
+            StateConfig,
+        },
+        artist,
+        audit::{self, Discrepancy},
+        card,
+        catalog::{self, Catalog},
+        clock::{Clocks, Zone},
+        common::{AppError, RawbitResult},
+        config::{Config, ConfigWatcher},
+        conflict::{Claims, OnConflict},
+        convert, decode,
+        eject::Volume,
+        exists::ExistsCmd,
+        failover::{self, Reconciled},
+        fsck::{self, Repair},
+        imports::BatchSpec,
+        index::{self, Index},
+        intents::{self, Intent, Intents},
+        job::{
+            self, CopyJob, DryRunJob, IsolatedJob, IsolatedResult, Job, JobConfig, Outcome,
+            RawConvertJob, Retry, Warning,
+        },
+        journal::Journal,
+        lanes::{Lanes, Priority},
+        lens::Lenses,
+        link::LinkArchive,
+        metadata::MetadataPolicy,
+        order,
+        originals::Originals,
+        parse::{ChronoIndex, MetadataKind},
+        paths,
+        perms::Permissions,
+        preview, provenance,
+        repeats::{Repeated, Repeats},
+        report::{FileReport, FileStatus, ImportReport, Meter, RunReport, Usage},
+        salvage::{ConvertFallback, Strategy},
+        sidecar::{self, JpegPolicy},
+        space,
+        state::StateDirs,
+        stem::{self, StemFallback},
+        trace,
+        transaction::{self, Transaction},
+        undersized::{self, Undersized, UndersizedPolicy},
+        video,
+        watch::{self, HotFolder, RECENT_CAPACITY, Recent, STATS_EVERY, StatusSocket, WatchStats},
+    },
+};
 
-use args::{ImportConfig, IngestItem, LogConfig};
-use common::{AppError, RawbitResult, map_err};
-use job::{DryRunJob, Job, JobConfig, RawConvertJob};
+const UPSTREAM_ISSUES: &str = "https://github.com/dnglab/dnglab/issues";
 
 fn main() -> Result<(), u32> {
     let args = ImportConfig::parse();
@@ -44,7 +103,14 @@ fn main() -> Result<(), u32> {
         verbose: verbose_logs,
     } = args.log_config;
 
-    let filter: LevelFilter = if quiet {
+    // logs share stdout with the preview, keep them out of machine-readable output
+    let quiet = quiet || args.json;
+    let to_stdout = args.stdout;
+
+    // nothing but the DNG can go to stdout, errors are written to stderr instead
+    let filter: LevelFilter = if to_stdout {
+        LevelFilter::Off
+    } else if quiet {
         ignore("rawler");
         LevelFilter::Error
     } else {
@@ -79,15 +145,11 @@ fn main() -> Result<(), u32> {
 
     match rt.block_on(run(args)) {
         Err(err) => {
-            use AppError::*;
-
-            let (err_str, cause, exit_code): (String, Option<&dyn Display>, _) = match err {
-                FmtStrParse(e) => (e.to_string(), None, 1),
-                Io(s, ref e) => (s, Some(e), 2),
-                DirNotFound(s, ref e) => (format!("{s}: {}", e.display()), None, 3),
-                AlreadyExists(s, ref e) => (format!("{s}: {}", e.display()), None, 4),
-                Other(s, ref e) => (s, Some(e), 5),
-            };
+            let (err_str, cause, exit_code) = err.describe();
+
+            if to_stdout {
+                eprintln!("error: {err_str}");
+            }
 
             error!("{err_str}");
             if let Some(cause) = cause {
@@ -102,105 +164,2195 @@ fn main() -> Result<(), u32> {
 }
 
 async fn run(args: ImportConfig) -> RawbitResult<()> {
-    let n_threads = args.n_threads();
+    if let Some(ref command) = args.command {
+        return run_command(command);
+    }
+
+    args.check()?;
+
+    // finishes the trace once the run's over
+    let _trace = args
+        .trace_output
+        .as_deref()
+        .map(trace::record)
+        .transpose()?;
+
+    let shared = Shared::new(&args)?;
+    let on_conflict = args.conflict_policy();
+    let assigned = Assigned::from(&args);
+
+    let cli = cli_settings(&args);
 
     let ImportConfig {
-        source,
+        mut source,
         output_dir,
-        fmt_str,
-        artist,
-        force,
-        embed,
         recurse,
-        no_preview,
-        no_thumbnail,
+        null,
         dry_run,
+        json,
+        isolated,
+        report,
+        stdout,
         ..
     } = args;
 
-    let ingest = source.ingest(recurse)?.leak();
+    source.read_files_from(null)?;
 
-    if output_dir.exists() {
-        if output_dir.is_dir() {
-            Ok(())
-        } else {
-            Err(AppError::AlreadyExists(
-                "destination path exists and isn't a directory".into(),
-                (&output_dir).into(),
-            ))
-        }
-    } else {
-        map_err!(
-            fs::create_dir_all(&output_dir).await,
-            AppError::Io,
-            "couldn't create destination directory"
+    if stdout {
+        return shared.convert_to_stdout(&source, &cli);
+    }
+
+    let (imports, concurrent) = if let Some(ref spec_path) = source.batch {
+        let spec = BatchSpec::load(spec_path)?;
+        (
+            spec_imports(&spec, &cli, recurse, on_conflict),
+            spec.concurrent,
         )
-    }?;
+    } else {
+        let import = Import {
+            label: String::new(),
+            source,
+            output_dir: output_dir
+                .or_else(|| shared.out_dir_relative.as_ref().map(|_| PathBuf::new()))
+                .expect("clap requires an output directory without --batch"),
+            overrides: cli,
+            recurse,
+            on_conflict,
+            priority: Priority::default(),
+            watch: shared.watch,
+        };
 
-    let fmt_str = fmt_str.map_or("", |s| s.leak() as &'static str);
-    let filename_format = Box::leak(Box::new(FilenameFormat::parse(fmt_str)?));
+        if let Some(ref result_path) = isolated {
+            return shared
+                .run_isolated_worker(import, result_path, assigned)
+                .await;
+        }
 
-    let opts = ConvertParams {
-        artist,
-        apply_scaling: false,
-        crop: CropMode::Best,
-        compression: DngCompression::Lossless,
-        embedded: embed,
-        index: 0,
-        preview: !no_preview,
-        thumbnail: !no_thumbnail,
-        software: "rawbit".into(),
-        ..Default::default()
+        (vec![import], false)
     };
 
-    for chunk in ingest.chunks(n_threads) {
-        let jobs = chunk
-            .par_iter()
-            .cloned()
-            .map(
-                |IngestItem {
-                     input_path,
-                     ref output_prefix,
-                 }| {
-                    let config = JobConfig {
-                        input_path,
-                        output_dir: output_dir.join(output_prefix),
-                        filename_format,
-                        force,
-                        convert_opts: opts.clone(),
-                    };
+    let results = shared.run_all(imports, concurrent).await;
 
-                    if dry_run {
-                        DryRunJob::new(config).run()
-                    } else {
-                        RawConvertJob::new(config).run()
-                    }
+    if let Some(ref intents) = shared.intents {
+        intents.close();
+    }
+
+    if let Some(ref path) = report {
+        write_report(path, &shared.run_id, &results)?;
+    }
+
+    let (planned, first_err) = summarize(results);
+
+    if dry_run && !(planned.is_empty() && first_err.is_some()) {
+        print_dry_run_preview(planned, json)?;
+    }
+
+    first_err.map_or(Ok(()), Err)
+}
+
+/// The settings given on the command line, which take precedence over the config file.
+fn cli_settings(args: &ImportConfig) -> Config {
+    Config {
+        format: args.fmt_str.clone(),
+        layout: args.layout,
+        missing_value: args.missing_value.clone(),
+        strict_format: args.strict_format.then_some(true),
+        counter_file: args.counter_file.clone(),
+        name_rules: args.name_rules,
+        replace_char: args.replace_char,
+        max_name_len: args.max_name_len,
+        normalize: args.normalize,
+        vars: args.set.clone(),
+        artist: args.artist.clone(),
+        lens: args.lens.clone(),
+        lens_profiles: args.lens_profiles.clone().unwrap_or_default(),
+        time_shifts: args.time_shift_for.clone(),
+        time_shift: args.time_shift,
+        date_source: args.date_source,
+        timezone: args.utc.then_some(Zone::UTC).or(args.timezone),
+        decode_params: args.decode_param.clone(),
+        copy: args.copy.clone(),
+        // only the config file sets these
+        ..Default::default()
+    }
+}
+
+/// Runs one of the subcommands, rather than an import.
+fn run_command(command: &Command) -> RawbitResult<()> {
+    match *command {
+        Command::Reconcile { ref fallback } => {
+            let Reconciled { moved, left } = failover::reconcile(fallback)?;
+            info!("moved {moved} file(s) back from {}", fallback.display());
+
+            if left > 0 {
+                warn!("{left} file(s) are still in {}", fallback.display());
+            }
+
+            Ok(())
+        }
+
+        Command::Fsck {
+            repair,
+            ref report,
+            ref state_config,
+        } => check_state(repair, report.as_deref(), state_config),
+
+        Command::Audit {
+            ref dir,
+            ref report,
+            ref state_config,
+        } => audit_archive(dir, report.as_deref(), state_config),
+
+        Command::Daemon {
+            command:
+                DaemonCommand::Status {
+                    json,
+                    ref state_config,
                 },
+        } => watch_status(json, state_config),
+    }
+}
+
+/// Prints how the `--watch` import keeping its state with `state_config` is doing.
+fn watch_status(json: bool, state_config: &StateConfig) -> RawbitResult<()> {
+    let state_dirs = StateDirs::resolve(
+        state_config.state_dir.clone(),
+        state_config.cache_dir.clone(),
+    );
+    let path = state_dirs.watch_socket_path()?;
+
+    let status = watch::query_status(&path).map_err(|err| {
+        AppError::Io(
+            format!("no --watch import is answering at {}", path.display()),
+            err,
+        )
+    })?;
+
+    if json {
+        let json = serde_json::to_string_pretty(&status).expect("a watch status always serializes");
+        println!("{json}");
+    } else {
+        println!("{status}");
+    }
+
+    Ok(())
+}
+
+fn spec_imports(
+    spec: &BatchSpec,
+    cli: &Config,
+    recurse: bool,
+    on_conflict: OnConflict,
+) -> Vec<Import> {
+    spec.imports
+        .iter()
+        .enumerate()
+        .map(|(i, import)| Import {
+            label: import.label(i),
+            source: import.source(),
+            output_dir: import.out_dir.clone(),
+            overrides: import.settings(cli),
+            recurse: import.recurse.unwrap_or(recurse),
+            on_conflict: import.conflict_policy().unwrap_or(on_conflict),
+            priority: import.priority,
+            watch: import.watch,
+        })
+        .collect()
+}
+
+/// Opens the run's intent log, pointing out the ones crashed runs left behind. A run that can't
+/// keep one only goes without `rawbit fsck`, so it isn't an error.
+fn open_intents(state_dirs: &StateDirs, run_id: &str, fsync: bool, owner: bool) -> Option<Intents> {
+    let opened = state_dirs.intents_dir().and_then(|dir| {
+        if owner
+            && let Ok((crashed, _)) = intents::unfinished(&dir)
+            && !crashed.is_empty()
+        {
+            warn!(
+                "{} earlier run(s) didn't finish, `rawbit fsck` cleans up after them",
+                crashed.len()
+            );
+        }
+
+        Intents::open(&dir, run_id, fsync, owner)
+    });
+
+    opened
+        .map_err(|err| warn!("not keeping an intent log: {}", err.describe().0))
+        .ok()
+}
+
+/// Runs `rawbit fsck`, failing if it only looked and found something to repair.
+fn check_state(
+    repair: bool,
+    report: Option<&Path>,
+    state_config: &StateConfig,
+) -> RawbitResult<()> {
+    let state_dirs = StateDirs::resolve(
+        state_config.state_dir.clone(),
+        state_config.cache_dir.clone(),
+    );
+
+    let found = fsck::fsck(&state_dirs, repair)?;
+
+    if found.running > 0 {
+        warn!(
+            "{} import(s) still running, leaving their files and the catalog alone",
+            found.running
+        );
+    }
+
+    for found in &found.repairs {
+        match found {
+            Repair::Partial { path, .. } => warn!("partial output: {}", path.display()),
+            Repair::Unconfirmed {
+                path,
+                checked: true,
+            } => info!("unconfirmed but complete: {}", path.display()),
+            Repair::Unconfirmed { path, .. } => {
+                warn!(
+                    "unconfirmed, its source couldn't be read: {}",
+                    path.display()
+                );
+            }
+            Repair::RolledBack { staging } => {
+                warn!("uncommitted batch: {}", staging.display());
+            }
+            Repair::RolledForward { staging, .. } => {
+                warn!("half-committed batch: {}", staging.display());
+            }
+            Repair::StaleRecord { path } => warn!("stale catalog record: {}", path.display()),
+            Repair::BrokenJournal { path } => warn!("broken journal: {}", path.display()),
+        }
+    }
+
+    if let Some(path) = report {
+        let written = serde_json::to_vec_pretty(&found)
+            .map_err(io::Error::from)
+            .and_then(|json| std::fs::write(path, json));
+
+        written.map_err(|err| {
+            AppError::Io(
+                format!("couldn't write fsck report: {}", path.display()),
+                err,
             )
-            .collect::<Vec<_>>();
+        })?;
+    }
 
-        join_all(jobs)
-            .await
-            .into_iter()
-            .zip(chunk.iter().map(|item| item.input_path.clone()))
-            .for_each(|(result, input_path)| {
-                if let Err(cvt_err) = result {
-                    use job::Error::*;
-
-                    let (err_str, cause): (&str, Option<&dyn Display>) = match cvt_err {
-                        AlreadyExists(ref err_str) => (err_str, None),
-                        Io(ref err_str, ref cause) => (err_str, Some(cause)),
-                        ImgOp(ref err_str, ref cause) => (err_str, Some(cause)),
-                        Other(ref err_str, ref cause) => (err_str, Some(cause)),
+    let to_repair = found
+        .repairs
+        .iter()
+        .filter(|found| !matches!(found, Repair::Unconfirmed { .. }))
+        .count();
+
+    if to_repair == 0 {
+        info!("nothing to repair");
+        Ok(())
+    } else if repair {
+        info!("made {to_repair} repair(s)");
+        Ok(())
+    } else {
+        Err(AppError::Discrepancies(format!(
+            "found {to_repair} thing(s) to repair, run again with --repair"
+        )))
+    }
+}
+
+/// Runs `rawbit audit`, failing if anything turned up.
+fn audit_archive(
+    dir: &Path,
+    report: Option<&Path>,
+    state_config: &StateConfig,
+) -> RawbitResult<()> {
+    let catalog = StateDirs::resolve(
+        state_config.state_dir.clone(),
+        state_config.cache_dir.clone(),
+    )
+    .existing_catalog_path()
+    .ok()
+    .filter(|path| path.exists());
+
+    if catalog.is_none() {
+        warn!("no catalog to check against, imports with --cache keep one");
+    }
+
+    let found = audit::audit(dir, catalog.as_deref())?;
+
+    for discrepancy in &found.discrepancies {
+        match discrepancy {
+            Discrepancy::Missing { path, manifest } => {
+                warn!("missing: {} (in {})", path.display(), manifest.display());
+            }
+            Discrepancy::ChecksumMismatch { path, manifest, .. } => {
+                warn!("altered: {} (in {})", path.display(), manifest.display());
+            }
+            Discrepancy::Uncataloged { path } => warn!("uncataloged: {}", path.display()),
+        }
+    }
+
+    if let Some(path) = report {
+        let written = serde_json::to_vec_pretty(&found)
+            .map_err(io::Error::from)
+            .and_then(|json| std::fs::write(path, json));
+
+        written.map_err(|err| {
+            AppError::Io(
+                format!("couldn't write audit report: {}", path.display()),
+                err,
+            )
+        })?;
+    }
+
+    let manifests = found.indexes.len() + usize::from(found.catalog.is_some());
+    info!(
+        "checked {} file(s) against {manifests} manifest(s)",
+        found.files
+    );
+
+    if found.discrepancies.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Discrepancies(format!(
+            "found {} discrepancies in {}",
+            found.discrepancies.len(),
+            found.root.display()
+        )))
+    }
+}
+
+/// Appends the run to the `--report` file.
+fn write_report(
+    path: &Path,
+    run_id: &str,
+    results: &[((String, PathBuf), RawbitResult<BatchReport>)],
+) -> RawbitResult<()> {
+    let imports = results
+        .iter()
+        .map(|((label, output_dir), result)| {
+            let (report, error) = match result {
+                Ok(report) => (Some(report), None),
+                Err(err) => (None, Some(err.describe().0)),
+            };
+
+            ImportReport {
+                label: label.clone(),
+                output_dir: output_dir.clone(),
+                converted: report.map_or(0, |r| r.converted),
+                cached: report.map_or(0, |r| r.cached),
+                skipped: report.map_or(0, |r| r.skipped),
+                salvaged: report.map_or(0, |r| r.salvaged),
+                failed: report.map_or(0, |r| r.failed),
+                undersized: report.map_or(0, |r| r.undersized),
+                failed_over_to: report.and_then(|r| r.failed_over_to.clone()),
+                error,
+                usage: report.map(|r| r.usage).unwrap_or_default(),
+                files: report.map(|r| r.files.clone()).unwrap_or_default(),
+                repeated: report.map(|r| r.repeated.clone()).unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    RunReport {
+        run_id,
+        finished: RunReport::now(),
+        imports,
+    }
+    .append(path)
+}
+
+/// Reports how each import in a `--batch` went, along with the first error any of them hit.
+fn summarize(
+    results: Vec<((String, PathBuf), RawbitResult<BatchReport>)>,
+) -> (Vec<(PathBuf, Outcome)>, Option<AppError>) {
+    let multiple = results.len() > 1;
+
+    let mut planned = vec![];
+    let mut first_err = None;
+
+    for ((label, output_dir), result) in results {
+        match result {
+            Ok(report) => {
+                if multiple {
+                    let (done, verb) = if report.planned.is_empty() {
+                        (report.converted, "converted")
+                    } else {
+                        (report.planned.len(), "planned")
                     };
 
-                    warn!("while processing \"{}\": {err_str}", input_path.display());
-                    if let Some(dbg) = cause {
-                        debug!("Cause of last error:\n{dbg}");
-                    }
+                    info!(
+                        "{label}: {done} {verb}, {} unchanged, {} skipped, {} salvaged, {} failed -> {}",
+                        report.cached,
+                        report.skipped,
+                        report.salvaged,
+                        report.failed,
+                        output_dir.display()
+                    );
+                }
+
+                if let Some(ref fallback) = report.failed_over_to {
+                    warn!(
+                        "some files were written to {} instead of {}, move them back with `rawbit reconcile {0}`",
+                        fallback.display(),
+                        output_dir.display()
+                    );
+                }
+
+                planned.extend(report.planned);
+            }
+
+            Err(err) => {
+                if multiple {
+                    error!("{label}: {}", err.describe().0);
                 }
+
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+
+    (planned, first_err)
+}
+
+/// Settings shared by every import in a run.
+#[allow(clippy::struct_excessive_bools)]
+struct Shared {
+    n_threads: usize,
+    /// workers shared by every import, so running several at once doesn't oversubscribe the CPU
+    lanes: Arc<Lanes>,
+    worker_args: Option<Vec<OsString>>,
+    run_id: Arc<str>,
+    state_dirs: StateDirs,
+    catalog: Option<Arc<Catalog>>,
+    /// what the run is about to write and has written, for `rawbit fsck`
+    intents: Option<Arc<Intents>>,
+    index: bool,
+    filters: Filters,
+    /// `--out-dir-relative`, each import's `output_dir` is empty when it's set
+    out_dir_relative: Option<PathBuf>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+    eject: bool,
+    /// copy raws under their new names instead of converting them, see `--no-convert`
+    no_convert: bool,
+    /// copy video clips along with the raws, see `--videos`
+    videos: bool,
+    /// copy each raw's sidecars along with it, see `--sidecars`
+    sidecars: bool,
+    jpeg_policy: JpegPolicy,
+    originals: Originals,
+    /// read outputs back before touching their raws, see `--verify`
+    verify: bool,
+    watch: bool,
+    audit_card: bool,
+    metadata: MetadataPolicy,
+    salvage: bool,
+    /// what's kept of files that can't be converted at all, see `--fallback`
+    convert_fallback: Option<ConvertFallback>,
+    unsorted: bool,
+    /// fail before converting anything if two inputs would get the same name
+    strict: bool,
+    retry: Retry,
+    transactional: bool,
+    fsync: bool,
+    preserve_times: bool,
+    mtime_from_exif: bool,
+    /// copies of every output, see `--backup-dir`
+    backups: Vec<PathBuf>,
+    permissions: Permissions,
+    link_archive: Option<LinkArchive>,
+    stem_fallback: Option<StemFallback>,
+    /// free space left over on the destination, see `--min-free-space`
+    min_free_space: u64,
+    /// raws smaller than this are left out, see `--min-size`
+    min_size: u64,
+    on_undersized: UndersizedPolicy,
+    /// the staging directory an isolated worker writes to, given by its parent
+    staging: Option<PathBuf>,
+    exists_cmd: Option<ExistsCmd>,
+    fallback: Option<PathBuf>,
+    convert_opts: ConvertParams,
+}
+
+/// One source/destination pair, either from the command line or a `--batch` spec.
+struct Import {
+    label: String,
+    source: RawSource,
+    output_dir: PathBuf,
+    /// settings that take precedence over the config file
+    overrides: Config,
+    recurse: bool,
+    on_conflict: OnConflict,
+    priority: Priority,
+    /// keep watching the input directory after the first pass
+    watch: bool,
+}
+
+impl Shared {
+    fn new(args: &ImportConfig) -> RawbitResult<Self> {
+        let n_threads = args.n_threads();
+        let state_dirs = StateDirs::resolve(
+            args.state_config.state_dir.clone(),
+            args.state_config.cache_dir.clone(),
+        );
+
+        let catalog = args
+            .cache
+            .then(|| Catalog::open(&state_dirs.catalog_path()?))
+            .transpose()?
+            .map(Arc::new);
+
+        let run_id: Arc<str> = args
+            .run_id
+            .clone()
+            .unwrap_or_else(provenance::new_run_id)
+            .into();
+
+        let intents = (!args.dry_run)
+            .then(|| open_intents(&state_dirs, &run_id, args.fsync, args.isolated.is_none()))
+            .flatten()
+            .map(Arc::new);
+
+        Ok(Self {
+            n_threads,
+            lanes: Arc::new(Lanes::new(n_threads)),
+            worker_args: args.isolate_jobs.then(|| args.isolated_worker_args()),
+            run_id,
+            state_dirs,
+            catalog,
+            intents,
+            index: args.index,
+            filters: ingest_filters(
+                &args.include,
+                args.exclude.clone(),
+                args.out_dir_relative.as_deref(),
+            )?
+            .skipping_symlinks(args.no_follow_symlinks)
+            .limiting_depth(args.max_depth),
+            out_dir_relative: args.out_dir_relative.clone(),
+            // the isolated worker gets its settings from the parent, on the command line
+            config_path: args
+                .config
+                .clone()
+                .or_else(|| Config::default_path().filter(|path| path.exists()))
+                .filter(|_| args.isolated.is_none()),
+            dry_run: args.dry_run,
+            eject: args.eject,
+            no_convert: args.no_convert,
+            videos: args.videos,
+            sidecars: args.sidecars,
+            jpeg_policy: args.jpeg_policy,
+            originals: args.originals.clone().unwrap_or_default(),
+            verify: args.verify,
+            watch: args.watch,
+            audit_card: args.audit_card,
+            metadata: args.metadata,
+            salvage: args.salvage,
+            convert_fallback: args.fallback,
+            unsorted: args.unsorted,
+            strict: args.strict,
+            retry: Retry {
+                retries: args.retries,
+                delay: Duration::from_millis(args.retry_delay),
+            },
+            transactional: args.transactional,
+            fsync: args.fsync,
+            preserve_times: args.preserve_times,
+            mtime_from_exif: args.mtime_from_exif,
+            backups: args.backup_dir.clone(),
+            permissions: args.permissions(),
+            link_archive: args.link_archive(),
+            stem_fallback: args.stem_fallback,
+            min_free_space: args.min_free_space.unwrap_or_default(),
+            min_size: args.min_size,
+            on_undersized: args.on_undersized,
+            staging: args.staging.clone(),
+            exists_cmd: args.exists_cmd.clone().map(ExistsCmd::new),
+            fallback: args.out_dir_fallback.clone(),
+            convert_opts: ConvertParams {
+                compression: args.compression.into(),
+                ..convert::convert_params(args.embed, !args.no_preview, !args.no_thumbnail)
+            },
+        })
+    }
+
+    /// The extensions copied through as-is, with `--copy` and the raws and videos that
+    /// `--no-convert` and `--videos` add to them.
+    fn copied(&self, settings: &Config) -> Vec<String> {
+        let mut copy = settings.copy.clone();
+
+        if self.videos {
+            copy.extend(video::VIDEO_EXTENSIONS.iter().map(ToString::to_string));
+        }
+
+        if self.no_convert {
+            copy = args::with_raws(copy);
+        }
+
+        copy
+    }
+
+    /// Keeps scans of the source out of the import's output directory when it's inside it, and
+    /// warns about the other ways the two can overlap.
+    fn import_filters(&self, import: &Import) -> Filters {
+        let filters = self.filters.clone();
+
+        // --out-dir-relative directories are already excluded
+        let Some(ref input_dir) = import.source.input_dir else {
+            return filters;
+        };
+        if import.output_dir.as_os_str().is_empty() {
+            return filters;
+        }
+
+        let input = args::resolve(input_dir);
+        let output = args::resolve(&import.output_dir);
+
+        if output == input {
+            warn!(
+                "writing into the source directory {}, importing it again will pick up the converted files too",
+                input.display()
+            );
+        } else if output.starts_with(&input) {
+            warn!(
+                "the output directory {} is inside the source, leaving it out of the scan",
+                output.display()
+            );
+            return filters.skipping_dir(&output);
+        } else if input.starts_with(&output) {
+            warn!(
+                "the source directory {} is inside the output directory, files the format puts in it won't be imported again",
+                input.display()
+            );
+        }
+
+        filters
+    }
+
+    /// Converts the one file given, or the raw piped in for `-`, and writes its DNG to stdout.
+    fn convert_to_stdout(&self, source: &RawSource, cli: &Config) -> RawbitResult<()> {
+        let settings = match self.config_path {
+            Some(ref path) => Config::load(path)?.under(cli),
+            None => cli.clone(),
+        };
+
+        let input = source
+            .files
+            .as_deref()
+            .and_then(<[PathBuf]>::first)
+            .map_or_else(|| Path::new("-"), PathBuf::as_path);
+
+        let (raw, name) = if input == Path::new("-") {
+            let mut raw = vec![];
+            io::stdin()
+                .lock()
+                .read_to_end(&mut raw)
+                .map_err(|err| AppError::Io("couldn't read the raw from stdin".into(), err))?;
+
+            // the decoders only need the contents, the name is recorded in the DNG
+            (raw, "stdin".to_string())
+        } else {
+            let raw = std::fs::read(input)
+                .map_err(|err| AppError::Io(format!("couldn't read {}", input.display()), err))?;
+
+            let name = input.file_name().unwrap_or_default().to_string_lossy();
+            (raw, name.into_owned())
+        };
+
+        let options = Options {
+            lenses: Arc::new(settings.lenses()),
+            clocks: Arc::new(settings.clocks()),
+            convert_opts: decode::apply(
+                &settings.decode_params,
+                ConvertParams {
+                    artist: settings.artist_tag(),
+                    ..self.convert_opts.clone()
+                },
+            ),
+            metadata: self.metadata,
+            run_id: self.run_id.clone(),
+            ..Options::default()
+        };
+
+        let dng = rawbit::convert_bytes(&raw, &name, &options).map_err(|err| {
+            let message = err.message();
+            AppError::Other(
+                format!("couldn't convert {name}: {message}"),
+                message.into(),
+            )
+        })?;
+
+        let mut stdout = io::stdout().lock();
+        stdout
+            .write_all(&dng)
+            .and_then(|()| stdout.flush())
+            .map_err(|err| AppError::Io("couldn't write the DNG to stdout".into(), err))
+    }
+
+    fn batch(&self, import: &Import) -> RawbitResult<Batch> {
+        let settings = match self.config_path {
+            Some(ref path) => Config::load(path)?.under(&import.overrides),
+            None => import.overrides.clone(),
+        };
+
+        Ok(Batch {
+            output_dir: import.output_dir.clone(),
+            filename_format: filename_format(&settings)?,
+            lenses: Arc::new(settings.lenses()),
+            clocks: Arc::new(settings.clocks()),
+            copy: self.copied(&settings),
+            filters: self.import_filters(import),
+            on_conflict: import.on_conflict,
+            // a dry run doesn't write anything to the destination, not even to probe it
+            claims: Arc::new(Mutex::new(if self.dry_run {
+                Claims::default()
+            } else {
+                Claims::writing()
+            })),
+            lanes: self.lanes.clone(),
+            priority: import.priority,
+            dry_run: self.dry_run,
+            convert_opts: decode::apply(
+                &settings.decode_params,
+                ConvertParams {
+                    artist: settings.artist_tag(),
+                    ..self.convert_opts.clone()
+                },
+            ),
+            isolation: self
+                .worker_args
+                .as_deref()
+                .map(|base| isolation_args(base, &settings)),
+            worker_args: self.worker_args.clone(),
+            run_id: self.run_id.clone(),
+            metadata: self.metadata,
+            salvage: self.salvage,
+            convert_fallback: self.convert_fallback,
+            unsorted: self.unsorted,
+            retry: self.retry,
+            staging: self.staging.clone().or_else(|| {
+                self.transactional
+                    .then(|| transaction::staging_dir(&import.output_dir, &self.run_id))
+            }),
+            fsync: self.fsync,
+            preserve_times: self.preserve_times,
+            mtime_from_exif: self.mtime_from_exif,
+            backups: self.backups.clone(),
+            permissions: self.permissions,
+            link_archive: self.link_archive.clone(),
+            sidecars: self.sidecars,
+            jpeg_policy: self.jpeg_policy,
+            stem_fallback: self.stem_fallback,
+            seq_counter: settings
+                .counter_file
+                .as_ref()
+                .map(|path| import.output_dir.join(path)),
+            catalog: self.catalog.clone(),
+            intents: self.intents.clone(),
+            fallback: self.fallback.clone(),
+            primary: None,
+            busy: AtomicU64::new(0),
+            cli: import.overrides.clone(),
+            config: self.config_path.clone().map(ConfigWatcher::new),
+        })
+    }
+
+    async fn run_all(
+        &self,
+        imports: Vec<Import>,
+        concurrent: bool,
+    ) -> Vec<((String, PathBuf), RawbitResult<BatchReport>)> {
+        if concurrent {
+            return join_all(imports.into_iter().map(|import| self.run_labeled(import))).await;
+        }
+
+        let mut results = vec![];
+        for import in imports {
+            results.push(self.run_labeled(import).await);
+        }
+
+        results
+    }
+
+    async fn run_labeled(&self, import: Import) -> ((String, PathBuf), RawbitResult<BatchReport>) {
+        let label = (import.label.clone(), import.output_dir.clone());
+        let span = info_span!(
+            parent: None,
+            "import",
+            label = %import.label,
+            output_dir = %import.output_dir.display()
+        );
+
+        (label, self.run_import(import).instrument(span).await)
+    }
+
+    async fn run_import(&self, import: Import) -> RawbitResult<BatchReport> {
+        if !import.label.is_empty() {
+            info!(
+                "{}: importing into {}",
+                import.label,
+                import.output_dir.display()
+            );
+        }
+
+        let mut batch = self.batch(&import)?;
+        let Import {
+            source,
+            recurse,
+            watch,
+            ..
+        } = import;
+
+        // watching starts first, so nothing that turns up during the first pass is missed
+        let watch_dir = source.input_dir.clone().filter(|_| watch);
+        let hot_folder = watch_dir
+            .as_deref()
+            .map(|dir| HotFolder::watch(dir, recurse, watch::DEFAULT_SETTLE))
+            .transpose()?;
+
+        let resumed_from = source.resume.clone();
+        if let Some(ref journal) = resumed_from {
+            Journal::read(journal)?.check_output_dir(&batch.output_dir)?;
+        }
+
+        let input_dir = source.input_dir.clone();
+        self.check_originals_dir(input_dir.as_deref())?;
+
+        if let (Some(archive), Some(input_dir)) = (&self.link_archive, &input_dir) {
+            archive.check(input_dir).map_err(AppError::Config)?;
+        }
+
+        let scanned =
+            info_span!("scan").in_scope(|| source.ingest(recurse, &batch.copy, &batch.filters))?;
+
+        // everything up to converting, closed once it's done
+        let plan = info_span!("plan", files = scanned.len());
+        let (mut ingest, undersized) = undersized::partition(scanned, self.min_size);
+        let undersized = self.set_aside(&batch, undersized)?;
+
+        // brought along by their raws instead
+        if self.jpeg_policy != JpegPolicy::Ignore {
+            ingest.retain(|item| !(item.copy && sidecar::is_paired_jpeg(&item.input_path)));
+        }
+
+        if let Some(ref exists_cmd) = self.exists_cmd {
+            ingest = exists_cmd.retain_new(ingest, self.n_threads).await;
+        }
+
+        // a resumed batch keeps the numbers it was given when it started
+        if resumed_from.is_none() {
+            number_batch(&batch, &mut ingest, 0)?;
+        }
+
+        if let Some(ref subdir) = self.out_dir_relative {
+            args::anchor_to_sources(&mut ingest, subdir);
+        } else {
+            prepare_output_dir(&batch.output_dir, self.dry_run, batch.permissions)?;
+        }
+
+        if !self.dry_run {
+            let destinations = ingest.iter().flat_map(|item| {
+                let size = space::estimate(item, batch.convert_opts.embedded);
+                let output = batch.output_dir.join(&item.output_prefix);
+                let backups = batch
+                    .backups
+                    .iter()
+                    .map(|dir| dir.join(&item.output_prefix));
+
+                std::iter::once(output)
+                    .chain(backups)
+                    .map(move |destination| (destination, size))
             });
+
+            space::check(destinations, self.min_free_space)?;
+        }
+
+        if self.audit_card {
+            audit_card(&ingest, &batch.output_dir, self.dry_run)?;
+        }
+
+        // naming everything first reads every file one more time, only worth it to stop early
+        if self.strict {
+            batch.preflight(&ingest, self.n_threads).await?;
+        }
+        drop(plan);
+
+        let report = batch.run(&ingest, self.n_threads, &self.state_dirs).await;
+        let mut report = batch.settle(report)?;
+
+        report.undersized = undersized.len();
+        report.files.extend(undersized);
+
+        self.originals
+            .apply(&report.files, input_dir.as_deref(), self.verify);
+
+        if self.index && !self.dry_run {
+            index_outputs(&batch.output_dir, &ingest)?;
+        }
+
+        if let (Some(dir), Some(hot_folder)) = (watch_dir, hot_folder) {
+            return self
+                .watch(&mut batch, hot_folder, &dir, recurse, &ingest, &report)
+                .await;
+        }
+
+        if let Some(ref journal) = resumed_from.filter(|_| !self.dry_run) {
+            Journal::remove(journal)?;
+            info!("resumed batch finished, removed {}", journal.display());
+        }
+
+        if self.eject {
+            if report.failed == 0 {
+                eject_source(&ingest).await;
+            } else {
+                warn!(
+                    "not ejecting the source, {} file(s) failed to convert",
+                    report.failed
+                );
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Deals with the inputs too small to import as `--on-undersized` says, before anything's
+    /// converted.
+    fn set_aside(
+        &self,
+        batch: &Batch,
+        undersized: Vec<Undersized>,
+    ) -> RawbitResult<Vec<FileReport>> {
+        for file in &undersized {
+            warn!(
+                "\"{}\": {}",
+                file.item.source_path().display(),
+                file.reason()
+            );
+        }
+
+        if self.on_undersized == UndersizedPolicy::Fail && !undersized.is_empty() {
+            return Err(AppError::Undersized(format!(
+                "{} file(s) are too small to import, see --min-size and --on-undersized",
+                undersized.len()
+            )));
+        }
+
+        undersized
+            .into_iter()
+            .map(|mut file| {
+                let output = if self.on_undersized == UndersizedPolicy::Quarantine && !self.dry_run
+                {
+                    if let Some(ref subdir) = self.out_dir_relative {
+                        args::anchor_to_sources(slice::from_mut(&mut file.item), subdir);
+                    }
+
+                    let quarantined = file
+                        .quarantine(&batch.output_dir, batch.permissions)
+                        .map_err(|err| {
+                            AppError::Io(
+                                format!(
+                                    "couldn't quarantine \"{}\"",
+                                    file.item.source_path().display()
+                                ),
+                                err,
+                            )
+                        })?;
+
+                    info!("quarantined {}", quarantined.display());
+                    Some(quarantined)
+                } else {
+                    None
+                };
+
+                Ok(FileReport {
+                    source: file.item.source_path(),
+                    output,
+                    status: FileStatus::Undersized,
+                    conflict: None,
+                    warnings: vec![],
+                    error: Some(file.reason()),
+                })
+            })
+            .collect()
+    }
+
+    /// Makes sure `--originals move:DIR` doesn't move raws somewhere they'd be imported again.
+    fn check_originals_dir(&self, input_dir: Option<&Path>) -> RawbitResult<()> {
+        let (Originals::Move(dir), Some(input_dir)) = (&self.originals, input_dir) else {
+            return Ok(());
+        };
+
+        let archive = paths::canonicalize(dir).or_else(|_| std::path::absolute(dir));
+        let input_dir = paths::canonicalize(input_dir);
+
+        if let (Ok(archive), Ok(input_dir)) = (archive, input_dir)
+            && archive.starts_with(&input_dir)
+        {
+            return Err(AppError::Config(format!(
+                "--originals can't move raws into {}, it's inside the input directory",
+                archive.display()
+            )));
+        }
+
+        Ok(())
     }
 
+    /// Converts files as they turn up in `dir`, until rawbit is interrupted.
+    async fn watch(
+        &self,
+        batch: &mut Batch,
+        mut hot_folder: HotFolder,
+        dir: &Path,
+        recurse: bool,
+        ingested: &[IngestItem],
+        first_pass: &BatchReport,
+    ) -> RawbitResult<BatchReport> {
+        let canonical =
+            |path: &Path| paths::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let root = canonical(dir);
+
+        // the first pass may already have converted files the watcher reports, and DNGs written
+        // inside the watched folder shouldn't come back around as new raws
+        let mut seen = Recent::new(RECENT_CAPACITY);
+        seen.extend(ingested.iter().map(|item| canonical(&item.input_path)));
+        let mut outputs = Recent::new(RECENT_CAPACITY);
+        outputs.extend(written(&first_pass.files).map(&canonical));
+
+        let mut last_seq = order::last_in_sequence(ingested);
+        let mut stats = WatchStats::default();
+        (stats.converted, stats.failed) = (first_pass.converted, first_pass.failed);
+        let stats = Arc::new(Mutex::new(stats));
+        let mut stats_every =
+            tokio::time::interval_at(tokio::time::Instant::now() + STATS_EVERY, STATS_EVERY);
+
+        // the watch only goes without answering `rawbit daemon status`
+        let _status_socket = self.state_dirs.watch_socket_path().ok().and_then(|path| {
+            StatusSocket::serve(&path, dir, Arc::clone(&stats))
+                .inspect_err(|err| {
+                    warn!(
+                        "`rawbit daemon status` can't ask this watch how it's doing, {}: {err}",
+                        path.display()
+                    );
+                })
+                .ok()
+        });
+
+        info!("watching {} for new files", dir.display());
+
+        loop {
+            stats
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .watching(hot_folder.pending(), seen.len() + outputs.len());
+
+            let settled = tokio::select! {
+                settled = hot_folder.next_settled() => settled,
+                _ = stats_every.tick() => {
+                    let mut watched = stats.lock().unwrap_or_else(PoisonError::into_inner);
+                    watched.watching(hot_folder.pending(), seen.len() + outputs.len());
+                    let doing = watched.status(dir);
+                    drop(watched);
+
+                    info!("{doing}");
+                    continue;
+                }
+            };
+
+            // a card put back in may well be another one, with files at the same paths
+            if hot_folder.remounted() {
+                seen.clear();
+            }
+
+            let mut items = settled
+                .into_iter()
+                .map(|path| canonical(&path))
+                .filter(|path| !outputs.contains(path) && seen.insert(path.clone()))
+                .flat_map(|path| {
+                    RawSource::watched_items(&root, &path, recurse, &batch.copy, &batch.filters)
+                })
+                .collect::<Vec<_>>();
+
+            if let Some(ref exists_cmd) = self.exists_cmd {
+                items = exists_cmd.retain_new(items, self.n_threads).await;
+            }
+
+            if items.is_empty() {
+                continue;
+            }
+
+            // names the last round handed out are on disk now, where --on-conflict deals with them
+            batch
+                .claims
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear();
+
+            // without a counter file, `{seq}` still carries on from the last round
+            number_batch(batch, &mut items, last_seq)?;
+            last_seq = last_seq.max(order::last_in_sequence(&items));
+
+            if let Some(ref subdir) = self.out_dir_relative {
+                args::anchor_to_sources(&mut items, subdir);
+            }
+
+            let report = batch.run(&items, self.n_threads, &self.state_dirs).await?;
+            outputs.extend(written(&report.files).map(&canonical));
+            {
+                let mut stats = stats.lock().unwrap_or_else(PoisonError::into_inner);
+                stats.converted += report.converted;
+                stats.failed += report.failed;
+            }
+            self.originals
+                .apply(&report.files, Some(&root), self.verify);
+            info!(
+                "converted {} new file(s), {} failed",
+                report.converted, report.failed
+            );
+
+            if self.index {
+                index_outputs(&batch.output_dir, &items)?;
+            }
+        }
+    }
+
+    async fn run_isolated_worker(
+        &self,
+        import: Import,
+        result_path: &Path,
+        assigned: Assigned,
+    ) -> RawbitResult<()> {
+        let Assigned {
+            chrono_index,
+            seq,
+            member,
+            fallback_stem,
+        } = assigned;
+
+        let batch = self.batch(&import)?;
+        let mut ingest = import
+            .source
+            .ingest(import.recurse, &batch.copy, &batch.filters)?;
+
+        // the worker is handed the whole archive, and converts just the one file in it
+        if let Some(ref member) = member {
+            ingest.retain(|item| item.member.as_ref().is_some_and(|m| m.path == *member));
+        }
+
+        // numbered and named by the parent, which saw the whole batch
+        for item in &mut ingest {
+            item.chrono_index = chrono_index;
+            item.seq = seq;
+            item.fallback_stem.clone_from(&fallback_stem);
+        }
+
+        batch.run_isolated_worker(&ingest, result_path).await
+    }
+}
+
+/// What the parent worked out for the one file an isolated worker converts, from the whole batch.
+struct Assigned {
+    chrono_index: Option<ChronoIndex>,
+    seq: Option<usize>,
+    /// the file inside the archive the worker's handed
+    member: Option<String>,
+    fallback_stem: Option<String>,
+}
+
+impl From<&ImportConfig> for Assigned {
+    fn from(args: &ImportConfig) -> Self {
+        Self {
+            chrono_index: args.chrono_index,
+            seq: args.seq,
+            member: args.member.clone(),
+            fallback_stem: args.fallback_stem.clone(),
+        }
+    }
+}
+
+/// The outputs a batch wrote, or found already in place.
+fn written(files: &[FileReport]) -> impl Iterator<Item = &Path> {
+    files.iter().filter_map(|file| file.output.as_deref())
+}
+
+/// Numbers the batch, if the format asks for it, reading every file for `{chrono_index}` takes a
+/// while.
+///
+/// `{seq}` carries on after `last_seq`, unless there's a `--counter-file` to carry on from. Files
+/// whose own names can't be used are named here too, with `--stem-fallback`.
+fn number_batch(batch: &Batch, ingest: &mut [IngestItem], last_seq: usize) -> RawbitResult<()> {
+    if batch.filename_format.uses(MetadataKind::ChronoIndex) {
+        order::number_by_capture_time(ingest, &batch.clocks);
+    }
+
+    if batch.filename_format.uses(MetadataKind::Seq) {
+        let counter = batch.seq_counter.as_deref();
+        let last = counter.map_or(Ok(last_seq), order::read_counter)?;
+        order::number_in_sequence(ingest, last);
+
+        // a dry run shows the numbers the import would give out, without using them up
+        if let Some(counter) = counter.filter(|_| !batch.dry_run && !ingest.is_empty()) {
+            order::write_counter(counter, last + ingest.len())?;
+        }
+    }
+
+    if let Some(policy) = batch.stem_fallback {
+        stem::name_unnamed(ingest, policy);
+    }
+
+    Ok(())
+}
+
+async fn eject_source(ingest: &[IngestItem]) {
+    let Some(first) = ingest.first() else {
+        return;
+    };
+
+    let volume = match Volume::containing(&first.input_path).await {
+        Ok(volume) => volume,
+        Err(err) => {
+            warn!("not ejecting the source: {}", err.describe().0);
+            return;
+        }
+    };
+
+    // `df` is only asked about each device once, or each directory where there's no telling
+    let mut checked = BTreeSet::new();
+    let others = ingest.iter().filter(|item| {
+        checked.insert(paths::device(&item.input_path).ok_or_else(|| item.input_path.parent()))
+    });
+
+    for item in others.skip(1) {
+        if !matches!(Volume::containing(&item.input_path).await, Ok(ref v) if *v == volume) {
+            warn!("not ejecting the source, the input files span more than one volume");
+            return;
+        }
+    }
+
+    if !volume.is_removable() {
+        warn!(
+            "not ejecting {}, it isn't a removable volume",
+            volume.mount_point.display()
+        );
+        return;
+    }
+
+    match volume.eject().await {
+        Ok(()) => info!(
+            "ejected {} ({}), it's safe to remove",
+            volume.mount_point.display(),
+            volume.device
+        ),
+
+        Err(err) => {
+            let (err_str, cause, _) = err.describe();
+            warn!("couldn't eject {}: {err_str}", volume.mount_point.display());
+
+            if let Some(cause) = cause {
+                debug!("{cause}");
+            }
+        }
+    }
+}
+
+/// Refreshes the index at the root of the output, or in every directory next to a source with
+/// `--out-dir-relative`.
+fn index_outputs(output_dir: &Path, ingest: &[IngestItem]) -> RawbitResult<()> {
+    let roots = if output_dir.as_os_str().is_empty() {
+        ingest
+            .iter()
+            .map(|item| item.output_prefix.clone())
+            .collect()
+    } else {
+        BTreeSet::from([output_dir.to_path_buf()])
+    };
+
+    for root in roots.iter().filter(|root| root.is_dir()) {
+        let indexed = Index::refresh(root)?;
+        info!(
+            "indexed {indexed} DNG(s) in {}",
+            root.join(index::INDEX_FILE).display()
+        );
+    }
+
+    Ok(())
+}
+
+/// `--include` and `--exclude`, also keeping a recursive walk out of the `--out-dir-relative`
+/// directories rawbit writes to.
+fn ingest_filters(
+    include: &[String],
+    mut exclude: Vec<String>,
+    out_dir_relative: Option<&Path>,
+) -> RawbitResult<Filters> {
+    if let Some(subdir) = out_dir_relative {
+        if !subdir.is_relative() {
+            return Err(AppError::Config(format!(
+                "--out-dir-relative must be a relative path, got {}",
+                subdir.display()
+            )));
+        }
+
+        let subdir = globset::escape(&subdir.to_string_lossy());
+        exclude.push(format!("**/{subdir}/**"));
+    }
+
+    Filters::new(include, &exclude)
+}
+
+/// Copies the card's structure files into the output's audit directory before anything is
+/// converted, so they reflect the card as it was found.
+fn audit_card(ingest: &[IngestItem], output_dir: &Path, dry_run: bool) -> RawbitResult<()> {
+    let Some(first) = ingest.first() else {
+        return Ok(());
+    };
+
+    let root = card::root(&first.input_path);
+    let files = card::structure_files(&root)?;
+    let audit_dir = output_dir.join(card::AUDIT_DIR);
+
+    if files.is_empty() {
+        info!("no card structure files found under {}", root.display());
+    } else if dry_run {
+        info!(
+            "dry run: would've copied {} card structure file(s) from {} to {}",
+            files.len(),
+            root.display(),
+            audit_dir.display()
+        );
+    } else {
+        card::copy_structure_files(&root, &files, &audit_dir)?;
+        info!(
+            "copied {} card structure file(s) from {} to {}",
+            files.len(),
+            root.display(),
+            audit_dir.display()
+        );
+    }
+
+    Ok(())
+}
+
+fn prepare_output_dir(
+    output_dir: &Path,
+    dry_run: bool,
+    permissions: Permissions,
+) -> RawbitResult<()> {
+    if output_dir.exists() {
+        if output_dir.is_dir() {
+            Ok(())
+        } else {
+            Err(AppError::AlreadyExists(
+                "destination path exists and isn't a directory".into(),
+                output_dir.into(),
+            ))
+        }
+    } else if dry_run {
+        Ok(())
+    } else {
+        permissions
+            .create_dir_all(output_dir)
+            .map_err(|err| AppError::Io("couldn't create destination directory".into(), err))
+    }
+}
+
+fn filename_format(settings: &Config) -> RawbitResult<Arc<FilenameFormat<'static>>> {
+    let fmt_str = settings.filename_format().unwrap_or_default();
+    let format = FilenameFormat::parse(&fmt_str)?
+        .sanitized(settings.sanitizer()?)
+        .with_missing_value(settings.missing_value.clone().unwrap_or_default())
+        .strictly(settings.strict_format.unwrap_or_default())
+        .with_vars(&settings.vars)?;
+    Ok(Arc::new(format.into_owned()))
+}
+
+fn isolation_args(base: &[OsString], settings: &Config) -> Arc<[OsString]> {
+    let mut args = base.to_vec();
+
+    if let Some(fmt_str) = settings.filename_format() {
+        args.extend(["--format".into(), fmt_str.into()]);
+    }
+
+    if settings.strict_format == Some(true) {
+        args.push("--strict-format".into());
+    }
+
+    if let Some(ref missing) = settings.missing_value {
+        args.extend(["--missing-value".into(), missing.into()]);
+    }
+
+    if let Some(rules) = settings
+        .name_rules
+        .and_then(|rules| rules.to_possible_value())
+    {
+        args.extend(["--name-rules".into(), rules.get_name().into()]);
+    }
+
+    if let Some(replacement) = settings.replace_char {
+        args.extend(["--replace-char".into(), replacement.to_string().into()]);
+    }
+
+    if let Some(max) = settings.max_name_len {
+        args.extend(["--max-name-len".into(), max.to_string().into()]);
+    }
+
+    if let Some(form) = settings.normalize.and_then(|form| form.to_possible_value()) {
+        args.extend(["--normalize".into(), form.get_name().into()]);
+    }
+
+    for artist in artist::normalize(&settings.artist, &settings.artist_normalize) {
+        args.extend(["--artist".into(), artist.into()]);
+    }
+
+    if let Some(ref lens) = settings.lens {
+        args.extend(["--lens".into(), lens.into()]);
+    }
+
+    if !settings.lens_profiles.is_empty() {
+        let json =
+            serde_json::to_string(&settings.lens_profiles).expect("lens profiles always serialize");
+        args.extend(["--lens-profiles".into(), json.into()]);
+    }
+
+    for shift in &settings.time_shifts {
+        args.extend(["--time-shift-for".into(), shift.to_string().into()]);
+    }
+
+    if let Some(shift) = settings.time_shift {
+        args.push(format!("--time-shift={shift}").into());
+    }
+
+    if let Some(source) = settings
+        .date_source
+        .and_then(|source| source.to_possible_value())
+    {
+        args.extend(["--date-source".into(), source.get_name().into()]);
+    }
+
+    if let Some(zone) = settings.timezone {
+        args.extend(["--timezone".into(), zone.to_string().into()]);
+    }
+
+    for param in &settings.decode_params {
+        args.extend(["--decode-param".into(), param.to_string().into()]);
+    }
+
+    for var in &settings.vars {
+        args.extend(["--set".into(), var.to_string().into()]);
+    }
+
+    args.into()
+}
+
+struct BatchReport {
+    /// Destinations of every file, only collected in dry-run.
+    planned: Vec<(PathBuf, Outcome)>,
+    converted: usize,
+    /// skipped by `--cache`, already converted with the same settings
+    cached: usize,
+    /// left out by `--on-conflict skip`, something was already where they'd go
+    skipped: usize,
+    /// how many of the converted files had to be salvaged
+    salvaged: usize,
+    failed: usize,
+    /// left out for being too small, see `--min-size`
+    undersized: usize,
+    usage: Usage,
+    /// What became of each file, outside of dry-run.
+    files: Vec<FileReport>,
+    /// the `--out-dir-fallback` the batch switched to partway through
+    failed_over_to: Option<PathBuf>,
+    /// messages logged for several files in a row, logged once and counted
+    repeated: Vec<Repeated>,
+    /// conversions to add to the catalog once a `--transactional` batch commits
+    uncommitted: Vec<catalog::Entry>,
+}
+
+#[allow(clippy::struct_excessive_bools)]
+struct Batch {
+    output_dir: PathBuf,
+    filename_format: Arc<FilenameFormat<'static>>,
+    lenses: Arc<Lenses>,
+    clocks: Arc<Clocks>,
+    /// extensions copied through as-is, fixed once the source has been listed
+    copy: Vec<String>,
+    /// what scans of the source leave out, its output directory among them if it's inside it
+    filters: Filters,
+    on_conflict: OnConflict,
+    /// outputs handed out so far, shared by the batch's jobs for `--on-conflict rename`
+    claims: Arc<Mutex<Claims>>,
+    lanes: Arc<Lanes>,
+    priority: Priority,
+    dry_run: bool,
+    convert_opts: ConvertParams,
+    isolation: Option<Arc<[OsString]>>,
+    worker_args: Option<Vec<OsString>>,
+    cli: Config,
+    config: Option<ConfigWatcher>,
+    run_id: Arc<str>,
+    metadata: MetadataPolicy,
+    salvage: bool,
+    /// what's kept of files that can't be converted at all, see `--fallback`
+    convert_fallback: Option<ConvertFallback>,
+    unsorted: bool,
+    retry: Retry,
+    /// where outputs are written until the batch commits, see `--transactional`
+    staging: Option<PathBuf>,
+    /// flush each output to disk before it's reported written, see `--fsync`
+    fsync: bool,
+    /// give each output its source's file times, see `--preserve-times`
+    preserve_times: bool,
+    /// date each output by when it was taken, see `--mtime-from-exif`
+    mtime_from_exif: bool,
+    /// where each output is also copied, see `--backup-dir`
+    backups: Vec<PathBuf>,
+    /// given to what the batch creates, see `--chmod` and `--chown`
+    permissions: Permissions,
+    /// where each raw is linked into, see `--link-originals`
+    link_archive: Option<LinkArchive>,
+    /// copy each raw's sidecars along with it, see `--sidecars`
+    sidecars: bool,
+    jpeg_policy: JpegPolicy,
+    /// how files whose own names can't be used are named, see `--stem-fallback`
+    stem_fallback: Option<StemFallback>,
+    /// where `{seq}` carries on from, see `--counter-file`
+    seq_counter: Option<PathBuf>,
+    catalog: Option<Arc<Catalog>>,
+    intents: Option<Arc<Intents>>,
+    /// where to write once `output_dir` becomes unavailable, see `--out-dir-fallback`
+    fallback: Option<PathBuf>,
+    /// the output directory the batch started with, once it's failed over to the fallback
+    primary: Option<PathBuf>,
+    /// nanoseconds spent converting, summed over every file, for the `--report`
+    busy: AtomicU64,
+}
+
+impl Batch {
+    /// Picks up edits to the config file, they apply to every file that hasn't started yet.
+    fn reload_config(&mut self) {
+        let Some(ref mut watcher) = self.config else {
+            return;
+        };
+
+        let Some(reloaded) = watcher.poll() else {
+            return;
+        };
+
+        let path = watcher.path().display().to_string();
+
+        let applied = reloaded.and_then(|config| {
+            let settings = config.under(&self.cli);
+
+            self.filename_format = filename_format(&settings)?;
+            self.lenses = Arc::new(settings.lenses());
+            self.clocks = Arc::new(settings.clocks());
+            self.isolation = self
+                .worker_args
+                .as_deref()
+                .map(|base| isolation_args(base, &settings));
+            self.convert_opts = decode::apply(
+                &settings.decode_params,
+                ConvertParams {
+                    artist: settings.artist_tag(),
+                    ..self.convert_opts.clone()
+                },
+            );
+
+            Ok(())
+        });
+
+        match applied {
+            Ok(()) => info!("config reloaded from {path}"),
+            Err(err) => warn!(
+                "ignoring changes to {path}, keeping the previous config: {}",
+                err.describe().0
+            ),
+        }
+    }
+
+    /// Records `intent` in the run's intent log, if it keeps one.
+    fn intend(&self, intent: &Intent) {
+        if let Some(ref intents) = self.intents {
+            intents.record(intent);
+        }
+    }
+
+    /// Commits a `--transactional` batch if every file converted, otherwise rolls it back.
+    fn settle(&self, report: RawbitResult<BatchReport>) -> RawbitResult<BatchReport> {
+        let Some(ref staging) = self.staging else {
+            return report;
+        };
+
+        let transaction = Transaction::new(self.output_dir.clone(), staging.clone())
+            .synced(self.fsync)
+            .permitted(self.permissions);
+
+        match report {
+            Ok(mut report) if report.failed == 0 => {
+                self.intend(&Intent::Commit {
+                    output_dir: self.output_dir.clone(),
+                    staging: staging.clone(),
+                });
+                let committed = transaction.commit()?;
+                self.intend(&Intent::Committed {
+                    staging: staging.clone(),
+                });
+
+                if let Some(ref catalog) = self.catalog {
+                    for entry in report.uncommitted.drain(..) {
+                        job::record_conversion(catalog, entry);
+                    }
+                }
+
+                info!(
+                    "committed {committed} file(s) into {}",
+                    self.output_dir.display()
+                );
+
+                Ok(report)
+            }
+
+            Ok(report) => {
+                transaction.roll_back()?;
+
+                Err(AppError::RolledBack(format!(
+                    "{} file(s) failed to convert, rolled back the batch, nothing was written to {}",
+                    report.failed,
+                    self.output_dir.display()
+                )))
+            }
+
+            Err(err) => {
+                transaction.roll_back()?;
+                Err(err)
+            }
+        }
+    }
+
+    async fn run(
+        &mut self,
+        ingest: &[IngestItem],
+        n_threads: usize,
+        state_dirs: &StateDirs,
+    ) -> RawbitResult<BatchReport> {
+        let meter = Meter::start();
+        let busy_before = self.busy.load(Ordering::Relaxed);
+        let (mut bytes_read, mut bytes_written) = (0, 0);
+
+        let mut planned = vec![];
+        let mut files = vec![];
+        let mut crashed = vec![];
+        let mut salvaged = vec![];
+        let mut uncommitted = vec![];
+        let mut repeats = Repeats::default();
+        let mut chunks = ingest.chunks(n_threads);
+
+        while let Some(chunk) = chunks.next() {
+            self.reload_config();
+
+            let mut pending = chunk.to_vec();
+
+            while !pending.is_empty() {
+                let results = self.run_chunk(&pending).await;
+
+                let mut unavailable = vec![];
+                let mut dest_err = None;
+
+                for (item, result) in pending.into_iter().zip(results) {
+                    if let Ok(ref outcome) = result {
+                        bytes_read += outcome.bytes_read;
+                        bytes_written += outcome.bytes_written;
+                    }
+
+                    match result {
+                        Ok(outcome) if self.dry_run => planned.push((item.source_path(), outcome)),
+                        Ok(outcome) if outcome.cached => {
+                            files.push(file_report(&item, outcome, FileStatus::Cached));
+                        }
+
+                        Ok(outcome) if outcome.conflict == Some(OnConflict::Skip) => {
+                            files.push(file_report(&item, outcome, FileStatus::Skipped));
+                        }
+
+                        Ok(mut outcome) => {
+                            uncommitted.extend(outcome.catalog_entry.take());
+
+                            for warning in &outcome.warnings {
+                                if repeats.note(&item.source_path(), warning.to_string()) {
+                                    warn!("\"{}\": {warning}", item.source_path().display());
+                                }
+                            }
+
+                            if let Some(strategy) = outcome.salvaged {
+                                salvaged.push((outcome.output_path.clone(), strategy));
+                            }
+
+                            self.record_failover(&outcome.output_path);
+                            files.push(file_report(&item, outcome, FileStatus::Converted));
+                        }
+
+                        Err(err @ job::Error::DestUnavailable(..)) => {
+                            unavailable.push(item);
+                            dest_err = Some(err);
+                        }
+
+                        Err(err) => {
+                            if repeats.note(&item.source_path(), err.describe().0) {
+                                report_job_error(&item.source_path(), &err);
+                            }
+
+                            files.push(FileReport::failed(item.source_path(), err.message()));
+
+                            if matches!(err, job::Error::Panicked(..)) {
+                                crashed.push(item.source_path());
+                            }
+                        }
+                    }
+                }
+
+                // report the destination going away once, rather than once per file
+                if let Some(ref err) = dest_err {
+                    repeats.flush();
+                    error!(
+                        "destination became unavailable, {} file(s) couldn't be written",
+                        unavailable.len()
+                    );
+                    report_job_error(&unavailable[0].source_path(), err);
+
+                    if !self.fail_over() && !prompt_retry().await {
+                        let rest = chunks.by_ref().flat_map(|c| c.iter().cloned());
+                        return Err(self.interrupt(unavailable.into_iter().chain(rest), state_dirs));
+                    }
+                }
+
+                pending = unavailable;
+            }
+        }
+
+        let count = |status| files.iter().filter(|file| file.status == status).count();
+        let cached = count(FileStatus::Cached);
+        let skipped = count(FileStatus::Skipped);
+        let repeated = repeats.finish();
+        log_summary(&crashed, &salvaged, &shared_names(&files), cached, skipped);
+
+        Ok(BatchReport {
+            planned,
+            converted: count(FileStatus::Converted),
+            cached,
+            skipped,
+            salvaged: salvaged.len(),
+            failed: count(FileStatus::Failed),
+            undersized: 0,
+            files,
+            failed_over_to: self.primary.as_ref().map(|_| self.output_dir.clone()),
+            repeated,
+            uncommitted,
+            usage: meter.finish(
+                Duration::from_nanos(self.busy.load(Ordering::Relaxed) - busy_before),
+                bytes_read,
+                bytes_written,
+            ),
+        })
+    }
+
+    /// Stops the batch once its destination has gone away, leaving a journal of the `pending` files
+    /// to `--resume` with.
+    fn interrupt(
+        &self,
+        pending: impl Iterator<Item = IngestItem>,
+        state_dirs: &StateDirs,
+    ) -> AppError {
+        // resuming would only write the rest, which isn't all or nothing
+        if self.staging.is_some() {
+            return AppError::RolledBack(format!(
+                "destination became unavailable, rolled back the batch, nothing was written to {}",
+                self.output_dir.display()
+            ));
+        }
+
+        let journal = Journal {
+            output_dir: self.primary.as_ref().unwrap_or(&self.output_dir).clone(),
+            pending: pending.collect(),
+        };
+
+        match journal.write(state_dirs) {
+            Ok(path) => AppError::Interrupted(
+                "destination became unavailable, continue the batch with --resume".into(),
+                path,
+            ),
+            Err(err) => err,
+        }
+    }
+
+    /// Sends the rest of the batch to `--out-dir-fallback`, unless there isn't one or it already
+    /// has.
+    fn fail_over(&mut self) -> bool {
+        let Some(fallback) = self.fallback.take() else {
+            return false;
+        };
+
+        warn!("writing the rest of the batch to {}", fallback.display());
+        self.primary = Some(mem::replace(&mut self.output_dir, fallback));
+
+        true
+    }
+
+    /// Notes a file written to the fallback, for `rawbit reconcile` to move into place later.
+    fn record_failover(&self, written: &Path) {
+        let Some(ref primary) = self.primary else {
+            return;
+        };
+
+        if let Err(err) = failover::record(&self.output_dir, primary, written) {
+            warn!(
+                "couldn't note that \"{}\" belongs in {}, move it there yourself: {err}",
+                written.display(),
+                primary.display()
+            );
+        }
+    }
+
+    async fn run_isolated_worker(
+        &self,
+        ingest: &[IngestItem],
+        result_path: &Path,
+    ) -> RawbitResult<()> {
+        let [item] = ingest else {
+            return Err(AppError::Config(
+                "an isolated worker expects exactly one input file".into(),
+            ));
+        };
+
+        let result = match self.run_chunk(slice::from_ref(item)).await.pop() {
+            Some(Ok(outcome)) => IsolatedResult::Converted(outcome),
+            Some(Err(job::Error::Panicked(msg))) => IsolatedResult::Crashed(msg),
+
+            Some(Err(err)) => IsolatedResult::Failed(err.message()),
+
+            None => IsolatedResult::Failed("no supported input file".into()),
+        };
+
+        result.write(result_path).map_err(|err| {
+            let (err_str, _) = err.describe();
+            AppError::Config(err_str.to_string())
+        })
+    }
+
+    async fn run_chunk(&self, chunk: &[IngestItem]) -> Vec<Result<Outcome, job::Error>> {
+        let jobs = chunk
+            .par_iter()
+            .cloned()
+            .map(|item| {
+                let copy = item.copy;
+                let source = item.source_path();
+                let config = self.job_config(item);
+
+                let job = async move {
+                    let _worker = self.lanes.acquire(self.priority).await;
+
+                    // made in here, a file that's gone since the scan fails like a panic would
+                    let job = if self.dry_run {
+                        DryRunJob::new(config).run()
+                    } else if copy {
+                        CopyJob::new(config).run()
+                    } else if self.isolation.is_some() {
+                        IsolatedJob::new(config).run()
+                    } else {
+                        RawConvertJob::new(config).run()
+                    };
+
+                    // a track of its own in --trace-output, from when it gets a worker
+                    let span = info_span!(parent: None, "job", file = %source.display());
+
+                    let started = Instant::now();
+                    let result = job.instrument(span).await;
+
+                    let busy = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+                    self.busy.fetch_add(busy, Ordering::Relaxed);
+
+                    result
+                };
+
+                // a panic in the decoder should fail this file, not the whole batch
+                AssertUnwindSafe(job).catch_unwind().map(|result| {
+                    result.unwrap_or_else(|panic| {
+                        Err(job::Error::Panicked(job::panic_message(&*panic)))
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        join_all(jobs).await
+    }
+
+    fn job_config(&self, item: IngestItem) -> JobConfig {
+        let IngestItem {
+            input_path,
+            output_prefix,
+            copy,
+            chrono_index,
+            seq,
+            member,
+            fallback_stem,
+        } = item;
+
+        JobConfig {
+            input_path,
+            output_dir: self.output_dir.join(&output_prefix),
+            filename_format: self.filename_format.clone(),
+            lenses: self.lenses.clone(),
+            clocks: self.clocks.clone(),
+            on_conflict: self.on_conflict,
+            claims: self.claims.clone(),
+            convert_opts: self.convert_opts.clone(),
+            isolation: self.isolation.clone(),
+            run_id: self.run_id.clone(),
+            metadata: self.metadata,
+            salvage: self.salvage,
+            fallback: self.convert_fallback,
+            copy,
+            unsorted: self.unsorted,
+            catalog: self.catalog.clone(),
+            chrono_index,
+            seq,
+            member,
+            fallback_stem,
+            retry: self.retry,
+            staging: self.staging.as_ref().map(|dir| dir.join(&output_prefix)),
+            fsync: self.fsync,
+            preserve_times: self.preserve_times,
+            mtime_from_exif: self.mtime_from_exif,
+            backups: self
+                .backups
+                .iter()
+                .map(|backup| backup.join(&output_prefix))
+                .collect(),
+            permissions: self.permissions,
+            link_archive: self.link_archive.clone(),
+            intents: self.intents.clone(),
+            sidecars: self.sidecars,
+            jpeg_policy: self.jpeg_policy,
+        }
+    }
+
+    /// Names every output before anything is converted, for `--strict`, failing the batch if
+    /// any inputs would be written to the same path, e.g. with a format that leaves out the
+    /// original name's number.
+    async fn preflight(&self, ingest: &[IngestItem], n_threads: usize) -> RawbitResult<()> {
+        // by claim, so names that differ only by case collide on a case-insensitive destination
+        let mut sources = BTreeMap::<PathBuf, (PathBuf, Vec<PathBuf>)>::new();
+
+        for chunk in ingest.chunks(n_threads) {
+            let named = chunk.iter().cloned().map(|item| {
+                let config = self.job_config(item);
+
+                // anything that can't be named now, or is gone already, fails properly once it's
+                // converted
+                AssertUnwindSafe(async move { DryRunJob::new(config).run().await })
+                    .catch_unwind()
+                    .map(|result| result.ok().and_then(Result::ok))
+            });
+
+            for (item, outcome) in chunk.iter().zip(join_all(named).await) {
+                if let Some(outcome) = outcome {
+                    let key = self
+                        .claims
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .key(&outcome.output_path);
+
+                    sources
+                        .entry(key)
+                        .or_insert_with(|| (outcome.output_path, vec![]))
+                        .1
+                        .push(item.source_path());
+                }
+            }
+        }
+
+        sources.retain(|_, (_, sources)| sources.len() > 1);
+
+        for (output_path, sources) in sources.values() {
+            warn!(
+                "{} would all be named \"{}\"",
+                quoted(sources),
+                output_path.display()
+            );
+        }
+
+        if sources.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Config(format!(
+                "{} output name(s) would be shared by more than one input, not converting anything",
+                sources.len()
+            )))
+        }
+    }
+}
+
+/// `paths`, quoted and separated by commas.
+fn quoted(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| format!("\"{}\"", path.display()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The names more than one file in the batch was given, with the files, the one that kept the
+/// name first.
+fn shared_names(files: &[FileReport]) -> BTreeMap<PathBuf, Vec<PathBuf>> {
+    let mut shared = BTreeMap::<PathBuf, Vec<PathBuf>>::new();
+
+    for file in files {
+        let Some(ref output) = file.output else {
+            continue;
+        };
+
+        for warning in &file.warnings {
+            // numbering leaves the directory alone
+            if let Warning::Numbered { name } = warning {
+                let name = Path::new(name).file_name().unwrap_or_default();
+                shared
+                    .entry(output.with_file_name(name))
+                    .or_default()
+                    .push(file.source.clone());
+            }
+        }
+    }
+
+    for file in files {
+        if let Some(sources) = file
+            .output
+            .as_ref()
+            .and_then(|output| shared.get_mut(output))
+        {
+            sources.insert(0, file.source.clone());
+        }
+    }
+
+    shared
+}
+
+/// Lists the files that need a closer look once a batch is done.
+fn log_summary(
+    crashed: &[PathBuf],
+    salvaged: &[(PathBuf, Strategy)],
+    shared: &BTreeMap<PathBuf, Vec<PathBuf>>,
+    cached: usize,
+    skipped: usize,
+) {
+    if !crashed.is_empty() {
+        error!(
+            "{} file(s) crashed the decoder and were skipped, please consider reporting them upstream at {UPSTREAM_ISSUES}:",
+            crashed.len()
+        );
+
+        for path in crashed {
+            error!("  {}", path.display());
+        }
+    }
+
+    if !salvaged.is_empty() {
+        warn!(
+            "{} file(s) couldn't be fully converted and were salvaged, check them before relying on them:",
+            salvaged.len()
+        );
+
+        for (path, strategy) in salvaged {
+            warn!("  {} ({strategy})", path.display());
+        }
+    }
+
+    if !shared.is_empty() {
+        warn!(
+            "{} output name(s) were given to more than one file and numbered after the first, --strict stops before converting anything instead:",
+            shared.len()
+        );
+
+        for (output_path, sources) in shared {
+            warn!("  {} ({})", output_path.display(), quoted(sources));
+        }
+    }
+
+    if cached > 0 {
+        info!("{cached} file(s) were already converted with the same settings, skipped them");
+    }
+
+    if skipped > 0 {
+        info!("{skipped} file(s) would have replaced existing files, skipped them");
+    }
+}
+
+/// Asks whether to carry on once the destination's back, off the runtime's threads, so the other
+/// imports of a `--batch` keep going while it waits for an answer.
+async fn prompt_retry() -> bool {
+    tokio::task::spawn_blocking(|| {
+        if !io::stdin().is_terminal() {
+            return false;
+        }
+
+        print!("retry the remaining files? [y/N] ");
+        if io::stdout().flush().is_err() {
+            return false;
+        }
+
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer).is_ok()
+            && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+    })
+    .await
+    .unwrap_or(false)
+}
+
+fn file_report(item: &IngestItem, outcome: Outcome, status: FileStatus) -> FileReport {
+    FileReport {
+        source: item.source_path(),
+        output: Some(outcome.output_path),
+        status,
+        conflict: outcome.conflict,
+        warnings: outcome.warnings,
+        error: None,
+    }
+}
+
+fn report_job_error(input_path: &Path, err: &job::Error) {
+    let (err_str, cause) = err.describe();
+
+    if matches!(err, job::Error::Panicked(..)) {
+        error!(
+            "crashed while processing \"{}\": {err_str}",
+            input_path.display()
+        );
+    } else {
+        warn!("while processing \"{}\": {err_str}", input_path.display());
+    }
+
+    if let Some(dbg) = cause {
+        debug!("Cause of last error:\n{dbg}");
+    }
+}
+
+fn print_dry_run_preview(planned: Vec<(PathBuf, Outcome)>, json: bool) -> RawbitResult<()> {
+    let rows = preview::plan_rows(planned);
+
+    let printed = if json {
+        preview::print_json(&rows)
+    } else {
+        preview::print_table(&rows)
+    };
+
+    printed.map_err(|err| AppError::Io("couldn't print dry-run preview".into(), err))?;
+
+    let collisions = rows.iter().filter(|row| row.collision).count();
+    let empty = rows
+        .iter()
+        .filter(|row| !row.empty_items.is_empty())
+        .count();
+
+    info!(
+        "dry run: {} files, {collisions} colliding, {empty} with empty expansions",
+        rows.len()
+    );
+
     Ok(())
 }
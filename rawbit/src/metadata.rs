@@ -0,0 +1,151 @@
+use std::io::{self, Read, Seek, Write};
+
+use clap::ValueEnum;
+
+use crate::tiff::{Ifd, TAG_EXIF_IFD, TAG_GPS_IFD, TAG_XMP, Tiff};
+
+const EXIF_IMAGE_NUMBER: u16 = 0x9211;
+const EXIF_USER_COMMENT: u16 = 0x9286;
+const EXIF_MAKER_NOTE: u16 = 0x927C;
+const EXIF_IMAGE_UNIQUE_ID: u16 = 0xA420;
+const EXIF_OWNER_NAME: u16 = 0xA430;
+const EXIF_BODY_SERIAL_NUMBER: u16 = 0xA431;
+const EXIF_LENS_SERIAL_NUMBER: u16 = 0xA435;
+
+const DNG_CAMERA_SERIAL_NUMBER: u16 = 0xC62F;
+const DNG_LENS_INFO: u16 = 0xC630;
+const DNG_PRIVATE_DATA: u16 = 0xC634;
+const DNG_MAKER_NOTE_SAFETY: u16 = 0xC635;
+
+/// EXIF tags that identify the photographer or their gear, or hold opaque vendor data.
+const PRIVATE_EXIF_TAGS: &[u16] = &[
+    EXIF_IMAGE_NUMBER,
+    EXIF_USER_COMMENT,
+    EXIF_MAKER_NOTE,
+    EXIF_IMAGE_UNIQUE_ID,
+    EXIF_OWNER_NAME,
+    EXIF_BODY_SERIAL_NUMBER,
+    EXIF_LENS_SERIAL_NUMBER,
+];
+
+/// The IFD0 equivalents, plus the camera's own XMP packet.
+const PRIVATE_ROOT_TAGS: &[u16] = &[
+    TAG_XMP,
+    DNG_CAMERA_SERIAL_NUMBER,
+    DNG_PRIVATE_DATA,
+    DNG_MAKER_NOTE_SAFETY,
+];
+
+/// How much of the camera's metadata is carried over into converted DNGs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum MetadataPolicy {
+    /// everything the decoder copies over
+    #[default]
+    All,
+    /// capture settings only; no maker notes, GPS, serial numbers or owner info
+    Minimal,
+    /// only the tags needed to render the image
+    None,
+}
+
+impl MetadataPolicy {
+    /// Drops whatever this policy excludes from a finished DNG.
+    ///
+    /// The dropped data is zeroed, not just unlinked, so it can't be recovered from the file.
+    pub fn apply<F: Read + Write + Seek>(self, file: &mut F) -> io::Result<()> {
+        if self == Self::All {
+            return Ok(());
+        }
+
+        let mut tiff = Tiff::open(file)?;
+        let ifd0 = tiff.read_ifd0()?;
+
+        let mut entries = vec![];
+
+        for entry in &ifd0.entries {
+            match entry.tag {
+                TAG_GPS_IFD => {
+                    let gps = tiff.read_ifd(tiff.pointer(entry))?;
+                    tiff.zero_ifd(&gps)?;
+                }
+
+                TAG_EXIF_IFD if self == Self::None => {
+                    let exif = tiff.read_ifd(tiff.pointer(entry))?;
+                    tiff.zero_ifd(&exif)?;
+                }
+
+                TAG_EXIF_IFD => {
+                    let exif = tiff.read_ifd(tiff.pointer(entry))?;
+                    let offset = scrub_exif(&mut tiff, &exif)?;
+                    entries.push(tiff.with_pointer(*entry, offset));
+                }
+
+                tag if PRIVATE_ROOT_TAGS.contains(&tag)
+                    || (self == Self::None && tag == DNG_LENS_INFO) =>
+                {
+                    tiff.zero_data(entry)?;
+                }
+
+                _ => entries.push(*entry),
+            }
+        }
+
+        let offset = tiff.append_ifd(entries, ifd0.next)?;
+        tiff.zero_directory(&ifd0)?;
+        tiff.set_ifd0_offset(offset)?;
+
+        tiff.flush()
+    }
+}
+
+/// Rewrites the EXIF directory without its private tags, returning where the new one lives.
+fn scrub_exif<F: Read + Write + Seek>(tiff: &mut Tiff<F>, exif: &Ifd) -> io::Result<u32> {
+    let mut kept = vec![];
+
+    for entry in &exif.entries {
+        if PRIVATE_EXIF_TAGS.contains(&entry.tag) {
+            tiff.zero_data(entry)?;
+        } else {
+            kept.push(*entry);
+        }
+    }
+
+    let offset = tiff.append_ifd(kept, exif.next)?;
+    tiff.zero_directory(exif)?;
+
+    Ok(offset)
+}
+
+#[cfg(test)]
+mod metadata_tests {
+    use std::io::Cursor;
+
+    use super::MetadataPolicy;
+    use crate::tiff::{Tiff, tiff_tests};
+
+    #[test]
+    fn minimal_drops_and_zeroes_private_tags() {
+        for le in [true, false] {
+            let mut file = Cursor::new(tiff_tests::sample(le));
+            MetadataPolicy::Minimal.apply(&mut file).unwrap();
+
+            let bytes = file.get_ref().clone();
+            assert!(!bytes.windows(8).any(|w| w == b"SECRETS!"));
+
+            let mut tiff = Tiff::open(file).unwrap();
+            let ifd0 = tiff.read_ifd0().unwrap();
+            assert_eq!(
+                ifd0.entries.iter().map(|e| e.tag).collect::<Vec<_>>(),
+                [0x0100]
+            );
+        }
+    }
+
+    #[test]
+    fn all_leaves_the_file_alone() {
+        let mut file = Cursor::new(tiff_tests::sample(true));
+        MetadataPolicy::All.apply(&mut file).unwrap();
+
+        assert_eq!(file.into_inner(), tiff_tests::sample(true));
+    }
+}
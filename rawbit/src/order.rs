@@ -0,0 +1,210 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write as _},
+    path::Path,
+};
+
+use chrono::NaiveDateTime;
+use rawler::{decoders::RawDecodeParams, get_decoder, rawsource::RawSource};
+use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator as _};
+
+use crate::{
+    args::IngestItem,
+    clock::Clocks,
+    common::{AppError, RawbitResult, map_err},
+    parse::{self, ChronoIndex},
+};
+
+/// Where `--counter-file` keeps the last `{seq}` number by default, in the output directory.
+pub const SEQ_FILE: &str = ".rawbit-seq";
+
+/// Numbers a batch in the order its files were taken, for `{chrono_index}`, so outputs sort
+/// chronologically even when several cameras' file numbers interleave.
+///
+/// Files without a capture time are numbered last, ties go by path so a rerun numbers them the
+/// same way.
+pub fn number_by_capture_time(items: &mut [IngestItem], clocks: &Clocks) {
+    let captured = items
+        .par_iter()
+        .map(|item| capture_time(item, clocks))
+        .collect::<Vec<_>>();
+
+    number(items, &captured);
+}
+
+fn number(items: &mut [IngestItem], captured: &[Option<NaiveDateTime>]) {
+    let mut order = (0..items.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        let key = |i: usize| (captured[i].is_none(), captured[i], &items[i].input_path);
+        key(a).cmp(&key(b))
+    });
+
+    let of = items.len();
+    for (n, i) in order.into_iter().enumerate() {
+        items[i].chrono_index = Some(ChronoIndex { index: n + 1, of });
+    }
+}
+
+/// Numbers a batch in order of where its files are, for `{seq}`, so outputs are numbered
+/// `1, 2, ...` whatever the cameras numbered them, the same way on every run.
+///
+/// Numbering starts after `last`, the last number an earlier run gave out.
+pub fn number_in_sequence(items: &mut [IngestItem], last: usize) {
+    let mut order = (0..items.len()).collect::<Vec<_>>();
+    order.sort_by_cached_key(|&i| items[i].source_path());
+
+    for (n, i) in order.into_iter().enumerate() {
+        items[i].seq = Some(last + n + 1);
+    }
+}
+
+/// The last `{seq}` number given to any of `items`, 0 if none were numbered, so the next batch
+/// can carry on from it, like each round of `--watch` does without a counter file.
+#[must_use]
+pub fn last_in_sequence(items: &[IngestItem]) -> usize {
+    items.iter().filter_map(|item| item.seq).max().unwrap_or(0)
+}
+
+/// The last `{seq}` number kept at `path`, 0 if nothing's been numbered there yet.
+pub fn read_counter(path: &Path) -> RawbitResult<usize> {
+    let counter = match fs::read_to_string(path) {
+        Ok(counter) => counter,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => {
+            return Err(AppError::Io(
+                format!("couldn't read the sequence counter: {}", path.display()),
+                err,
+            ));
+        }
+    };
+
+    counter.trim().parse().map_err(|_| {
+        AppError::Config(format!(
+            "the sequence counter at {} isn't a number: {:?}",
+            path.display(),
+            counter.trim()
+        ))
+    })
+}
+
+/// Keeps `last` as the last `{seq}` number at `path`, replacing it in one step so a crash can't
+/// leave it half written.
+pub fn write_counter(path: &Path, last: usize) -> RawbitResult<()> {
+    let partial = path.with_extension("partial");
+
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        map_err!(
+            fs::create_dir_all(dir),
+            AppError::Io,
+            format!("couldn't create {}", dir.display())
+        )?;
+    }
+
+    map_err!(
+        File::create(&partial).and_then(|mut file| {
+            writeln!(file, "{last}")?;
+            file.sync_all()
+        }),
+        AppError::Io,
+        format!("couldn't write the sequence counter: {}", partial.display())
+    )?;
+
+    map_err!(
+        fs::rename(&partial, path),
+        AppError::Io,
+        format!("couldn't replace the sequence counter: {}", path.display())
+    )
+}
+
+fn capture_time(item: &IngestItem, clocks: &Clocks) -> Option<NaiveDateTime> {
+    let bytes = match item.member {
+        Some(ref member) => Some(member.read(&item.input_path).ok()?),
+        None => None,
+    };
+
+    let src = match bytes {
+        Some(ref bytes) => RawSource::new_from_slice(bytes),
+        None => RawSource::new(&item.input_path).ok()?,
+    };
+
+    let mut md = get_decoder(&src)
+        .ok()?
+        .raw_metadata(&src, &RawDecodeParams::default())
+        .ok()?;
+
+    clocks.correct(&mut md, Some(&item.input_path));
+    parse::capture_time(&md)
+}
+
+#[cfg(test)]
+mod order_tests {
+    use chrono::{NaiveDateTime, Timelike as _};
+    use rawler::decoders::RawMetadata;
+
+    use super::{last_in_sequence, number, number_in_sequence};
+
+    use crate::{
+        args::IngestItem,
+        parse::{FilenameFormat, RenderContext},
+    };
+
+    #[test]
+    fn numbers_by_capture_time_then_sub_second_then_path() {
+        let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y:%m:%d %H:%M:%S").ok();
+        let second = at("2024:05:06 07:08:09").unwrap();
+
+        let mut items = [
+            "b/IMG_0001.CR3",
+            "a/DSC_0900.NEF",
+            "b/IMG_0002.CR3",
+            "a/DSC_0901.NEF",
+        ]
+        .map(|path| IngestItem::from((path, "")));
+
+        let captured = [
+            second.with_nanosecond(500_000_000),
+            second.with_nanosecond(50_000_000),
+            None,
+            at("2024:05:06 07:08:01"),
+        ];
+
+        number(&mut items, &captured);
+
+        let indexes = items
+            .iter()
+            .map(|item| item.chrono_index.unwrap().to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(indexes, ["0003", "0002", "0004", "0001"]);
+    }
+
+    #[test]
+    fn numbers_in_sequence_by_path() {
+        let mut items = ["b/IMG_0001.CR3", "a/DSC_0901.NEF", "a/DSC_0900.NEF"]
+            .map(|path| IngestItem::from((path, "")));
+
+        number_in_sequence(&mut items, 0);
+        assert_eq!(
+            items.each_ref().map(|item| item.seq),
+            [Some(3), Some(2), Some(1)]
+        );
+
+        let format = FilenameFormat::parse("{seq:04}_{seq}_").unwrap();
+        let rendered = format.render_in(
+            &RenderContext {
+                original_filename: "IMG_0001",
+                seq: items[0].seq,
+                ..Default::default()
+            },
+            &RawMetadata::default(),
+        );
+        assert_eq!(rendered.filename, "0003_3_IMG_0001");
+
+        // the next round of a watch carries on where this one left off
+        let mut next =
+            ["c/IMG_0003.CR3", "c/IMG_0002.CR3"].map(|path| IngestItem::from((path, "")));
+        number_in_sequence(&mut next, last_in_sequence(&items));
+        assert_eq!(next.each_ref().map(|item| item.seq), [Some(5), Some(4)]);
+        assert_eq!(last_in_sequence(&next), 5);
+    }
+}
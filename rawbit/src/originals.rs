@@ -0,0 +1,251 @@
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use rawler::{decoders::RawDecodeParams, get_decoder, rawsource::RawSource};
+use smlog::{info, warn};
+
+use crate::{
+    durable,
+    job::Warning,
+    provenance,
+    report::{FileReport, FileStatus},
+};
+
+/// What's done with each raw once it's been converted, see `--originals`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Originals {
+    /// leave it where it is
+    #[default]
+    Keep,
+    /// move it into this directory, in the same place as under the source
+    Move(PathBuf),
+    /// remove it
+    Delete,
+}
+
+impl FromStr for Originals {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "delete" => Ok(Self::Delete),
+            _ => match s.strip_prefix("move:") {
+                Some(dir) if !dir.is_empty() => Ok(Self::Move(dir.into())),
+                _ => Err(format!("expected keep, move:DIR or delete, got \"{s}\"")),
+            },
+        }
+    }
+}
+
+impl Display for Originals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keep => f.write_str("keep"),
+            Self::Move(dir) => write!(f, "move:{}", dir.display()),
+            Self::Delete => f.write_str("delete"),
+        }
+    }
+}
+
+impl Originals {
+    /// Moves or deletes the raw behind every file in `files` that was converted, or was already
+    /// converted before, once its output is flushed to disk, and with `verify`, read back.
+    ///
+    /// `input_dir` is the source directory, the moved files keep their places under it. Raws that
+    /// had to be salvaged and files inside archives are left alone.
+    pub fn apply(&self, files: &[FileReport], input_dir: Option<&Path>, verify: bool) {
+        if *self == Self::Keep {
+            return;
+        }
+
+        let mut handled = 0;
+
+        for file in files {
+            let Some(ref output) = file.output else {
+                continue;
+            };
+
+            if !matches!(file.status, FileStatus::Converted | FileStatus::Cached)
+                || !file.source.is_file()
+            {
+                continue;
+            }
+
+            if file.warnings.iter().any(|warning| {
+                matches!(
+                    warning,
+                    Warning::SalvagedDecode { .. } | Warning::FallbackPreview { .. }
+                )
+            }) {
+                warn!(
+                    "keeping \"{}\", it could only be salvaged",
+                    file.source.display()
+                );
+                continue;
+            }
+
+            match self.dispose(&file.source, output, input_dir, verify) {
+                Ok(()) => handled += 1,
+                Err(err) => warn!("keeping \"{}\": {err}", file.source.display()),
+            }
+        }
+
+        if handled > 0 {
+            let done = if *self == Self::Delete {
+                "deleted"
+            } else {
+                "moved"
+            };
+            info!("{done} {handled} original(s)");
+        }
+    }
+
+    fn dispose(
+        &self,
+        source: &Path,
+        output: &Path,
+        input_dir: Option<&Path>,
+        verify: bool,
+    ) -> Result<(), String> {
+        // the raw is the only copy until its output is safely on disk
+        durable::sync_file(output, output.parent().unwrap_or(output))
+            .map_err(|err| format!("couldn't flush {}: {err}", output.display()))?;
+
+        if verify {
+            check_output(source, output)?;
+        }
+
+        match self {
+            Self::Keep => Ok(()),
+            Self::Move(dir) => {
+                let relative = input_dir
+                    .and_then(|input_dir| source.strip_prefix(input_dir).ok())
+                    .or_else(|| source.file_name().map(Path::new))
+                    .unwrap_or(source);
+
+                let moved = dir.join(relative);
+                if moved.exists() {
+                    return Err(format!("{} is already there", moved.display()));
+                }
+
+                durable::move_file(source, &moved)
+                    .map_err(|err| format!("couldn't move it to {}: {err}", moved.display()))
+            }
+            Self::Delete => fs::remove_file(source)
+                .and_then(|()| durable::sync_dir(source.parent().unwrap_or(source)))
+                .map_err(|err| format!("couldn't delete it: {err}")),
+        }
+    }
+}
+
+/// What reading an output back found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Intact,
+    /// it doesn't decode, or doesn't match its source
+    Broken(String),
+    /// a copy whose source, or itself, couldn't be read to compare them
+    Unknown(String),
+}
+
+/// Reads `output` back: a DNG has to decode, a copy has to match `source` byte for byte.
+pub fn verdict(source: &Path, output: &Path) -> Verdict {
+    let is_dng = output
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("dng"));
+
+    if is_dng {
+        let decoded = RawSource::new(output)
+            .map_err(|err| err.to_string())
+            .and_then(|raw| {
+                get_decoder(&raw)
+                    .and_then(|decoder| decoder.raw_image(&raw, &RawDecodeParams::default(), false))
+                    .map(|_| ())
+                    .map_err(|err| err.to_string())
+            });
+
+        return decoded.map_or_else(Verdict::Broken, |()| Verdict::Intact);
+    }
+
+    match provenance::sha256_file(source)
+        .and_then(|expected| Ok(provenance::sha256_file(output)? == expected))
+    {
+        Ok(true) => Verdict::Intact,
+        Ok(false) => Verdict::Broken("it doesn't match the original".into()),
+        Err(err) => Verdict::Unknown(err.to_string()),
+    }
+}
+
+/// Like [`verdict`], failing unless `output` is intact.
+pub fn check_output(source: &Path, output: &Path) -> Result<(), String> {
+    match verdict(source, output) {
+        Verdict::Intact => Ok(()),
+        Verdict::Broken(err) | Verdict::Unknown(err) => {
+            Err(format!("{} didn't verify: {err}", output.display()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod originals_tests {
+    use std::fs;
+
+    use super::Originals;
+    use crate::report::{FileReport, FileStatus};
+
+    #[test]
+    fn moves_and_deletes_only_converted_originals() {
+        assert_eq!("keep".parse(), Ok(Originals::Keep));
+        assert_eq!(
+            "move:/archive".parse(),
+            Ok(Originals::Move("/archive".into()))
+        );
+        assert!("move:".parse::<Originals>().is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let card = dir.path().join("card");
+        let out = dir.path().join("out");
+        fs::create_dir_all(card.join("100CANON")).unwrap();
+        fs::create_dir(&out).unwrap();
+
+        let file = |name: &str, status| {
+            let source = card.join("100CANON").join(name);
+            let output = out.join(name);
+            fs::write(&source, name).unwrap();
+            fs::write(&output, name).unwrap();
+
+            FileReport {
+                source,
+                output: Some(output),
+                status,
+                conflict: None,
+                warnings: vec![],
+                error: None,
+            }
+        };
+
+        let files = [
+            file("IMG_0001.HEIF", FileStatus::Converted),
+            file("IMG_0002.HEIF", FileStatus::Skipped),
+        ];
+
+        let archive = dir.path().join("archive");
+        Originals::Move(archive.clone()).apply(&files, Some(&card), true);
+        assert!(archive.join("100CANON/IMG_0001.HEIF").is_file());
+        assert!(!files[0].source.exists());
+        assert!(files[1].source.exists());
+
+        let files = [file("IMG_0003.HEIF", FileStatus::Converted)];
+        fs::write(files[0].output.as_ref().unwrap(), b"edited").unwrap();
+        Originals::Delete.apply(&files, Some(&card), true);
+        assert!(files[0].source.exists());
+
+        Originals::Delete.apply(&files, Some(&card), false);
+        assert!(!files[0].source.exists());
+    }
+}
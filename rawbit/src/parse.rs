@@ -2,22 +2,62 @@
 // rawbit is free software, distributable under the terms of the MIT license
 // See https://raw.githubusercontent.com/cartercanedy/rawbit/refs/heads/master/LICENSE.txt
 
-use std::{borrow::Cow, cell::LazyCell, error, fmt};
+use std::{
+    borrow::Cow,
+    cell::LazyCell,
+    collections::HashMap,
+    error,
+    fmt::{self, Write as _},
+    path,
+    str::FromStr,
+};
 
-use chrono::NaiveDateTime;
+use chrono::{
+    NaiveDateTime, Timelike as _,
+    format::{Item, StrftimeItems},
+};
 use phf::{Map, phf_map};
-use rawler::decoders::RawMetadata;
+use rawler::{decoders::RawMetadata, formats::tiff::Rational};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
 use smlog::warn;
-use zips::zip;
 
-use crate::common::{AppError, RawbitResult};
+use crate::{
+    common::{AppError, RawbitResult},
+    drive::DriveMode,
+    sanitize::Sanitizer,
+};
 
 const OPEN_EXPANSION: char = '{';
 const CLOSE_EXPANSION: char = '}';
-const EXIF_DT_FMT: &str = "%Y:%m:%d %H:%M:%S";
+pub const EXIF_DT_FMT: &str = "%Y:%m:%d %H:%M:%S";
+
+/// `{date}`, shorthand for [`DEFAULT_DATE_FORMAT`] or the config's `date-format`.
+const DATE_EXPANSION: &str = "{date}";
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+const MODIFIER_SEP: char = ':';
+
+/// What a `--set` variable's name follows in the format, like `{var.project}`.
+const VAR_PREFIX: &str = "var.";
+
+/// Separates an expansion from the text it renders as when the file doesn't have the value, like
+/// `{lens.model|nolens}`.
+const FALLBACK_SEP: char = '|';
+
+/// Makes the next character of an expansion's fallback literal, for a `}` or `\` in it.
+const ESCAPE: char = '\\';
+
+/// EXIF `ExposureMode` for a frame of an automatic exposure bracket.
+const EXPOSURE_MODE_AUTO_BRACKET: u16 = 2;
+
+/// EXIF `ColorSpace` values, cameras shooting Adobe RGB mostly say uncalibrated.
+const COLOR_SPACE_SRGB: u16 = 1;
+const COLOR_SPACE_ADOBE_RGB: u16 = 2;
+const COLOR_SPACE_UNCALIBRATED: u16 = 0xFFFF;
 
 const IMG_ORIG_FNAME_ITEM: FmtItem<'static> =
-    FmtItem::Metadata(MetadataKind::ImageOriginalFilename);
+    FmtItem::Metadata(MetadataKind::ImageOriginalFilename, None, vec![], None);
 
 const MD_KIND_MAP: Map<&str, MetadataKind> = const {
     use MetadataKind::*;
@@ -25,9 +65,12 @@ const MD_KIND_MAP: Map<&str, MetadataKind> = const {
         "camera.make" => CameraMake,
         "camera.model" => CameraModel,
         "camera.shutter_speed" => CameraShutterSpeed,
+        "camera.exposure_time" => CameraExposureTime,
         "camera.iso" => CameraISO,
         "camera.exposure_compensation" => CameraExposureComp,
-        "camea.flash" => CameraFlash,
+        "camera.drive_mode" => CameraDriveMode,
+        "camera.flash" => CameraFlash,
+        "camera.serial" => CameraSerial,
         "lens.make" => LensMake,
         "lens.model" => LensModel,
         "lens.focal_length" => LensFocalLength,
@@ -38,19 +81,200 @@ const MD_KIND_MAP: Map<&str, MetadataKind> = const {
         "image.bit_depth" => ImageBitDepth,
         "image.color_space" => ImageColorSpace,
         "image.sequence_number" => ImageSequenceNumber,
-        "image.original_filename" => ImageOriginalFilename
+        "image.original_filename" => ImageOriginalFilename,
+        "time.subsec" => TimeSubsec,
+        "chrono_index" => ChronoIndex,
+        "seq" => Seq,
+        "archive.path" => ArchivePath,
+        "bracket.position" => BracketPosition,
+        "gps.lat" => GpsLat,
+        "gps.lon" => GpsLon,
+        "gps.alt" => GpsAlt,
+        "date.year" => DateYear,
+        "date.month" => DateMonth,
+        "date.day" => DateDay,
+        "date.hour" => DateHour,
+        "date.minute" => DateMinute,
+        "date.second" => DateSecond
+    }
+};
+
+const MODIFIER_MAP: Map<&str, Modifier> = const {
+    use Modifier::*;
+    phf_map! {
+        "rational" => Rational,
+        "decimal" => Decimal,
+        "apex" => Apex,
+        "fraction" => Fraction,
+    }
+};
+
+/// Exact renderings of exposure values, selected with a suffix like `{lens.fstop:rational}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Modifier {
+    /// the fraction exactly as the camera recorded it, with `_` in place of `/`
+    Rational,
+    /// the recorded fraction as a decimal number
+    Decimal,
+    /// the recorded APEX value: Tv for shutter speed, Av for aperture, EV for compensation
+    Apex,
+    /// an exposure time as a fraction of a second in lowest terms, like `1-250s`
+    Fraction,
+}
+
+impl Modifier {
+    fn as_str(self) -> &'static str {
+        MODIFIER_MAP.entries().find(|(_, v)| **v == self).unwrap().0
+    }
+}
+
+/// Separates a transform's name from its argument, like `pad=5`.
+const TRANSFORM_ARG_SEP: char = '=';
+
+/// The widest `pad` there's any use for, the longest name most file systems take.
+const MAX_PAD: usize = crate::sanitize::DEFAULT_MAX_NAME_LEN;
+
+/// How big a compiled `regex` can get, far more than a pattern for a file name needs.
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// Makes a [`Transform`] from its argument, `None` if the argument isn't one it takes.
+type TransformCtor = fn(Option<&str>) -> Option<Transform>;
+
+const TRANSFORM_MAP: Map<&str, TransformCtor> = const {
+    use Transform::*;
+    phf_map! {
+        "lower" => |arg| arg.is_none().then_some(Lower),
+        "upper" => |arg| arg.is_none().then_some(Upper),
+        "trim" => |arg| arg.is_none().then_some(Trim),
+        "pad" => |arg| match arg?.parse() {
+            Ok(width @ 1..=MAX_PAD) => Some(Pad(width)),
+            _ => None,
+        },
+        "regex" => |arg| pattern(arg?).map(Regex),
     }
 };
 
+/// A `regex` argument compiled, with the escapes the format needs for its own separators taken
+/// out, `None` if it isn't valid or has more than one group to capture.
+fn pattern(arg: &str) -> Option<Regex> {
+    let mut unescaped = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+
+    while let Some(c) = chars.next() {
+        if c != ESCAPE {
+            unescaped.push(c);
+            continue;
+        }
+
+        // the rest are the pattern's own, like `\d`
+        match chars.next() {
+            Some(escaped @ (MODIFIER_SEP | FALLBACK_SEP | CLOSE_EXPANSION)) => {
+                unescaped.push(escaped);
+            }
+            Some(escaped) => unescaped.extend([ESCAPE, escaped]),
+            None => unescaped.push(ESCAPE),
+        }
+    }
+
+    let regex = RegexBuilder::new(&unescaped)
+        .size_limit(MAX_REGEX_SIZE)
+        .build()
+        .ok()?;
+
+    // the whole pattern and at most one group in it
+    (regex.captures_len() <= 2).then_some(regex)
+}
+
+/// Changes to a rendered value, applied in order after its key like `{camera.model:trim:lower}`.
+#[derive(Clone, Debug)]
+pub enum Transform {
+    /// lowercase
+    Lower,
+    /// uppercase
+    Upper,
+    /// zeros on the left to make it at least this many characters long
+    Pad(usize),
+    /// without whitespace at the start or end, which cameras pad some fields with
+    Trim,
+    /// the part the pattern's group captures, or all it matches if it doesn't have one
+    Regex(Regex),
+}
+
+impl PartialEq for Transform {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Regex(regex), Self::Regex(other)) => regex.as_str() == other.as_str(),
+            (Self::Pad(width), Self::Pad(other)) => width == other,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for Transform {}
+
+impl Transform {
+    fn parse(s: &str) -> Option<Self> {
+        let (name, arg) = match s.split_once(TRANSFORM_ARG_SEP) {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+
+        // `04`, like a width in a format string, is shorthand for `pad=4`
+        if s.len() > 1 && s.starts_with('0') && s.bytes().all(|b| b.is_ascii_digit()) {
+            return TRANSFORM_MAP["pad"](Some(s));
+        }
+
+        TRANSFORM_MAP.get(name)?(arg)
+    }
+
+    /// `value` transformed, `None` if a pattern doesn't match it.
+    fn apply(&self, value: &str) -> Option<String> {
+        Some(match self {
+            Self::Lower => value.to_lowercase(),
+            Self::Upper => value.to_uppercase(),
+            Self::Pad(width) => format!("{value:0>width$}"),
+            Self::Trim => value.trim().into(),
+            Self::Regex(regex) => {
+                let captures = regex.captures(value)?;
+                captures.get(1).or_else(|| captures.get(0))?.as_str().into()
+            }
+        })
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lower => f.write_str("lower"),
+            Self::Upper => f.write_str("upper"),
+            Self::Pad(width) => write!(f, "pad{TRANSFORM_ARG_SEP}{width}"),
+            Self::Trim => f.write_str("trim"),
+            Self::Regex(regex) => {
+                write!(f, "regex{TRANSFORM_ARG_SEP}")?;
+                for c in regex.as_str().chars() {
+                    if matches!(c, MODIFIER_SEP | FALLBACK_SEP | CLOSE_EXPANSION) {
+                        f.write_char(ESCAPE)?;
+                    }
+                    f.write_char(c)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MetadataKind {
     CameraMake,
     CameraModel,
     CameraShutterSpeed,
+    CameraExposureTime,
     CameraExposureComp,
     CameraISO,
     CameraFlash,
+    CameraDriveMode,
+    CameraSerial,
     LensFStop,
     LensMake,
     LensModel,
@@ -62,13 +286,48 @@ pub enum MetadataKind {
     ImageWidth,
     ImageBitDepth,
     ImageOriginalFilename,
+    TimeSubsec,
+    ChronoIndex,
+    Seq,
+    ArchivePath,
+    BracketPosition,
+    GpsLat,
+    GpsLon,
+    GpsAlt,
+    DateYear,
+    DateMonth,
+    DateDay,
+    DateHour,
+    DateMinute,
+    DateSecond,
 }
 
 impl MetadataKind {
-    pub fn expand_with_metadata<'a>(self, md: &'a RawMetadata, original: &str) -> Cow<'a, str> {
+    /// Whether `{key:modifier}` is allowed, only exposure values have alternate renderings.
+    #[must_use]
+    pub const fn takes_modifier(self, modifier: Modifier) -> bool {
+        match self {
+            Self::CameraExposureTime => !matches!(modifier, Modifier::Apex),
+            Self::CameraShutterSpeed | Self::LensFStop | Self::CameraExposureComp => {
+                !matches!(modifier, Modifier::Fraction)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn expand_with_metadata<'a>(
+        self,
+        modifier: Option<Modifier>,
+        md: &'a RawMetadata,
+        context: &RenderContext<'_>,
+    ) -> Cow<'a, str> {
         use MetadataKind::*;
         type CowStr<'a> = Cow<'a, str>;
 
+        if let Some(modifier) = modifier {
+            return CowStr::Owned(self.expand_exposure(modifier, md).unwrap_or_default());
+        }
+
         match self {
             CameraMake => CowStr::Borrowed(&md.make),
             CameraModel => CowStr::Borrowed(&md.model),
@@ -80,6 +339,8 @@ impl MetadataKind {
                     .map_or(const { String::new() }, ToString::to_string),
             ),
 
+            CameraExposureTime => CowStr::Owned(exposure_time(md, None).unwrap_or_default()),
+
             CameraShutterSpeed => CowStr::Owned(
                 md.exif
                     .shutter_speed_value
@@ -87,6 +348,10 @@ impl MetadataKind {
                     .map_or(const { String::new() }, ToString::to_string),
             ),
 
+            CameraSerial => {
+                CowStr::Borrowed(md.exif.serial_number.as_deref().map_or("", str::trim))
+            }
+
             LensMake => CowStr::Borrowed(md.exif.lens_make.as_ref().map_or("", |s| s.as_ref())),
 
             LensModel => CowStr::Borrowed(md.exif.lens_model.as_ref().map_or("", |s| s.as_ref())),
@@ -100,294 +365,1930 @@ impl MetadataKind {
                     }),
             ),
 
-            ImageOriginalFilename => CowStr::Owned(original.to_string()),
+            LensFStop => CowStr::Owned(
+                md.exif
+                    .fnumber
+                    .and_then(|f| ratio(f.n.into(), f.d.into()))
+                    .map_or(const { String::new() }, |f| format!("{f:.1}")),
+            ),
+
+            CameraExposureComp => CowStr::Owned(
+                md.exif
+                    .exposure_bias
+                    .and_then(|ev| ratio(ev.n.into(), ev.d.into()))
+                    .map_or(const { String::new() }, |ev| {
+                        let ev = format!("{ev:+.1}");
+                        if &ev[1..] == "0.0" { "0".into() } else { ev }
+                    }),
+            ),
+
+            CameraFlash => CowStr::Borrowed(
+                // bit 0 is whether it fired
+                md.exif
+                    .flash
+                    .map_or("", |flash| if flash & 1 == 1 { "flash" } else { "noflash" }),
+            ),
+
+            LensFocusDist => CowStr::Owned(focus_distance(md).unwrap_or_default()),
+
+            ImageColorSpace => CowStr::Owned(color_space(md).unwrap_or_default()),
+
+            ImageSequenceNumber => CowStr::Owned(
+                md.exif
+                    .image_number
+                    .map_or(const { String::new() }, |number| number.to_string()),
+            ),
+
+            ImageWidth | ImageHeight | ImageBitDepth => {
+                CowStr::Owned(context.image.map_or(const { String::new() }, |image| {
+                    match self {
+                        ImageWidth => image.width,
+                        ImageHeight => image.height,
+                        _ => image.bit_depth,
+                    }
+                    .to_string()
+                }))
+            }
+
+            BracketPosition => CowStr::Owned(bracket_position(md).unwrap_or_default()),
 
-            _ => {
-                warn!("using unimplemented metadata tag: {}", self.as_str());
-                CowStr::Borrowed("")
+            DateYear | DateMonth | DateDay | DateHour | DateMinute | DateSecond => {
+                CowStr::Owned(self.expand_date(md).unwrap_or_default())
             }
+
+            GpsLat | GpsLon | GpsAlt => CowStr::Owned(self.expand_gps(md).unwrap_or_default()),
+
+            CameraDriveMode => CowStr::Borrowed(context.drive_mode.map_or("", DriveMode::as_str)),
+
+            ImageOriginalFilename => CowStr::Owned(context.original_filename.to_string()),
+
+            TimeSubsec => CowStr::Borrowed(subsec_digits(md).unwrap_or_default()),
+
+            ChronoIndex => CowStr::Owned(
+                context
+                    .chrono_index
+                    .map_or(const { String::new() }, |index| index.to_string()),
+            ),
+
+            Seq => CowStr::Owned(
+                context
+                    .seq
+                    .map_or(const { String::new() }, |seq| seq.to_string()),
+            ),
+
+            ArchivePath => CowStr::Owned(
+                context
+                    .archive_path
+                    .map_or(const { String::new() }, |path| path.replace('/', "_")),
+            ),
         }
     }
 
-    fn as_str(self) -> &'static str {
-        MD_KIND_MAP.entries().find(|(_, v)| **v == self).unwrap().0
-    }
-}
+    /// Renders an exposure value exactly as recorded, `None` if the camera didn't record it.
+    fn expand_exposure(self, modifier: Modifier, md: &RawMetadata) -> Option<String> {
+        use MetadataKind::*;
+        use Modifier::*;
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum FmtItem<'a> {
-    Literal(Cow<'a, str>),
-    DateTime(Cow<'a, str>),
-    Metadata(MetadataKind),
-}
+        if self == CameraExposureTime {
+            return exposure_time(md, Some(modifier));
+        }
 
-#[derive(Debug)]
-pub struct FilenameFormat<'a>(Box<[FmtItem<'a>]>);
+        let exif = &md.exif;
 
-impl<'a> FilenameFormat<'a> {
-    pub fn render_filename(&self, original_filename: &str, md: &RawMetadata) -> String {
-        let mut fname_str = String::new();
+        let (n, d): (i64, i64) = match (self, modifier) {
+            (CameraShutterSpeed, Rational | Decimal) => {
+                exif.exposure_time.map(|t| (t.n.into(), t.d.into()))
+            }
+            (CameraShutterSpeed, Apex) => exif
+                .shutter_speed_value
+                .map(|tv| (tv.n.into(), tv.d.into())),
+            (LensFStop, Rational | Decimal) => exif.fnumber.map(|f| (f.n.into(), f.d.into())),
+            (LensFStop, Apex) => exif.aperture_value.map(|av| (av.n.into(), av.d.into())),
+            (CameraExposureComp, _) => exif.exposure_bias.map(|ev| (ev.n.into(), ev.d.into())),
+            _ => None,
+        }?;
+
+        match modifier {
+            Rational => Some(format!("{n}_{d}")),
+            Decimal | Apex => ratio(n, d).map(|r| r.to_string()),
+            // only exposure times take it, above
+            Fraction => None,
+        }
+    }
 
-        let date = LazyCell::new(Box::new(move || {
-            let date_str = &md.exif.date_time_original.clone().unwrap_or_default();
-            NaiveDateTime::parse_from_str(date_str, EXIF_DT_FMT).ok()
-        }));
+    /// Renders one part of when the file was taken, zero padded like its strftime specifier.
+    fn expand_date(self, md: &RawMetadata) -> Option<String> {
+        use MetadataKind::*;
 
-        for atom in &self.0 {
-            let rendered = match atom {
-                FmtItem::Literal(lit) => lit.clone(),
-                FmtItem::Metadata(md_kind) => md_kind.expand_with_metadata(md, original_filename),
+        let specifier = match self {
+            DateYear => "%Y",
+            DateMonth => "%m",
+            DateDay => "%d",
+            DateHour => "%H",
+            DateMinute => "%M",
+            DateSecond => "%S",
+            _ => return None,
+        };
+
+        capture_time(md).map(|date| date.format(specifier).to_string())
+    }
 
-                FmtItem::DateTime(item) => date.as_ref().map_or(Cow::Borrowed(""), |date| {
-                    Cow::Owned(date.format(item.as_ref()).to_string())
-                }),
-            };
+    /// Renders where the file was taken in decimal degrees to about a meter, or its altitude in
+    /// whole meters, negative south, west and below sea level. `None` without a GPS fix.
+    fn expand_gps(self, md: &RawMetadata) -> Option<String> {
+        use MetadataKind::*;
 
-            fname_str.push_str((rendered).as_ref());
+        let gps = md.exif.gps.as_ref()?;
+
+        // "V" is a measurement the receiver itself says is void
+        if gps.gps_status.as_deref().map(str::trim) == Some("V") {
+            return None;
         }
 
-        fname_str
+        let (value, negative) = match self {
+            GpsLat => (
+                degrees(gps.gps_latitude?)?,
+                gps.gps_latitude_ref.as_deref().map(str::trim) == Some("S"),
+            ),
+            GpsLon => (
+                degrees(gps.gps_longitude?)?,
+                gps.gps_longitude_ref.as_deref().map(str::trim) == Some("W"),
+            ),
+            GpsAlt => {
+                let alt = gps.gps_altitude?;
+                let alt = ratio(alt.n.into(), alt.d.into())?.round();
+                return Some(
+                    // 1 is below sea level
+                    if gps.gps_altitude_ref == Some(1) && alt != 0.0 {
+                        format!("{}", -alt)
+                    } else {
+                        format!("{alt}")
+                    },
+                );
+            }
+            _ => return None,
+        };
+
+        let value = if negative { -value } else { value };
+        Some(format!("{value:.5}"))
     }
 
-    pub fn parse(fmt: &'a str) -> RawbitResult<Self> {
-        #[derive(Debug)]
-        enum ScanState {
-            Start,
-            Literal,
-            DateTime,
-            ExpansionStart,
-            ExpansionBody,
-        }
-
-        let mut items = vec![];
-        let mut to_parse = fmt;
-
-        let mut consumed = 0;
-        let mut state = ScanState::Start;
-
-        while !to_parse.is_empty() {
-            let mut end = false;
-            let split_at = to_parse
-                .chars()
-                .zip(1..)
-                .take_while(|(c, _)| {
-                    use ScanState::*;
-                    match (&state, c) {
-                        _ if end => false,
-
-                        (Start, sym) => {
-                            state = match sym {
-                                '%' => DateTime,
-                                &OPEN_EXPANSION => ExpansionStart,
-                                _ => Literal,
-                            };
-
-                            true
-                        }
-
-                        (ExpansionStart, sym) => {
-                            (state, end) = if sym == &OPEN_EXPANSION {
-                                (Literal, true)
-                            } else {
-                                (ExpansionBody, false)
-                            };
-
-                            true
-                        }
-
-                        (DateTime, _) | (ExpansionBody, &CLOSE_EXPANSION) => {
-                            end = true;
-                            true
-                        }
-
-                        (Literal, '%' | &OPEN_EXPANSION) => false,
-
-                        _ => true,
-                    }
-                })
-                .last()
-                .unwrap()
-                .1;
-
-            if let Some((s, remainder)) = to_parse.split_at_checked(split_at) {
-                to_parse = remainder;
-
-                // catch escaped double left squirly braces, only render one
-                if s == "{{" {
-                    items.push(FmtItem::Literal(Cow::Borrowed(&s[0..1])));
-                } else {
-                    items.push(match state {
-                        ScanState::Literal => FmtItem::Literal(Cow::Borrowed(s)),
-
-                        ScanState::DateTime => {
-                            if s.len() != 2 {
-                                return Err(AppError::FmtStrParse(Error::invalid_expansion(
-                                    consumed,
-                                    s.len(),
-                                    to_parse,
-                                )));
-                            }
-
-                            FmtItem::DateTime(Cow::Borrowed(s))
-                        }
-
-                        ScanState::ExpansionBody => {
-                            assert!(
-                                s.starts_with(OPEN_EXPANSION),
-                                "An expansion was interpreted incorrectly: fmt: {to_parse}, seq: {s}"
-                            );
-
-                            if s.ends_with(CLOSE_EXPANSION) {
-                                expand(&s[1..s.len() - 1]).ok_or(AppError::FmtStrParse(
-                                    Error::invalid_expansion(consumed, s.len(), to_parse),
-                                ))?
-                            } else {
-                                return Err(AppError::FmtStrParse(
-                                    Error::unterminated_expansion(consumed, s.len(), to_parse),
-                                ));
-                            }
-                        }
-
-                        _ => unreachable!(),
-                    });
-                }
+    fn as_str(self) -> &'static str {
+        MD_KIND_MAP.entries().find(|(_, v)| **v == self).unwrap().0
+    }
+}
 
-                consumed += s.len();
-            } else {
-                return Err(AppError::FmtStrParse(Error::new(
-                    consumed,
-                    to_parse.len() - consumed,
-                    to_parse,
-                    ErrorKind::Unknown,
-                )));
-            }
+/// When the file was taken, to the fraction of a second if the camera recorded it.
+#[must_use]
+pub fn capture_time(md: &RawMetadata) -> Option<NaiveDateTime> {
+    let date = md.exif.date_time_original.as_deref()?;
+    let date = NaiveDateTime::parse_from_str(date, EXIF_DT_FMT).ok()?;
+
+    let Some(digits) = subsec_digits(md) else {
+        return Some(date);
+    };
+
+    // the digits after the second's decimal point, so "5" is half a second
+    let nanos = format!("{digits:0<9.9}").parse().ok();
+    Some(
+        nanos
+            .and_then(|nanos| date.with_nanosecond(nanos))
+            .unwrap_or(date),
+    )
+}
 
-            state = ScanState::Start;
+/// How long the shutter was open in seconds, `1-250s` below 0.3s and `0.3s` above like a camera
+/// shows it unless `modifier` asks for one or the other, or for the recorded fraction.
+fn exposure_time(md: &RawMetadata, modifier: Option<Modifier>) -> Option<String> {
+    let time = md.exif.exposure_time?;
+    let seconds = ratio(time.n.into(), time.d.into())?;
+    let (n, d) = (u64::from(time.n), u64::from(time.d));
+
+    let fraction = || {
+        let gcd = gcd(n, d);
+        match (n / gcd, d / gcd) {
+            (n, 1) => format!("{n}s"),
+            (n, d) => format!("{n}-{d}s"),
         }
-
-        if !items.contains(&IMG_ORIG_FNAME_ITEM) {
-            items.push(IMG_ORIG_FNAME_ITEM);
+    };
+
+    Some(match modifier {
+        Some(Modifier::Rational) => format!("{n}_{d}"),
+        // to the microsecond, enough for the fastest shutters
+        Some(Modifier::Decimal) => {
+            let seconds = format!("{seconds:.6}");
+            format!("{}s", seconds.trim_end_matches('0').trim_end_matches('.'))
         }
+        Some(Modifier::Fraction) => fraction(),
+        // a camera rounds the fast ones to 1/N
+        _ if seconds < 0.3 && n != 0 => format!("1-{}s", seconds.recip().round()),
+        _ => {
+            let seconds = format!("{seconds:.1}");
+            format!("{}s", seconds.strip_suffix(".0").unwrap_or(&seconds))
+        }
+    })
+}
 
-        Ok(Self(items.into_boxed_slice()))
+const fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
     }
+    a
 }
 
-#[inline]
-fn expand(s: &str) -> Option<FmtItem<'_>> {
-    Some(FmtItem::Metadata(MD_KIND_MAP.get(s)?.to_owned()))
+/// The frame's exposure offset within an automatic bracket, e.g. `-1EV`, `0EV` or `+0.7EV`.
+fn bracket_position(md: &RawMetadata) -> Option<String> {
+    if md.exif.exposure_mode != Some(EXPOSURE_MODE_AUTO_BRACKET) {
+        return None;
+    }
+
+    let ev = md
+        .exif
+        .exposure_bias
+        .and_then(|ev| ratio(ev.n.into(), ev.d.into()))?;
+
+    let ev = format!("{ev:+.1}");
+    let ev = ev.strip_suffix(".0").unwrap_or(&ev);
+
+    Some(if &ev[1..] == "0" {
+        "0EV".into()
+    } else {
+        format!("{ev}EV")
+    })
 }
 
-#[cfg(test)]
-mod test_parse {
-    use crate::parse::FilenameFormat;
+/// The color space the camera was set to, by name where EXIF gives it one.
+fn color_space(md: &RawMetadata) -> Option<String> {
+    Some(match md.exif.color_space? {
+        COLOR_SPACE_SRGB => "sRGB".into(),
+        COLOR_SPACE_ADOBE_RGB => "AdobeRGB".into(),
+        COLOR_SPACE_UNCALIBRATED => "uncalibrated".into(),
+        other => other.to_string(),
+    })
+}
 
-    use super::{FmtItem, MetadataKind, OPEN_EXPANSION};
-    #[test]
-    fn parses_expansions_and_strftime_ok() {
-        assert!(FilenameFormat::parse("%Y-%m-%d_{camera.make}").is_ok());
-    }
+/// How far away the camera focused, in meters, e.g. `1.25m` or `inf`.
+fn focus_distance(md: &RawMetadata) -> Option<String> {
+    let distance = md.exif.subject_distance?;
 
-    #[test]
-    fn fails_to_parse_incomplete_expansion() {
-        const BAD_EXPANSION: &str = "{camera.make";
-        assert!(FilenameFormat::parse(BAD_EXPANSION).is_err());
+    // EXIF's infinity, while 0 says the distance isn't known
+    if distance.n == u32::MAX {
+        return Some("inf".into());
     }
 
-    #[test]
-    fn escaped_double_squirly_brace_only_prints_one() {
-        let escaped = format!(
-            "{}{}%Y{{image.original_filename}}",
-            &OPEN_EXPANSION, &OPEN_EXPANSION
-        );
-        let parsed = FilenameFormat::parse(&escaped);
+    let meters = format!("{:.2}", ratio(distance.n.into(), distance.d.into())?);
+    let meters = meters.trim_end_matches('0').trim_end_matches('.');
+    (meters != "0").then(|| format!("{meters}m"))
+}
 
-        assert!(parsed.is_ok());
+/// EXIF's sub-second capture time, as recorded.
+fn subsec_digits(md: &RawMetadata) -> Option<&str> {
+    md.exif
+        .sub_sec_time_original
+        .as_deref()
+        .map(str::trim)
+        .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+}
 
-        let parsed = parsed.unwrap();
+/// Degrees, minutes and seconds as decimal degrees.
+fn degrees([d, m, s]: [Rational; 3]) -> Option<f64> {
+    let part = |r: Rational| ratio(r.n.into(), r.d.into());
+    Some(part(d)? + part(m)? / 60.0 + part(s)? / 3600.0)
+}
 
-        assert!(parsed.0.len() == 3);
+#[allow(clippy::cast_precision_loss)]
+fn ratio(n: i64, d: i64) -> Option<f64> {
+    (d != 0).then(|| n as f64 / d as f64)
+}
 
-        assert!(matches!(
-            parsed.0[0], FmtItem::Literal(ref s) if s.chars().next().unwrap() == OPEN_EXPANSION && s.len() == 1
-        ));
+/// A file's place in its batch once every file is sorted by capture time, see `{chrono_index}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChronoIndex {
+    /// counting from 1
+    pub index: usize,
+    /// how many files the batch has
+    pub of: usize,
+}
 
-        assert!(matches!(parsed.0[1], FmtItem::DateTime(..)));
+impl fmt::Display for ChronoIndex {
+    /// Zero padded to at least 4 digits like a camera's file number, more if the batch needs them,
+    /// so names sort in the same order as the index.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.of.to_string().len().max(4);
+        write!(f, "{:0width$}", self.index)
     }
+}
 
-    #[test]
-    fn inserts_fname_automatically() {
-        const FMT_STR_NO_FNAME: &str = "%Y";
+// how the index reaches an isolated worker, `INDEX/OF`
+impl FromStr for ChronoIndex {
+    type Err = String;
 
-        let parsed = FilenameFormat::parse(FMT_STR_NO_FNAME).unwrap();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s
+            .split_once('/')
+            .and_then(|(index, of)| Some((index.parse().ok()?, of.parse().ok()?)));
 
-        assert_eq!(
-            parsed.0.as_ref(),
-            &[
-                FmtItem::DateTime("%Y".into()),
-                FmtItem::Metadata(MetadataKind::ImageOriginalFilename)
-            ]
-        );
+        match parsed {
+            Some((index, of)) if (1..=of).contains(&index) => Ok(Self { index, of }),
+            _ => Err(format!("expected INDEX/OF, got {s:?}")),
+        }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum ErrorKind {
-    UnterminatedExpansion,
-    InvalidExpansion,
-    Unknown,
+/// Everything a name is rendered from besides the file's metadata.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderContext<'a> {
+    /// the file's name, without its extension
+    pub original_filename: &'a str,
+    pub chrono_index: Option<ChronoIndex>,
+    /// the file's place in the batch sorted by path, counting from 1
+    pub seq: Option<usize>,
+    /// the directory the file is in inside its archive, if it came out of one
+    pub archive_path: Option<&'a str>,
+    /// from the maker notes, which only the raw itself has
+    pub drive_mode: Option<DriveMode>,
+    /// from decoding the raw, which its metadata doesn't say
+    pub image: Option<ImageInfo>,
 }
 
-#[derive(Clone, Debug)]
-pub struct Error {
-    pub kind: ErrorKind,
-    pub original: String,
-    pub start: u16,
-    pub width: u16,
+/// The size of the raw image, see `{image.width}`, `{image.height}` and `{image.bit_depth}`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImageInfo {
+    /// in pixels, cropped to the area the camera means to be seen
+    pub width: usize,
+    pub height: usize,
+    /// bits per sample
+    pub bit_depth: usize,
 }
 
-impl Error {
-    pub fn new(
-        start: impl TryInto<u16>,
-        width: impl TryInto<u16>,
-        original: impl AsRef<str>,
-        kind: ErrorKind,
-    ) -> Self {
-        // there's no way that someone is using a fmt str > 65535 chars
-        // just in case, tho
-        assert!(
-            original.as_ref().len() < u16::MAX as usize,
-            "what are you doing with that long of a format string"
-        );
+#[derive(Debug, PartialEq, Eq)]
+pub enum FmtItem<'a> {
+    Literal(Cow<'a, str>),
+    DateTime(Cow<'a, str>),
+    /// with the transforms applied to the value in order, and the fallback it renders as when the
+    /// file doesn't have the value
+    Metadata(
+        MetadataKind,
+        Option<Modifier>,
+        Vec<Transform>,
+        Option<Cow<'a, str>>,
+    ),
+    /// a `--set` variable by name, with transforms and a fallback like metadata
+    Var(Cow<'a, str>, Vec<Transform>, Option<Cow<'a, str>>),
+}
 
-        let (start, width) = zip!(start.try_into().ok(), width.try_into().ok()).unwrap();
+impl FmtItem<'_> {
+    fn into_owned(self) -> FmtItem<'static> {
+        fn owned(cow: Cow<'_, str>) -> Cow<'static, str> {
+            Cow::Owned(cow.into_owned())
+        }
 
-        Self {
-            original: original.as_ref().into(),
-            start,
-            width,
-            kind,
+        match self {
+            Self::Literal(lit) => FmtItem::Literal(owned(lit)),
+            Self::DateTime(item) => FmtItem::DateTime(owned(item)),
+            Self::Metadata(md_kind, modifier, transforms, fallback) => {
+                FmtItem::Metadata(md_kind, modifier, transforms, fallback.map(owned))
+            }
+            Self::Var(name, transforms, fallback) => {
+                FmtItem::Var(owned(name), transforms, fallback.map(owned))
+            }
         }
     }
+}
 
-    pub fn unterminated_expansion<S: TryInto<u16>, W: TryInto<u16>>(
-        start: S,
-        width: W,
-        original: &str,
-    ) -> Self {
-        Self::new(start, width, original, ErrorKind::UnterminatedExpansion)
+impl fmt::Display for FmtItem<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Literal(lit) => write!(
+                f,
+                "{}",
+                lit.replace(OPEN_EXPANSION, "{{").replace('%', "%%")
+            ),
+            Self::DateTime(item) => write!(f, "{item}"),
+            Self::Metadata(md_kind, modifier, transforms, fallback) => {
+                write!(f, "{{{}", md_kind.as_str())?;
+
+                if let Some(modifier) = modifier {
+                    write!(f, "{MODIFIER_SEP}{}", modifier.as_str())?;
+                }
+
+                write_suffixes(f, transforms, fallback.as_deref())
+            }
+            Self::Var(name, transforms, fallback) => {
+                write!(f, "{{{VAR_PREFIX}{name}")?;
+                write_suffixes(f, transforms, fallback.as_deref())
+            }
+        }
     }
+}
 
-    pub fn invalid_expansion<S: TryInto<u16>, W: TryInto<u16>>(
-        start: S,
-        width: W,
-        original: &str,
-    ) -> Self {
-        Self::new(start, width, original, ErrorKind::InvalidExpansion)
+/// An expansion's transforms and fallback, and the brace that closes it.
+fn write_suffixes(
+    f: &mut fmt::Formatter<'_>,
+    transforms: &[Transform],
+    fallback: Option<&str>,
+) -> fmt::Result {
+    for transform in transforms {
+        write!(f, "{MODIFIER_SEP}{transform}")?;
     }
 
-    fn print_error_details(&self, f: &mut fmt::Formatter<'_>, msg: &str) -> fmt::Result {
-        let (start, width) = (self.start as usize, self.width as usize);
+    if let Some(fallback) = fallback {
+        f.write_char(FALLBACK_SEP)?;
+        for c in fallback.chars() {
+            if matches!(c, CLOSE_EXPANSION | ESCAPE) {
+                f.write_char(ESCAPE)?;
+            }
+            f.write_char(c)?;
+        }
+    }
+
+    f.write_char(CLOSE_EXPANSION)
+}
+
+#[derive(Debug, Default)]
+pub struct RenderedFilename {
+    /// relative to the output directory, with a directory for each separator in the format
+    pub filename: String,
+    /// format items, excluding literals, that expanded to nothing for this file
+    pub empty_items: Vec<String>,
+    /// format items whose `regex` didn't match the file's value, with nothing to stand in for it
+    pub unmatched: Vec<String>,
+    /// every item besides the original filename expanded to nothing, so the name says nothing
+    /// about the file beyond what it was already called
+    pub blank: bool,
+}
+
+#[derive(Debug)]
+pub struct FilenameFormat<'a> {
+    items: Box<[FmtItem<'a>]>,
+    sanitizer: Sanitizer,
+    /// what a metadata expansion renders as when the file doesn't have the value
+    missing_value: String,
+    /// a file with anything missing fails rather than being named without it
+    strict: bool,
+    /// what `{var.NAME}` renders as, see `--set`
+    vars: HashMap<String, String>,
+}
+
+impl<'a> FilenameFormat<'a> {
+    #[must_use]
+    pub fn render_filename(&self, original_filename: &str, md: &RawMetadata) -> String {
+        self.render(original_filename, md).filename
+    }
+
+    #[must_use]
+    pub fn render(&self, original_filename: &str, md: &RawMetadata) -> RenderedFilename {
+        self.render_in(
+            &RenderContext {
+                original_filename,
+                ..Default::default()
+            },
+            md,
+        )
+    }
+
+    /// Whether the format has a `{kind}` expansion, for values that take extra work to find.
+    #[must_use]
+    pub fn uses(&self, kind: MetadataKind) -> bool {
+        self.items
+            .iter()
+            .any(|item| matches!(item, FmtItem::Metadata(used, ..) if *used == kind))
+    }
+
+    /// What makes rendered names valid.
+    #[must_use]
+    pub const fn sanitizer(&self) -> Sanitizer {
+        self.sanitizer
+    }
+
+    /// Makes rendered names valid with `sanitizer` rather than under the running system's rules.
+    #[must_use]
+    pub fn sanitized(self, sanitizer: Sanitizer) -> Self {
+        Self { sanitizer, ..self }
+    }
+
+    /// Whether a file that can't have every item filled in fails, see [`Self::strictly`].
+    #[must_use]
+    pub const fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Fails files the format can't be filled in for completely, rather than naming them without
+    /// what's missing.
+    #[must_use]
+    pub fn strictly(self, strict: bool) -> Self {
+        Self { strict, ..self }
+    }
+
+    /// Renders each `{var.NAME}` as the value `vars` last gives NAME.
+    ///
+    /// Fails if the format has one that isn't given and has no fallback, which is more likely a
+    /// typo than something meant to be left out.
+    pub fn with_vars(self, vars: &[Var]) -> RawbitResult<Self> {
+        let vars = vars
+            .iter()
+            .map(|var| (var.name.clone(), var.value.clone()))
+            .collect::<HashMap<_, _>>();
+
+        for item in &self.items {
+            if let FmtItem::Var(name, _, None) = item
+                && !vars.contains_key(name.as_ref())
+            {
+                return Err(AppError::Config(format!(
+                    "{item} isn't set, give it a value with --set {name}=VALUE or a fallback like {{var.{name}|VALUE}}"
+                )));
+            }
+        }
+
+        Ok(Self { vars, ..self })
+    }
+
+    /// Renders metadata expansions the file doesn't have a value for as `missing_value`, rather
+    /// than leaving them out.
+    #[must_use]
+    pub fn with_missing_value(self, missing_value: String) -> Self {
+        Self {
+            missing_value,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn render_in(&self, context: &RenderContext<'_>, md: &RawMetadata) -> RenderedFilename {
+        let mut rendered_fname = RenderedFilename::default();
+        let mut expanded = 0;
+        let mut expandable = 0;
+
+        let date = LazyCell::new(Box::new(move || capture_time(md)));
+
+        for atom in &self.items {
+            let (mut missing, mut unmatched) = (false, false);
+
+            let rendered = match atom {
+                FmtItem::Literal(lit) => lit.clone(),
+                // only the format's own separators make directories
+                FmtItem::Metadata(md_kind, modifier, transforms, fallback) => {
+                    let value = md_kind.expand_with_metadata(*modifier, md, context);
+                    self.fill_in(
+                        value,
+                        transforms,
+                        fallback.as_deref(),
+                        (&mut missing, &mut unmatched),
+                    )
+                }
+
+                FmtItem::Var(name, transforms, fallback) => {
+                    let value = self
+                        .vars
+                        .get(name.as_ref())
+                        .map_or(Cow::Borrowed(""), |value| Cow::Borrowed(value));
+                    self.fill_in(
+                        value,
+                        transforms,
+                        fallback.as_deref(),
+                        (&mut missing, &mut unmatched),
+                    )
+                }
+
+                FmtItem::DateTime(item) => date.as_ref().map_or(Cow::Borrowed(""), |date| {
+                    let mut rendered = String::new();
+
+                    // specifiers like `%z` need an offset, which a naive EXIF date doesn't carry
+                    if write!(rendered, "{}", date.format(item.as_ref())).is_err() {
+                        warn!("couldn't render date specifier: {item}");
+                        rendered.clear();
+                    }
+
+                    Cow::Owned(rendered)
+                }),
+            };
+
+            let is_expansion = !matches!(
+                atom,
+                FmtItem::Literal(..) | FmtItem::Metadata(MetadataKind::ImageOriginalFilename, ..)
+            );
+
+            // the missing value stands in for the expansion, but it still says nothing
+            let missing = missing
+                || (rendered.is_empty()
+                    && !matches!(
+                        atom,
+                        FmtItem::Literal(..)
+                            | FmtItem::Metadata(.., Some(_))
+                            | FmtItem::Var(.., Some(_))
+                    ));
+
+            if is_expansion {
+                expandable += 1;
+                expanded += usize::from(!missing);
+            }
+
+            if missing {
+                rendered_fname.empty_items.push(atom.to_string());
+            }
+
+            if unmatched {
+                rendered_fname.unmatched.push(atom.to_string());
+            }
+
+            rendered_fname.filename.push_str(rendered.as_ref());
+        }
+
+        rendered_fname.blank = expandable > 0 && expanded == 0;
+        rendered_fname.filename = self
+            .sanitizer
+            .path(&relative_path(&rendered_fname.filename));
+
+        rendered_fname
+    }
+
+    /// `value` transformed, or what stands in for it if it's empty, with its separators replaced
+    /// so it can't make directories.
+    ///
+    /// Sets `missing` if nothing of the format's own stood in for it, and `unmatched` as well if
+    /// a `regex` didn't match it and nothing did.
+    fn fill_in<'v>(
+        &'v self,
+        mut value: Cow<'v, str>,
+        transforms: &[Transform],
+        fallback: Option<&'v str>,
+        (missing, unmatched): (&mut bool, &mut bool),
+    ) -> Cow<'v, str> {
+        let mut no_match = false;
+
+        // padding nothing would make something of a value the file doesn't have
+        for transform in transforms {
+            if value.is_empty() {
+                break;
+            }
+
+            value = transform.apply(&value).map_or_else(
+                || {
+                    no_match = true;
+                    Cow::Borrowed("")
+                },
+                Cow::Owned,
+            );
+        }
+
+        if let (true, Some(fallback)) = (value.is_empty(), fallback) {
+            // a fallback of the format's own fills the value in, it isn't missing
+            value = Cow::Borrowed(fallback);
+        } else if value.is_empty() {
+            *missing = true;
+            value = Cow::Borrowed(&self.missing_value);
+
+            // a pattern that doesn't match is a mistake, unless told what to use
+            *unmatched = no_match && value.is_empty();
+        }
+
+        if value.contains(path::is_separator) {
+            let replacement = self.sanitizer.replacement().to_string();
+            Cow::Owned(value.replace(path::is_separator, &replacement))
+        } else {
+            value
+        }
+    }
+
+    /// Parses a filename format string.
+    ///
+    /// Never panics on arbitrary input, malformed format strings are reported as
+    /// [`AppError::FmtStrParse`]. See the `parse_format` fuzz target.
+    pub fn parse(fmt: &'a str) -> RawbitResult<Self> {
+        let mut items = parse_items(fmt)?;
+
+        let has_original = items.iter().any(|item| {
+            matches!(
+                item,
+                FmtItem::Metadata(MetadataKind::ImageOriginalFilename, ..)
+            )
+        });
+
+        if !has_original {
+            items.push(IMG_ORIG_FNAME_ITEM);
+        }
+
+        Ok(Self {
+            items: items.into_boxed_slice(),
+            sanitizer: Sanitizer::default(),
+            missing_value: String::new(),
+            strict: false,
+            vars: HashMap::new(),
+        })
+    }
+}
+
+impl FilenameFormat<'_> {
+    /// The format, owning what it borrowed from the string it was parsed from, so it can be kept
+    /// past it.
+    #[must_use]
+    pub fn into_owned(self) -> FilenameFormat<'static> {
+        FilenameFormat {
+            items: self
+                .items
+                .into_vec()
+                .into_iter()
+                .map(FmtItem::into_owned)
+                .collect(),
+            sanitizer: self.sanitizer,
+            missing_value: self.missing_value,
+            strict: self.strict,
+            vars: self.vars,
+        }
+    }
+}
+
+/// `fmt` with each `{date}` in it spelled out as `date`, a pattern checked by
+/// [`check_date_format`].
+#[must_use]
+pub fn with_date_format(fmt: &str, date: &str) -> String {
+    let mut expanded = String::with_capacity(fmt.len());
+    let mut to_parse = fmt;
+
+    while !to_parse.is_empty() {
+        let (_, split_at) = scan(to_parse);
+        let (s, remainder) = to_parse
+            .split_at_checked(split_at)
+            .unwrap_or((to_parse, ""));
+
+        expanded.push_str(if s == DATE_EXPANSION { date } else { s });
+        to_parse = remainder;
+    }
+
+    expanded
+}
+
+/// Checks that a `date-format` is only strftime specifiers and text, which `{date}` can stand for.
+pub fn check_date_format(date: &str) -> RawbitResult<()> {
+    let items = parse_items(date)?;
+
+    if items
+        .iter()
+        .any(|item| matches!(item, FmtItem::Metadata(..) | FmtItem::Var(..)))
+    {
+        return Err(AppError::Config(format!(
+            "date-format can only have strftime specifiers and text: {date}"
+        )));
+    }
+
+    Ok(())
+}
+
+fn parse_items(fmt: &str) -> RawbitResult<Vec<FmtItem<'_>>> {
+    let mut items = vec![];
+    let mut to_parse = fmt;
+
+    let mut consumed = 0;
+
+    while !to_parse.is_empty() {
+        let (state, split_at) = scan(to_parse);
+
+        let Some((s, remainder)) = to_parse.split_at_checked(split_at) else {
+            return Err(AppError::FmtStrParse(Error::new(
+                consumed,
+                to_parse.len(),
+                fmt,
+                ErrorKind::Unknown,
+            )));
+        };
+
+        to_parse = remainder;
+
+        // catch escaped double left squirly braces and percent signs, only render one
+        if s == "{{" || s == "%%" {
+            items.push(FmtItem::Literal(Cow::Borrowed(&s[0..1])));
+        } else if s == DATE_EXPANSION {
+            items.extend(parse_items(DEFAULT_DATE_FORMAT)?);
+        } else {
+            items.push(match state {
+                ScanState::Literal => FmtItem::Literal(Cow::Borrowed(s)),
+
+                ScanState::DateTime => datetime(s).ok_or_else(|| {
+                    AppError::FmtStrParse(Error::invalid_expansion(consumed, s.len(), fmt))
+                })?,
+
+                ScanState::ExpansionBody if s.ends_with(CLOSE_EXPANSION) => {
+                    expand(&s[1..s.len() - 1]).ok_or_else(|| {
+                        AppError::FmtStrParse(Error::invalid_expansion(consumed, s.len(), fmt))
+                    })?
+                }
+
+                ScanState::ExpansionStart
+                | ScanState::ExpansionBody
+                | ScanState::ExpansionEscape => {
+                    return Err(AppError::FmtStrParse(Error::unterminated_expansion(
+                        consumed,
+                        s.len(),
+                        fmt,
+                    )));
+                }
+
+                ScanState::Start => {
+                    return Err(AppError::FmtStrParse(Error::new(
+                        consumed,
+                        s.len(),
+                        fmt,
+                        ErrorKind::Unknown,
+                    )));
+                }
+            });
+        }
+
+        consumed += s.len();
+    }
+
+    Ok(items)
+}
+
+/// Keeps a rendered name inside the output directory: separators make directories, but empty
+/// and `.` segments are dropped and `..` can't climb out.
+fn relative_path(rendered: &str) -> String {
+    if !rendered.contains(path::is_separator) && !matches!(rendered, "." | "..") {
+        return rendered.into();
+    }
+
+    rendered
+        .split(path::is_separator)
+        .filter(|segment| !matches!(*segment, "" | "."))
+        .map(|segment| if segment == ".." { "_" } else { segment })
+        .collect::<Vec<_>>()
+        .join(path::MAIN_SEPARATOR_STR)
+}
+
+#[derive(Debug)]
+enum ScanState {
+    Start,
+    Literal,
+    DateTime,
+    ExpansionStart,
+    ExpansionBody,
+    // right after an expansion's escape character
+    ExpansionEscape,
+}
+
+/// Finds the end of the item `to_parse` starts with, and what kind of item it is.
+fn scan(to_parse: &str) -> (ScanState, usize) {
+    let mut state = ScanState::Start;
+    let mut end = false;
+    let split_at = to_parse
+        .char_indices()
+        .map(|(idx, c)| (c, idx + c.len_utf8()))
+        .take_while(|(c, _)| {
+            use ScanState::*;
+            match (&state, c) {
+                _ if end => false,
+
+                (Start, sym) => {
+                    state = match sym {
+                        '%' => DateTime,
+                        &OPEN_EXPANSION => ExpansionStart,
+                        _ => Literal,
+                    };
+
+                    true
+                }
+
+                (ExpansionStart, sym) => {
+                    (state, end) = if sym == &OPEN_EXPANSION {
+                        (Literal, true)
+                    } else {
+                        (ExpansionBody, false)
+                    };
+
+                    true
+                }
+
+                // what comes between the `%` and the specifier, like the `-` of `%-d`, the `.3` of
+                // `%.3f` or the `:` of `%:z`
+                (DateTime, '-' | '_' | '#' | ':' | '.' | '0'..='9') => true,
+
+                (ExpansionBody, &ESCAPE) => {
+                    state = ExpansionEscape;
+                    true
+                }
+
+                (ExpansionEscape, _) => {
+                    state = ExpansionBody;
+                    true
+                }
+
+                (DateTime, _) | (ExpansionBody, &CLOSE_EXPANSION) => {
+                    end = true;
+                    true
+                }
+
+                (Literal, '%' | &OPEN_EXPANSION) => false,
+
+                _ => true,
+            }
+        })
+        .last()
+        .map_or(to_parse.len(), |(_, idx)| idx);
+
+    (state, split_at)
+}
+
+/// A single strftime specifier in chrono's grammar, with whatever padding, precision or colons it
+/// takes, like `%-d`, `%.3f` or `%:z`.
+///
+/// chrono only reports unknown specifiers while rendering, so they're rejected up front.
+fn datetime(s: &str) -> Option<FmtItem<'_>> {
+    let mut items = StrftimeItems::new(s);
+    let valid = matches!(
+        (items.next(), items.next()),
+        (Some(item), None) if !matches!(item, Item::Error)
+    );
+
+    valid.then_some(FmtItem::DateTime(Cow::Borrowed(s)))
+}
+
+fn expand(s: &str) -> Option<FmtItem<'_>> {
+    let mut parts = s.splitn(2, unescaped(FALLBACK_SEP));
+    let s = parts.next()?;
+    let fallback = match parts.next() {
+        Some(fallback) => Some(unescape(fallback)?),
+        None => None,
+    };
+
+    let mut suffixes = s.split(unescaped(MODIFIER_SEP));
+    let key = suffixes.next()?;
+
+    if let Some(name) = key.strip_prefix(VAR_PREFIX) {
+        if !is_var_name(name) {
+            return None;
+        }
+
+        let transforms = suffixes.map(Transform::parse).collect::<Option<_>>()?;
+        return Some(FmtItem::Var(Cow::Borrowed(name), transforms, fallback));
+    }
+
+    let md_kind = *MD_KIND_MAP.get(key)?;
+
+    // a modifier comes first, the transforms after it are applied to what it renders
+    let mut suffixes = suffixes.peekable();
+    let modifier = suffixes
+        .next_if(|suffix| MODIFIER_MAP.contains_key(suffix))
+        .map(|modifier| MODIFIER_MAP[modifier]);
+
+    if modifier.is_some_and(|modifier| !md_kind.takes_modifier(modifier)) {
+        return None;
+    }
+
+    let transforms = suffixes.map(Transform::parse).collect::<Option<_>>()?;
+
+    Some(FmtItem::Metadata(md_kind, modifier, transforms, fallback))
+}
+
+/// A value for `{var.NAME}` in the format, `NAME=VALUE`, see `--set`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Var {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for Var {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=VALUE, e.g. project=iceland, got {s:?}"))?;
+
+        if !is_var_name(name) {
+            return Err(format!(
+                "invalid variable name {name:?}, it can only have letters, digits, _ and -"
+            ));
+        }
+
+        Ok(Self {
+            name: name.into(),
+            value: value.into(),
+        })
+    }
+}
+
+impl TryFrom<String> for Var {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Var> for String {
+    fn from(var: Var) -> Self {
+        var.to_string()
+    }
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+/// Whether `name` can be a `--set` variable's: letters, digits, `_` and `-`.
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+/// Matches `sep` where it isn't escaped, so a `regex` can have the format's separators in it.
+fn unescaped(sep: char) -> impl FnMut(char) -> bool {
+    let mut escaped = false;
+    move |c| {
+        let matched = c == sep && !escaped;
+        escaped = !escaped && c == ESCAPE;
+        matched
+    }
+}
+
+/// A fallback with its escapes taken out, `None` if it ends in an escape with nothing to escape.
+fn unescape(fallback: &str) -> Option<Cow<'_, str>> {
+    if !fallback.contains(ESCAPE) {
+        return Some(Cow::Borrowed(fallback));
+    }
+
+    let mut unescaped = String::with_capacity(fallback.len());
+    let mut chars = fallback.chars();
+
+    while let Some(c) = chars.next() {
+        unescaped.push(if c == ESCAPE { chars.next()? } else { c });
+    }
+
+    Some(Cow::Owned(unescaped))
+}
+
+#[cfg(test)]
+mod test_parse {
+    use proptest::prelude::*;
+    use rawler::{
+        decoders::RawMetadata,
+        exif::{Exif, ExifGPS},
+        formats::tiff::{Rational, SRational},
+    };
+
+    use crate::{
+        parse::{FilenameFormat, Var, check_date_format, with_date_format},
+        sanitize::{NameRules, Sanitizer},
+    };
+
+    use super::{FmtItem, ImageInfo, MetadataKind, OPEN_EXPANSION, RenderContext};
+    #[test]
+    fn parses_expansions_and_strftime_ok() {
+        assert!(FilenameFormat::parse("%Y-%m-%d_{camera.make}").is_ok());
+    }
+
+    #[test]
+    fn fails_to_parse_incomplete_expansion() {
+        const BAD_EXPANSION: &str = "{camera.make";
+        assert!(FilenameFormat::parse(BAD_EXPANSION).is_err());
+    }
+
+    #[test]
+    fn escaped_double_squirly_brace_only_prints_one() {
+        let escaped = format!(
+            "{}{}%Y{{image.original_filename}}",
+            &OPEN_EXPANSION, &OPEN_EXPANSION
+        );
+        let parsed = FilenameFormat::parse(&escaped);
+
+        assert!(parsed.is_ok());
+
+        let parsed = parsed.unwrap();
+
+        assert!(parsed.items.len() == 3);
+
+        assert!(matches!(
+            parsed.items[0], FmtItem::Literal(ref s) if s.chars().next().unwrap() == OPEN_EXPANSION && s.len() == 1
+        ));
+
+        assert!(matches!(parsed.items[1], FmtItem::DateTime(..)));
+    }
+
+    #[test]
+    fn inserts_fname_automatically() {
+        const FMT_STR_NO_FNAME: &str = "%Y";
+
+        let parsed = FilenameFormat::parse(FMT_STR_NO_FNAME).unwrap();
+
+        assert_eq!(
+            parsed.items.as_ref(),
+            &[
+                FmtItem::DateTime("%Y".into()),
+                FmtItem::Metadata(MetadataKind::ImageOriginalFilename, None, vec![], None)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_input_without_panicking() {
+        for fmt in ["{", "%", "é{", "%é", "%Q", "{}", "{{{", "x{camera.make"] {
+            let parsed = FilenameFormat::parse(fmt);
+            assert!(parsed.is_err(), "expected {fmt:?} to be rejected");
+
+            // rendering the error must not panic either
+            let _ = parsed.unwrap_err().to_string();
+        }
+    }
+
+    #[test]
+    fn renders_offset_specifiers_without_panicking() {
+        let parsed = FilenameFormat::parse("%z").unwrap();
+
+        let md = RawMetadata {
+            exif: Exif {
+                date_time_original: Some("2024:01:02 03:04:05".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(parsed.render_filename("IMG_0001", &md), "IMG_0001");
+    }
+
+    #[test]
+    fn renders_subsecond_capture_times() {
+        let md = RawMetadata {
+            exif: Exif {
+                date_time_original: Some("2024:01:02 03:04:05".into()),
+                sub_sec_time_original: Some("07 ".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let render = |fmt: &str| FilenameFormat::parse(fmt).unwrap().render_filename("", &md);
+
+        assert_eq!(render("{time.subsec}"), "07");
+        assert_eq!(render("%S%.3f"), "05.070");
+        assert_eq!(render("%S_%6f"), "05_070000");
+        assert_eq!(render("%f"), "070000000");
+
+        for fmt in ["%3", "%.3Y", "%4f"] {
+            assert!(
+                FilenameFormat::parse(fmt).is_err(),
+                "expected {fmt:?} to be rejected"
+            );
+        }
+
+        let md = RawMetadata::default();
+        assert_eq!(
+            FilenameFormat::parse("{time.subsec}")
+                .unwrap()
+                .render_filename("", &md),
+            ""
+        );
+    }
+
+    #[test]
+    fn takes_chronos_whole_specifier_grammar() {
+        let md = RawMetadata {
+            exif: Exif {
+                date_time_original: Some("2024:05:06 07:08:09".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let render = |fmt: &str| FilenameFormat::parse(fmt).unwrap().render_filename("", &md);
+
+        assert_eq!(render("%-d.%-m.%y_"), "6.5.24_");
+        assert_eq!(render("%_H%M%S_"), " 70809_");
+        assert_eq!(render("%e-%b_"), " 6-May_");
+        assert_eq!(render("%H%M%S_%-I%P_"), "070809_7am_");
+
+        for fmt in ["%-", "%-Q", "%::", "%.3d", "%--d", "%_"] {
+            assert!(
+                FilenameFormat::parse(fmt).is_err(),
+                "expected {fmt:?} to be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn escaped_percent_signs_render_once() {
+        let render = |fmt: &str, md: &RawMetadata| {
+            FilenameFormat::parse(fmt).unwrap().render("IMG_0001", md)
+        };
+
+        // a literal, so it doesn't need a date
+        let rendered = render("100%%_crop_", &RawMetadata::default());
+        assert_eq!(rendered.filename, "100%_crop_IMG_0001");
+        assert!(rendered.empty_items.is_empty());
+
+        let md = RawMetadata {
+            exif: Exif {
+                date_time_original: Some("2024:05:06 07:08:09".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(render("%%%Y%%%%_", &md).filename, "%2024%%_IMG_0001");
+
+        // as it's named in warnings
+        let parsed = FilenameFormat::parse("50%%_%Y_").unwrap();
+        let items = parsed
+            .items
+            .iter()
+            .map(ToString::to_string)
+            .collect::<String>();
+        assert_eq!(items, "50%%_%Y_{image.original_filename}");
+    }
+
+    #[test]
+    fn renders_named_date_parts() {
+        let md = RawMetadata {
+            exif: Exif {
+                date_time_original: Some("2024:05:06 07:08:09".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let render = |fmt: &str, md: &RawMetadata| {
+            FilenameFormat::parse(fmt).unwrap().render_filename("", md)
+        };
+
+        assert_eq!(
+            render(
+                "{date.year}/{date.month}/{date.day}_{date.hour}{date.minute}{date.second}_",
+                &md
+            ),
+            render("%Y/%m/%d_%H%M%S_", &md)
+        );
+        assert_eq!(render("{date.year}/{date.month}_", &md), "2024/05_");
+        assert_eq!(
+            render(
+                "{date.year|undated}/{date.month:pad=3}_",
+                &RawMetadata::default()
+            ),
+            "undated/_"
+        );
+    }
+
+    #[test]
+    fn date_is_shorthand_for_a_configurable_pattern() {
+        let md = RawMetadata {
+            exif: Exif {
+                date_time_original: Some("2024:05:06 07:08:09".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let render = |fmt: &str| FilenameFormat::parse(fmt).unwrap().render_filename("", &md);
+
+        assert_eq!(render("{date}_"), "2024-05-06_");
+        assert_eq!(render("{{date}_"), "{date}_");
+
+        let fmt = with_date_format("{date}/{{date}_{camera.make}", "%d.%m.%Y");
+        assert_eq!(fmt, "%d.%m.%Y/{{date}_{camera.make}");
+
+        // names of days and months are the same whatever the system's locale
+        assert_eq!(render("%A_%d_%B"), "Monday_06_May");
+
+        assert!(check_date_format("%Y/%m").is_ok());
+        assert!(check_date_format("%Y_{camera.make}").is_err());
+        assert!(check_date_format("%Q").is_err());
+    }
+
+    #[test]
+    fn renders_directories_that_stay_inside_the_output() {
+        let md = RawMetadata {
+            make: "../Acme".into(),
+            model: "X/1".into(),
+            exif: Exif {
+                date_time_original: Some("2024:01:02 03:04:05".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let render = |fmt: &str| {
+            FilenameFormat::parse(fmt)
+                .unwrap()
+                .render_filename("IMG_0001", &md)
+                .replace(std::path::MAIN_SEPARATOR, "/")
+        };
+
+        assert_eq!(
+            render("%Y/%m/%d/{camera.model}/{image.original_filename}"),
+            "2024/01/02/X_1/IMG_0001"
+        );
+        assert_eq!(render("{camera.make}/"), ".._Acme/IMG_0001");
+        assert_eq!(render("/../{lens.model}//./"), "_/IMG_0001");
+    }
+
+    #[test]
+    fn replaces_characters_names_cant_have() {
+        let md = RawMetadata {
+            model: "X/1".into(),
+            exif: Exif {
+                date_time_original: Some("2024:01:02 03:04:05".into()),
+                lens_model: Some("EF 50mm f/1.8 <II>".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let render = |fmt: &str, rules, replacement| {
+            FilenameFormat::parse(fmt)
+                .unwrap()
+                .sanitized(Sanitizer::new(rules, replacement).unwrap())
+                .render_filename("IMG_0001", &md)
+                .replace(std::path::MAIN_SEPARATOR, "/")
+        };
+
+        let fmt = "%Y/%H:%M_{camera.model}_{lens.model}_";
+        assert_eq!(
+            render(fmt, NameRules::Windows, '-'),
+            "2024/03-04_X-1_EF 50mm f-1.8 -II-_IMG_0001"
+        );
+        assert_eq!(
+            render(fmt, NameRules::Macos, '-'),
+            "2024/03-04_X-1_EF 50mm f-1.8 <II>_IMG_0001"
+        );
+        assert_eq!(
+            render(fmt, NameRules::Unix, '-'),
+            "2024/03:04_X-1_EF 50mm f-1.8 <II>_IMG_0001"
+        );
+    }
+
+    #[test]
+    fn renders_fallbacks_for_missing_metadata() {
+        let md = RawMetadata {
+            model: "T1".into(),
+            ..Default::default()
+        };
+        let render = |fmt: &str| {
+            FilenameFormat::parse(fmt)
+                .unwrap()
+                .sanitized(Sanitizer::new(NameRules::Unix, '_').unwrap())
+                .with_missing_value("unknown".into())
+                .render("IMG_0001", &md)
+        };
+
+        let rendered = render("{camera.model|nocam}_{lens.model|nolens}_{lens.make}_");
+        assert_eq!(rendered.filename, "T1_nolens_unknown_IMG_0001");
+        assert_eq!(rendered.empty_items, ["{lens.make}"]);
+
+        // an empty fallback leaves the value out without it being missing
+        assert!(render("{lens.model|}").empty_items.is_empty());
+        assert_eq!(
+            render("{lens.fstop:rational|f_x:y|z}_").filename,
+            "f_x:y|z_IMG_0001"
+        );
+
+        // `\` makes the next character literal, so a fallback can have a `}` in it
+        let escaped = r"{lens.model|{no\}lens\\}_";
+        let parsed = FilenameFormat::parse(escaped).unwrap();
+        assert_eq!(
+            parsed.items[0],
+            FmtItem::Metadata(
+                MetadataKind::LensModel,
+                None,
+                vec![],
+                Some(r"{no}lens\".into())
+            )
+        );
+        assert_eq!(parsed.items[0].to_string(), r"{lens.model|{no\}lens\\}");
+        assert_eq!(
+            render(r"{lens.model|{no\}lens}_").filename,
+            "{no}lens_IMG_0001"
+        );
+
+        for fmt in [
+            r"{lens.model|no\}",
+            r"{lens\.model|x}",
+            "{camera.make:apex|x}",
+        ] {
+            assert!(
+                FilenameFormat::parse(fmt).is_err(),
+                "expected {fmt:?} to be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn transforms_rendered_values() {
+        let md = RawMetadata {
+            make: " Canon ".into(),
+            model: "EOS R5".into(),
+            exif: Exif {
+                exposure_time: Some(Rational::new(1, 250)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let render = |fmt: &str| FilenameFormat::parse(fmt).unwrap().render("IMG_0001", &md);
+
+        assert_eq!(
+            render("{camera.make:trim:upper}_{camera.model:lower}_").filename,
+            "CANON_eos r5_IMG_0001"
+        );
+        assert_eq!(
+            render("{camera.shutter_speed:rational:pad=6}_{image.original_filename:lower}")
+                .filename,
+            "01_250_img_0001"
+        );
+
+        // a value the file doesn't have isn't padded into one
+        let rendered = render("{lens.model:pad=4|none}_{chrono_index:pad=4}_");
+        assert_eq!(rendered.filename, "none__IMG_0001");
+        assert_eq!(rendered.empty_items, ["{chrono_index:pad=4}"]);
+
+        let parsed = FilenameFormat::parse("{lens.fstop:apex:trim:pad=3|x}").unwrap();
+        assert_eq!(
+            parsed.items[0].to_string(),
+            "{lens.fstop:apex:trim:pad=3|x}"
+        );
+
+        for fmt in [
+            "{camera.make:shout}",
+            "{camera.make:lower=1}",
+            "{camera.make:pad}",
+            "{camera.make:pad=0}",
+            "{camera.make:pad=99999999}",
+            "{lens.fstop:lower:apex}",
+        ] {
+            assert!(
+                FilenameFormat::parse(fmt).is_err(),
+                "expected {fmt:?} to be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn captures_from_values_with_a_regex() {
+        let md = RawMetadata {
+            model: "ILCE-7M3".into(),
+            ..Default::default()
+        };
+        let render = |fmt: &str, original: &str| {
+            FilenameFormat::parse(fmt)
+                .unwrap()
+                .sanitized(Sanitizer::new(NameRules::Unix, '_').unwrap())
+                .render(original, &md)
+        };
+
+        let fmt =
+            r"{camera.model:regex=\d+[A-Z]\d*}_{image.original_filename:regex=^IMG_(\d+)$:pad=6}";
+        assert_eq!(render(fmt, "IMG_4321").filename, "7M3_004321");
+
+        // separators the format has, escaped, and the pattern's own escapes as they are
+        let fmt = r"{image.original_filename:regex=^(?\:DSC\|IMG)_(\d{2\}):upper|x}";
+        let parsed = FilenameFormat::parse(fmt).unwrap();
+        assert_eq!(parsed.items[0].to_string(), fmt);
+        assert_eq!(render(fmt, "DSC_0042").filename, "00");
+
+        // a value it doesn't match is a mistake, unless there's something to stand in for it
+        assert_eq!(render(fmt, "clip").filename, "x");
+        let fmt = r"{image.original_filename:regex=\d+}_";
+        let rendered = render(fmt, "clip");
+        assert_eq!(rendered.unmatched, [fmt.trim_end_matches('_')]);
+
+        let rendered = FilenameFormat::parse(fmt)
+            .unwrap()
+            .with_missing_value("none".into())
+            .render("clip", &md);
+        assert_eq!(rendered.filename, "none_");
+        assert!(rendered.unmatched.is_empty());
+
+        for fmt in [
+            "{camera.model:regex=(}",
+            "{camera.model:regex=(a)(b)}",
+            "{camera.model:regex}",
+            "{camera.model:regex=a{2}}",
+        ] {
+            assert!(
+                FilenameFormat::parse(fmt).is_err(),
+                "expected {fmt:?} to be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn renders_variables_set_for_the_import() {
+        let vars = ["project=iceland2025", "client=", "project=Iceland 2025"]
+            .map(|var| var.parse::<Var>().unwrap());
+        let render = |fmt: &str| {
+            FilenameFormat::parse(fmt)
+                .unwrap()
+                .with_vars(&vars)
+                .map(|format| format.render("IMG_0001", &RawMetadata::default()))
+        };
+
+        // the last value given for a name wins
+        let rendered = render("{var.project:lower}_{var.client|none}_{var.client}_").unwrap();
+        assert_eq!(rendered.filename, "iceland 2025_none__IMG_0001");
+        assert_eq!(rendered.empty_items, ["{var.client}"]);
+
+        assert!(render("{var.shoot|x}").is_ok());
+        let err = render("{var.shoot}_").unwrap_err().to_string();
+        assert!(err.contains("--set shoot=VALUE"), "{err}");
+
+        assert!(FilenameFormat::parse("{var.}").is_err());
+        assert!(FilenameFormat::parse("{var.a b}").is_err());
+        assert!("a.b=c".parse::<Var>().is_err());
+        assert!("project".parse::<Var>().is_err());
+        assert_eq!(vars[1].to_string(), "client=");
+    }
+
+    #[test]
+    fn flags_formats_that_expand_to_nothing() {
+        let parsed = FilenameFormat::parse("%Y-%m-%d_{camera.model}_").unwrap();
+        let md = RawMetadata::default();
+
+        let rendered = parsed.render("IMG_0001", &md);
+        assert_eq!(rendered.filename, "--__IMG_0001");
+        assert!(rendered.blank);
+
+        let md = RawMetadata {
+            model: "T1".into(),
+            ..Default::default()
+        };
+        assert!(!parsed.render("IMG_0001", &md).blank);
+
+        let original_only = FilenameFormat::parse("").unwrap();
+        assert!(!original_only.render("IMG_0001", &md).blank);
+    }
+
+    #[test]
+    fn renders_missing_metadata_as_a_placeholder() {
+        let parsed = FilenameFormat::parse("%Y_{camera.model}_{lens.model}_")
+            .unwrap()
+            .with_missing_value("n/a".into());
+
+        let md = RawMetadata {
+            model: "T1".into(),
+            ..Default::default()
+        };
+        let rendered = parsed.render("IMG_0001", &md);
+        assert_eq!(rendered.filename, "_T1_n_a_IMG_0001");
+        assert_eq!(rendered.empty_items, ["%Y", "{lens.model}"]);
+        assert!(!rendered.blank);
+
+        // the placeholder doesn't say anything about the file either
+        let rendered = parsed.render("IMG_0001", &RawMetadata::default());
+        assert_eq!(rendered.filename, "_n_a_n_a_IMG_0001");
+        assert!(rendered.blank);
+    }
+
+    #[test]
+    fn modifiers_only_apply_to_exposure_values() {
+        for fmt in [
+            "{camera.shutter_speed:rational}",
+            "{lens.fstop:decimal}",
+            "{camera.exposure_compensation:apex}",
+        ] {
+            let parsed = FilenameFormat::parse(fmt).unwrap();
+            assert_eq!(parsed.items[0].to_string(), fmt);
+        }
+
+        for fmt in ["{camera.make:apex}", "{lens.fstop:round}", "{lens.fstop:}"] {
+            assert!(
+                FilenameFormat::parse(fmt).is_err(),
+                "expected {fmt:?} to be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn renders_exposure_values_exactly() {
+        let md = RawMetadata {
+            exif: Exif {
+                exposure_time: Some(Rational::new(1, 250)),
+                shutter_speed_value: Some(SRational::new(7_965_784, 1_000_000)),
+                fnumber: Some(Rational::new(28, 10)),
+                aperture_value: Some(Rational::new(297_092, 100_000)),
+                exposure_bias: Some(SRational::new(-2, 3)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let render = |fmt: &str| FilenameFormat::parse(fmt).unwrap().render_filename("", &md);
+
+        assert_eq!(render("{camera.shutter_speed:rational}"), "1_250");
+        assert_eq!(render("{camera.shutter_speed:decimal}"), "0.004");
+        assert_eq!(render("{camera.shutter_speed:apex}"), "7.965784");
+        assert_eq!(render("{lens.fstop}"), "2.8");
+        assert_eq!(render("{lens.fstop:rational}"), "28_10");
+        assert_eq!(render("{lens.fstop:apex}"), "2.97092");
+        assert_eq!(render("{camera.exposure_time}"), "1-250s");
+        assert_eq!(render("{camera.exposure_time:fraction}"), "1-250s");
+        assert_eq!(render("{camera.exposure_time:decimal}"), "0.004s");
+        assert_eq!(render("{camera.exposure_time:rational}"), "1_250");
+        assert_eq!(render("{camera.exposure_compensation}"), "-0.7");
+        assert_eq!(render("{camera.exposure_compensation:rational}"), "-2_3");
+    }
+
+    #[test]
+    fn renders_the_rest_of_the_exif_and_the_image_size() {
+        let md = RawMetadata {
+            exif: Exif {
+                flash: Some(0x19),
+                subject_distance: Some(Rational::new(125, 100)),
+                color_space: Some(0xFFFF),
+                image_number: Some(4242),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let context = RenderContext {
+            image: Some(ImageInfo {
+                width: 6000,
+                height: 4000,
+                bit_depth: 14,
+            }),
+            ..Default::default()
+        };
+
+        let render = |fmt: &str, md: &RawMetadata, context: &RenderContext<'_>| {
+            FilenameFormat::parse(fmt)
+                .unwrap()
+                .render_in(context, md)
+                .filename
+        };
+
+        assert_eq!(
+            render(
+                "{camera.flash}_{lens.focus_distance}_{image.color_space}_{image.sequence_number}_",
+                &md,
+                &context
+            ),
+            "flash_1.25m_uncalibrated_4242_"
+        );
+        assert_eq!(
+            render(
+                "{image.width}x{image.height}_{image.bit_depth}bit_",
+                &md,
+                &context
+            ),
+            "6000x4000_14bit_"
+        );
+
+        let md = RawMetadata {
+            exif: Exif {
+                flash: Some(0x10),
+                subject_distance: Some(Rational::new(u32::MAX, 1)),
+                color_space: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            render(
+                "{camera.flash}_{lens.focus_distance}_{image.color_space}_",
+                &md,
+                &RenderContext::default()
+            ),
+            "noflash_inf_sRGB_"
+        );
+
+        let md = RawMetadata {
+            exif: Exif {
+                serial_number: Some("  012345678 ".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            FilenameFormat::parse("{camera.serial}_")
+                .unwrap()
+                .render_filename("", &md),
+            "012345678_"
+        );
+
+        // unknown, and not decoded
+        let rendered = FilenameFormat::parse("{lens.focus_distance}{image.width}_")
+            .unwrap()
+            .render("IMG_0001", &RawMetadata::default());
+        assert_eq!(rendered.filename, "_IMG_0001");
+        assert_eq!(
+            rendered.empty_items,
+            ["{lens.focus_distance}", "{image.width}"]
+        );
+    }
+
+    #[test]
+    fn renders_where_the_file_was_taken() {
+        let at = |lat_ref: &str, lon_ref: &str, alt_ref| RawMetadata {
+            exif: Exif {
+                gps: Some(ExifGPS {
+                    gps_latitude_ref: Some(lat_ref.into()),
+                    gps_latitude: Some([
+                        Rational::new(64, 1),
+                        Rational::new(8, 1),
+                        Rational::new(4812, 100),
+                    ]),
+                    gps_longitude_ref: Some(lon_ref.into()),
+                    gps_longitude: Some([
+                        Rational::new(21, 1),
+                        Rational::new(5623, 100),
+                        Rational::new(0, 1),
+                    ]),
+                    gps_altitude_ref: Some(alt_ref),
+                    gps_altitude: Some(Rational::new(615, 10)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let render = |md: &RawMetadata| {
+            FilenameFormat::parse("{gps.lat}_{gps.lon}_{gps.alt}_")
+                .unwrap()
+                .render_filename("", md)
+        };
+
+        assert_eq!(render(&at("N", "W", 0)), "64.14670_-21.93717_62_");
+        assert_eq!(render(&at("S", "E", 1)), "-64.14670_21.93717_-62_");
+
+        let mut void = at("N", "E", 0);
+        void.exif.gps.as_mut().unwrap().gps_status = Some("V".into());
+        assert_eq!(render(&void), "___");
+        assert_eq!(render(&RawMetadata::default()), "___");
+    }
+
+    #[test]
+    fn renders_exposure_times_like_a_camera() {
+        let render = |fmt: &str, (n, d)| {
+            let md = RawMetadata {
+                exif: Exif {
+                    exposure_time: Some(Rational::new(n, d)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            FilenameFormat::parse(fmt).unwrap().render_filename("", &md)
+        };
+
+        assert_eq!(render("{camera.exposure_time}", (10, 2500)), "1-250s");
+        assert_eq!(render("{camera.exposure_time}", (1, 3)), "0.3s");
+        assert_eq!(render("{camera.exposure_time}", (13, 10)), "1.3s");
+        assert_eq!(render("{camera.exposure_time}", (30, 1)), "30s");
+        assert_eq!(render("{camera.exposure_time:fraction}", (10, 30)), "1-3s");
+        assert_eq!(
+            render("{camera.exposure_time:fraction}", (26, 20)),
+            "13-10s"
+        );
+        assert_eq!(render("{camera.exposure_time:fraction}", (2, 1)), "2s");
+        assert_eq!(
+            render("{camera.exposure_time:decimal}", (1, 3)),
+            "0.333333s"
+        );
+        assert_eq!(
+            render("{camera.exposure_time:decimal}", (1, 8000)),
+            "0.000125s"
+        );
+        assert_eq!(render("{camera.exposure_time}", (1, 0)), "");
+
+        assert!(FilenameFormat::parse("{camera.exposure_time:apex}").is_err());
+        assert!(FilenameFormat::parse("{lens.fstop:fraction}").is_err());
+    }
+
+    #[test]
+    fn names_bracketed_frames_by_their_offset() {
+        let frame = |mode, (n, d)| RawMetadata {
+            exif: Exif {
+                exposure_mode: mode,
+                exposure_bias: Some(SRational::new(n, d)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let render = |md: &RawMetadata| {
+            FilenameFormat::parse("{bracket.position}")
+                .unwrap()
+                .render_filename("", md)
+        };
+
+        assert_eq!(render(&frame(Some(2), (-1, 1))), "-1EV");
+        assert_eq!(render(&frame(Some(2), (0, 3))), "0EV");
+        assert_eq!(render(&frame(Some(2), (2, 3))), "+0.7EV");
+        // plain exposure compensation isn't a bracket
+        assert_eq!(render(&frame(Some(0), (-1, 1))), "");
+        assert_eq!(render(&frame(None, (-1, 1))), "");
+    }
+
+    #[test]
+    fn renders_the_same_once_it_owns_its_format() {
+        let fmt = String::from("{camera.model|nocamera}_{lens.model:upper}_%Y_");
+        let md = RawMetadata::default();
+
+        let borrowed = FilenameFormat::parse(&fmt)
+            .unwrap()
+            .render_filename("IMG_0001", &md);
+        let owned = FilenameFormat::parse(&fmt).unwrap().into_owned();
+        drop(fmt);
+
+        assert_eq!(owned.render_filename("IMG_0001", &md), borrowed);
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics_on_arbitrary_input(fmt in "\\PC*") {
+            if let Ok(parsed) = FilenameFormat::parse(&fmt) {
+                let _ = parsed.render_filename("IMG_0001", &RawMetadata::default());
+            }
+        }
+
+        #[test]
+        fn literals_round_trip(lit in "[^%{/\\\\\\x00]+") {
+            prop_assume!(lit != "." && lit != "..");
+
+            // the running system's rules could take out more, Unix's only take out NUL
+            let parsed = FilenameFormat::parse(&lit)
+                .unwrap()
+                .sanitized(Sanitizer::new(NameRules::Unix, '_').unwrap());
+            let rendered = parsed.render_filename("", &RawMetadata::default());
+
+            prop_assert_eq!(rendered, lit);
+        }
+
+        #[test]
+        fn escaped_braces_render_once(lit in "[^%{/\\\\\\x00]*") {
+            let fmt = format!("{{{{{lit}{{{{");
+            let parsed = FilenameFormat::parse(&fmt)
+                .unwrap()
+                .sanitized(Sanitizer::new(NameRules::Unix, '_').unwrap());
+            let rendered = parsed.render_filename("", &RawMetadata::default());
+
+            prop_assert_eq!(rendered, format!("{{{lit}{{"));
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ErrorKind {
+    UnterminatedExpansion,
+    InvalidExpansion,
+    Unknown,
+}
+
+#[derive(Clone, Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub original: String,
+    pub start: u16,
+    pub width: u16,
+}
+
+impl Error {
+    pub fn new(
+        start: impl TryInto<u16>,
+        width: impl TryInto<u16>,
+        original: impl AsRef<str>,
+        kind: ErrorKind,
+    ) -> Self {
+        // there's no way that someone is using a fmt str > 65535 chars
+        // just in case, tho, clamp instead of panicking
+        let start = start.try_into().unwrap_or(u16::MAX);
+        let width = width.try_into().unwrap_or(u16::MAX);
+
+        Self {
+            original: original.as_ref().into(),
+            start,
+            width,
+            kind,
+        }
+    }
+
+    pub fn unterminated_expansion<S: TryInto<u16>, W: TryInto<u16>>(
+        start: S,
+        width: W,
+        original: &str,
+    ) -> Self {
+        Self::new(start, width, original, ErrorKind::UnterminatedExpansion)
+    }
+
+    pub fn invalid_expansion<S: TryInto<u16>, W: TryInto<u16>>(
+        start: S,
+        width: W,
+        original: &str,
+    ) -> Self {
+        Self::new(start, width, original, ErrorKind::InvalidExpansion)
+    }
+
+    fn print_error_details(&self, f: &mut fmt::Formatter<'_>, msg: &str) -> fmt::Result {
+        let (start, width) = (self.start as usize, self.width as usize);
+
+        // pad by chars rather than bytes so the underline lines up with non-ascii input
+        let start = self
+            .original
+            .get(..start)
+            .map_or(start, |s| s.chars().count());
+        let width = self
+            .original
+            .get(self.start as usize..self.start as usize + width)
+            .map_or(width, |s| s.chars().count());
 
         let padding = format!("{:>1$}", "", start);
         let underline = format!("{:~<1$}", "^", width);
@@ -404,7 +2305,7 @@ impl fmt::Display for Error {
 
         let (start, width, orig) = (self.start as usize, self.width as usize, &self.original);
 
-        let err_seq = &orig[start..start + width];
+        let err_seq = orig.get(start..start + width).unwrap_or(orig);
 
         let err_msg = format!(
             "{}: {}",
@@ -0,0 +1,136 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The longest path Windows takes without the `\\?\` prefix.
+const MAX_PATH: usize = 260;
+
+/// Names Windows reserves for devices, whatever their extension.
+pub const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The device the file at `path` is on, where the platform says, so two files can be told apart
+/// as being on the same filesystem or not.
+#[cfg(unix)]
+#[must_use]
+pub fn device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt as _;
+
+    fs::metadata(path).ok().map(|stat| stat.dev())
+}
+
+#[cfg(not(unix))]
+#[must_use]
+pub fn device(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// `path` made absolute with its symlinks resolved, like [`fs::canonicalize`].
+///
+/// Unlike it, this leaves off the `\\?\` prefix Windows adds wherever the path works without one,
+/// so it still starts with the `E:\DCIM` or `\\nas\photos` it was given as, and paths under it can
+/// be made relative to it.
+pub fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    fs::canonicalize(path).map(simplify)
+}
+
+/// `path` without its `\\?\` prefix, if it works as well without one, see [`canonicalize`].
+#[must_use]
+pub fn simplify(path: PathBuf) -> PathBuf {
+    if cfg!(windows) {
+        path.to_str()
+            .and_then(strip_verbatim)
+            .map_or(path, PathBuf::from)
+    } else {
+        path
+    }
+}
+
+/// A bare drive letter like `E:` as the root of the drive, `E:\`, which is what a card reader's
+/// letter is meant as, rather than the directory Windows last had open on it.
+#[must_use]
+pub fn drive_root(path: PathBuf) -> PathBuf {
+    if cfg!(windows) && path.to_str().is_some_and(is_drive) && path.as_os_str().len() == 2 {
+        let mut root = path.into_os_string();
+        root.push("\\");
+        root.into()
+    } else {
+        path
+    }
+}
+
+/// Whether `s` starts with a drive, `C:`, followed by nothing or a separator.
+fn is_drive(s: &str) -> bool {
+    let bytes = s.as_bytes();
+
+    bytes.len() >= 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && bytes.get(2).is_none_or(|&b| b == b'\\' || b == b'/')
+}
+
+/// `\\?\C:\DCIM` as `C:\DCIM` and `\\?\UNC\nas\photos` as `\\nas\photos`, unless something in it
+/// only works verbatim: a name Windows reserves, a name ending in a dot or a space, or a path
+/// longer than [`MAX_PATH`].
+fn strip_verbatim(path: &str) -> Option<String> {
+    let rest = path.strip_prefix(r"\\?\")?;
+
+    let (simple, names) = if let Some(unc) = rest.strip_prefix(r"UNC\") {
+        (format!(r"\\{unc}"), unc)
+    } else if is_drive(rest) {
+        (rest.to_string(), rest.get(2..).unwrap_or_default())
+    } else {
+        return None;
+    };
+
+    let plain = |name: &str| {
+        let stem = name.split('.').next().unwrap_or_default();
+
+        name.is_empty()
+            || !(name == "."
+                || name == ".."
+                || name.ends_with(['.', ' '])
+                || name.contains('/')
+                || RESERVED_NAMES
+                    .iter()
+                    .any(|reserved| reserved.eq_ignore_ascii_case(stem.trim_end())))
+    };
+
+    (simple.len() < MAX_PATH && names.split('\\').all(plain)).then_some(simple)
+}
+
+#[cfg(test)]
+mod paths_tests {
+    use super::strip_verbatim;
+
+    #[test]
+    fn strips_verbatim_prefixes_that_are_safe_to_strip() {
+        let simple = |path| strip_verbatim(path);
+
+        assert_eq!(
+            simple(r"\\?\E:\DCIM\100CANON").unwrap(),
+            r"E:\DCIM\100CANON"
+        );
+        assert_eq!(simple(r"\\?\E:\").unwrap(), r"E:\");
+        assert_eq!(
+            simple(r"\\?\UNC\nas\photos\2024").unwrap(),
+            r"\\nas\photos\2024"
+        );
+
+        // already plain, or a device rather than a drive
+        assert_eq!(simple(r"E:\DCIM"), None);
+        assert_eq!(simple(r"\\?\Volume{0b1c}\DCIM"), None);
+
+        // names that only work verbatim
+        assert_eq!(simple(r"\\?\E:\DCIM\con.txt"), None);
+        assert_eq!(simple(r"\\?\E:\DCIM\shoot."), None);
+        assert_eq!(simple(r"\\?\E:\DCIM\shoot \IMG_0001.CR3"), None);
+        assert_eq!(simple(r"\\?\E:\a\..\b"), None);
+
+        let long = format!(r"\\?\E:\{}", "a".repeat(300));
+        assert_eq!(simple(&long), None);
+    }
+}
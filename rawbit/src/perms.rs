@@ -0,0 +1,262 @@
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::Path,
+    str::FromStr,
+};
+
+/// Permission bits given to created files, octal like `chmod`'s, see `--chmod`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode(u32);
+
+impl Mode {
+    /// The bits for a directory: the file's, searchable wherever they're readable, so `0640`
+    /// gives `0750`.
+    #[must_use]
+    pub const fn for_dir(self) -> u32 {
+        self.0 | ((self.0 & 0o444) >> 2)
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected an octal mode like 0644, got \"{s}\"");
+
+        if s.is_empty() || s.len() > 4 || !s.bytes().all(|b| (b'0'..=b'7').contains(&b)) {
+            return Err(invalid());
+        }
+
+        u32::from_str_radix(s, 8).map(Self).map_err(|_| invalid())
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+/// The user and group given to created files, `USER[:GROUP]` or `:GROUP` by name or id, see
+/// `--chown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl FromStr for Owner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if cfg!(not(unix)) {
+            return Err("--chown is only supported on Unix".into());
+        }
+
+        let (user, group) = s.split_once(':').unwrap_or((s, ""));
+
+        let uid = (!user.is_empty())
+            .then(|| id_of(user, user_id).ok_or_else(|| format!("no such user: \"{user}\"")))
+            .transpose()?;
+        let gid = (!group.is_empty())
+            .then(|| id_of(group, group_id).ok_or_else(|| format!("no such group: \"{group}\"")))
+            .transpose()?;
+
+        if uid.is_none() && gid.is_none() {
+            return Err(format!("expected USER[:GROUP] or :GROUP, got \"{s}\""));
+        }
+
+        Ok(Self { uid, gid })
+    }
+}
+
+impl Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(uid) = self.uid {
+            write!(f, "{uid}")?;
+        }
+
+        if let Some(gid) = self.gid {
+            write!(f, ":{gid}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A numeric id as it is, otherwise the id `lookup` finds for the name.
+fn id_of(name: &str, lookup: fn(&str) -> Option<u32>) -> Option<u32> {
+    name.parse().ok().or_else(|| lookup(name))
+}
+
+#[cfg(unix)]
+fn user_id(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    let mut buf = vec![0; 16 * 1024];
+
+    // SAFETY: passwd is plain old data, getpwnam_r fills it in
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut found = std::ptr::null_mut();
+
+    // SAFETY: the name is NUL-terminated, and the pointers are to live values and a buffer of the
+    // length given
+    let err = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &raw mut passwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &raw mut found,
+        )
+    };
+
+    (err == 0 && !found.is_null()).then_some(passwd.pw_uid)
+}
+
+#[cfg(unix)]
+fn group_id(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    let mut buf = vec![0; 16 * 1024];
+
+    // SAFETY: group is plain old data, getgrnam_r fills it in
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut found = std::ptr::null_mut();
+
+    // SAFETY: the name is NUL-terminated, and the pointers are to live values and a buffer of the
+    // length given
+    let err = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &raw mut group,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &raw mut found,
+        )
+    };
+
+    (err == 0 && !found.is_null()).then_some(group.gr_gid)
+}
+
+#[cfg(not(unix))]
+const fn user_id(_name: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(not(unix))]
+const fn group_id(_name: &str) -> Option<u32> {
+    None
+}
+
+/// The permissions and owner given to every file and directory an import creates, instead of
+/// whatever the umask and the user running rawbit would give them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Permissions {
+    pub mode: Option<Mode>,
+    pub owner: Option<Owner>,
+}
+
+impl Permissions {
+    /// Gives the file at `path` its mode and owner.
+    pub fn apply(&self, path: &Path) -> io::Result<()> {
+        self.set(path, self.mode.map(|Mode(mode)| mode))
+    }
+
+    /// Creates `dir` and any missing parents, giving each one it creates its mode and owner.
+    pub fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        if *self == Self::default() {
+            return fs::create_dir_all(dir);
+        }
+
+        if dir.as_os_str().is_empty() || dir.is_dir() {
+            return Ok(());
+        }
+
+        if let Some(parent) = dir.parent() {
+            self.create_dir_all(parent)?;
+        }
+
+        match fs::create_dir(dir) {
+            Ok(()) => self.set(dir, self.mode.map(Mode::for_dir)),
+            // made by another job in the meantime
+            Err(_) if dir.is_dir() => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn set(&self, path: &Path, mode: Option<u32>) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{PermissionsExt as _, chown};
+
+            if let Some(owner) = self.owner {
+                chown(path, owner.uid, owner.gid)?;
+            }
+
+            // after the owner, since changing it can clear the setuid and setgid bits
+            if let Some(mode) = mode {
+                fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+            }
+        }
+
+        #[cfg(not(unix))]
+        if let Some(mode) = mode {
+            let mut permissions = fs::metadata(path)?.permissions();
+            permissions.set_readonly(mode & 0o200 == 0);
+            fs::set_permissions(path, permissions)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod perms_tests {
+    use std::fs;
+
+    use super::{Mode, Owner, Permissions};
+
+    #[test]
+    fn gives_created_files_and_dirs_their_mode() {
+        let mode = "0640".parse::<Mode>().unwrap();
+        assert_eq!(mode.for_dir(), 0o750);
+        assert_eq!(mode.to_string(), "0640");
+        assert!("0648".parse::<Mode>().is_err());
+        assert!("".parse::<Mode>().is_err());
+
+        assert!(":".parse::<Owner>().is_err());
+        assert!("no-such-user-here".parse::<Owner>().is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let permissions = Permissions {
+            mode: Some(mode),
+            owner: None,
+        };
+
+        let nested = dir.path().join("a/b");
+        permissions.create_dir_all(&nested).unwrap();
+
+        let file = nested.join("IMG_0001.dng");
+        fs::write(&file, b"dng").unwrap();
+        permissions.apply(&file).unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            let mode_of = |path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+            assert_eq!(mode_of(dir.path().join("a")), 0o750);
+            assert_eq!(mode_of(nested), 0o750);
+            assert_eq!(mode_of(file), 0o640);
+
+            assert_eq!(
+                "0:0".parse::<Owner>().unwrap(),
+                Owner {
+                    uid: Some(0),
+                    gid: Some(0)
+                }
+            );
+            assert_eq!("root".parse::<Owner>().unwrap().uid, Some(0));
+        }
+    }
+}
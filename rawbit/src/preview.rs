@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal as _, Write as _},
+    path::{Path, PathBuf},
+};
+
+use clap::builder::styling::{AnsiColor, Color, Style};
+use serde::Serialize;
+
+use crate::{
+    conflict::Claims,
+    job::{Outcome, Warning},
+};
+
+const ARROW: &str = "→";
+
+#[derive(Debug, Serialize)]
+pub struct PreviewRow {
+    pub source: String,
+    pub destination: String,
+    pub collision: bool,
+    pub exists: bool,
+    /// where a colliding or existing destination would go instead with the `rename` collision
+    /// policy, null if it keeps its name
+    pub renamed: Option<String>,
+    pub empty_items: Vec<String>,
+    pub warnings: Vec<Warning>,
+}
+
+impl PreviewRow {
+    fn notes(&self) -> String {
+        let mut notes = vec![];
+
+        if self.collision {
+            notes.push("collision".to_string());
+        }
+
+        if self.exists {
+            notes.push("exists".to_string());
+        }
+
+        if let Some(ref renamed) = self.renamed {
+            let name = Path::new(renamed).file_name().unwrap_or_default();
+            notes.push(format!("rename: {}", name.to_string_lossy()));
+        }
+
+        if !self.empty_items.is_empty() {
+            notes.push(format!("empty: {}", self.empty_items.join(" ")));
+        }
+
+        notes.join(", ")
+    }
+
+    fn style(&self) -> Style {
+        let color = if self.collision {
+            Some(AnsiColor::Red)
+        } else if self.exists || !self.empty_items.is_empty() {
+            Some(AnsiColor::Yellow)
+        } else {
+            None
+        };
+
+        Style::new().fg_color(color.map(Color::Ansi))
+    }
+}
+
+/// Builds the preview rows for a batch, sorted by source path.
+///
+/// Renames are worked out in that order too, so the first source keeps a contested name unless
+/// something's already there.
+#[must_use]
+pub fn plan_rows(mut planned: Vec<(PathBuf, Outcome)>) -> Vec<PreviewRow> {
+    let mut claims = Claims::default();
+
+    // by claim, so names that differ only by case collide on a case-insensitive destination
+    let mut destinations: HashMap<PathBuf, usize> = HashMap::new();
+    for (_, outcome) in &planned {
+        *destinations
+            .entry(claims.key(&outcome.output_path))
+            .or_default() += 1;
+    }
+
+    planned.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    planned
+        .into_iter()
+        .map(|(source, outcome)| {
+            let collision = destinations[&claims.key(&outcome.output_path)] > 1;
+            let renamed = claims
+                .claim(&outcome.output_path, Path::exists)
+                .filter(|claimed| *claimed != outcome.output_path)
+                .map(|claimed| claimed.display().to_string());
+
+            PreviewRow {
+                source: source.display().to_string(),
+                destination: outcome.output_path.display().to_string(),
+                collision,
+                exists: outcome.output_path.exists(),
+                renamed,
+                empty_items: outcome.empty_items,
+                warnings: outcome.warnings,
+            }
+        })
+        .collect()
+}
+
+pub fn print_table(rows: &[PreviewRow]) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    let color = io::stdout().is_terminal();
+
+    let src_width = rows
+        .iter()
+        .map(|r| r.source.chars().count())
+        .max()
+        .unwrap_or(0);
+    let dst_width = rows
+        .iter()
+        .map(|r| r.destination.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for row in rows {
+        let style = if color { row.style() } else { Style::new() };
+        let notes = row.notes();
+
+        write!(
+            out,
+            "{:<src_width$}  {ARROW}  {}",
+            row.source,
+            style.render()
+        )?;
+
+        if notes.is_empty() {
+            writeln!(out, "{}{}", row.destination, style.render_reset())?;
+        } else {
+            writeln!(
+                out,
+                "{:<dst_width$}{}  {notes}",
+                row.destination,
+                style.render_reset()
+            )?;
+        }
+    }
+
+    out.flush()
+}
+
+pub fn print_json(rows: &[PreviewRow]) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+
+    serde_json::to_writer_pretty(&mut out, rows)?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod preview_tests {
+    use std::path::PathBuf;
+
+    use super::plan_rows;
+    use crate::job::Outcome;
+
+    fn planned(source: &str, dest: &str) -> (PathBuf, Outcome) {
+        (
+            source.into(),
+            Outcome {
+                output_path: dest.into(),
+                empty_items: vec![],
+                salvaged: None,
+                warnings: vec![],
+                cached: false,
+                conflict: None,
+                bytes_read: 0,
+                bytes_written: 0,
+                catalog_entry: None,
+            },
+        )
+    }
+
+    #[test]
+    fn flags_colliding_destinations() {
+        let rows = plan_rows(vec![
+            planned("b.ARW", "out/same.dng"),
+            planned("a.ARW", "out/same.dng"),
+            planned("c.ARW", "out/other.dng"),
+        ]);
+
+        assert_eq!(rows[0].source, "a.ARW");
+        assert!(rows[0].collision && rows[1].collision);
+        assert!(!rows[2].collision);
+
+        assert_eq!(rows[0].renamed, None);
+        assert_eq!(rows[1].renamed.as_deref(), Some("out/same_001.dng"));
+        assert_eq!(rows[2].renamed, None);
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{self, Read, Seek, Write},
+    path::Path,
+    process,
+    sync::{Arc, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rawler::dng::convert::ConvertParams;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use sha2::{Digest as _, Sha256};
+use tokio::sync::oneshot;
+
+use crate::{
+    salvage::Strategy,
+    tiff::{TAG_XMP, Tiff},
+};
+
+const RAWBIT_VERSION: &str = env!("CARGO_PKG_VERSION");
+const RAWLER_VERSION: &str = env!("RAWBIT_RAWLER_VERSION");
+
+const XMP_NS: &str = "https://github.com/cartercanedy/rawbit/ns/1.0/";
+
+/// Threads kept for hashing sources, see [`Digest`].
+const HASH_THREADS: usize = 2;
+
+/// Identifies one invocation of rawbit, shared by every file it converts.
+#[must_use]
+pub fn new_run_id() -> String {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+
+    format!("{started:x}-{:x}", process::id())
+}
+
+#[must_use]
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+/// The SHA-256 of a file's contents, read in pieces rather than all at once.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(hex(&hasher.finalize()))
+}
+
+/// A source's SHA-256, worked out on a small pool of its own while the source is converted,
+/// instead of before.
+#[derive(Debug)]
+pub struct Digest(DigestState);
+
+#[derive(Debug)]
+enum DigestState {
+    Pending(oneshot::Receiver<String>),
+    Done(String),
+}
+
+impl Digest {
+    #[must_use]
+    pub fn spawn(data: Arc<Vec<u8>>) -> Self {
+        let (tx, rx) = oneshot::channel();
+
+        hash_pool().spawn(move || {
+            // nobody's waiting on it anymore if the conversion failed first
+            let _ = tx.send(sha256_hex(&data));
+        });
+
+        Self(DigestState::Pending(rx))
+    }
+
+    /// Waits for the digest from async code, keeping it for [`Self::blocking_finish`].
+    pub async fn wait(&mut self) -> &str {
+        if let DigestState::Pending(ref mut rx) = self.0 {
+            self.0 = DigestState::Done(rx.await.expect(HASH_POOL_DROPPED));
+        }
+
+        match self.0 {
+            DigestState::Done(ref sha256) => sha256,
+            DigestState::Pending(_) => unreachable!("the digest was just received"),
+        }
+    }
+
+    /// Waits for the digest from a blocking thread.
+    #[must_use]
+    pub fn blocking_finish(self) -> String {
+        match self.0 {
+            DigestState::Done(sha256) => sha256,
+            DigestState::Pending(rx) => rx.blocking_recv().expect(HASH_POOL_DROPPED),
+        }
+    }
+}
+
+const HASH_POOL_DROPPED: &str = "the hashing pool finishes every job it's given";
+
+/// Hashing has threads of its own, so it doesn't queue behind the conversions it overlaps with.
+fn hash_pool() -> &'static ThreadPool {
+    static POOL: OnceLock<ThreadPool> = OnceLock::new();
+
+    POOL.get_or_init(|| {
+        ThreadPoolBuilder::new()
+            .num_threads(HASH_THREADS)
+            .thread_name(|n| format!("rawbit-hash-{n}"))
+            .build()
+            .expect("couldn't start the hashing threads")
+    })
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Records which tool and settings produced a DNG, written into its XMP block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub run_id: String,
+    pub source_name: String,
+    /// filled in once the source's [`Digest`] is done
+    pub source_sha256: String,
+    pub params: String,
+    pub salvaged: Option<Strategy>,
+}
+
+impl Provenance {
+    #[must_use]
+    pub fn new(run_id: &str, source_name: &str, params: &ConvertParams) -> Self {
+        Self {
+            run_id: run_id.into(),
+            source_name: source_name.into(),
+            source_sha256: String::new(),
+            params: format!(
+                "compression={:?} crop={:?} scaling={} embedded={} preview={} thumbnail={} index={} \
+                 predictor={} photometric={:?}",
+                params.compression,
+                params.crop,
+                params.apply_scaling,
+                params.embedded,
+                params.preview,
+                params.thumbnail,
+                params.index,
+                params.predictor,
+                params.photometric_conversion
+            ),
+            salvaged: None,
+        }
+    }
+
+    /// Identifies everything besides the source that decides what a conversion writes, the tool
+    /// versions and conversion parameters along with any other `settings`.
+    #[must_use]
+    pub fn params_sha256(&self, settings: &str) -> String {
+        sha256_hex(
+            format!(
+                "rawbit={RAWBIT_VERSION} rawler={RAWLER_VERSION} {} {settings}",
+                self.params
+            )
+            .as_bytes(),
+        )
+    }
+
+    #[must_use]
+    pub fn to_xmp(&self) -> String {
+        format!(
+            concat!(
+                "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+                "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+                " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+                "  <rdf:Description rdf:about=\"\" xmlns:rawbit=\"{ns}\"\n",
+                "   rawbit:Version=\"{rawbit}\"\n",
+                "   rawbit:RawlerVersion=\"{rawler}\"\n",
+                "   rawbit:RunID=\"{run_id}\"\n",
+                "   rawbit:SourceFile=\"{source}\"\n",
+                "   rawbit:SourceSHA256=\"{sha256}\"\n",
+                "   rawbit:ConvertParams=\"{params}\"{salvaged}/>\n",
+                " </rdf:RDF>\n",
+                "</x:xmpmeta>\n",
+                "<?xpacket end=\"w\"?>"
+            ),
+            ns = XMP_NS,
+            rawbit = RAWBIT_VERSION,
+            rawler = RAWLER_VERSION,
+            run_id = escape(&self.run_id),
+            source = escape(&self.source_name),
+            sha256 = self.source_sha256,
+            params = escape(&self.params),
+            salvaged = self.salvaged.map_or_else(String::new, |strategy| {
+                format!("\n   rawbit:Salvaged=\"{}\"", escape(&strategy.to_string()))
+            }),
+        )
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Adds an XMP packet to IFD0 of a finished TIFF/DNG, replacing any that's already there.
+pub fn write_xmp<F: Read + Write + Seek>(file: &mut F, xmp: &[u8]) -> io::Result<()> {
+    let mut tiff = Tiff::open(file)?;
+    let ifd0 = tiff.read_ifd0()?;
+
+    let mut entries = ifd0.entries;
+    entries.retain(|entry| entry.tag != TAG_XMP);
+    entries.push(tiff.append_bytes(TAG_XMP, xmp)?);
+
+    let offset = tiff.append_ifd(entries, ifd0.next)?;
+    tiff.set_ifd0_offset(offset)?;
+
+    tiff.flush()
+}
+
+/// The run that wrote a DNG, from its provenance record, if it has one.
+pub fn read_run_id<F: Read + Write + Seek>(file: &mut F) -> io::Result<Option<String>> {
+    let mut tiff = Tiff::open(file)?;
+    let ifd0 = tiff.read_ifd0()?;
+
+    let Some(xmp) = ifd0.get(TAG_XMP) else {
+        return Ok(None);
+    };
+
+    let xmp = tiff.read_data(xmp)?;
+    let run_id = String::from_utf8_lossy(&xmp)
+        .split_once("rawbit:RunID=\"")
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .map(|(run_id, _)| run_id.to_string());
+
+    Ok(run_id)
+}
+
+#[cfg(test)]
+mod provenance_tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use super::{Digest, Provenance, read_run_id, sha256_hex, write_xmp};
+    use crate::salvage::Strategy;
+    use crate::tiff::{TAG_XMP, Tiff, tiff_tests};
+
+    #[test]
+    fn adds_and_replaces_xmp_in_both_byte_orders() {
+        for le in [true, false] {
+            let mut file = Cursor::new(tiff_tests::sample(le));
+
+            write_xmp(&mut file, b"<first/>").unwrap();
+            write_xmp(&mut file, b"<second/>").unwrap();
+
+            let mut tiff = Tiff::open(file).unwrap();
+            let ifd0 = tiff.read_ifd0().unwrap();
+            let xmp = *ifd0.get(TAG_XMP).unwrap();
+            let offset = tiff.offset(&xmp).unwrap() as usize;
+
+            let bytes = tiff.into_inner().into_inner();
+            assert_eq!(&bytes[offset..offset + 9], b"<second/>");
+            assert_eq!(ifd0.entries.iter().filter(|e| e.tag == TAG_XMP).count(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn hashes_on_the_side() {
+        let data = Arc::new(b"raw".to_vec());
+
+        let mut digest = Digest::spawn(Arc::clone(&data));
+        assert_eq!(digest.wait().await, sha256_hex(b"raw"));
+        assert_eq!(digest.blocking_finish(), sha256_hex(b"raw"));
+
+        let pending = Digest::spawn(data);
+        let finished = tokio::task::spawn_blocking(|| pending.blocking_finish()).await;
+        assert_eq!(finished.unwrap(), sha256_hex(b"raw"));
+    }
+
+    #[test]
+    fn reads_back_the_run_id() {
+        let mut file = Cursor::new(tiff_tests::sample(true));
+        assert_eq!(read_run_id(&mut file).unwrap(), None);
+
+        let provenance = Provenance {
+            run_id: "18f-2a".into(),
+            source_name: "a.ARW".into(),
+            source_sha256: sha256_hex(b""),
+            params: String::new(),
+            salvaged: None,
+        };
+
+        write_xmp(&mut file, provenance.to_xmp().as_bytes()).unwrap();
+        assert_eq!(read_run_id(&mut file).unwrap().as_deref(), Some("18f-2a"));
+    }
+
+    #[test]
+    fn escapes_source_names() {
+        let provenance = Provenance {
+            run_id: "run".into(),
+            source_name: "a&b\".ARW".into(),
+            source_sha256: sha256_hex(b""),
+            params: String::new(),
+            salvaged: Some(Strategy::ZeroFilled),
+        };
+
+        let xmp = provenance.to_xmp();
+        assert!(xmp.contains("rawbit:SourceFile=\"a&amp;b&quot;.ARW\""));
+        assert!(xmp.contains("rawbit:Salvaged=\"zero-filled truncated image data\""));
+        assert!(xmp.contains("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"));
+    }
+}
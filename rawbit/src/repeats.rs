@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use smlog::warn;
+
+/// A message logged about several files in a row, like a folder of files no decoder supports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Repeated {
+    pub message: String,
+    /// the file it was logged for first
+    pub first: PathBuf,
+    /// how many files in a row it was logged for
+    pub times: usize,
+}
+
+/// Logs a message about one file after another once, then counts it until a different one comes
+/// along, so the failure that's different doesn't get buried under hundreds of the same one.
+#[derive(Debug, Default)]
+pub struct Repeats {
+    last: Option<Repeated>,
+    repeated: Vec<Repeated>,
+}
+
+impl Repeats {
+    /// Whether `message` about `path` should be logged, rather than counted as a repeat of the
+    /// last one. The message is what's said about the file, without the file itself.
+    pub fn note(&mut self, path: &Path, message: impl Into<String>) -> bool {
+        let message = message.into();
+
+        if let Some(ref mut last) = self.last
+            && last.message == message
+        {
+            last.times += 1;
+            return false;
+        }
+
+        self.flush();
+        self.last = Some(Repeated {
+            message,
+            first: path.to_path_buf(),
+            times: 1,
+        });
+
+        true
+    }
+
+    /// Logs how many times the last message was repeated, if it was.
+    pub fn flush(&mut self) {
+        if let Some(last) = self.last.take().filter(|last| last.times > 1) {
+            warn!("previous message repeated {} times", last.times - 1);
+            self.repeated.push(last);
+        }
+    }
+
+    /// Every message that was repeated, in the order they were logged.
+    #[must_use]
+    pub fn finish(mut self) -> Vec<Repeated> {
+        self.flush();
+        self.repeated
+    }
+}
+
+#[cfg(test)]
+mod repeats_tests {
+    use std::path::Path;
+
+    use super::Repeats;
+
+    #[test]
+    fn counts_a_message_until_another_comes_along() {
+        let mut repeats = Repeats::default();
+        let unsupported = "no compatible RAW image decoder available";
+
+        assert!(repeats.note(Path::new("IMG_0001.CR3"), unsupported));
+        assert!(!repeats.note(Path::new("IMG_0002.CR3"), unsupported));
+        assert!(!repeats.note(Path::new("IMG_0003.CR3"), unsupported));
+        assert!(repeats.note(Path::new("IMG_0004.NEF"), "truncated file"));
+        assert!(repeats.note(Path::new("IMG_0005.CR3"), unsupported));
+
+        let repeated = repeats.finish();
+        assert_eq!(repeated.len(), 1);
+        assert_eq!(repeated[0].message, unsupported);
+        assert_eq!(repeated[0].first, Path::new("IMG_0001.CR3"));
+        assert_eq!(repeated[0].times, 3);
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read as _, Seek as _, SeekFrom, Write as _},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{
+    common::{AppError, RawbitResult, map_err},
+    conflict::OnConflict,
+    job::Warning,
+    repeats::Repeated,
+};
+
+/// What a batch used, for planning capacity across runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub wall_secs: f64,
+    /// user and system time of rawbit and its isolated workers while the batch ran, none where it
+    /// can't be measured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_secs: Option<f64>,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// how many files were being converted at once, on average
+    pub avg_concurrency: f64,
+}
+
+/// Starts measuring a batch's [`Usage`] when it's created.
+#[derive(Debug)]
+pub struct Meter {
+    started: Instant,
+    cpu: Option<Duration>,
+}
+
+impl Meter {
+    #[must_use]
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            cpu: cpu_time(),
+        }
+    }
+
+    /// `busy` is the time spent on each file, summed over every file in the batch.
+    #[must_use]
+    pub fn finish(self, busy: Duration, bytes_read: u64, bytes_written: u64) -> Usage {
+        let wall = self.started.elapsed();
+        let cpu = self
+            .cpu
+            .zip(cpu_time())
+            .map(|(start, end)| end.saturating_sub(start));
+
+        Usage {
+            wall_secs: wall.as_secs_f64(),
+            cpu_secs: cpu.as_ref().map(Duration::as_secs_f64),
+            bytes_read,
+            bytes_written,
+            avg_concurrency: if wall.is_zero() {
+                0.0
+            } else {
+                busy.as_secs_f64() / wall.as_secs_f64()
+            },
+        }
+    }
+}
+
+/// CPU time used so far by this process and the children it has waited for.
+#[cfg(unix)]
+fn cpu_time() -> Option<Duration> {
+    let mut total = Duration::ZERO;
+
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        // SAFETY: rusage is plain old data, getrusage fills it in
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+
+        // SAFETY: the pointer is to a live rusage
+        if unsafe { libc::getrusage(who, &raw mut usage) } != 0 {
+            return None;
+        }
+
+        for time in [usage.ru_utime, usage.ru_stime] {
+            total += Duration::from_secs(u64::try_from(time.tv_sec).ok()?)
+                + Duration::from_micros(u64::try_from(time.tv_usec).ok()?);
+        }
+    }
+
+    Some(total)
+}
+
+#[cfg(not(unix))]
+const fn cpu_time() -> Option<Duration> {
+    None
+}
+
+/// What became of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileStatus {
+    Converted,
+    /// skipped by `--cache`
+    Cached,
+    /// left out by `--on-conflict skip`
+    Skipped,
+    /// too small to import, see `--min-size`
+    Undersized,
+    Failed,
+}
+
+/// How one file in an import went, so a wrapper can show the result next to each image.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub source: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    pub status: FileStatus,
+    /// the `--on-conflict` policy applied because something was already where the file would go
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflict: Option<OnConflict>,
+    pub warnings: Vec<Warning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl FileReport {
+    #[must_use]
+    pub const fn failed(source: PathBuf, error: String) -> Self {
+        Self {
+            source,
+            output: None,
+            status: FileStatus::Failed,
+            conflict: None,
+            warnings: vec![],
+            error: Some(error),
+        }
+    }
+}
+
+/// How one import in a run went.
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    pub output_dir: PathBuf,
+    pub converted: usize,
+    pub cached: usize,
+    pub skipped: usize,
+    pub salvaged: usize,
+    pub failed: usize,
+    pub undersized: usize,
+    /// the `--out-dir-fallback` the import switched to when the output directory became
+    /// unavailable, the files after that are there instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_over_to: Option<PathBuf>,
+    /// set when the import as a whole failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub usage: Usage,
+    pub files: Vec<FileReport>,
+    /// warnings and errors logged for several files in a row, each of them is still in `files`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub repeated: Vec<Repeated>,
+}
+
+/// One line of a `--report` file.
+#[derive(Debug, Serialize)]
+pub struct RunReport<'a> {
+    pub run_id: &'a str,
+    /// seconds since the Unix epoch
+    pub finished: u64,
+    pub imports: Vec<ImportReport>,
+}
+
+impl RunReport<'_> {
+    /// Appends the report to `path` as a line of JSON, so one file can collect every run.
+    pub fn append(&self, path: &Path) -> RawbitResult<()> {
+        let mut line = serde_json::to_vec(self).map_err(|err| {
+            AppError::Other("couldn't serialize the run report".into(), Box::new(err))
+        })?;
+        line.push(b'\n');
+
+        let mut file = map_err!(
+            OpenOptions::new()
+                .create(true)
+                .read(true)
+                .append(true)
+                .open(path),
+            AppError::Io,
+            format!("couldn't open report: {}", path.display())
+        )?;
+
+        // keep this run off the end of one a crash cut short
+        if ends_mid_line(&mut file).unwrap_or(false) {
+            line.insert(0, b'\n');
+        }
+
+        map_err!(
+            file.write_all(&line),
+            AppError::Io,
+            format!("couldn't write report: {}", path.display())
+        )
+    }
+
+    #[must_use]
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+}
+
+fn ends_mid_line(file: &mut File) -> io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(false);
+    }
+
+    let mut last = [0];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+
+    Ok(last[0] != b'\n')
+}
+
+#[cfg(test)]
+mod report_tests {
+    use std::{fs, thread, time::Duration};
+
+    use super::{FileReport, FileStatus, ImportReport, Meter, RunReport};
+    use crate::{job::Warning, salvage::Strategy};
+
+    #[test]
+    fn measures_a_batch_and_appends_a_line_per_run() {
+        let meter = Meter::start();
+        thread::sleep(Duration::from_millis(50));
+        let usage = meter.finish(Duration::from_millis(100), 10, 20);
+
+        assert!(usage.wall_secs >= 0.05);
+        assert!(usage.avg_concurrency > 0.0 && usage.avg_concurrency <= 2.0);
+        #[cfg(unix)]
+        assert!(usage.cpu_secs.is_some());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.jsonl");
+
+        for run_id in ["a", "b"] {
+            RunReport {
+                run_id,
+                finished: RunReport::now(),
+                imports: vec![ImportReport {
+                    label: String::new(),
+                    output_dir: "out".into(),
+                    converted: 2,
+                    cached: 0,
+                    skipped: 0,
+                    salvaged: 0,
+                    failed: 0,
+                    undersized: 0,
+                    failed_over_to: None,
+                    error: None,
+                    usage,
+                    repeated: vec![],
+                    files: vec![FileReport {
+                        source: "card/IMG_0001.CR3".into(),
+                        output: Some("out/IMG_0001.dng".into()),
+                        status: FileStatus::Converted,
+                        conflict: None,
+                        warnings: vec![Warning::SalvagedDecode {
+                            strategy: Strategy::ZeroFilled,
+                            reason: "truncated".into(),
+                        }],
+                        error: None,
+                    }],
+                }],
+            }
+            .append(&path)
+            .unwrap();
+
+            // a run that crashed while appending
+            if run_id == "a" {
+                let mut report = fs::read(&path).unwrap();
+                report.extend(b"{\"run_id\": \"cr");
+                fs::write(&path, report).unwrap();
+            }
+        }
+
+        let report = fs::read_to_string(&path).unwrap();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+
+        let json: serde_json::Value = serde_json::from_str(lines[2]).unwrap();
+        assert_eq!(json["run_id"], "b");
+        assert_eq!(json["imports"][0]["usage"]["bytes_written"], 20);
+        assert!(json["imports"][0].get("label").is_none());
+
+        let file = &json["imports"][0]["files"][0];
+        assert_eq!(file["status"], "converted");
+        assert_eq!(file["warnings"][0]["code"], "salvaged-decode");
+        assert_eq!(file["warnings"][0]["reason"], "truncated");
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    io::{self, Cursor, Read, Seek, Write},
+    path::Path,
+};
+
+use crate::tiff::{Entry, Tiff};
+
+/// The raw a DNG was converted from, as `--embed-raw` embeds it.
+const TAG_ORIGINAL_RAW_FILE_NAME: u16 = 0xC68B;
+const TAG_ORIGINAL_RAW_FILE_DATA: u16 = 0xC68C;
+const TAG_ORIGINAL_RAW_FILE_DIGEST: u16 = 0xC71D;
+
+const ORIGINAL_TAGS: [u16; 3] = [
+    TAG_ORIGINAL_RAW_FILE_NAME,
+    TAG_ORIGINAL_RAW_FILE_DATA,
+    TAG_ORIGINAL_RAW_FILE_DIGEST,
+];
+
+/// Whether `path` is a DNG, which is converted again like any other raw to change how it's
+/// stored, e.g. to add a preview or drop the original embedded in it.
+#[must_use]
+pub fn is_dng(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("dng"))
+}
+
+/// The original raw embedded in a DNG, kept as its entries' bytes so it can be written into
+/// another DNG untouched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedOriginal {
+    /// each entry's tag and type, with its data
+    entries: Vec<(u16, u16, Vec<u8>)>,
+}
+
+impl EmbeddedOriginal {
+    /// The original embedded in the DNG in `dng`, if it has one.
+    #[must_use]
+    pub fn read(dng: &[u8]) -> Option<Self> {
+        let mut tiff = Tiff::open(Cursor::new(dng)).ok()?;
+        let ifd0 = tiff.read_ifd0().ok()?;
+        ifd0.get(TAG_ORIGINAL_RAW_FILE_DATA)?;
+
+        let entries = ORIGINAL_TAGS
+            .iter()
+            .filter_map(|&tag| ifd0.get(tag))
+            .map(|entry| Some((entry.tag, entry.kind, tiff.read_data(entry).ok()?)))
+            .collect::<Option<_>>()?;
+
+        Some(Self { entries })
+    }
+
+    /// Embeds the original into the DNG in `file`, in place of any it already has.
+    pub fn write<F: Read + Write + Seek>(&self, file: &mut F) -> io::Result<()> {
+        let mut tiff = Tiff::open(file)?;
+        let ifd0 = tiff.read_ifd0()?;
+
+        let mut entries = ifd0.entries;
+        entries.retain(|entry| !ORIGINAL_TAGS.contains(&entry.tag));
+
+        for (tag, kind, data) in &self.entries {
+            // all three are byte-sized, so only the type differs from plain bytes
+            entries.push(Entry {
+                kind: *kind,
+                ..tiff.append_bytes(*tag, data)?
+            });
+        }
+
+        let offset = tiff.append_ifd(entries, ifd0.next)?;
+        tiff.set_ifd0_offset(offset)?;
+
+        tiff.flush()
+    }
+}
+
+#[cfg(test)]
+mod reprocess_tests {
+    use std::{io::Cursor, path::Path};
+
+    use super::{EmbeddedOriginal, TAG_ORIGINAL_RAW_FILE_DATA, is_dng};
+    use crate::tiff::{Tiff, tiff_tests};
+
+    #[test]
+    fn carries_the_embedded_original_into_a_new_dng() {
+        assert!(is_dng(Path::new("IMG_0001.DNG")));
+        assert!(!is_dng(Path::new("IMG_0001.CR3")));
+
+        let original = EmbeddedOriginal {
+            entries: vec![(TAG_ORIGINAL_RAW_FILE_DATA, 7, b"compressed raw".to_vec())],
+        };
+
+        let mut dng = Cursor::new(tiff_tests::sample(true));
+        assert_eq!(EmbeddedOriginal::read(dng.get_ref()), None);
+
+        original.write(&mut dng).unwrap();
+        assert_eq!(EmbeddedOriginal::read(dng.get_ref()), Some(original));
+
+        let mut tiff = Tiff::open(dng).unwrap();
+        let ifd0 = tiff.read_ifd0().unwrap();
+        assert_eq!(ifd0.get(TAG_ORIGINAL_RAW_FILE_DATA).unwrap().kind, 7);
+    }
+}
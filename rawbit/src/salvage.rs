@@ -0,0 +1,290 @@
+use std::{
+    fmt::{self, Display},
+    io::Cursor,
+    panic::{self, AssertUnwindSafe},
+};
+
+use clap::ValueEnum;
+use image::{DynamicImage, ImageFormat, codecs::jpeg::JpegEncoder};
+use rawler::{
+    decoders::{Decoder, RawDecodeParams},
+    dng::convert::{ConvertParams, convert_raw_source},
+    get_decoder,
+    rawsource::RawSource,
+};
+use serde::{Deserialize, Serialize};
+
+const JPEG_QUALITY: u8 = 95;
+
+/// JPEG markers
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+/// lossless frames, which is how some cameras store the raw data itself
+const LOSSLESS_FRAMES: [u8; 4] = [0xC3, 0xC7, 0xCB, 0xCF];
+
+/// What's kept of a file no decoder can convert, see `--fallback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConvertFallback {
+    /// the camera's embedded JPEG, in a `fallback/` directory of its own
+    ExtractJpeg,
+}
+
+/// The fallback `--salvage` used to recover a file that the decoder couldn't convert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Strategy {
+    /// another raw image stored in the same file
+    ImageIndex(usize),
+    /// the file was cut short and its missing tail was filled with zeroes
+    ZeroFilled,
+    /// nothing but the camera's embedded JPEG could be read
+    EmbeddedJpeg,
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ImageIndex(index) => write!(f, "converted raw image #{index} instead"),
+            Self::ZeroFilled => f.write_str("zero-filled truncated image data"),
+            Self::EmbeddedJpeg => f.write_str("extracted the embedded JPEG"),
+        }
+    }
+}
+
+/// What a fallback recovered: a DNG, or a JPEG when [`Strategy::EmbeddedJpeg`] was used.
+#[derive(Debug)]
+pub struct Salvaged {
+    pub strategy: Strategy,
+    pub data: Vec<u8>,
+}
+
+/// Runs a conversion, treating a decoder panic as just another failed attempt.
+fn attempt<T>(f: impl FnOnce() -> Option<T>) -> Option<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).ok().flatten()
+}
+
+fn convert(source: &RawSource, name: &str, params: &ConvertParams) -> Option<Vec<u8>> {
+    let mut dng = Cursor::new(vec![]);
+
+    attempt(|| convert_raw_source(source, &mut dng, name, params).ok())?;
+    Some(dng.into_inner())
+}
+
+/// Tries each fallback in turn on a file whose conversion failed, returning the first that works.
+///
+/// Other raw images in the file are tried first, then the same image with any truncated data
+/// zero-filled, and as a last resort the camera's embedded JPEG, re-encoded at full size if the
+/// decoder has it.
+#[must_use]
+pub fn salvage(source: &[u8], name: &str, params: &ConvertParams) -> Option<Salvaged> {
+    let raw = RawSource::new_from_slice(source);
+    let decoder = attempt(|| get_decoder(&raw).ok())?;
+    let count = attempt(|| decoder.raw_image_count().ok()).unwrap_or(1);
+
+    for index in (0..count).filter(|&index| index != params.index) {
+        let params = ConvertParams {
+            index,
+            ..params.clone()
+        };
+
+        if let Some(data) = convert(&raw, name, &params) {
+            return Some(Salvaged {
+                strategy: Strategy::ImageIndex(index),
+                data,
+            });
+        }
+    }
+
+    // decoders bail when a strip or tile runs past the end of the file, padding the file back
+    // out lets them read everything that did make it onto the card
+    let mut padded = source.to_vec();
+    padded.resize(source.len() * 2, 0);
+
+    if let Some(data) = convert(&RawSource::new_from_slice(&padded), name, params) {
+        return Some(Salvaged {
+            strategy: Strategy::ZeroFilled,
+            data,
+        });
+    }
+
+    embedded_jpeg(decoder.as_ref(), &raw, params.index).map(|data| Salvaged {
+        strategy: Strategy::EmbeddedJpeg,
+        data,
+    })
+}
+
+/// The camera's embedded JPEG, for a file that couldn't be converted at all, see `--fallback`.
+///
+/// The largest JPEG stored in the file is kept as it is, which also works for cameras no decoder
+/// knows yet. Failing that, the decoder's preview is re-encoded, if there's a decoder for the file.
+#[must_use]
+pub fn extract_jpeg(source: &[u8]) -> Option<Vec<u8>> {
+    let mut stored = (0..source.len().saturating_sub(2))
+        .filter(|&at| source[at..].starts_with(&[0xFF, SOI, 0xFF]))
+        .filter_map(|at| jpeg_at(&source[at..]))
+        .collect::<Vec<_>>();
+
+    stored.sort_by_key(|jpeg| std::cmp::Reverse(jpeg.len()));
+
+    if let Some(jpeg) = stored
+        .into_iter()
+        .find(|jpeg| image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg).is_ok())
+    {
+        return Some(jpeg.to_vec());
+    }
+
+    let raw = RawSource::new_from_slice(source);
+    let decoder = attempt(|| get_decoder(&raw).ok())?;
+    embedded_jpeg(decoder.as_ref(), &raw, 0)
+}
+
+/// The JPEG at the start of `data`, up to its end of image marker, unless it's a lossless one.
+fn jpeg_at(data: &[u8]) -> Option<&[u8]> {
+    let byte = |at: usize| data.get(at).copied();
+    let mut at = 2;
+
+    loop {
+        if byte(at)? != 0xFF {
+            return None;
+        }
+
+        let marker = byte(at + 1)?;
+        match marker {
+            EOI => return Some(&data[..at + 2]),
+            // fill byte before a marker
+            0xFF => {
+                at += 1;
+                continue;
+            }
+            _ if LOSSLESS_FRAMES.contains(&marker) => return None,
+            _ => {}
+        }
+
+        let len = usize::from(u16::from_be_bytes([byte(at + 2)?, byte(at + 3)?]));
+        at += 2 + len;
+
+        // the image data runs up to the next marker that isn't a stuffed 0xFF or a restart
+        if marker == SOS {
+            while byte(at)? != 0xFF || matches!(byte(at + 1)?, 0x00 | 0xD0..=0xD7 | 0xFF) {
+                at += 1;
+            }
+        }
+    }
+}
+
+fn embedded_jpeg(decoder: &dyn Decoder, raw: &RawSource, image_index: usize) -> Option<Vec<u8>> {
+    let params = RawDecodeParams { image_index };
+
+    let image = attempt(|| decoder.full_image(raw, &params).ok().flatten())
+        .or_else(|| attempt(|| decoder.preview_image(raw, &params).ok().flatten()))
+        .or_else(|| attempt(|| decoder.thumbnail_image(raw, &params).ok().flatten()))?;
+
+    encode_jpeg(&image)
+}
+
+fn encode_jpeg(image: &DynamicImage) -> Option<Vec<u8>> {
+    let mut jpeg = vec![];
+
+    JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+        .encode_image(&DynamicImage::ImageRgb8(image.to_rgb8()))
+        .ok()?;
+
+    Some(jpeg)
+}
+
+#[cfg(test)]
+mod salvage_tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, RgbImage};
+    use rawler::{
+        decoders::RawMetadata,
+        dng::{DNG_VERSION_V1_4, DngCompression, convert::ConvertParams, writer::DngWriter},
+        rawsource::RawSource,
+    };
+
+    use super::{Strategy, convert, encode_jpeg, extract_jpeg, salvage};
+    use crate::tiff::Tiff;
+
+    const TAG_STRIP_OFFSETS: u16 = 0x0111;
+
+    /// A small DNG whose only strip claims to run past the end of the file, like one cut short
+    /// by a card pulled mid-write.
+    fn truncated_dng() -> Vec<u8> {
+        let mut dng = Cursor::new(vec![]);
+
+        let mut writer = DngWriter::new(&mut dng, DNG_VERSION_V1_4).unwrap();
+        writer.load_metadata(&RawMetadata::default()).unwrap();
+
+        let mut frame = writer.subframe_on_root(0);
+        frame
+            .rgb_image_u8(&[128; 16 * 16 * 3], 16, 16, DngCompression::Uncompressed, 1)
+            .unwrap();
+        frame.finalize().unwrap();
+        writer.close().unwrap();
+
+        let len = u32::try_from(dng.get_ref().len()).unwrap();
+        let mut tiff = Tiff::open(dng).unwrap();
+        let ifd0 = tiff.read_ifd0().unwrap();
+
+        let entries = ifd0
+            .entries
+            .iter()
+            .map(|entry| match entry.tag {
+                TAG_STRIP_OFFSETS => tiff.with_pointer(*entry, len - 100),
+                _ => *entry,
+            })
+            .collect();
+
+        let offset = tiff.append_ifd(entries, ifd0.next).unwrap();
+        tiff.set_ifd0_offset(offset).unwrap();
+
+        tiff.into_inner().into_inner()
+    }
+
+    #[test]
+    fn zero_fills_a_truncated_file() {
+        let dng = truncated_dng();
+        let params = ConvertParams::default();
+        assert!(convert(&RawSource::new_from_slice(&dng), "CUT.DNG", &params).is_none());
+
+        let salvaged = salvage(&dng, "CUT.DNG", &params).unwrap();
+
+        assert_eq!(salvaged.strategy, Strategy::ZeroFilled);
+        assert!(salvaged.data.starts_with(b"II*\0"));
+    }
+
+    #[test]
+    fn encodes_a_decodable_jpeg() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(8, 4));
+        let jpeg = encode_jpeg(&image).unwrap();
+
+        let decoded = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (8, 4));
+    }
+
+    #[test]
+    fn extracts_the_largest_stored_jpeg() {
+        let preview = encode_jpeg(&DynamicImage::ImageRgb8(RgbImage::new(64, 48))).unwrap();
+        let thumbnail = encode_jpeg(&DynamicImage::ImageRgb8(RgbImage::new(8, 6))).unwrap();
+
+        // a raw from a camera no decoder knows, with its JPEGs somewhere in the middle
+        let mut raw = b"FUTURE-RAW".to_vec();
+        raw.extend_from_slice(&thumbnail);
+        raw.extend_from_slice(&[0xFF, 0xD8, 0xFF, 0x00, 0x12]);
+        raw.extend_from_slice(&preview);
+        raw.extend_from_slice(&[0; 64]);
+
+        assert_eq!(extract_jpeg(&raw).unwrap(), preview);
+        assert_eq!(extract_jpeg(b"FUTURE-RAW"), None);
+    }
+
+    #[test]
+    fn strategies_describe_themselves() {
+        assert_eq!(
+            Strategy::ImageIndex(1).to_string(),
+            "converted raw image #1 instead"
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use std::{borrow::Cow, path};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use smlog::debug;
+use unicode_normalization::UnicodeNormalization as _;
+
+use crate::{
+    common::{AppError, RawbitResult},
+    paths::RESERVED_NAMES,
+};
+
+/// What a rendered name's characters are replaced with when they can't be in one, unless
+/// `--replace-char` says otherwise.
+pub const DEFAULT_REPLACEMENT: char = '_';
+
+/// The longest name most file systems take, in bytes, unless `--max-name-len` says otherwise.
+pub const DEFAULT_MAX_NAME_LEN: usize = 255;
+
+/// Room left in a name cut short for the `_NNN` that `--on-conflict rename` adds, so it's never
+/// cut again, see [`numbered`](crate::conflict::numbered).
+const NUMBER_LEN: usize = "_000".len();
+
+/// The shortest `--max-name-len`, enough for a few characters of the name along with its
+/// extension and number.
+pub const MIN_NAME_LEN: usize = 16;
+
+/// Whose rules rendered names follow, see `--name-rules`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameRules {
+    /// the rules of the system rawbit is running on
+    #[default]
+    Auto,
+    /// no `< > : " / \ | ? *` or control characters, no names ending in a dot or a space, and
+    /// none of the names Windows reserves for devices, like `CON`
+    Windows,
+    /// no `/` or `:`, which Finder shows as `/`
+    Macos,
+    /// no `/`
+    Unix,
+}
+
+impl NameRules {
+    /// The rules `Auto` stands for here.
+    #[must_use]
+    pub const fn resolve(self) -> Self {
+        match self {
+            Self::Auto if cfg!(windows) => Self::Windows,
+            Self::Auto if cfg!(target_os = "macos") => Self::Macos,
+            Self::Auto => Self::Unix,
+            rules => rules,
+        }
+    }
+
+    /// Whether `c` can't be in a name.
+    #[must_use]
+    pub const fn is_illegal(self, c: char) -> bool {
+        match self.resolve() {
+            Self::Windows => {
+                c.is_ascii_control()
+                    || matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*')
+            }
+            Self::Macos => matches!(c, '\0' | '/' | ':'),
+            Self::Auto | Self::Unix => matches!(c, '\0' | '/'),
+        }
+    }
+}
+
+/// The Unicode normalization form rendered names are put in, see `--normalize`.
+///
+/// The same accented letter can be one character or a letter followed by a combining accent.
+/// Cameras write either, and HFS+ decomposes names, so two names that look the same can be two
+/// different files once synced between macOS and Linux.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Normalization {
+    /// leave names as they're rendered
+    #[default]
+    None,
+    /// composed, accented letters as one character where there's one for them
+    Nfc,
+    /// decomposed, accented letters as the letter and its accents
+    Nfd,
+}
+
+impl Normalization {
+    /// `s` in this form.
+    #[must_use]
+    pub fn apply(self, s: &str) -> Cow<'_, str> {
+        match self {
+            Self::None => Cow::Borrowed(s),
+            Self::Nfc => Cow::Owned(s.nfc().collect()),
+            Self::Nfd => Cow::Owned(s.nfd().collect()),
+        }
+    }
+}
+
+/// Makes rendered names valid under a set of [`NameRules`], replacing what can't be in them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sanitizer {
+    rules: NameRules,
+    replacement: char,
+    /// in bytes
+    max_name_len: usize,
+    normalization: Normalization,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Self {
+            rules: NameRules::Auto,
+            replacement: DEFAULT_REPLACEMENT,
+            max_name_len: DEFAULT_MAX_NAME_LEN,
+            normalization: Normalization::None,
+        }
+    }
+}
+
+impl Sanitizer {
+    /// Replaces what `rules` don't allow with `replacement`, which they have to allow.
+    pub fn new(rules: NameRules, replacement: char) -> RawbitResult<Self> {
+        if rules.is_illegal(replacement) || path::is_separator(replacement) {
+            return Err(AppError::Config(format!(
+                "{replacement:?} can't be in a name, so it can't replace characters that can't be"
+            )));
+        }
+
+        Ok(Self {
+            rules,
+            replacement,
+            max_name_len: DEFAULT_MAX_NAME_LEN,
+            normalization: Normalization::None,
+        })
+    }
+
+    /// Cuts names longer than `max_name_len` bytes short.
+    pub fn with_max_name_len(self, max_name_len: usize) -> RawbitResult<Self> {
+        if max_name_len < MIN_NAME_LEN {
+            return Err(AppError::Config(format!(
+                "names can't be limited to less than {MIN_NAME_LEN} bytes, got {max_name_len}"
+            )));
+        }
+
+        Ok(Self {
+            max_name_len,
+            ..self
+        })
+    }
+
+    /// Puts names in the Unicode normalization form `normalization`.
+    #[must_use]
+    pub const fn with_normalization(self, normalization: Normalization) -> Self {
+        Self {
+            normalization,
+            ..self
+        }
+    }
+
+    /// What's put in place of each character that can't be in a name.
+    #[must_use]
+    pub const fn replacement(self) -> char {
+        self.replacement
+    }
+
+    /// `path`, relative and separated by [`path::MAIN_SEPARATOR`], with each directory and file
+    /// name in it made valid and normalized.
+    #[must_use]
+    pub fn path(self, path: &str) -> String {
+        let path = self.normalization.apply(path);
+        let mut segments = path.split(path::MAIN_SEPARATOR).peekable();
+        let mut sanitized = String::with_capacity(path.len());
+
+        while let Some(segment) = segments.next() {
+            // the file name has its extension put after it, so only directories can end in a dot
+            let is_dir = segments.peek().is_some();
+            sanitized.push_str(&self.name(segment, is_dir));
+
+            if is_dir {
+                sanitized.push(path::MAIN_SEPARATOR);
+            }
+        }
+
+        sanitized
+    }
+
+    /// `path`'s file name cut short enough to take `.ext` and a number after it, if it's too long
+    /// for both.
+    #[must_use]
+    pub fn fit(self, path: &str, ext: &str) -> String {
+        let (dir, name) = path
+            .rsplit_once(path::MAIN_SEPARATOR)
+            .map_or(("", path), |(dir, name)| (dir, name));
+
+        let max = self
+            .max_name_len
+            .saturating_sub(ext.len() + 1 + NUMBER_LEN)
+            .max(1);
+
+        if name.len() <= max {
+            return path.into();
+        }
+
+        let fitted = truncate(name, max);
+        debug!("cut {name:?} short to {fitted:?}, see --max-name-len");
+
+        if dir.is_empty() {
+            fitted.into()
+        } else {
+            format!("{dir}{}{fitted}", path::MAIN_SEPARATOR)
+        }
+    }
+
+    fn name(self, name: &str, is_dir: bool) -> String {
+        let mut sanitized = name
+            .chars()
+            .map(|c| {
+                if self.rules.is_illegal(c) {
+                    self.replacement
+                } else {
+                    c
+                }
+            })
+            .collect::<String>();
+
+        // a file name is cut short along with its extension, see `fit`
+        if is_dir {
+            let len = truncate(&sanitized, self.max_name_len).len();
+            sanitized.truncate(len);
+        }
+
+        if self.rules.resolve() != NameRules::Windows {
+            return sanitized;
+        }
+
+        if is_dir {
+            let kept = sanitized.trim_end_matches(['.', ' ']).len();
+            let trailing = sanitized.len() - kept;
+            sanitized.truncate(kept);
+            sanitized.extend(std::iter::repeat_n(self.replacement, trailing));
+        }
+
+        let stem = sanitized.split('.').next().unwrap_or_default();
+        if RESERVED_NAMES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(stem.trim_end()))
+        {
+            sanitized.insert(stem.len(), self.replacement);
+        }
+
+        sanitized
+    }
+}
+
+/// `name` cut to at most `max` bytes, without splitting a character.
+fn truncate(name: &str, max: usize) -> &str {
+    if name.len() <= max {
+        return name;
+    }
+
+    let end = (0..=max)
+        .rev()
+        .find(|&end| name.is_char_boundary(end))
+        .unwrap_or_default();
+
+    &name[..end]
+}
+
+#[cfg(test)]
+mod sanitize_tests {
+    use std::path::{MAIN_SEPARATOR_STR, Path};
+
+    use super::{NameRules, Normalization, Sanitizer};
+    use crate::conflict;
+
+    #[test]
+    fn replaces_what_each_system_does_not_allow() {
+        let sanitize = |rules, replacement, path: &str| {
+            Sanitizer::new(rules, replacement)
+                .unwrap()
+                .path(&path.replace('/', MAIN_SEPARATOR_STR))
+                .replace(MAIN_SEPARATOR_STR, "/")
+        };
+
+        let name = "EF 24-70mm f2.8L II? 1:250*";
+        assert_eq!(sanitize(NameRules::Unix, '_', name), name);
+        assert_eq!(
+            sanitize(NameRules::Macos, '_', name),
+            "EF 24-70mm f2.8L II? 1_250*"
+        );
+        assert_eq!(
+            sanitize(NameRules::Windows, '-', name),
+            "EF 24-70mm f2.8L II- 1-250-"
+        );
+
+        // names Windows gives special meaning to, the file name's dot comes before its extension
+        assert_eq!(
+            sanitize(NameRules::Windows, '_', "shoot. /con/aux.txt/IMG_0001."),
+            "shoot__/con_/aux_.txt/IMG_0001."
+        );
+
+        assert!(Sanitizer::new(NameRules::Windows, ':').is_err());
+        assert!(Sanitizer::new(NameRules::Unix, '/').is_err());
+        assert!(Sanitizer::new(NameRules::Unix, ':').is_ok());
+    }
+
+    #[test]
+    fn cuts_long_names_short() {
+        let sanitizer = Sanitizer::new(NameRules::Unix, '_')
+            .unwrap()
+            .with_max_name_len(20)
+            .unwrap();
+        let unix = |path: String| path.replace(MAIN_SEPARATOR_STR, "/");
+
+        let long = format!("{0}/{0}", "a".repeat(30)).replace('/', MAIN_SEPARATOR_STR);
+        let path = sanitizer.path(&long);
+        assert_eq!(
+            unix(path.clone()),
+            format!("{}/{}", "a".repeat(20), "a".repeat(30))
+        );
+
+        // room for `.dng` and `_001`, which then fit
+        let fitted = sanitizer.fit(&path, "dng");
+        assert_eq!(
+            unix(fitted.clone()),
+            format!("{}/{}", "a".repeat(20), "a".repeat(12))
+        );
+        let numbered = conflict::numbered(Path::new(&format!("{fitted}.dng")), 1);
+        assert_eq!(numbered.file_name().unwrap().len(), 20);
+
+        assert_eq!(sanitizer.fit("IMG_0001", "dng"), "IMG_0001");
+        // not in the middle of a character
+        assert_eq!(sanitizer.fit(&"é".repeat(10), "dng"), "é".repeat(6));
+
+        assert!(sanitizer.with_max_name_len(8).is_err());
+    }
+
+    #[test]
+    fn normalizes_names() {
+        let (composed, decomposed) = ("Caf\u{e9}/Obj\u{e9}ctif", "Cafe\u{301}/Obje\u{301}ctif");
+        let normalize = |normalization, path: &str| {
+            Sanitizer::default()
+                .with_normalization(normalization)
+                .path(&path.replace('/', MAIN_SEPARATOR_STR))
+                .replace(MAIN_SEPARATOR_STR, "/")
+        };
+
+        assert_eq!(normalize(Normalization::Nfc, decomposed), composed);
+        assert_eq!(normalize(Normalization::Nfc, composed), composed);
+        assert_eq!(normalize(Normalization::Nfd, composed), decomposed);
+        assert_eq!(normalize(Normalization::None, decomposed), decomposed);
+    }
+}
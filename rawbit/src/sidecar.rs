@@ -0,0 +1,88 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use crate::args;
+
+/// Extensions of the files kept next to a raw that belong with it: edits from Lightroom and
+/// darktable, and voice memos recorded in camera. See `--sidecars`.
+pub const SIDECAR_EXTENSIONS: &[&str] = &["xmp", "wav"];
+
+/// Extensions of the JPEG a camera shooting RAW+JPEG writes next to each raw.
+pub const JPEG_EXTENSIONS: &[&str] = &["jpg", "jpeg"];
+
+/// What's done with the JPEG of a RAW+JPEG pair.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum JpegPolicy {
+    /// leave it on the card
+    #[default]
+    Ignore,
+    /// copy it next to its raw's output under its own name
+    Copy,
+    /// copy it next to its raw's output, named the same
+    Rename,
+}
+
+/// Whether `path` has one of the [`SIDECAR_EXTENSIONS`].
+#[must_use]
+pub fn is_sidecar(path: &Path) -> bool {
+    has_extension(path, SIDECAR_EXTENSIONS)
+}
+
+/// Whether `path` has one of the [`JPEG_EXTENSIONS`].
+#[must_use]
+pub fn is_jpeg(path: &Path) -> bool {
+    has_extension(path, JPEG_EXTENSIONS)
+}
+
+/// Whether `path` is the JPEG half of a RAW+JPEG pair, a JPEG with a raw of the same name next to
+/// it.
+#[must_use]
+pub fn is_paired_jpeg(path: &Path) -> bool {
+    is_jpeg(path) && companions(path, &args::with_raws(vec![])).next().is_some()
+}
+
+/// The sidecars next to `raw`, the files with its name and one of [`SIDECAR_EXTENSIONS`].
+#[must_use]
+pub fn find(raw: &Path) -> Vec<PathBuf> {
+    companions(raw, SIDECAR_EXTENSIONS).collect()
+}
+
+/// The JPEG the camera wrote along with `raw`, if it shot RAW+JPEG.
+#[must_use]
+pub fn find_jpeg(raw: &Path) -> Option<PathBuf> {
+    companions(raw, JPEG_EXTENSIONS).next()
+}
+
+fn has_extension(path: &Path, extensions: &[impl AsRef<str>]) -> bool {
+    path.extension().is_some_and(|ext| {
+        extensions
+            .iter()
+            .any(|known| ext.eq_ignore_ascii_case(known.as_ref()))
+    })
+}
+
+/// The files next to `path` with its name and one of `extensions`.
+///
+/// Each extension is tried in lower and upper case rather than listing the directory, which would
+/// be read again for every file in it.
+fn companions<'a>(
+    path: &'a Path,
+    extensions: &'a [impl AsRef<str>],
+) -> impl Iterator<Item = PathBuf> + 'a {
+    let stem = path.file_stem();
+
+    extensions.iter().filter_map(move |ext| {
+        let (stem, ext) = (stem?, ext.as_ref());
+
+        [ext.to_ascii_lowercase(), ext.to_ascii_uppercase()]
+            .into_iter()
+            .map(|ext| {
+                let mut name = stem.to_os_string();
+                name.push(".");
+                name.push(ext);
+                path.with_file_name(name)
+            })
+            .find(|companion| companion != path && companion.is_file())
+    })
+}
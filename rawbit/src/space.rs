@@ -0,0 +1,199 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use smlog::debug;
+
+use crate::{
+    args::IngestItem,
+    common::{AppError, RawbitResult},
+};
+
+const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+/// Roughly how much room `item` takes once it's written: about the input's size, twice that with
+/// the raw embedded in the DNG.
+#[must_use]
+pub fn estimate(item: &IngestItem, embed: bool) -> u64 {
+    let size = source_size(item).unwrap_or(0);
+
+    if embed && !item.copy {
+        size.saturating_mul(2)
+    } else {
+        size
+    }
+}
+
+/// How big the input is, inside its archive if it's in one.
+#[must_use]
+pub fn source_size(item: &IngestItem) -> Option<u64> {
+    item.member.as_ref().map_or_else(
+        || fs::metadata(&item.input_path).ok().map(|stat| stat.len()),
+        |member| Some(member.size()),
+    )
+}
+
+/// Fails unless every filesystem being written to has room for what's going to it, with `margin`
+/// to spare. Destinations don't have to exist yet, and ones whose free space can't be read are
+/// let through.
+pub fn check(
+    destinations: impl IntoIterator<Item = (PathBuf, u64)>,
+    margin: u64,
+) -> RawbitResult<()> {
+    // by filesystem: a destination on it, the space free and the space needed
+    let mut filesystems = BTreeMap::<u64, (PathBuf, u64, u64)>::new();
+
+    for (destination, bytes) in destinations {
+        let Some((fs_id, free)) = destination.ancestors().find_map(free_space) else {
+            debug!("couldn't read the free space for {}", destination.display());
+            continue;
+        };
+
+        // joining an empty subdirectory leaves a trailing separator
+        let destination = destination.components().collect();
+        filesystems.entry(fs_id).or_insert((destination, free, 0)).2 += bytes;
+    }
+
+    for (destination, free, needed) in filesystems.into_values() {
+        debug!(
+            "{} needed in {}, {} free",
+            human(needed),
+            destination.display(),
+            human(free)
+        );
+
+        if needed.saturating_add(margin) > free {
+            return Err(AppError::Io(
+                format!(
+                    "not enough space for {}: about {} is needed{}, only {} is free",
+                    destination.display(),
+                    human(needed),
+                    if margin > 0 {
+                        format!(" plus {} to spare", human(margin))
+                    } else {
+                        String::new()
+                    },
+                    human(free)
+                ),
+                io::ErrorKind::StorageFull.into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The filesystem `path` is on and the space free on it, if `path` exists.
+#[cfg(unix)]
+#[allow(clippy::useless_conversion)] // the field types differ between platforms
+fn free_space(path: &Path) -> Option<(u64, u64)> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt as _};
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+
+    // SAFETY: statvfs is plain old data, statvfs fills it in
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    // SAFETY: the path is NUL-terminated and the pointer is to a live statvfs
+    if unsafe { libc::statvfs(path.as_ptr(), &raw mut stat) } != 0 {
+        return None;
+    }
+
+    let free = u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize));
+    Some((u64::from(stat.f_fsid), free))
+}
+
+#[cfg(not(unix))]
+const fn free_space(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Parses a size like `500M`, `2G` or `1.5GiB`, units are powers of 1024, a bare number is bytes.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("expected a size like 500M or 2G, got \"{size}\""))?;
+
+    let exponent = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches(['B', 'I'])
+    {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return Err(format!("unknown unit \"{unit}\", expected K, M, G or T")),
+    };
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok((number * 1024_f64.powi(exponent)) as u64)
+}
+
+/// `bytes` in the largest unit that keeps it above 1, e.g. `1.5 GiB`.
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn human(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod space_tests {
+    use super::{check, human, parse_size};
+
+    #[test]
+    fn reads_and_prints_sizes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("500M"), Ok(500 << 20));
+        assert_eq!(parse_size("1.5GiB"), Ok(3 << 29));
+        assert_eq!(parse_size("2 gb"), Ok(2 << 30));
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("G").is_err());
+
+        assert_eq!(human(512), "512 B");
+        assert_eq!(human(3 << 29), "1.5 GiB");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_batches_the_destination_has_no_room_for() {
+        let dir = tempfile::tempdir().unwrap();
+        let not_yet = dir.path().join("out/2024");
+
+        assert!(check([(not_yet.clone(), 1)], 0).is_ok());
+        assert!(check([(not_yet.clone(), u64::MAX / 2)], 0).is_err());
+        // every destination on a filesystem counts towards it
+        assert!(
+            check(
+                [
+                    (not_yet.clone(), u64::MAX / 4 + 1),
+                    (dir.path().into(), u64::MAX / 4 + 1)
+                ],
+                0
+            )
+            .is_err()
+        );
+        assert!(check([(not_yet, 1)], u64::MAX / 2).is_err());
+    }
+}
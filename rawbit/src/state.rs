@@ -0,0 +1,123 @@
+use std::{
+    fs::create_dir_all,
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+
+use crate::common::{AppError, RawbitResult, map_err};
+
+const CATALOG_FILE: &str = "catalog.jsonl";
+const COUNTERS_DIR: &str = "counters";
+const INTENTS_DIR: &str = "intents";
+const JOURNALS_DIR: &str = "journals";
+const METADATA_CACHE_DIR: &str = "metadata";
+const WATCH_SOCKET: &str = "watch.sock";
+
+/// Locations of rawbit's persistent state and caches.
+///
+/// Defaults to the XDG state/cache directories on Linux and the platform equivalents elsewhere.
+/// Nothing is created until a feature asks for one of the paths below.
+#[derive(Debug, Clone)]
+pub struct StateDirs {
+    state: Option<PathBuf>,
+    cache: Option<PathBuf>,
+}
+
+impl StateDirs {
+    #[must_use]
+    pub fn resolve(state_dir: Option<PathBuf>, cache_dir: Option<PathBuf>) -> Self {
+        let project = ProjectDirs::from("", "", "rawbit");
+
+        // only linux has a dedicated state dir, the local data dir is the closest elsewhere
+        let state = state_dir.or_else(|| {
+            project.as_ref().map(|dirs| {
+                dirs.state_dir()
+                    .unwrap_or_else(|| dirs.data_local_dir())
+                    .to_path_buf()
+            })
+        });
+
+        let cache =
+            cache_dir.or_else(|| project.as_ref().map(|dirs| dirs.cache_dir().to_path_buf()));
+
+        Self { state, cache }
+    }
+
+    pub fn catalog_path(&self) -> RawbitResult<PathBuf> {
+        Ok(ensure_dir(self.state_dir()?)?.join(CATALOG_FILE))
+    }
+
+    /// Where the catalog is, without creating the state directory for it.
+    pub fn existing_catalog_path(&self) -> RawbitResult<PathBuf> {
+        Ok(self.state_dir()?.join(CATALOG_FILE))
+    }
+
+    pub fn counters_dir(&self) -> RawbitResult<PathBuf> {
+        ensure_dir(&self.state_dir()?.join(COUNTERS_DIR))
+    }
+
+    pub fn intents_dir(&self) -> RawbitResult<PathBuf> {
+        ensure_dir(&self.state_dir()?.join(INTENTS_DIR))
+    }
+
+    pub fn journals_dir(&self) -> RawbitResult<PathBuf> {
+        ensure_dir(&self.state_dir()?.join(JOURNALS_DIR))
+    }
+
+    /// Where a `--watch` import answers `rawbit daemon status`.
+    pub fn watch_socket_path(&self) -> RawbitResult<PathBuf> {
+        Ok(ensure_dir(self.state_dir()?)?.join(WATCH_SOCKET))
+    }
+
+    pub fn metadata_cache_dir(&self) -> RawbitResult<PathBuf> {
+        ensure_dir(&self.cache_dir()?.join(METADATA_CACHE_DIR))
+    }
+
+    fn state_dir(&self) -> RawbitResult<&Path> {
+        self.state.as_deref().ok_or_else(|| {
+            AppError::Config("couldn't determine a state directory, set --state-dir".into())
+        })
+    }
+
+    fn cache_dir(&self) -> RawbitResult<&Path> {
+        self.cache.as_deref().ok_or_else(|| {
+            AppError::Config("couldn't determine a cache directory, set --cache-dir".into())
+        })
+    }
+}
+
+fn ensure_dir(dir: &Path) -> RawbitResult<PathBuf> {
+    map_err!(
+        create_dir_all(dir),
+        AppError::Io,
+        format!("couldn't create state directory: {}", dir.display())
+    )?;
+
+    Ok(dir.to_path_buf())
+}
+
+#[cfg(test)]
+mod state_tests {
+    use std::io::Result;
+
+    use tempfile::tempdir;
+
+    use super::StateDirs;
+
+    #[test]
+    fn overrides_take_precedence() -> Result<()> {
+        let state = tempdir()?;
+        let cache = tempdir()?;
+
+        let dirs = StateDirs::resolve(Some(state.path().into()), Some(cache.path().into()));
+
+        let journals = dirs.journals_dir().unwrap();
+        assert!(journals.starts_with(state.path()) && journals.is_dir());
+
+        let md_cache = dirs.metadata_cache_dir().unwrap();
+        assert!(md_cache.starts_with(cache.path()) && md_cache.is_dir());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use std::{borrow::Cow, ffi::OsStr, path::Path};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{args::IngestItem, provenance};
+
+/// What a file is called when its own name doesn't leave anything to name it by, like `.nef`,
+/// see `--stem-fallback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum StemFallback {
+    /// `unnamed_` and a hash of the file's path, the same every time it's imported
+    Hash,
+    /// `unnamed_` and the file's place among the batch's unnamed files, `unnamed_0001`
+    Counter,
+}
+
+/// The file's extension, or for a name that's nothing but one, like `.nef`, what's after the dot.
+#[must_use]
+pub fn extension(path: &Path) -> Option<&OsStr> {
+    path.extension().or_else(|| {
+        let name = path.file_name()?.to_str()?;
+        let ext = name.strip_prefix('.')?;
+        (!ext.is_empty() && !ext.contains('.')).then(|| OsStr::new(ext))
+    })
+}
+
+/// The file's name without its extension, or `None` if that leaves nothing to name it by.
+#[must_use]
+pub fn stem(path: &Path) -> Option<Cow<'_, str>> {
+    if path.extension().is_none() && extension(path).is_some() {
+        return None;
+    }
+
+    path.file_stem()
+        .map(OsStr::to_string_lossy)
+        .filter(|stem| !stem.trim_matches('.').trim().is_empty())
+}
+
+/// Names each file in the batch whose own name can't be used, by `policy`.
+///
+/// Counted files go by path, so a rerun numbers them the same way.
+pub fn name_unnamed(items: &mut [IngestItem], policy: StemFallback) {
+    let mut unnamed = items
+        .iter_mut()
+        .filter(|item| stem(&item.source_path()).is_none())
+        .collect::<Vec<_>>();
+
+    unnamed.sort_by_cached_key(|item| item.source_path());
+
+    for (n, item) in unnamed.into_iter().enumerate() {
+        item.fallback_stem = Some(match policy {
+            StemFallback::Hash => {
+                let source = item.source_path();
+                let hash = provenance::sha256_hex(source.to_string_lossy().as_bytes());
+                format!("unnamed_{}", &hash[..12])
+            }
+            StemFallback::Counter => format!("unnamed_{:04}", n + 1),
+        });
+    }
+}
+
+#[cfg(test)]
+mod stem_tests {
+    use std::{ffi::OsStr, path::Path};
+
+    use super::{StemFallback, extension, name_unnamed, stem};
+    use crate::args::IngestItem;
+
+    #[test]
+    fn names_files_without_a_usable_stem() {
+        assert_eq!(stem(Path::new("in/IMG_0001.NEF")).unwrap(), "IMG_0001");
+        assert_eq!(extension(Path::new("in/.NEF")), Some(OsStr::new("NEF")));
+        assert!(stem(Path::new("in/.NEF")).is_none());
+        assert!(stem(Path::new("in/...NEF")).is_none());
+        assert!(stem(Path::new("/")).is_none());
+
+        let batch = || -> Vec<IngestItem> {
+            ["in/b/.nef", "in/IMG_0001.nef", "in/a/.nef"]
+                .into_iter()
+                .map(|path| (path, "").into())
+                .collect()
+        };
+
+        let mut items = batch();
+        name_unnamed(&mut items, StemFallback::Counter);
+        let names = items
+            .iter()
+            .map(|item| item.fallback_stem.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [Some("unnamed_0002"), None, Some("unnamed_0001")]);
+
+        let mut items = batch();
+        name_unnamed(&mut items, StemFallback::Hash);
+        let mut again = batch();
+        name_unnamed(&mut again, StemFallback::Hash);
+
+        let hashed = items[0].fallback_stem.as_deref().unwrap();
+        assert!(hashed.starts_with("unnamed_") && hashed.len() == 20);
+        assert_eq!(items[0].fallback_stem, again[0].fallback_stem);
+        assert_ne!(items[0].fallback_stem, items[2].fallback_stem);
+    }
+}
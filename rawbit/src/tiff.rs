@@ -0,0 +1,419 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+pub const TAG_XMP: u16 = 0x02BC;
+pub const TAG_EXIF_IFD: u16 = 0x8769;
+pub const TAG_GPS_IFD: u16 = 0x8825;
+pub const TAG_MAKE: u16 = 0x010F;
+pub const TAG_MAKER_NOTE: u16 = 0x927C;
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+
+/// One 12-byte IFD entry, the value/offset field is kept in the file's byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub tag: u16,
+    pub kind: u16,
+    pub count: u32,
+    pub value: [u8; 4],
+}
+
+impl Entry {
+    /// Size of the entry's data, which lives at [`Tiff::offset`] when it doesn't fit in 4 bytes.
+    #[must_use]
+    pub fn data_len(&self) -> u64 {
+        let width = match self.kind {
+            3 | 8 => 2,
+            4 | 9 | 11 | 13 => 4,
+            5 | 10 | 12 => 8,
+            _ => 1,
+        };
+
+        u64::from(self.count) * width
+    }
+}
+
+#[derive(Debug)]
+pub struct Ifd {
+    pub offset: u32,
+    pub entries: Vec<Entry>,
+    pub next: u32,
+}
+
+impl Ifd {
+    #[must_use]
+    pub fn get(&self, tag: u16) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+}
+
+/// Just enough TIFF to edit the directories of a DNG that's already been written, or read a few
+/// values out of a raw's maker notes.
+///
+/// Edited directories are appended to the end of the file and pointed to in place of the
+/// originals, so nothing already written has to move.
+pub struct Tiff<F> {
+    file: F,
+    le: bool,
+}
+
+impl<F> Tiff<F> {
+    #[cfg(test)]
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+
+    /// Where an entry's data lives, if it's too large to be stored inline.
+    #[must_use]
+    pub fn offset(&self, entry: &Entry) -> Option<u32> {
+        (entry.data_len() > 4).then(|| self.read_u32_from(entry.value))
+    }
+
+    /// The directory an IFD-pointer entry (EXIF, GPS, ...) points to.
+    #[must_use]
+    pub const fn pointer(&self, entry: &Entry) -> u32 {
+        self.read_u32_from(entry.value)
+    }
+
+    /// A new entry pointing `tag` at the directory at `offset`.
+    #[must_use]
+    pub const fn pointer_entry(&self, tag: u16, offset: u32) -> Entry {
+        Entry {
+            tag,
+            kind: TYPE_LONG,
+            count: 1,
+            value: self.u32_bytes(offset),
+        }
+    }
+
+    #[must_use]
+    pub const fn with_pointer(&self, entry: Entry, offset: u32) -> Entry {
+        Entry {
+            value: self.u32_bytes(offset),
+            ..entry
+        }
+    }
+
+    const fn read_u32_from(&self, buf: [u8; 4]) -> u32 {
+        if self.le {
+            u32::from_le_bytes(buf)
+        } else {
+            u32::from_be_bytes(buf)
+        }
+    }
+
+    const fn u16_bytes(&self, v: u16) -> [u8; 2] {
+        if self.le {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        }
+    }
+
+    const fn u32_bytes(&self, v: u32) -> [u8; 4] {
+        if self.le {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        }
+    }
+}
+
+impl<F: Read + Seek> Tiff<F> {
+    pub fn open(mut file: F) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut magic)?;
+
+        let le = match &magic {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return Err(invalid("not a TIFF file")),
+        };
+
+        Ok(Self { file, le })
+    }
+
+    pub fn ifd0_offset(&mut self) -> io::Result<u32> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.read_u32()
+    }
+
+    pub fn read_ifd0(&mut self) -> io::Result<Ifd> {
+        let offset = self.ifd0_offset()?;
+        self.read_ifd(offset)
+    }
+
+    pub fn read_ifd(&mut self, offset: u32) -> io::Result<Ifd> {
+        self.file.seek(SeekFrom::Start(offset.into()))?;
+
+        let count = self.read_u16()?;
+        let mut entries = Vec::with_capacity(count.into());
+
+        for _ in 0..count {
+            let tag = self.read_u16()?;
+            let kind = self.read_u16()?;
+            let count = self.read_u32()?;
+
+            let mut value = [0; 4];
+            self.file.read_exact(&mut value)?;
+
+            entries.push(Entry {
+                tag,
+                kind,
+                count,
+                value,
+            });
+        }
+
+        let next = self.read_u32()?;
+
+        Ok(Ifd {
+            offset,
+            entries,
+            next,
+        })
+    }
+
+    /// An entry's data, wherever it's stored.
+    pub fn read_data(&mut self, entry: &Entry) -> io::Result<Vec<u8>> {
+        let len = usize::try_from(entry.data_len()).map_err(|_| too_large())?;
+
+        let Some(offset) = self.offset(entry) else {
+            return Ok(entry.value[..len].to_vec());
+        };
+
+        let mut data = vec![0; len];
+        self.file.seek(SeekFrom::Start(offset.into()))?;
+        self.file.read_exact(&mut data)?;
+
+        Ok(data)
+    }
+
+    /// An entry's data as the 16-bit values it holds, in the file's byte order.
+    pub fn read_shorts(&mut self, entry: &Entry) -> io::Result<Vec<u16>> {
+        Ok(self
+            .read_data(entry)?
+            .chunks_exact(2)
+            .map(|short| {
+                let short = [short[0], short[1]];
+                if self.le {
+                    u16::from_le_bytes(short)
+                } else {
+                    u16::from_be_bytes(short)
+                }
+            })
+            .collect())
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let mut buf = [0; 2];
+        self.file.read_exact(&mut buf)?;
+
+        Ok(if self.le {
+            u16::from_le_bytes(buf)
+        } else {
+            u16::from_be_bytes(buf)
+        })
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.file.read_exact(&mut buf)?;
+
+        Ok(self.read_u32_from(buf))
+    }
+}
+
+impl<F: Read + Write + Seek> Tiff<F> {
+    pub fn set_ifd0_offset(&mut self, offset: u32) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&self.u32_bytes(offset))
+    }
+
+    /// Appends a directory, sorting its entries as TIFF requires, and returns its offset.
+    pub fn append_ifd(&mut self, mut entries: Vec<Entry>, next: u32) -> io::Result<u32> {
+        entries.sort_by_key(|entry| entry.tag);
+
+        let count = u16::try_from(entries.len()).map_err(|_| invalid("too many IFD entries"))?;
+        let offset = self.pad_to_word()?;
+
+        let mut buf = self.u16_bytes(count).to_vec();
+        for entry in &entries {
+            buf.extend(self.u16_bytes(entry.tag));
+            buf.extend(self.u16_bytes(entry.kind));
+            buf.extend(self.u32_bytes(entry.count));
+            buf.extend(entry.value);
+        }
+        buf.extend(self.u32_bytes(next));
+
+        self.file.write_all(&buf)?;
+        Ok(offset)
+    }
+
+    /// Appends `data` as an undefined-bytes entry for `tag`.
+    pub fn append_bytes(&mut self, tag: u16, data: &[u8]) -> io::Result<Entry> {
+        self.append_data(tag, TYPE_BYTE, data)
+    }
+
+    /// Appends a NUL-terminated ASCII entry for `tag`.
+    pub fn append_ascii(&mut self, tag: u16, s: &str) -> io::Result<Entry> {
+        let mut data = s.as_bytes().to_vec();
+        data.push(0);
+
+        self.append_data(tag, TYPE_ASCII, &data)
+    }
+
+    fn append_data(&mut self, tag: u16, kind: u16, data: &[u8]) -> io::Result<Entry> {
+        let count = u32::try_from(data.len()).map_err(|_| too_large())?;
+
+        // TIFF requires values that fit in the entry itself to be stored there
+        let value = if data.len() <= 4 {
+            let mut inline = [0; 4];
+            inline[..data.len()].copy_from_slice(data);
+            inline
+        } else {
+            let offset = self.pad_to_word()?;
+            self.file.write_all(data)?;
+            self.u32_bytes(offset)
+        };
+
+        Ok(Entry {
+            tag,
+            kind,
+            count,
+            value,
+        })
+    }
+
+    /// Overwrites an entry's out-of-line data with zeroes, so dropping the entry leaves nothing behind.
+    pub fn zero_data(&mut self, entry: &Entry) -> io::Result<()> {
+        self.offset(entry)
+            .map_or(Ok(()), |offset| self.zero(offset.into(), entry.data_len()))
+    }
+
+    /// Zeroes a whole directory along with the data of every entry in it.
+    pub fn zero_ifd(&mut self, ifd: &Ifd) -> io::Result<()> {
+        for entry in &ifd.entries {
+            self.zero_data(entry)?;
+        }
+
+        self.zero_directory(ifd)
+    }
+
+    /// Zeroes a directory that's been superseded, leaving the data its entries point to alone.
+    pub fn zero_directory(&mut self, ifd: &Ifd) -> io::Result<()> {
+        let len = 2 + 12 * ifd.entries.len() as u64 + 4;
+        self.zero(ifd.offset.into(), len)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    fn zero(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        let end = self.file.seek(SeekFrom::End(0))?;
+        if offset.saturating_add(len) > end {
+            return Err(invalid("entry data runs past the end of the file"));
+        }
+
+        self.file.seek(SeekFrom::Start(offset))?;
+        io::copy(&mut io::repeat(0).take(len), &mut self.file)?;
+
+        Ok(())
+    }
+
+    /// Seeks to the end of the file, padding it to an even offset as TIFF requires.
+    fn pad_to_word(&mut self) -> io::Result<u32> {
+        let mut end = self.file.seek(SeekFrom::End(0))?;
+
+        if end % 2 != 0 {
+            self.file.write_all(&[0])?;
+            end += 1;
+        }
+
+        u32::try_from(end).map_err(|_| too_large())
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn too_large() -> io::Error {
+    invalid("TIFF file exceeds 4GiB")
+}
+
+#[cfg(test)]
+pub mod tiff_tests {
+    use std::io::Cursor;
+
+    use super::{Entry, Tiff};
+
+    /// A minimal TIFF: an IFD0 with an inline image width and 8 bytes of out-of-line DNG private data.
+    pub fn sample(le: bool) -> Vec<u8> {
+        let u16b = |v: u16| if le { v.to_le_bytes() } else { v.to_be_bytes() };
+        let u32b = |v: u32| if le { v.to_le_bytes() } else { v.to_be_bytes() };
+
+        let mut tiff = if le {
+            b"II*\0".to_vec()
+        } else {
+            b"MM\0*".to_vec()
+        };
+
+        tiff.extend(u32b(16));
+        tiff.extend(b"SECRETS!");
+
+        tiff.extend(u16b(2));
+        tiff.extend(u16b(0x0100));
+        tiff.extend(u16b(3));
+        tiff.extend(u32b(1));
+        tiff.extend(u16b(16));
+        tiff.extend([0, 0]);
+
+        tiff.extend(u16b(0xC634));
+        tiff.extend(u16b(1));
+        tiff.extend(u32b(8));
+        tiff.extend(u32b(8));
+
+        tiff.extend(u32b(0));
+
+        tiff
+    }
+
+    #[test]
+    fn reads_and_rewrites_ifd0() {
+        for le in [true, false] {
+            let mut tiff = Tiff::open(Cursor::new(sample(le))).unwrap();
+
+            let ifd0 = tiff.read_ifd0().unwrap();
+            assert_eq!(
+                ifd0.entries.iter().map(|e| e.tag).collect::<Vec<_>>(),
+                [0x0100, 0xC634]
+            );
+
+            let note = *ifd0.get(0xC634).unwrap();
+            assert_eq!(tiff.offset(&note), Some(8));
+            tiff.zero_data(&note).unwrap();
+
+            let extra = tiff.append_bytes(0x02BC, b"<xmp/>").unwrap();
+            let kept = vec![extra, ifd0.entries[0]];
+            let offset = tiff.append_ifd(kept, ifd0.next).unwrap();
+            tiff.set_ifd0_offset(offset).unwrap();
+
+            let rewritten = tiff.read_ifd0().unwrap();
+            assert_eq!(
+                rewritten
+                    .entries
+                    .iter()
+                    .map(|e: &Entry| e.tag)
+                    .collect::<Vec<_>>(),
+                [0x0100, 0x02BC]
+            );
+
+            let bytes = tiff.into_inner().into_inner();
+            assert_eq!(&bytes[8..16], [0; 8]);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::{fs::File, path::Path};
+
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard, TraceStyle};
+use tracing_subscriber::layer::SubscriberExt as _;
+
+use crate::common::{AppError, RawbitResult, map_err};
+
+/// Records the run's spans into a Chrome trace at `path`, which Perfetto and `chrome://tracing`
+/// open. See `--trace-output`.
+///
+/// Each import is a track with its `scan` and `plan` stages, and each file one with its `read`,
+/// `decode`, `convert` and `write` stages. The trace is finished once the guard is dropped.
+pub fn record(path: &Path) -> RawbitResult<FlushGuard> {
+    let file = map_err!(
+        File::create(path),
+        AppError::Io,
+        format!("couldn't create trace: {}", path.display())
+    )?;
+
+    // a track for each root span rather than each thread, since jobs move between threads
+    let (layer, guard) = ChromeLayerBuilder::new()
+        .writer(file)
+        .trace_style(TraceStyle::Async)
+        .include_args(true)
+        .include_locations(false)
+        .build();
+
+    // not `try_init`, which would take over from smlog as the `log` logger
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+        .map_err(|err| AppError::Other("couldn't start tracing".into(), Box::new(err)))?;
+
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use std::fs;
+
+    use tracing::info_span;
+
+    use super::record;
+
+    #[test]
+    fn writes_spans_as_a_chrome_trace() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.json");
+
+        let guard = record(&path).unwrap();
+        info_span!(parent: None, "job", file = %"IMG_0001.CR3")
+            .in_scope(|| info_span!("convert").in_scope(|| ()));
+        drop(guard);
+
+        let trace: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let events = trace.as_array().unwrap();
+
+        // other tests' spans end up in it too
+        let job = events
+            .iter()
+            .find(|event| event["args"]["file"] == "IMG_0001.CR3")
+            .unwrap();
+        let stages = events
+            .iter()
+            .filter(|event| event["ph"] == "b" && event["id"] == job["id"])
+            .map(|event| event["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(stages, ["job", "convert"]);
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use smlog::warn;
+
+use crate::{
+    common::{AppError, RawbitResult, map_err},
+    durable,
+    perms::Permissions,
+};
+
+/// Files replaced by the commit are kept here until it's done, so a failed commit can put them
+/// back.
+const REPLACED_DIR: &str = ".replaced";
+
+const STAGING_PREFIX: &str = ".rawbit-staging-";
+
+/// The staging directory for a run's `--transactional` batch into `output_dir`, inside it so that
+/// committing is a rename rather than a copy.
+#[must_use]
+pub fn staging_dir(output_dir: &Path, run_id: &str) -> PathBuf {
+    output_dir.join(format!("{STAGING_PREFIX}{run_id}"))
+}
+
+/// The staging directory `path` was written under, if it was.
+#[must_use]
+pub fn staging_root(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|dir| {
+        dir.file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(STAGING_PREFIX))
+    })
+}
+
+/// The outputs of a `--transactional` batch, written under a staging directory and only moved
+/// into the output directory once every file in the batch has converted.
+#[derive(Debug)]
+pub struct Transaction {
+    output_dir: PathBuf,
+    staging: PathBuf,
+    /// flush the output directories once everything's moved in, see `--fsync`
+    fsync: bool,
+    /// given to the directories the commit creates, see `--chmod` and `--chown`
+    permissions: Permissions,
+}
+
+/// A move made by [`Transaction::commit`], undone in reverse if a later one fails.
+enum Move {
+    CreatedDir(PathBuf),
+    Replaced { path: PathBuf, kept: PathBuf },
+    Committed { staged: PathBuf, path: PathBuf },
+}
+
+impl Transaction {
+    #[must_use]
+    pub const fn new(output_dir: PathBuf, staging: PathBuf) -> Self {
+        Self {
+            output_dir,
+            staging,
+            fsync: false,
+            permissions: Permissions {
+                mode: None,
+                owner: None,
+            },
+        }
+    }
+
+    /// Flushes the directories files are moved into with the commit, the staged files
+    /// themselves are already on disk.
+    #[must_use]
+    pub const fn synced(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Gives the directories the commit creates in the output directory `permissions`, like
+    /// the staged files already have.
+    #[must_use]
+    pub const fn permitted(mut self, permissions: Permissions) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    /// Moves every staged file into place, replacing files already there, and returns how many
+    /// were moved. If any move fails, the ones already made are undone, leaving the output
+    /// directory as it was.
+    ///
+    /// A commit a crash cut short is finished by committing again, the files it already moved
+    /// are no longer staged.
+    pub fn commit(self) -> RawbitResult<usize> {
+        let mut staged = vec![];
+        if self.staging.is_dir() {
+            map_err!(
+                staged_files(&self.staging, Path::new(""), &mut staged),
+                AppError::Io,
+                format!("couldn't list staged files: {}", self.staging.display())
+            )?;
+        }
+
+        staged.sort();
+
+        let mut moves = vec![];
+        for relative in &staged {
+            if let Err(err) = self.commit_file(relative, &mut moves) {
+                // what couldn't be put back is only left in the staging directory
+                if !undo(moves) {
+                    return Err(AppError::Io(
+                        format!(
+                            "couldn't commit {} into {}, or roll all of it back, what's left of the batch is in {}",
+                            relative.display(),
+                            self.output_dir.display(),
+                            self.staging.display()
+                        ),
+                        err,
+                    ));
+                }
+
+                self.roll_back()?;
+
+                return Err(AppError::Io(
+                    format!(
+                        "couldn't commit {} into {}, rolled the batch back",
+                        relative.display(),
+                        self.output_dir.display()
+                    ),
+                    err,
+                ));
+            }
+        }
+
+        if self.fsync {
+            self.sync_dirs(&staged)?;
+        }
+
+        self.roll_back()?;
+        Ok(staged.len())
+    }
+
+    /// Flushes every directory a committed file was moved into, up to the output directory.
+    fn sync_dirs(&self, committed: &[PathBuf]) -> RawbitResult<()> {
+        let dirs = committed
+            .iter()
+            .flat_map(|relative| relative.ancestors().skip(1))
+            .collect::<BTreeSet<_>>();
+
+        for dir in dirs {
+            let dir = self.output_dir.join(dir);
+            map_err!(
+                durable::sync_dir(&dir),
+                AppError::Io,
+                format!("couldn't flush {} to disk", dir.display())
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn commit_file(&self, relative: &Path, moves: &mut Vec<Move>) -> io::Result<()> {
+        let staged = self.staging.join(relative);
+        let path = self.output_dir.join(relative);
+
+        if let Some(parent) = path.parent() {
+            create_dirs(parent, self.permissions, moves)?;
+        }
+
+        if path.exists() {
+            let kept = self.staging.join(REPLACED_DIR).join(relative);
+            fs::create_dir_all(kept.parent().unwrap_or(&self.staging))?;
+            fs::rename(&path, &kept)?;
+            moves.push(Move::Replaced {
+                path: path.clone(),
+                kept,
+            });
+        }
+
+        fs::rename(&staged, &path)?;
+        moves.push(Move::Committed { staged, path });
+
+        Ok(())
+    }
+
+    /// Throws away everything staged, the output directory is left as it was.
+    pub fn roll_back(&self) -> RawbitResult<()> {
+        if !self.staging.exists() {
+            return Ok(());
+        }
+
+        map_err!(
+            fs::remove_dir_all(&self.staging),
+            AppError::Io,
+            format!(
+                "couldn't remove staging directory: {}",
+                self.staging.display()
+            )
+        )
+    }
+}
+
+/// Creates `dir` and any missing parents with `permissions`, recording each one so it can be
+/// removed again.
+fn create_dirs(dir: &Path, permissions: Permissions, moves: &mut Vec<Move>) -> io::Result<()> {
+    if dir.is_dir() {
+        return Ok(());
+    }
+
+    if let Some(parent) = dir.parent() {
+        create_dirs(parent, permissions, moves)?;
+    }
+
+    permissions.create_dir_all(dir)?;
+    moves.push(Move::CreatedDir(dir.to_path_buf()));
+
+    Ok(())
+}
+
+/// Puts back what a failed commit did, returning whether all of it was.
+fn undo(moves: Vec<Move>) -> bool {
+    let mut undone_all = true;
+
+    for done in moves.into_iter().rev() {
+        let undone = match done {
+            Move::CreatedDir(ref dir) => fs::remove_dir(dir),
+            Move::Replaced { ref path, ref kept } => fs::rename(kept, path),
+            Move::Committed {
+                ref staged,
+                ref path,
+            } => fs::rename(path, staged),
+        };
+
+        if let Err(err) = undone {
+            warn!("couldn't undo part of a failed commit: {err}");
+            undone_all = false;
+        }
+    }
+
+    undone_all
+}
+
+/// Every file under `dir`, relative to the staging directory.
+fn staged_files(dir: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let relative = relative.join(entry.file_name());
+
+        // what an earlier, interrupted commit replaced
+        if relative == Path::new(REPLACED_DIR) {
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            staged_files(&entry.path(), &relative, files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod transaction_tests {
+    use std::fs;
+
+    use super::{Move, Transaction, staging_dir, undo};
+
+    #[test]
+    fn commits_everything_or_leaves_the_output_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        fs::create_dir(&output).unwrap();
+        fs::write(output.join("a.dng"), b"old").unwrap();
+
+        let stage = |run_id| {
+            let staging = staging_dir(&output, run_id);
+            fs::create_dir_all(staging.join("sub")).unwrap();
+            fs::write(staging.join("a.dng"), b"new").unwrap();
+            fs::write(staging.join("sub/b.dng"), b"new").unwrap();
+            Transaction::new(output.clone(), staging)
+        };
+
+        // sub/ can't be created over a file, so a.dng has to be put back
+        fs::write(output.join("sub"), b"in the way").unwrap();
+        assert!(stage("1").commit().is_err());
+        assert_eq!(fs::read(output.join("a.dng")).unwrap(), b"old");
+        assert!(!staging_dir(&output, "1").exists());
+
+        fs::remove_file(output.join("sub")).unwrap();
+        assert_eq!(stage("2").commit().unwrap(), 2);
+        assert_eq!(fs::read(output.join("a.dng")).unwrap(), b"new");
+        assert_eq!(fs::read(output.join("sub/b.dng")).unwrap(), b"new");
+
+        let mut left = fs::read_dir(&output)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(left, ["a.dng", "sub"]);
+    }
+
+    #[test]
+    fn says_when_a_commit_could_not_be_undone() {
+        let dir = tempfile::tempdir().unwrap();
+        let (staged, path) = (dir.path().join("staged.dng"), dir.path().join("a.dng"));
+        fs::write(&path, b"new").unwrap();
+
+        assert!(undo(vec![Move::Committed {
+            staged: staged.clone(),
+            path: path.clone(),
+        }]));
+        assert!(staged.exists());
+
+        // already gone from the output directory
+        assert!(!undo(vec![Move::Committed { staged, path }]));
+    }
+}
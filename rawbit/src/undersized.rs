@@ -0,0 +1,151 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{args::IngestItem, perms::Permissions, space};
+
+/// Where `--on-undersized quarantine` puts files that are too small, inside the output directory.
+pub const QUARANTINE_DIR: &str = "_quarantine";
+
+/// The smallest a raw can plausibly be. Even a reduced-size raw is megabytes, and the preview the
+/// camera embeds in it is bigger than this on its own.
+pub const DEFAULT_MIN_SIZE: u64 = 64 * 1024;
+
+/// What's done with inputs too small to be what they say they are, most likely left that way by a
+/// card write that didn't finish.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum UndersizedPolicy {
+    /// leave them out of the import with a warning
+    #[default]
+    Skip,
+    /// copy them into `_quarantine/` in the output directory to look at later
+    Quarantine,
+    /// don't import anything
+    Fail,
+}
+
+/// An input that's too small to import.
+#[derive(Debug, Clone)]
+pub struct Undersized {
+    pub item: IngestItem,
+    pub size: u64,
+}
+
+impl Undersized {
+    /// Why the file was left out, for logs and the report.
+    #[must_use]
+    pub fn reason(&self) -> String {
+        if self.size == 0 {
+            "the file is empty, it may not have been written to the card completely".into()
+        } else {
+            format!(
+                "the file is only {}, it may not have been written to the card completely",
+                space::human(self.size)
+            )
+        }
+    }
+
+    /// Copies the file into [`QUARANTINE_DIR`] in the directory under `output_dir` it would
+    /// have been imported to, returning where it went.
+    pub fn quarantine(&self, output_dir: &Path, permissions: Permissions) -> io::Result<PathBuf> {
+        let dir = output_dir
+            .join(&self.item.output_prefix)
+            .join(QUARANTINE_DIR);
+        permissions.create_dir_all(&dir)?;
+
+        let source = self.item.source_path();
+        let path = dir.join(source.file_name().unwrap_or(source.as_os_str()));
+
+        match self.item.member {
+            Some(ref member) => fs::write(&path, member.read(&self.item.input_path)?)?,
+            None => {
+                fs::copy(&self.item.input_path, &path)?;
+            }
+        }
+
+        permissions.apply(&path)?;
+        Ok(path)
+    }
+}
+
+/// Splits out the inputs that are empty, and the raws smaller than `min_size`.
+///
+/// Files brought along with `--copy` can be small, so only empty ones are taken out of those.
+/// Files whose size can't be read are left in for their jobs to fail on.
+#[must_use]
+pub fn partition(ingest: Vec<IngestItem>, min_size: u64) -> (Vec<IngestItem>, Vec<Undersized>) {
+    let mut kept = Vec::with_capacity(ingest.len());
+    let mut undersized = vec![];
+
+    for item in ingest {
+        match space::source_size(&item) {
+            Some(size) if size == 0 || (!item.copy && size < min_size) => {
+                undersized.push(Undersized { item, size });
+            }
+            _ => kept.push(item),
+        }
+    }
+
+    (kept, undersized)
+}
+
+#[cfg(test)]
+mod undersized_tests {
+    use std::fs;
+
+    use super::{QUARANTINE_DIR, partition};
+    use crate::{args::IngestItem, perms::Permissions};
+
+    #[test]
+    fn takes_out_files_too_small_to_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let item = |name: &str, contents: &[u8], copy: bool| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+
+            IngestItem {
+                copy,
+                ..(path, "").into()
+            }
+        };
+
+        let ingest = vec![
+            item("IMG_0001.CR3", &[0; 1024], false),
+            item("IMG_0002.CR3", b"", false),
+            item("IMG_0003.CR3", b"cut short", false),
+            item("IMG_0004.WAV", b"memo", true),
+            item("IMG_0005.WAV", b"", true),
+        ];
+
+        let (kept, undersized) = partition(ingest, 1024);
+        let names = |items: Vec<&IngestItem>| {
+            items
+                .into_iter()
+                .map(|item| item.input_path.file_name().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(kept.iter().collect()),
+            ["IMG_0001.CR3", "IMG_0004.WAV"]
+        );
+        assert_eq!(
+            names(undersized.iter().map(|file| &file.item).collect()),
+            ["IMG_0002.CR3", "IMG_0003.CR3", "IMG_0005.WAV"]
+        );
+        assert!(undersized[0].reason().contains("empty"));
+        assert!(undersized[1].reason().contains("9 B"));
+
+        let out = dir.path().join("out");
+        let quarantined = undersized[1]
+            .quarantine(&out, Permissions::default())
+            .unwrap();
+        assert_eq!(quarantined, out.join(QUARANTINE_DIR).join("IMG_0003.CR3"));
+        assert_eq!(fs::read(quarantined).unwrap(), b"cut short");
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+
+/// Extensions of the clips cameras record next to their raws, brought along by `--videos`.
+pub const VIDEO_EXTENSIONS: &[&str] = &["mov", "mp4", "m4v", "mts", "m2ts", "avi"];
+
+/// Whether `path` has one of the [`VIDEO_EXTENSIONS`].
+#[must_use]
+pub fn is_video(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        VIDEO_EXTENSIONS
+            .iter()
+            .any(|video| ext.eq_ignore_ascii_case(video))
+    })
+}
+
+/// Seconds from 1904-01-01, when the `QuickTime` clock starts, to the Unix epoch.
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// When the clip at `path` was recorded, in local time like a raw's EXIF date.
+///
+/// MOV and MP4 files say when in their movie header, anything else, or one that doesn't, goes by
+/// when the file was last modified, which on a card is when the camera finished writing it.
+#[must_use]
+pub fn capture_time(path: &Path) -> Option<NaiveDateTime> {
+    let recorded = File::open(path)
+        .and_then(|mut file| movie_created(&mut file))
+        .ok()
+        .flatten();
+
+    let utc = match recorded {
+        Some(recorded) => recorded,
+        None => DateTime::<Utc>::from(fs::metadata(path).and_then(|stat| stat.modified()).ok()?),
+    };
+
+    Some(utc.with_timezone(&Local).naive_local())
+}
+
+/// The creation time in the `mvhd` box of a `QuickTime` or ISO media file, which is UTC. Only the
+/// box headers are read, so it's found without reading the clip itself.
+fn movie_created<F: Read + Seek>(file: &mut F) -> io::Result<Option<DateTime<Utc>>> {
+    let len = file.seek(SeekFrom::End(0))?;
+
+    let Some((moov, moov_end)) = find_box(file, 0, len, *b"moov")? else {
+        return Ok(None);
+    };
+    let Some((mvhd, _)) = find_box(file, moov, moov_end, *b"mvhd")? else {
+        return Ok(None);
+    };
+
+    file.seek(SeekFrom::Start(mvhd))?;
+    let mut version = [0; 4];
+    file.read_exact(&mut version)?;
+
+    let created = if version[0] == 1 {
+        let mut created = [0; 8];
+        file.read_exact(&mut created)?;
+        u64::from_be_bytes(created)
+    } else {
+        let mut created = [0; 4];
+        file.read_exact(&mut created)?;
+        u64::from(u32::from_be_bytes(created))
+    };
+
+    // left unset by some cameras
+    if created == 0 {
+        return Ok(None);
+    }
+
+    Ok(i64::try_from(created)
+        .ok()
+        .and_then(|created| created.checked_sub(QUICKTIME_EPOCH_OFFSET))
+        .and_then(|since_epoch| DateTime::from_timestamp(since_epoch, 0)))
+}
+
+/// The box of type `kind` between `start` and `end`, as where its contents start and end.
+fn find_box<F: Read + Seek>(
+    file: &mut F,
+    mut start: u64,
+    end: u64,
+    kind: [u8; 4],
+) -> io::Result<Option<(u64, u64)>> {
+    while start + 8 <= end {
+        file.seek(SeekFrom::Start(start))?;
+
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let (size, found) = header.split_at(4);
+
+        let (contents, size) = match u32::from_be_bytes(size.try_into().unwrap_or_default()) {
+            // the rest of the file
+            0 => (start + 8, end - start),
+            // a 64-bit size after the type
+            1 => {
+                let mut size = [0; 8];
+                file.read_exact(&mut size)?;
+                (start + 16, u64::from_be_bytes(size))
+            }
+            size => (start + 8, u64::from(size)),
+        };
+
+        let box_end = start.saturating_add(size).min(end);
+
+        if found == kind {
+            return Ok(Some((contents, box_end)));
+        }
+
+        if box_end <= start {
+            break;
+        }
+
+        start = box_end;
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod video_tests {
+    use std::io::Cursor;
+
+    use chrono::{DateTime, Utc};
+
+    use super::{QUICKTIME_EPOCH_OFFSET, movie_created};
+
+    fn mp4_box(kind: [u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut bx = u32::try_from(contents.len() + 8)
+            .unwrap()
+            .to_be_bytes()
+            .to_vec();
+        bx.extend(kind);
+        bx.extend(contents);
+        bx
+    }
+
+    #[test]
+    fn reads_when_a_clip_was_recorded() {
+        let created = 1_714_979_289 + QUICKTIME_EPOCH_OFFSET;
+
+        let mut mvhd = vec![0; 4];
+        mvhd.extend(u32::try_from(created).unwrap().to_be_bytes());
+        mvhd.resize(100, 0);
+
+        // the movie header comes after the media on some cameras
+        let mut mp4 = mp4_box(*b"ftyp", b"qt  ");
+        mp4.extend(mp4_box(*b"mdat", &[0; 64]));
+        mp4.extend(mp4_box(
+            *b"moov",
+            &[mp4_box(*b"udta", &[]), mp4_box(*b"mvhd", &mvhd)].concat(),
+        ));
+
+        assert_eq!(
+            movie_created(&mut Cursor::new(mp4)).unwrap(),
+            DateTime::<Utc>::from_timestamp(1_714_979_289, 0)
+        );
+
+        let without = mp4_box(*b"ftyp", b"qt  ");
+        assert_eq!(movie_created(&mut Cursor::new(without)).unwrap(), None);
+    }
+}
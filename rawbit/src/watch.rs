@@ -0,0 +1,598 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::{self, Display},
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use serde::{Deserialize, Serialize};
+use smlog::{info, warn};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    time::{self, Instant},
+};
+
+use crate::common::{AppError, RawbitResult};
+
+/// How long a file has to go without changing before it's handed out by default.
+pub const DEFAULT_SETTLE: Duration = Duration::from_secs(2);
+
+/// How many files a watch remembers having seen before it forgets the oldest.
+pub const RECENT_CAPACITY: usize = 100_000;
+
+/// How often a watch logs how it's doing.
+pub const STATS_EVERY: Duration = Duration::from_hours(1);
+
+/// A file seen changing, and how it looked when it last did.
+#[derive(Debug)]
+struct Pending {
+    len: u64,
+    modified: Option<SystemTime>,
+    since: Instant,
+}
+
+/// Files appearing in a directory, handed out once they've stopped changing, so a copy or a
+/// tethered capture that's still being written isn't picked up half done.
+///
+/// A folder on a card, like `E:\DCIM`, goes away when the card is pulled. It's watched again once
+/// a card is put back in, and everything on it is handed out like new files.
+#[derive(Debug)]
+pub struct HotFolder {
+    dir: PathBuf,
+    mode: RecursiveMode,
+    // stops watching when dropped, and is dropped while the folder is gone
+    watcher: Option<RecommendedWatcher>,
+    tx: UnboundedSender<PathBuf>,
+    events: UnboundedReceiver<PathBuf>,
+    pending: HashMap<PathBuf, Pending>,
+    settle: Duration,
+    /// the folder came back since [`HotFolder::remounted`] was last asked
+    remounted: bool,
+}
+
+impl HotFolder {
+    pub fn watch(dir: &Path, recurse: bool, settle: Duration) -> RawbitResult<Self> {
+        let (tx, events) = mpsc::unbounded_channel();
+
+        let mode = if recurse {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+
+        let watcher = arm(dir, mode, tx.clone()).map_err(|err| {
+            AppError::Other(format!("couldn't watch {}", dir.display()), Box::new(err))
+        })?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            mode,
+            watcher: Some(watcher),
+            tx,
+            events,
+            pending: HashMap::new(),
+            settle,
+            remounted: false,
+        })
+    }
+
+    /// How many files have been seen changing and haven't settled yet.
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Whether the folder went away and came back since this was last asked, e.g. with another
+    /// card, whose files can have the same paths as the last one's.
+    pub const fn remounted(&mut self) -> bool {
+        std::mem::replace(&mut self.remounted, false)
+    }
+
+    /// Waits for files that have stopped changing, returning them all at once.
+    pub async fn next_settled(&mut self) -> Vec<PathBuf> {
+        let mut tick = time::interval(self.settle / 4);
+
+        loop {
+            tokio::select! {
+                Some(path) = self.events.recv() => self.saw(path),
+                _ = tick.tick() => {
+                    self.check_mount();
+
+                    let settled = self.settled();
+                    if !settled.is_empty() {
+                        return settled;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Notices the folder going away with the card it's on, and watches it again once it's back,
+    /// with everything already in it pending.
+    fn check_mount(&mut self) {
+        match (self.watcher.is_some(), self.dir.is_dir()) {
+            (true, false) => {
+                warn!(
+                    "{} went away, waiting for it to come back",
+                    self.dir.display()
+                );
+                self.watcher = None;
+            }
+
+            (false, true) => {
+                // it can take a moment before a freshly mounted card can be watched
+                let Ok(watcher) = arm(&self.dir, self.mode, self.tx.clone()) else {
+                    return;
+                };
+
+                info!("{} is back, looking for new files", self.dir.display());
+                self.watcher = Some(watcher);
+                self.remounted = true;
+
+                let mut files = vec![];
+                list_files(&self.dir, self.mode == RecursiveMode::Recursive, &mut files);
+                for path in files {
+                    self.saw(path);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn saw(&mut self, path: PathBuf) {
+        let Ok(stat) = fs::metadata(&path) else {
+            return;
+        };
+
+        if stat.is_file() {
+            self.pending.insert(
+                path,
+                Pending {
+                    len: stat.len(),
+                    modified: stat.modified().ok(),
+                    since: Instant::now(),
+                },
+            );
+        }
+    }
+
+    /// Takes the files that haven't changed for the settle time, restarting the clock on any
+    /// that have.
+    fn settled(&mut self) -> Vec<PathBuf> {
+        let mut settled = vec![];
+
+        self.pending.retain(|path, pending| {
+            // deleted before it settled, e.g. a temporary file
+            let Ok(stat) = fs::metadata(path) else {
+                return false;
+            };
+
+            let (len, modified) = (stat.len(), stat.modified().ok());
+            if (len, modified) != (pending.len, pending.modified) {
+                *pending = Pending {
+                    len,
+                    modified,
+                    since: Instant::now(),
+                };
+
+                return true;
+            }
+
+            if pending.since.elapsed() < self.settle {
+                return true;
+            }
+
+            settled.push(path.clone());
+            false
+        });
+
+        settled.sort();
+        settled
+    }
+}
+
+/// Paths a watch has already dealt with, forgetting the oldest once there are more than it holds,
+/// so one left running on an ingest box for months doesn't keep growing.
+///
+/// A forgotten file is only picked up again if it changes.
+#[derive(Debug)]
+pub struct Recent {
+    paths: HashSet<PathBuf>,
+    order: VecDeque<PathBuf>,
+    capacity: usize,
+}
+
+impl Recent {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            paths: HashSet::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    #[must_use]
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.contains(path)
+    }
+
+    /// Remembers `path`, returning whether it's new.
+    pub fn insert(&mut self, path: PathBuf) -> bool {
+        if !self.paths.insert(path.clone()) {
+            return false;
+        }
+
+        self.order.push_back(path);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.paths.remove(&oldest);
+            }
+        }
+
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+        self.order.clear();
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+impl Extend<PathBuf> for Recent {
+    fn extend<I: IntoIterator<Item = PathBuf>>(&mut self, paths: I) {
+        for path in paths {
+            self.insert(path);
+        }
+    }
+}
+
+/// What a watch has done since it started, logged every [`STATS_EVERY`] and answered to
+/// `rawbit daemon status`.
+#[derive(Debug)]
+pub struct WatchStats {
+    started: Instant,
+    pub converted: usize,
+    pub failed: usize,
+    /// files waiting to settle, as of the last time the watch woke up
+    pending: usize,
+    /// files already seen, which aren't picked up again
+    remembered: usize,
+}
+
+impl Default for WatchStats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            converted: 0,
+            failed: 0,
+            pending: 0,
+            remembered: 0,
+        }
+    }
+}
+
+impl WatchStats {
+    /// Takes note of `pending` files waiting to settle and `remembered` already seen.
+    pub const fn watching(&mut self, pending: usize, remembered: usize) {
+        self.pending = pending;
+        self.remembered = remembered;
+    }
+
+    /// The counts for the watch of `dir`, with how much memory rawbit is using.
+    #[must_use]
+    pub fn status(&self, dir: &Path) -> WatchStatus {
+        WatchStatus {
+            dir: dir.to_path_buf(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            converted: self.converted,
+            failed: self.failed,
+            pending: self.pending,
+            remembered: self.remembered,
+            resident_bytes: resident_memory(),
+        }
+    }
+}
+
+/// How a running watch is doing, as `rawbit daemon status` reports it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WatchStatus {
+    pub dir: PathBuf,
+    pub uptime_secs: u64,
+    pub converted: usize,
+    pub failed: usize,
+    pub pending: usize,
+    pub remembered: usize,
+    /// null where the OS doesn't say
+    pub resident_bytes: Option<u64>,
+}
+
+impl Display for WatchStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "watching {} for {}h: converted {} file(s), {} failed",
+            self.dir.display(),
+            self.uptime_secs / 3600,
+            self.converted,
+            self.failed
+        )?;
+
+        if let Some(bytes) = self.resident_bytes {
+            write!(f, ", {} MiB resident", bytes / (1024 * 1024))?;
+        }
+
+        write!(
+            f,
+            ", {} waiting to settle, {} remembered",
+            self.pending, self.remembered
+        )
+    }
+}
+
+/// Answers `rawbit daemon status` for a running watch on a Unix socket, until it's dropped.
+#[derive(Debug)]
+pub struct StatusSocket {
+    path: PathBuf,
+}
+
+impl StatusSocket {
+    /// Listens at `path`, answering each connection with a line of JSON about the watch of `dir`.
+    ///
+    /// A socket left behind by a watch that's gone is replaced, one another watch is still
+    /// answering on isn't.
+    #[cfg(unix)]
+    pub fn serve(path: &Path, dir: &Path, stats: Arc<Mutex<WatchStats>>) -> io::Result<Self> {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another watch is answering there",
+            ));
+        }
+
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+
+        let listener = UnixListener::bind(path)?;
+        let dir = dir.to_path_buf();
+
+        // answered off the runtime, so a query doesn't wait for the round being converted
+        thread::Builder::new()
+            .name("rawbit-status".into())
+            .spawn(move || {
+                for mut stream in listener.incoming().map_while(Result::ok) {
+                    let answer = stats
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .status(&dir);
+
+                    // the one asking may have given up already
+                    let _ = serde_json::to_writer(&mut stream, &answer)
+                        .map_err(io::Error::from)
+                        .and_then(|()| stream.write_all(b"\n"));
+                }
+            })?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn serve(_path: &Path, _dir: &Path, _stats: Arc<Mutex<WatchStats>>) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "status queries need Unix sockets",
+        ))
+    }
+}
+
+impl Drop for StatusSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Asks the watch answering at `path` how it's doing.
+#[cfg(unix)]
+pub fn query_status(path: &Path) -> io::Result<WatchStatus> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    Ok(serde_json::from_reader(stream)?)
+}
+
+#[cfg(not(unix))]
+pub fn query_status(_path: &Path) -> io::Result<WatchStatus> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "status queries need Unix sockets",
+    ))
+}
+
+/// How much of rawbit is in memory, where the OS says.
+fn resident_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kib * 1024)
+}
+
+/// Watches `dir`, sending each file created or changed in it to `tx`.
+fn arm(
+    dir: &Path,
+    mode: RecursiveMode,
+    tx: UnboundedSender<PathBuf>,
+) -> notify::Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+
+        // a file moved into the folder shows up as a rename, which is a modification
+        if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            for path in event.paths {
+                let _ = tx.send(path);
+            }
+        }
+    })?;
+
+    watcher.watch(dir, mode)?;
+    Ok(watcher)
+}
+
+fn list_files(dir: &Path, recurse: bool, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+        if path.is_dir() {
+            if recurse {
+                list_files(&path, recurse, files);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod watch_tests {
+    use std::{fs, io::Write as _, time::Duration};
+
+    use tokio::time;
+
+    use super::{HotFolder, Recent};
+
+    #[tokio::test]
+    async fn hands_out_files_once_they_stop_changing() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().canonicalize().unwrap();
+        let mut hot = HotFolder::watch(&dir_path, false, Duration::from_millis(300)).unwrap();
+
+        let path = dir_path.join("IMG_0001.CR3");
+        let mut file = fs::File::create(&path).unwrap();
+
+        let writer = tokio::spawn(async move {
+            for _ in 0..4 {
+                file.write_all(b"partial").unwrap();
+                file.flush().unwrap();
+                time::sleep(Duration::from_millis(100)).await;
+            }
+        });
+
+        let started = time::Instant::now();
+        let settled = hot.next_settled().await;
+        writer.await.unwrap();
+
+        assert_eq!(settled, std::slice::from_ref(&path));
+        assert_eq!(fs::metadata(&path).unwrap().len(), 4 * 7);
+        assert!(started.elapsed() >= Duration::from_millis(600));
+    }
+
+    #[tokio::test]
+    async fn watches_a_card_again_once_its_put_back() {
+        let card = tempfile::tempdir().unwrap();
+        let dcim = card.path().canonicalize().unwrap().join("DCIM");
+        fs::create_dir(&dcim).unwrap();
+
+        let mut hot = HotFolder::watch(&dcim, true, Duration::from_millis(200)).unwrap();
+
+        let swap = tokio::spawn({
+            let dcim = dcim.clone();
+            async move {
+                time::sleep(Duration::from_millis(200)).await;
+                fs::remove_dir_all(&dcim).unwrap();
+                time::sleep(Duration::from_millis(200)).await;
+
+                // the next card already has its files on it
+                fs::create_dir_all(dcim.join("100CANON")).unwrap();
+                fs::write(dcim.join("100CANON/IMG_0001.CR3"), b"raw").unwrap();
+            }
+        });
+
+        let settled = time::timeout(Duration::from_secs(5), hot.next_settled())
+            .await
+            .unwrap();
+        swap.await.unwrap();
+
+        assert_eq!(settled, [dcim.join("100CANON/IMG_0001.CR3")]);
+        assert!(hot.remounted());
+        assert!(!hot.remounted());
+    }
+
+    #[test]
+    fn forgets_the_oldest_files_it_has_seen() {
+        let mut recent = Recent::new(2);
+
+        assert!(recent.insert("IMG_0001.CR3".into()));
+        assert!(!recent.insert("IMG_0001.CR3".into()));
+        recent.extend(["IMG_0002.CR3".into(), "IMG_0003.CR3".into()]);
+
+        assert_eq!(recent.len(), 2);
+        assert!(!recent.contains("IMG_0001.CR3".as_ref()));
+        assert!(recent.contains("IMG_0003.CR3".as_ref()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn answers_status_queries_until_dropped() {
+        use std::sync::{Arc, Mutex};
+
+        use super::{StatusSocket, WatchStats, query_status};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("watch.sock");
+        let stats = Arc::new(Mutex::new(WatchStats::default()));
+
+        let socket = StatusSocket::serve(&path, dir.path(), Arc::clone(&stats)).unwrap();
+        assert!(StatusSocket::serve(&path, dir.path(), Arc::clone(&stats)).is_err());
+
+        {
+            let mut stats = stats.lock().unwrap();
+            stats.converted = 3;
+            stats.watching(1, 4);
+        }
+
+        let answer = query_status(&path).unwrap();
+        assert_eq!(answer.dir, dir.path());
+        assert_eq!(
+            (
+                answer.converted,
+                answer.failed,
+                answer.pending,
+                answer.remembered
+            ),
+            (3, 0, 1, 4)
+        );
+
+        drop(socket);
+        assert!(query_status(&path).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use std::{ffi::OsStr, fs};
+
+use common::{Sandbox, camera};
+
+const TAKEN: &str = "2024:05:06 07:08:09";
+
+#[test]
+fn converts_a_card_into_named_dngs() {
+    let sandbox = Sandbox::new();
+    sandbox.raw("IMG_0001.DNG", TAKEN);
+    sandbox.raw("IMG_0002.DNG", "2024:05:07 10:11:12");
+
+    sandbox
+        .run(["-i", "card", "-o", "out", "-F", "%Y/%m-%d_{camera.model}_"])
+        .success();
+
+    assert_eq!(
+        sandbox.outputs(),
+        ["2024/05-06_T1_IMG_0001.dng", "2024/05-07_T1_IMG_0002.dng"]
+    );
+
+    let converted = sandbox.out().join("2024/05-06_T1_IMG_0001.dng");
+    assert_eq!(camera(&converted), ("Testmake".into(), "T1".into()));
+}
+
+#[test]
+fn leaves_existing_files_alone() {
+    let sandbox = Sandbox::new();
+    sandbox.raw("IMG_0001.DNG", TAKEN);
+
+    sandbox.run(["-i", "card", "-o", "out"]).success();
+    let converted = sandbox.out().join("IMG_0001.dng");
+    fs::write(&converted, b"edited").unwrap();
+
+    // a file that couldn't be imported is reported, not a failed run
+    let run = sandbox.run(["-i", "card", "-o", "out"]).success();
+    assert!(run.logs().contains("won't overwrite existing file"));
+    assert_eq!(fs::read(&converted).unwrap(), b"edited");
+
+    sandbox
+        .run(["-i", "card", "-o", "out", "--on-conflict", "overwrite"])
+        .success();
+    assert_eq!(camera(&converted).1, "T1");
+}
+
+#[test]
+fn writes_nothing_for_a_bad_format() {
+    let sandbox = Sandbox::new();
+    sandbox.raw("IMG_0001.DNG", TAKEN);
+
+    let run = sandbox
+        .run(["-i", "card", "-o", "out", "-F", "{camera.model"])
+        .failure();
+
+    assert!(run.logs().contains("unterminated variable expansion"));
+    assert!(sandbox.outputs().is_empty());
+
+    // rejected by the argument parser, before anything runs
+    let run = sandbox
+        .run(["-i", "card", "-o", "out", "--name-rules", "beos"])
+        .failure();
+    assert_eq!(run.0.status.code(), Some(2));
+}
+
+#[test]
+fn fails_files_missing_what_a_strict_format_asks_for() {
+    let sandbox = Sandbox::new();
+    sandbox.raw("IMG_0001.DNG", TAKEN);
+    let format = [
+        "-i",
+        "card",
+        "-o",
+        "out",
+        "-F",
+        "{camera.model}_{lens.model}_",
+    ];
+
+    // a file that fails is reported, not a failed run
+    let run = sandbox
+        .run(format.iter().chain(&["--strict-format"]))
+        .success();
+    assert!(run.logs().contains("couldn't fill in {lens.model} for"));
+    assert!(sandbox.outputs().is_empty());
+
+    sandbox.run(format).success();
+    assert_eq!(sandbox.outputs(), ["T1__IMG_0001.dng"]);
+}
+
+#[test]
+fn numbers_files_that_would_share_a_name_unless_strict() {
+    let sandbox = Sandbox::new();
+    sandbox.raw("IMG_0001.DNG", TAKEN);
+    sandbox.raw("IMG_0001.dng", TAKEN);
+
+    let run = sandbox
+        .run(["-i", "card", "-o", "out", "--strict"])
+        .failure();
+    assert!(run.logs().contains("would all be named"));
+    assert!(sandbox.outputs().is_empty());
+
+    let run = sandbox.run(["-i", "card", "-o", "out"]).success();
+    assert!(run.logs().contains("numbered after the first"));
+    assert_eq!(sandbox.outputs(), ["IMG_0001.dng", "IMG_0001_001.dng"]);
+}
+
+#[test]
+fn carries_seq_on_across_imports() {
+    let sandbox = Sandbox::new();
+    sandbox.raw("IMG_0001.DNG", TAKEN);
+    sandbox.raw("IMG_0002.DNG", TAKEN);
+    let import = [
+        "-i",
+        "card",
+        "-o",
+        "out",
+        "-F",
+        "{seq:03}_",
+        "--counter-file",
+    ];
+
+    sandbox.run(import).success();
+    fs::remove_dir_all(sandbox.card()).unwrap();
+    fs::create_dir(sandbox.card()).unwrap();
+    sandbox.raw("IMG_0003.DNG", TAKEN);
+
+    // a dry run doesn't use numbers up
+    sandbox.run(import.iter().chain(&["--dry-run"])).success();
+    sandbox.run(import).success();
+
+    assert_eq!(
+        sandbox.outputs(),
+        [
+            ".rawbit-seq",
+            "001_IMG_0001.dng",
+            "002_IMG_0002.dng",
+            "003_IMG_0003.dng"
+        ]
+    );
+    let counter = fs::read_to_string(sandbox.out().join(".rawbit-seq")).unwrap();
+    assert_eq!(counter.trim(), "3");
+}
+
+#[test]
+fn dry_run_only_plans() {
+    let sandbox = Sandbox::new();
+    sandbox.raw("IMG_0001.DNG", TAKEN);
+
+    let run = sandbox
+        .run(["-i", "card", "-o", "out", "-F", "%Y_", "--dry-run"])
+        .success();
+
+    assert!(run.logs().contains("2024_IMG_0001.dng"));
+    assert!(!sandbox.out().exists());
+}
+
+#[test]
+fn reports_how_each_file_went() {
+    let sandbox = Sandbox::new();
+    sandbox.raw("IMG_0001.DNG", TAKEN);
+    fs::write(sandbox.card().join("IMG_0002.DNG"), [0; 4096]).unwrap();
+
+    sandbox
+        .run(["-i", "card", "-o", "out", "--report", "report.jsonl"])
+        .success();
+
+    let report = fs::read_to_string(sandbox.path().join("report.jsonl")).unwrap();
+    let report: serde_json::Value = serde_json::from_str(report.trim_end()).unwrap();
+    let import = &report["imports"][0];
+
+    assert_eq!(import["converted"], 1);
+    assert_eq!(import["failed"], 1);
+
+    let status = |name: &str| {
+        import["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|file| file["source"].as_str().unwrap().ends_with(name))
+            .map(|file| file["status"].clone())
+            .unwrap()
+    };
+    assert_eq!(status("IMG_0001.DNG"), "converted");
+    assert_eq!(status("IMG_0002.DNG"), "failed");
+
+    assert_eq!(sandbox.outputs(), ["IMG_0001.dng"]);
+}
+
+#[test]
+fn converts_in_a_pipeline() {
+    let sandbox = Sandbox::new();
+    let raw = sandbox.raw("IMG_0001.DNG", TAKEN);
+
+    let run = sandbox
+        .run([OsStr::new("--stdout"), raw.as_os_str()])
+        .success();
+
+    let piped = sandbox.path().join("piped.dng");
+    fs::write(&piped, &run.0.stdout).unwrap();
+    assert_eq!(camera(&piped).1, "T1");
+}
+
+#[test]
+fn catalogs_a_transactional_batch_only_once_it_commits() {
+    let sandbox = Sandbox::new();
+    sandbox.raw("IMG_0001.DNG", TAKEN);
+    fs::write(sandbox.card().join("IMG_0002.DNG"), [0; 4096]).unwrap();
+    let import = ["-i", "card", "-o", "out", "--cache", "--transactional"];
+
+    sandbox.run(import).failure();
+    assert!(sandbox.outputs().is_empty());
+
+    let catalog = sandbox.path().join(".state/catalog.jsonl");
+    let recorded = || fs::read_to_string(&catalog).unwrap_or_default();
+    assert_eq!(recorded(), "");
+
+    fs::remove_file(sandbox.card().join("IMG_0002.DNG")).unwrap();
+    sandbox.run(import).success();
+    assert_eq!(sandbox.outputs(), ["IMG_0001.dng"]);
+    assert_eq!(recorded().lines().count(), 1);
+}
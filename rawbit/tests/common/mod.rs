@@ -0,0 +1,186 @@
+//! Runs the real `rawbit` binary against raws synthesized into a temporary directory, so the
+//! whole import is covered without sample files from any camera.
+
+use std::{
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use rawler::{
+    decoders::RawMetadata,
+    dng::{DNG_VERSION_V1_4, DngCompression, writer::DngWriter},
+    exif::Exif,
+    get_decoder,
+    rawsource::RawSource,
+    tags::TiffCommonTag,
+};
+use tempfile::TempDir;
+
+/// A temporary directory with a card to import from, and its own state, cache and config
+/// directories so runs don't see each other's or the user's.
+pub struct Sandbox {
+    dir: TempDir,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        let sandbox = Self {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        fs::create_dir(sandbox.card()).unwrap();
+        fs::write(sandbox.config(), "").unwrap();
+
+        sandbox
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// The config file runs read, empty unless a test writes one.
+    pub fn config(&self) -> PathBuf {
+        self.path().join("config.toml")
+    }
+
+    /// Where raws are put to import.
+    pub fn card(&self) -> PathBuf {
+        self.path().join("card")
+    }
+
+    /// Where the tests import to.
+    pub fn out(&self) -> PathBuf {
+        self.path().join("out")
+    }
+
+    /// Puts a raw named `name` on the card, taken by a "Testmake T1" at `taken`, an EXIF date.
+    pub fn raw(&self, name: &str, taken: &str) -> PathBuf {
+        let path = self.card().join(name);
+        fs::write(&path, dng("T1", taken)).unwrap();
+        path
+    }
+
+    /// Runs `rawbit` with `args`.
+    ///
+    /// The synthesized raws are a couple of KiB, much smaller than `--min-size` expects a raw to
+    /// be, so it's turned off.
+    pub fn run<I, S>(&self, args: I) -> Run
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let output = Command::new(env!("CARGO_BIN_EXE_rawbit"))
+            .current_dir(self.path())
+            .env("RAWBIT_STATE_DIR", self.path().join(".state"))
+            .env("RAWBIT_CACHE_DIR", self.path().join(".cache"))
+            .env("RAWBIT_CONFIG", self.config())
+            .args(["--min-size", "0"])
+            .args(args)
+            .output()
+            .unwrap();
+
+        Run(output)
+    }
+
+    /// Every file under the output directory, relative to it with `/` separators, in order.
+    pub fn outputs(&self) -> Vec<String> {
+        let mut files = vec![];
+        let mut dirs = vec![self.out()];
+
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+
+            for entry in entries.map(Result::unwrap) {
+                let path = entry.path();
+
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    let relative = path.strip_prefix(self.out()).unwrap();
+                    files.push(relative.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+
+        files.sort();
+        files
+    }
+}
+
+/// What a run of the binary did.
+pub struct Run(pub Output);
+
+impl Run {
+    /// Panics with what the run logged unless it exited successfully.
+    pub fn success(self) -> Self {
+        assert!(self.0.status.success(), "rawbit failed:\n{}", self.logs());
+        self
+    }
+
+    /// Panics with what the run logged if it exited successfully.
+    pub fn failure(self) -> Self {
+        assert!(
+            !self.0.status.success(),
+            "rawbit succeeded:\n{}",
+            self.logs()
+        );
+        self
+    }
+
+    /// Everything the run wrote, logs go to stdout and errors to stderr.
+    pub fn logs(&self) -> String {
+        format!(
+            "{}{}",
+            String::from_utf8_lossy(&self.0.stdout),
+            String::from_utf8_lossy(&self.0.stderr)
+        )
+    }
+}
+
+/// A DNG from a camera of `model`, taken at `taken`, with a small gray image.
+pub fn dng(model: &str, taken: &str) -> Vec<u8> {
+    let mut dng = Cursor::new(vec![]);
+
+    let mut writer = DngWriter::new(&mut dng, DNG_VERSION_V1_4).unwrap();
+    writer
+        .load_metadata(&RawMetadata {
+            make: "Testmake".into(),
+            model: model.into(),
+            exif: Exif {
+                date_time_original: Some(taken.into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+
+    // the make and model are read back from these tags, `load_metadata` only writes the EXIF
+    let root = writer.root_ifd_mut();
+    root.add_tag(TiffCommonTag::Make, "Testmake");
+    root.add_tag(TiffCommonTag::Model, model);
+
+    let mut frame = writer.subframe_on_root(0);
+    frame
+        .rgb_image_u8(&[128; 16 * 16 * 3], 16, 16, DngCompression::Uncompressed, 1)
+        .unwrap();
+    frame.finalize().unwrap();
+
+    writer.close().unwrap();
+
+    dng.into_inner()
+}
+
+/// The make and model of the raw at `path`, read back by rawler, which fails on anything that
+/// isn't a valid raw.
+pub fn camera(path: &Path) -> (String, String) {
+    let source = RawSource::new(path).unwrap();
+    let decoder = get_decoder(&source).unwrap();
+    let md = decoder
+        .raw_metadata(&source, &rawler::decoders::RawDecodeParams::default())
+        .unwrap();
+
+    (md.make, md.model)
+}
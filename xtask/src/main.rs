@@ -1,4 +1,4 @@
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 use gen_cli_docs::gen_docs;
 
 mod gen_cli_docs;